@@ -1,8 +1,169 @@
-use nesemu;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+
+use nesemu::cartridge::{read_rom_file, Cartridge, CartridgeError, OpenOptions};
 use nesemu::cpu::CPU;
+use nesemu::nsf::{Nsf, Player};
+
+mod wav;
+
+const SAMPLE_RATE: u32 = 44100;
+
+// A subcommand, given the arguments after its name
+type Command = fn(&[String]) -> Result<(), String>;
 
 fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let command: Option<Command> = match args.first().map(String::as_str) {
+        Some("nsf-wav") => Some(nsf_wav),
+        Some("nsf-soundtrack") => Some(nsf_soundtrack),
+        _ => None,
+    };
+
+    if let Some(command) = command {
+        if let Err(error) = command(&args[1..]) {
+            eprintln!("{}", error);
+            process::exit(1);
+        }
+
+        return;
+    }
+
     println!("Hello, NESemu!");
 
     let mut cpu = CPU::new();
+
+    if let Some(path) = args.first() {
+        match open(path, args.get(1)) {
+            Ok(cartridge) => cpu.insert_cartridge(cartridge),
+            Err(error) => {
+                eprintln!("{}: {}", path, error);
+                process::exit(1);
+            }
+        }
+    }
+
+    if let Some(cartridge) = cpu.cartridge_mut() {
+        if let Err(error) = cartridge.flush() {
+            eprintln!("could not write save: {}", error);
+        }
+    }
+}
+
+// Disk images, zipped or not, need the FDS BIOS, given after the image. ROMs
+// get the patch named like them applied, if there is one.
+fn open(path: &str, bios: Option<&String>) -> Result<Cartridge, String> {
+    let fds_bios = match bios {
+        Some(bios) => Some(fs::read(bios).map_err(|error| format!("{}: {}", bios, error))?),
+        None => None,
+    };
+
+    let options = OpenOptions {
+        patch: None,
+        auto_patch: true,
+        entry: None,
+        fds_bios,
+    };
+
+    Cartridge::open_with(path, &options).map_err(|error| match error {
+        CartridgeError::MissingBios => {
+            "disk images need the path of the FDS BIOS after them".to_string()
+        }
+        error => error.to_string(),
+    })
+}
+
+fn open_nsf(path: &str) -> Result<(Nsf, Player), String> {
+    let (_, data) = read_rom_file(path, None).map_err(|error| format!("{}: {}", path, error))?;
+    let nsf = Nsf::parse(&data).map_err(|error| format!("{}: {}", path, error))?;
+    let player = Player::new(&nsf, SAMPLE_RATE).map_err(|error| error.to_string())?;
+
+    Ok((nsf, player))
+}
+
+// Renders the song the player was started on until it has faded out
+fn render_track(player: &mut Player) -> Vec<f32> {
+    let mut samples = Vec::new();
+    let mut buffer = vec![0.0; SAMPLE_RATE as usize / 10];
+
+    while !player.finished() {
+        player.render(&mut buffer);
+        samples.extend_from_slice(&buffer);
+    }
+
+    samples
+}
+
+// nsf-wav <file.nsf> <track> [<seconds>] <output.wav>, with tracks counted
+// from 1. Without a length, the track plays for as long as the file says.
+fn nsf_wav(args: &[String]) -> Result<(), String> {
+    let usage = "usage: nesemu-desktop nsf-wav <file.nsf> <track> [<seconds>] <output.wav>";
+
+    let (path, track, seconds, output) = match args {
+        [path, track, output] => (path, track, None, output),
+        [path, track, seconds, output] => (path, track, Some(seconds), output),
+        _ => return Err(usage.to_string()),
+    };
+    let track: u8 = track.parse().map_err(|_| usage.to_string())?;
+    let seconds: Option<f64> = seconds
+        .map(|seconds| seconds.parse())
+        .transpose()
+        .map_err(|_| usage.to_string())?;
+
+    let (nsf, mut player) = open_nsf(path)?;
+
+    if track == 0 || !player.start(track - 1) {
+        return Err(format!("{} has tracks 1 to {}", path, nsf.song_count));
+    }
+
+    let samples = match seconds {
+        Some(seconds) => {
+            let mut samples = vec![0.0; (seconds * SAMPLE_RATE as f64) as usize];
+            player.render(&mut samples);
+            samples
+        }
+        None => render_track(&mut player),
+    };
+
+    wav::write(output, SAMPLE_RATE, &samples).map_err(|error| format!("{}: {}", output, error))
+}
+
+// nsf-soundtrack <file.nsf> <directory> writes each track in the file's
+// playlist to "NN - Title.wav"
+fn nsf_soundtrack(args: &[String]) -> Result<(), String> {
+    let [path, directory] = args else {
+        return Err("usage: nesemu-desktop nsf-soundtrack <file.nsf> <directory>".to_string());
+    };
+
+    let (nsf, mut player) = open_nsf(path)?;
+    fs::create_dir_all(directory).map_err(|error| format!("{}: {}", directory, error))?;
+
+    for (index, song) in nsf.track_order().into_iter().enumerate() {
+        let title = nsf.tracks[song as usize]
+            .title
+            .clone()
+            .unwrap_or_else(|| format!("Track {}", song + 1));
+
+        // Keep titles from reaching outside the directory
+        let title: String = title
+            .chars()
+            .map(|character| match character {
+                '/' | '\\' | ':' => '_',
+                _ => character,
+            })
+            .collect();
+        let output = Path::new(directory).join(format!("{:02} - {}.wav", index + 1, title));
+
+        player.start(song);
+        let samples = render_track(&mut player);
+
+        wav::write(&output, SAMPLE_RATE, &samples)
+            .map_err(|error| format!("{}: {}", output.display(), error))?;
+        println!("{}", output.display());
+    }
+
+    Ok(())
 }
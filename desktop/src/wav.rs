@@ -0,0 +1,32 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+// Writes mono 16-bit PCM
+pub fn write<P: AsRef<Path>>(path: P, sample_rate: u32, samples: &[f32]) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    let data_size = samples.len() as u32 * 2;
+
+    file.write_all(b"RIFF")?;
+    file.write_all(&(36 + data_size).to_le_bytes())?;
+    file.write_all(b"WAVE")?;
+
+    file.write_all(b"fmt ")?;
+    file.write_all(&16u32.to_le_bytes())?;
+    file.write_all(&1u16.to_le_bytes())?; // PCM
+    file.write_all(&1u16.to_le_bytes())?; // Channels
+    file.write_all(&sample_rate.to_le_bytes())?;
+    file.write_all(&(sample_rate * 2).to_le_bytes())?; // Bytes per second
+    file.write_all(&2u16.to_le_bytes())?; // Bytes per frame
+    file.write_all(&16u16.to_le_bytes())?; // Bits per sample
+
+    file.write_all(b"data")?;
+    file.write_all(&data_size.to_le_bytes())?;
+
+    for sample in samples {
+        let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        file.write_all(&value.to_le_bytes())?;
+    }
+
+    file.flush()
+}
@@ -0,0 +1,698 @@
+use crate::cartridge::Region;
+
+const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14, 12, 16, 24, 18, 48, 20, 96, 22,
+    192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+const DUTY_TABLE: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+const TRIANGLE_TABLE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12,
+    13, 14, 15,
+];
+
+// Noise and DMC periods, in CPU cycles
+const NOISE_PERIODS_NTSC: [u16; 16] = [
+    4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+];
+const NOISE_PERIODS_PAL: [u16; 16] = [
+    4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778,
+];
+const DMC_RATES_NTSC: [u16; 16] = [
+    428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+];
+const DMC_RATES_PAL: [u16; 16] = [
+    398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50,
+];
+
+// CPU cycles at which the frame counter steps. The fourth step is skipped
+// in the 5-step sequence.
+const FRAME_STEPS_NTSC: [u32; 5] = [7457, 14913, 22371, 29829, 37281];
+const FRAME_STEPS_PAL: [u32; 5] = [8313, 16627, 24939, 33253, 41565];
+
+#[derive(Default)]
+struct Envelope {
+    start: bool,
+    looping: bool,
+    constant: bool,
+    volume: u8,
+    divider: u8,
+    decay: u8,
+}
+
+impl Envelope {
+    fn write(&mut self, value: u8) {
+        self.looping = value & 0x20 != 0;
+        self.constant = value & 0x10 != 0;
+        self.volume = value & 0x0F;
+    }
+
+    fn clock(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay = 15;
+            self.divider = self.volume;
+        } else if self.divider == 0 {
+            self.divider = self.volume;
+
+            if self.decay > 0 {
+                self.decay -= 1;
+            } else if self.looping {
+                self.decay = 15;
+            }
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.constant {
+            self.volume
+        } else {
+            self.decay
+        }
+    }
+}
+
+#[derive(Default)]
+struct Pulse {
+    // Pulse 1 negates its sweep with the ones' complement
+    ones_complement: bool,
+    enabled: bool,
+    duty: u8,
+    step: u8,
+    period: u16,
+    timer: u16,
+    length: u8,
+    // Also the envelope's loop flag
+    length_halted: bool,
+    envelope: Envelope,
+    sweep_enabled: bool,
+    sweep_period: u8,
+    sweep_negate: bool,
+    sweep_shift: u8,
+    sweep_divider: u8,
+    sweep_reload: bool,
+}
+
+impl Pulse {
+    fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.duty = value >> 6;
+                self.length_halted = value & 0x20 != 0;
+                self.envelope.write(value);
+            }
+            1 => {
+                self.sweep_enabled = value & 0x80 != 0;
+                self.sweep_period = (value >> 4) & 0x07;
+                self.sweep_negate = value & 0x08 != 0;
+                self.sweep_shift = value & 0x07;
+                self.sweep_reload = true;
+            }
+            2 => self.period = (self.period & 0x0700) | value as u16,
+            _ => {
+                self.period = (self.period & 0x00FF) | ((value as u16 & 0x07) << 8);
+
+                if self.enabled {
+                    self.length = LENGTH_TABLE[value as usize >> 3];
+                }
+
+                self.step = 0;
+                self.envelope.start = true;
+            }
+        }
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+
+        if !enabled {
+            self.length = 0;
+        }
+    }
+
+    // Clocked every other CPU cycle
+    fn clock(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.period;
+            self.step = (self.step + 1) & 0x07;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn target_period(&self) -> u16 {
+        let change = self.period >> self.sweep_shift;
+
+        if !self.sweep_negate {
+            self.period + change
+        } else if self.ones_complement {
+            self.period.saturating_sub(change + 1)
+        } else {
+            self.period.saturating_sub(change)
+        }
+    }
+
+    fn muted(&self) -> bool {
+        self.period < 8 || self.target_period() > 0x07FF
+    }
+
+    fn clock_length(&mut self) {
+        if !self.length_halted && self.length > 0 {
+            self.length -= 1;
+        }
+    }
+
+    fn clock_sweep(&mut self) {
+        if self.sweep_divider == 0 && self.sweep_enabled && self.sweep_shift > 0 && !self.muted() {
+            self.period = self.target_period();
+        }
+
+        if self.sweep_divider == 0 || self.sweep_reload {
+            self.sweep_divider = self.sweep_period;
+            self.sweep_reload = false;
+        } else {
+            self.sweep_divider -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.length == 0
+            || self.muted()
+            || DUTY_TABLE[self.duty as usize][self.step as usize] == 0
+        {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+}
+
+#[derive(Default)]
+struct Triangle {
+    enabled: bool,
+    step: u8,
+    period: u16,
+    timer: u16,
+    length: u8,
+    // Also the linear counter's control flag
+    length_halted: bool,
+    linear_reload_value: u8,
+    linear_counter: u8,
+    linear_reload: bool,
+}
+
+impl Triangle {
+    fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.length_halted = value & 0x80 != 0;
+                self.linear_reload_value = value & 0x7F;
+            }
+            2 => self.period = (self.period & 0x0700) | value as u16,
+            3 => {
+                self.period = (self.period & 0x00FF) | ((value as u16 & 0x07) << 8);
+
+                if self.enabled {
+                    self.length = LENGTH_TABLE[value as usize >> 3];
+                }
+
+                self.linear_reload = true;
+            }
+            _ => {}
+        }
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+
+        if !enabled {
+            self.length = 0;
+        }
+    }
+
+    fn clock(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.period;
+
+            // Ultrasonic periods are silenced by holding the sequencer,
+            // rather than letting it pop
+            if self.length > 0 && self.linear_counter > 0 && self.period >= 2 {
+                self.step = (self.step + 1) & 0x1F;
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn clock_linear(&mut self) {
+        if self.linear_reload {
+            self.linear_counter = self.linear_reload_value;
+        } else if self.linear_counter > 0 {
+            self.linear_counter -= 1;
+        }
+
+        if !self.length_halted {
+            self.linear_reload = false;
+        }
+    }
+
+    fn clock_length(&mut self) {
+        if !self.length_halted && self.length > 0 {
+            self.length -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        TRIANGLE_TABLE[self.step as usize]
+    }
+}
+
+struct Noise {
+    enabled: bool,
+    short_mode: bool,
+    period: u16,
+    timer: u16,
+    shift: u16,
+    length: u8,
+    length_halted: bool,
+    envelope: Envelope,
+}
+
+impl Noise {
+    fn new() -> Self {
+        Noise {
+            enabled: false,
+            short_mode: false,
+            period: NOISE_PERIODS_NTSC[0],
+            timer: 0,
+            shift: 1,
+            length: 0,
+            length_halted: false,
+            envelope: Envelope::default(),
+        }
+    }
+
+    fn write(&mut self, register: u16, value: u8, periods: &[u16; 16]) {
+        match register {
+            0 => {
+                self.length_halted = value & 0x20 != 0;
+                self.envelope.write(value);
+            }
+            2 => {
+                self.short_mode = value & 0x80 != 0;
+                self.period = periods[value as usize & 0x0F];
+            }
+            3 => {
+                if self.enabled {
+                    self.length = LENGTH_TABLE[value as usize >> 3];
+                }
+
+                self.envelope.start = true;
+            }
+            _ => {}
+        }
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+
+        if !enabled {
+            self.length = 0;
+        }
+    }
+
+    fn clock(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+
+        self.timer = self.period - 1;
+
+        let tap = if self.short_mode { 6 } else { 1 };
+        let feedback = (self.shift ^ (self.shift >> tap)) & 0x01;
+        self.shift = (self.shift >> 1) | (feedback << 14);
+    }
+
+    fn clock_length(&mut self) {
+        if !self.length_halted && self.length > 0 {
+            self.length -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.length == 0 || self.shift & 0x01 != 0 {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+}
+
+struct Dmc {
+    irq_enabled: bool,
+    irq: bool,
+    looping: bool,
+    rate: u16,
+    timer: u16,
+    level: u8,
+    sample_address: u16,
+    sample_length: u16,
+    address: u16,
+    remaining: u16,
+    buffer: Option<u8>,
+    shift: u8,
+    bits: u8,
+    silent: bool,
+}
+
+impl Dmc {
+    fn new() -> Self {
+        Dmc {
+            irq_enabled: false,
+            irq: false,
+            looping: false,
+            rate: DMC_RATES_NTSC[0],
+            timer: 0,
+            level: 0,
+            sample_address: 0xC000,
+            sample_length: 1,
+            address: 0xC000,
+            remaining: 0,
+            buffer: None,
+            shift: 0,
+            bits: 8,
+            silent: true,
+        }
+    }
+
+    fn write(&mut self, register: u16, value: u8, rates: &[u16; 16]) {
+        match register {
+            0 => {
+                self.irq_enabled = value & 0x80 != 0;
+                self.looping = value & 0x40 != 0;
+                self.rate = rates[value as usize & 0x0F];
+
+                if !self.irq_enabled {
+                    self.irq = false;
+                }
+            }
+            1 => self.level = value & 0x7F,
+            2 => self.sample_address = 0xC000 + value as u16 * 64,
+            _ => self.sample_length = value as u16 * 16 + 1,
+        }
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        if !enabled {
+            self.remaining = 0;
+        } else if self.remaining == 0 {
+            self.restart();
+        }
+    }
+
+    fn restart(&mut self) {
+        self.address = self.sample_address;
+        self.remaining = self.sample_length;
+    }
+
+    fn fill(&mut self, value: u8) {
+        self.buffer = Some(value);
+        self.address = self.address.checked_add(1).unwrap_or(0x8000);
+        self.remaining -= 1;
+
+        if self.remaining == 0 {
+            if self.looping {
+                self.restart();
+            } else if self.irq_enabled {
+                self.irq = true;
+            }
+        }
+    }
+
+    fn clock(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+
+        self.timer = self.rate - 1;
+
+        if !self.silent {
+            if self.shift & 0x01 != 0 {
+                if self.level <= 125 {
+                    self.level += 2;
+                }
+            } else if self.level >= 2 {
+                self.level -= 2;
+            }
+        }
+
+        self.shift >>= 1;
+        self.bits -= 1;
+
+        if self.bits == 0 {
+            self.bits = 8;
+
+            match self.buffer.take() {
+                Some(value) => {
+                    self.shift = value;
+                    self.silent = false;
+                }
+                None => self.silent = true,
+            }
+        }
+    }
+}
+
+// The 2A03's audio: two pulses, a triangle, noise and delta modulation
+pub struct Apu {
+    pulses: [Pulse; 2],
+    triangle: Triangle,
+    noise: Noise,
+    dmc: Dmc,
+
+    noise_periods: &'static [u16; 16],
+    dmc_rates: &'static [u16; 16],
+    frame_steps: &'static [u32; 5],
+
+    five_step: bool,
+    frame_irq_inhibited: bool,
+    frame_irq: bool,
+    frame_cycle: u32,
+    odd_cycle: bool,
+}
+
+impl Default for Apu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Apu {
+    pub fn new() -> Self {
+        Apu {
+            pulses: [
+                Pulse {
+                    ones_complement: true,
+                    ..Pulse::default()
+                },
+                Pulse::default(),
+            ],
+            triangle: Triangle::default(),
+            noise: Noise::new(),
+            dmc: Dmc::new(),
+            noise_periods: &NOISE_PERIODS_NTSC,
+            dmc_rates: &DMC_RATES_NTSC,
+            frame_steps: &FRAME_STEPS_NTSC,
+            five_step: false,
+            frame_irq_inhibited: false,
+            frame_irq: false,
+            frame_cycle: 0,
+            odd_cycle: false,
+        }
+    }
+
+    // The Dendy runs the APU on NTSC timings
+    pub fn set_region(&mut self, region: Region) {
+        let pal = region == Region::Pal;
+
+        self.noise_periods = if pal {
+            &NOISE_PERIODS_PAL
+        } else {
+            &NOISE_PERIODS_NTSC
+        };
+        self.dmc_rates = if pal { &DMC_RATES_PAL } else { &DMC_RATES_NTSC };
+        self.frame_steps = if pal {
+            &FRAME_STEPS_PAL
+        } else {
+            &FRAME_STEPS_NTSC
+        };
+    }
+
+    // $4015
+    pub fn read_status(&mut self) -> u8 {
+        let status = (self.pulses[0].length > 0) as u8
+            | ((self.pulses[1].length > 0) as u8) << 1
+            | ((self.triangle.length > 0) as u8) << 2
+            | ((self.noise.length > 0) as u8) << 3
+            | ((self.dmc.remaining > 0) as u8) << 4
+            | (self.frame_irq as u8) << 6
+            | (self.dmc.irq as u8) << 7;
+
+        self.frame_irq = false;
+
+        status
+    }
+
+    // $4000-$4013, $4015 and $4017
+    pub fn write(&mut self, address: u16, value: u8) {
+        match address {
+            0x4000..=0x4003 => self.pulses[0].write(address & 0x03, value),
+            0x4004..=0x4007 => self.pulses[1].write(address & 0x03, value),
+            0x4008..=0x400B => self.triangle.write(address & 0x03, value),
+            0x400C..=0x400F => self.noise.write(address & 0x03, value, self.noise_periods),
+            0x4010..=0x4013 => self.dmc.write(address & 0x03, value, self.dmc_rates),
+            0x4015 => {
+                self.pulses[0].set_enabled(value & 0x01 != 0);
+                self.pulses[1].set_enabled(value & 0x02 != 0);
+                self.triangle.set_enabled(value & 0x04 != 0);
+                self.noise.set_enabled(value & 0x08 != 0);
+                self.dmc.set_enabled(value & 0x10 != 0);
+                self.dmc.irq = false;
+            }
+            0x4017 => {
+                self.five_step = value & 0x80 != 0;
+                self.frame_irq_inhibited = value & 0x40 != 0;
+                self.frame_cycle = 0;
+
+                if self.frame_irq_inhibited {
+                    self.frame_irq = false;
+                }
+
+                if self.five_step {
+                    self.clock_quarter_frame();
+                    self.clock_half_frame();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Called once per CPU cycle
+    pub fn clock(&mut self) {
+        self.triangle.clock();
+        self.noise.clock();
+        self.dmc.clock();
+
+        if self.odd_cycle {
+            self.pulses[0].clock();
+            self.pulses[1].clock();
+        }
+
+        self.odd_cycle = !self.odd_cycle;
+        self.clock_frame_counter();
+    }
+
+    // Address of the next DMC sample byte, when the DMC wants one. The CPU
+    // reads it and hands it back through `fill_dmc`.
+    pub fn dmc_request(&self) -> Option<u16> {
+        if self.dmc.buffer.is_none() && self.dmc.remaining > 0 {
+            Some(self.dmc.address)
+        } else {
+            None
+        }
+    }
+
+    pub fn fill_dmc(&mut self, value: u8) {
+        self.dmc.fill(value);
+    }
+
+    // State of the frame counter and DMC /IRQ line
+    pub fn irq(&self) -> bool {
+        self.frame_irq || self.dmc.irq
+    }
+
+    // The channels mixed the way the console's resistors do, from 0.0 to
+    // about 1.0
+    pub fn output(&self) -> f32 {
+        let pulses = (self.pulses[0].output() + self.pulses[1].output()) as f32;
+        let pulse_out = if pulses == 0.0 {
+            0.0
+        } else {
+            95.88 / (8128.0 / pulses + 100.0)
+        };
+
+        let tnd = self.triangle.output() as f32 / 8227.0
+            + self.noise.output() as f32 / 12241.0
+            + self.dmc.level as f32 / 22638.0;
+        let tnd_out = if tnd == 0.0 {
+            0.0
+        } else {
+            159.79 / (1.0 / tnd + 100.0)
+        };
+
+        pulse_out + tnd_out
+    }
+
+    fn clock_frame_counter(&mut self) {
+        self.frame_cycle += 1;
+
+        let steps = self.frame_steps;
+        let step = match steps.iter().position(|&cycle| cycle == self.frame_cycle) {
+            Some(step) => step,
+            None => return,
+        };
+
+        match (step, self.five_step) {
+            (0 | 2, _) => self.clock_quarter_frame(),
+            (1, _) | (4, true) => {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+            }
+            (3, false) => {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+
+                if !self.frame_irq_inhibited {
+                    self.frame_irq = true;
+                }
+            }
+            _ => {}
+        }
+
+        let last = if self.five_step { 4 } else { 3 };
+
+        if step == last {
+            self.frame_cycle = 0;
+        }
+    }
+
+    fn clock_quarter_frame(&mut self) {
+        self.pulses[0].envelope.clock();
+        self.pulses[1].envelope.clock();
+        self.noise.envelope.clock();
+        self.triangle.clock_linear();
+    }
+
+    fn clock_half_frame(&mut self) {
+        for pulse in &mut self.pulses {
+            pulse.clock_length();
+            pulse.clock_sweep();
+        }
+
+        self.triangle.clock_length();
+        self.noise.clock_length();
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,147 @@
+use super::*;
+
+fn clock(apu: &mut Apu, cycles: u32) {
+    for _ in 0..cycles {
+        apu.clock();
+    }
+}
+
+#[test]
+fn test_length_counters() {
+    let mut apu = Apu::new();
+
+    // Lengths only load while the channel is enabled
+    apu.write(0x4003, 0x08);
+    assert_eq!(apu.read_status() & 0x01, 0);
+
+    apu.write(0x4015, 0x0F);
+    apu.write(0x4003, 0x18);
+    apu.write(0x4007, 0x18);
+    apu.write(0x400B, 0x18);
+    apu.write(0x400F, 0x18);
+    assert_eq!(apu.read_status() & 0x0F, 0x0F);
+
+    // A length of 2 runs out after two half frames
+    clock(&mut apu, FRAME_STEPS_NTSC[1]);
+    assert_eq!(apu.read_status() & 0x0F, 0x0F);
+    clock(&mut apu, FRAME_STEPS_NTSC[3] - FRAME_STEPS_NTSC[1]);
+    assert_eq!(apu.read_status() & 0x0F, 0);
+
+    // Halted counters keep going
+    apu.write(0x4000, 0x20);
+    apu.write(0x4003, 0x18);
+    clock(&mut apu, FRAME_STEPS_NTSC[3]);
+    assert_eq!(apu.read_status() & 0x01, 0x01);
+
+    apu.write(0x4015, 0x00);
+    assert_eq!(apu.read_status() & 0x0F, 0);
+}
+
+#[test]
+fn test_frame_irq() {
+    let mut apu = Apu::new();
+    clock(&mut apu, FRAME_STEPS_NTSC[3] - 1);
+    assert!(!apu.irq());
+    apu.clock();
+    assert!(apu.irq());
+
+    // Reading the status acknowledges it
+    assert_eq!(apu.read_status() & 0x40, 0x40);
+    assert!(!apu.irq());
+
+    // Neither the 5-step sequence nor an inhibited 4-step one raises it
+    apu.write(0x4017, 0x80);
+    clock(&mut apu, FRAME_STEPS_NTSC[4] * 2);
+    assert!(!apu.irq());
+
+    apu.write(0x4017, 0x40);
+    clock(&mut apu, FRAME_STEPS_NTSC[3] * 2);
+    assert!(!apu.irq());
+}
+
+#[test]
+fn test_pulse_output() {
+    let mut apu = Apu::new();
+
+    // The triangle rests at its highest step, so the mix isn't silent
+    let rest = apu.output();
+    assert!(rest > 0.0);
+
+    // Constant volume 15, 50% duty, period 253
+    apu.write(0x4015, 0x01);
+    apu.write(0x4000, 0xBF);
+    apu.write(0x4002, 0xFD);
+    apu.write(0x4003, 0x00);
+
+    let mut high = 0;
+
+    for _ in 0..(254 * 2 * 8) {
+        apu.clock();
+
+        if apu.output() > rest {
+            high += 1;
+        }
+    }
+
+    assert_eq!(high, 254 * 2 * 4);
+
+    // Periods below 8 are muted
+    apu.write(0x4002, 0x07);
+    assert_eq!(apu.output(), rest);
+}
+
+#[test]
+fn test_pulse_sweep() {
+    let mut apu = Apu::new();
+    apu.write(0x4015, 0x03);
+
+    // Negated sweeps differ by one between the two pulses
+    for base in [0x4000, 0x4004] {
+        apu.write(base, 0x30);
+        apu.write(base + 1, 0x89);
+        apu.write(base + 2, 0x00);
+        apu.write(base + 3, 0x01);
+    }
+
+    clock(&mut apu, FRAME_STEPS_NTSC[1]);
+    assert_eq!(apu.pulses[0].period, 0x0100 - 0x80 - 1);
+    assert_eq!(apu.pulses[1].period, 0x0100 - 0x80);
+}
+
+#[test]
+fn test_dmc() {
+    let mut apu = Apu::new();
+    let rest = apu.output();
+    apu.write(0x4011, 0x40);
+    assert!(apu.output() > rest);
+
+    // One byte at $C040, with an IRQ at the end
+    apu.write(0x4010, 0x8F);
+    apu.write(0x4012, 0x01);
+    apu.write(0x4013, 0x00);
+    apu.write(0x4015, 0x10);
+    assert_eq!(apu.read_status() & 0x10, 0x10);
+    assert_eq!(apu.dmc_request(), Some(0xC040));
+
+    apu.fill_dmc(0xFF);
+    assert_eq!(apu.dmc_request(), None);
+    assert!(apu.irq());
+    assert_eq!(apu.read_status() & 0x90, 0x80);
+
+    // Every set bit raises the level by 2
+    clock(&mut apu, 54 * 16);
+    assert_eq!(apu.dmc.level, 0x40 + 16);
+
+    apu.write(0x4015, 0x00);
+    assert!(!apu.irq());
+}
+
+#[test]
+fn test_pal_timing() {
+    let mut apu = Apu::new();
+    apu.set_region(Region::Pal);
+    clock(&mut apu, FRAME_STEPS_NTSC[3]);
+    assert!(!apu.irq());
+    clock(&mut apu, FRAME_STEPS_PAL[3] - FRAME_STEPS_NTSC[3]);
+    assert!(apu.irq());
+}
@@ -0,0 +1,218 @@
+use std::fmt;
+use std::sync::OnceLock;
+
+use super::hash::RomHash;
+use super::{Header, InputDevice, Mirroring, Region};
+
+const BUILTIN: &str = include_str!("database.txt");
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DatabaseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for DatabaseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for DatabaseError {}
+
+// Header fields to override for one dump. `None` keeps the header's value.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Correction {
+    pub mapper: Option<u16>,
+    pub submapper: Option<u8>,
+    pub mirroring: Option<Mirroring>,
+    pub region: Option<Region>,
+    pub battery: Option<bool>,
+    pub input_device: Option<InputDevice>,
+    pub prg_ram_size: Option<usize>,
+    pub chr_ram_size: Option<usize>,
+}
+
+impl Correction {
+    pub fn apply(&self, header: &mut Header) {
+        if let Some(mapper) = self.mapper {
+            header.mapper = mapper;
+        }
+
+        if let Some(submapper) = self.submapper {
+            header.submapper = submapper;
+        }
+
+        if let Some(mirroring) = self.mirroring {
+            header.mirroring = mirroring;
+
+            if mirroring != Mirroring::FourScreen {
+                header.hardwired_mirroring = mirroring;
+            }
+        }
+
+        if let Some(region) = self.region {
+            header.region = region;
+        }
+
+        if let Some(battery) = self.battery {
+            header.battery = battery;
+        }
+
+        if let Some(input_device) = self.input_device {
+            header.input_device = input_device;
+        }
+
+        if let Some(size) = self.prg_ram_size {
+            header.prg_ram_size = size;
+        }
+
+        if let Some(size) = self.chr_ram_size {
+            header.chr_ram_size = size;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Key {
+    Crc32(u32),
+    Sha1([u8; 20]),
+}
+
+#[derive(Debug, Default)]
+pub struct Database {
+    entries: Vec<(Key, Correction)>,
+}
+
+impl Database {
+    // The corrections shipped with the crate
+    pub fn builtin() -> &'static Database {
+        static BUILTIN_DATABASE: OnceLock<Database> = OnceLock::new();
+
+        BUILTIN_DATABASE
+            .get_or_init(|| Database::parse(BUILTIN).expect("built-in ROM database is invalid"))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, DatabaseError> {
+        let mut entries = Vec::new();
+
+        for (index, line) in text.lines().enumerate() {
+            let error = |message: String| DatabaseError {
+                line: index + 1,
+                message,
+            };
+
+            let line = line.split('#').next().unwrap_or("");
+            let mut fields = line.split_whitespace();
+
+            let key = match fields.next() {
+                Some(hash) => {
+                    parse_key(hash).ok_or_else(|| error(format!("bad hash {:?}", hash)))?
+                }
+                None => continue,
+            };
+
+            let mut correction = Correction::default();
+
+            for field in fields {
+                let (name, value) = field
+                    .split_once('=')
+                    .ok_or_else(|| error(format!("expected name=value, found {:?}", field)))?;
+
+                parse_field(&mut correction, name, value)
+                    .ok_or_else(|| error(format!("bad field {:?}", field)))?;
+            }
+
+            entries.push((key, correction));
+        }
+
+        Ok(Database { entries })
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // SHA-1 entries take precedence, since CRC32s can collide
+    pub fn lookup(&self, hash: &RomHash) -> Option<&Correction> {
+        let find = |wanted: Key| {
+            self.entries
+                .iter()
+                .find(|(key, _)| *key == wanted)
+                .map(|(_, correction)| correction)
+        };
+
+        find(Key::Sha1(hash.sha1)).or_else(|| find(Key::Crc32(hash.crc32)))
+    }
+}
+
+fn parse_key(hash: &str) -> Option<Key> {
+    match hash.len() {
+        8 => u32::from_str_radix(hash, 16).ok().map(Key::Crc32),
+        40 => {
+            let mut sha1 = [0; 20];
+
+            for (i, byte) in sha1.iter_mut().enumerate() {
+                *byte = u8::from_str_radix(hash.get(i * 2..i * 2 + 2)?, 16).ok()?;
+            }
+
+            Some(Key::Sha1(sha1))
+        }
+        _ => None,
+    }
+}
+
+fn parse_field(correction: &mut Correction, name: &str, value: &str) -> Option<()> {
+    match name {
+        "mapper" => correction.mapper = Some(value.parse().ok()?),
+        "submapper" => correction.submapper = Some(value.parse().ok()?),
+        "mirroring" => {
+            correction.mirroring = Some(match value {
+                "horizontal" => Mirroring::Horizontal,
+                "vertical" => Mirroring::Vertical,
+                "four-screen" => Mirroring::FourScreen,
+                _ => return None,
+            });
+        }
+        "region" => {
+            correction.region = Some(match value {
+                "ntsc" => Region::Ntsc,
+                "pal" => Region::Pal,
+                "multiple" => Region::Multiple,
+                "dendy" => Region::Dendy,
+                _ => return None,
+            });
+        }
+        "battery" => {
+            correction.battery = Some(match value {
+                "yes" => true,
+                "no" => false,
+                _ => return None,
+            });
+        }
+        "input" => {
+            correction.input_device = Some(match value {
+                "standard" => InputDevice::StandardControllers,
+                "four-score" => InputDevice::FourScore,
+                "four-players" => InputDevice::FamicomFourPlayers,
+                "zapper" => InputDevice::Zapper,
+                "power-pad" => InputDevice::PowerPad,
+                "vaus" => InputDevice::ArkanoidNes,
+                "vaus-famicom" => InputDevice::ArkanoidFamicom,
+                "keyboard" => InputDevice::FamilyBasicKeyboard,
+                _ => return None,
+            });
+        }
+        "prg-ram" => correction.prg_ram_size = Some(value.parse().ok()?),
+        "chr-ram" => correction.chr_ram_size = Some(value.parse().ok()?),
+        _ => return None,
+    }
+
+    Some(())
+}
@@ -0,0 +1,66 @@
+use super::{Mapper, Memory, Mirroring};
+
+// ANROM, AN1ROM, AMROM and AOROM (mapper 7)
+pub struct Axrom {
+    memory: Memory,
+    bank: usize,
+    mirroring: Mirroring,
+    bus_conflicts: bool,
+}
+
+impl Axrom {
+    pub fn new(memory: Memory, submapper: u8) -> Self {
+        Axrom {
+            memory,
+            bank: 0,
+            mirroring: Mirroring::SingleScreenLower,
+            bus_conflicts: submapper == 2,
+        }
+    }
+
+    fn prg(&self, address: u16) -> u8 {
+        match address {
+            0x8000..=0xFFFF => self.memory.read_prg_rom(self.bank, 0x8000, address),
+            _ => 0,
+        }
+    }
+}
+
+impl Mapper for Axrom {
+    fn read_prg(&mut self, address: u16) -> u8 {
+        self.prg(address)
+    }
+
+    fn write_prg(&mut self, address: u16, mut value: u8) {
+        if address < 0x8000 {
+            return;
+        }
+
+        if self.bus_conflicts {
+            value &= self.prg(address);
+        }
+
+        self.bank = (value & 0x0F) as usize;
+        self.mirroring = if value & 0x10 != 0 {
+            Mirroring::SingleScreenUpper
+        } else {
+            Mirroring::SingleScreenLower
+        };
+    }
+
+    fn read_chr(&mut self, address: u16) -> u8 {
+        self.memory.read_chr(0, 0x2000, address)
+    }
+
+    fn write_chr(&mut self, address: u16, value: u8) {
+        self.memory.write_chr(0, 0x2000, address, value);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn memory(&mut self) -> &mut Memory {
+        &mut self.memory
+    }
+}
@@ -0,0 +1,95 @@
+use super::{Mapper, Memory, Mirroring};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Board {
+    Bnrom,
+    Nina001,
+}
+
+// BNROM and AVE NINA-001 (mapper 34)
+pub struct Bnrom {
+    memory: Memory,
+    board: Board,
+    prg_bank: usize,
+    chr_banks: [usize; 2],
+}
+
+impl Bnrom {
+    pub fn new(memory: Memory, submapper: u8) -> Self {
+        let board = match submapper {
+            1 => Board::Nina001,
+            2 => Board::Bnrom,
+            // Without a submapper, only NINA-001 has CHR ROM larger than 8 KB
+            _ if memory.chr.len() > 0x2000 => Board::Nina001,
+            _ => Board::Bnrom,
+        };
+
+        Bnrom {
+            memory,
+            board,
+            prg_bank: 0,
+            chr_banks: [0, 1],
+        }
+    }
+
+    fn prg(&self, address: u16) -> u8 {
+        match address {
+            0x6000..=0x7FFF if self.board == Board::Nina001 => self.memory.read_prg_ram(address),
+            0x8000..=0xFFFF => self.memory.read_prg_rom(self.prg_bank, 0x8000, address),
+            _ => 0,
+        }
+    }
+}
+
+impl Mapper for Bnrom {
+    fn read_prg(&mut self, address: u16) -> u8 {
+        self.prg(address)
+    }
+
+    fn write_prg(&mut self, address: u16, value: u8) {
+        match (self.board, address) {
+            (Board::Bnrom, 0x8000..=0xFFFF) => {
+                self.prg_bank = (value & self.prg(address)) as usize;
+            }
+            (Board::Nina001, 0x6000..=0x7FFF) => {
+                self.memory.write_prg_ram(address, value);
+
+                match address {
+                    0x7FFD => self.prg_bank = (value & 0x01) as usize,
+                    0x7FFE => self.chr_banks[0] = (value & 0x0F) as usize,
+                    0x7FFF => self.chr_banks[1] = (value & 0x0F) as usize,
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, address: u16) -> u8 {
+        match self.board {
+            Board::Bnrom => self.memory.read_chr(0, 0x2000, address),
+            Board::Nina001 => {
+                let bank = self.chr_banks[(address >> 12) as usize & 1];
+                self.memory.read_chr(bank, 0x1000, address)
+            }
+        }
+    }
+
+    fn write_chr(&mut self, address: u16, value: u8) {
+        match self.board {
+            Board::Bnrom => self.memory.write_chr(0, 0x2000, address, value),
+            Board::Nina001 => {
+                let bank = self.chr_banks[(address >> 12) as usize & 1];
+                self.memory.write_chr(bank, 0x1000, address, value);
+            }
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.memory.mirroring
+    }
+
+    fn memory(&mut self) -> &mut Memory {
+        &mut self.memory
+    }
+}
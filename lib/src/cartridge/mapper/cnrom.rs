@@ -0,0 +1,59 @@
+use super::{Mapper, Memory, Mirroring};
+
+// CNROM (mapper 3)
+pub struct Cnrom {
+    memory: Memory,
+    bank: usize,
+    bus_conflicts: bool,
+}
+
+impl Cnrom {
+    pub fn new(memory: Memory, submapper: u8) -> Self {
+        Cnrom {
+            memory,
+            bank: 0,
+            bus_conflicts: submapper == 2,
+        }
+    }
+
+    fn prg(&self, address: u16) -> u8 {
+        match address {
+            0x8000..=0xFFFF => self.memory.read_prg_rom(0, 0x8000, address),
+            _ => 0,
+        }
+    }
+}
+
+impl Mapper for Cnrom {
+    fn read_prg(&mut self, address: u16) -> u8 {
+        self.prg(address)
+    }
+
+    fn write_prg(&mut self, address: u16, mut value: u8) {
+        if address < 0x8000 {
+            return;
+        }
+
+        if self.bus_conflicts {
+            value &= self.prg(address);
+        }
+
+        self.bank = value as usize;
+    }
+
+    fn read_chr(&mut self, address: u16) -> u8 {
+        self.memory.read_chr(self.bank, 0x2000, address)
+    }
+
+    fn write_chr(&mut self, address: u16, value: u8) {
+        self.memory.write_chr(self.bank, 0x2000, address, value);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.memory.mirroring
+    }
+
+    fn memory(&mut self) -> &mut Memory {
+        &mut self.memory
+    }
+}
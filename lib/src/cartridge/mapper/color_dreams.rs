@@ -0,0 +1,58 @@
+use super::{Mapper, Memory, Mirroring};
+
+// Color Dreams (mapper 11)
+pub struct ColorDreams {
+    memory: Memory,
+    prg_bank: usize,
+    chr_bank: usize,
+}
+
+impl ColorDreams {
+    pub fn new(memory: Memory) -> Self {
+        ColorDreams {
+            memory,
+            prg_bank: 0,
+            chr_bank: 0,
+        }
+    }
+
+    fn prg(&self, address: u16) -> u8 {
+        match address {
+            0x8000..=0xFFFF => self.memory.read_prg_rom(self.prg_bank, 0x8000, address),
+            _ => 0,
+        }
+    }
+}
+
+impl Mapper for ColorDreams {
+    fn read_prg(&mut self, address: u16) -> u8 {
+        self.prg(address)
+    }
+
+    fn write_prg(&mut self, address: u16, value: u8) {
+        if address < 0x8000 {
+            return;
+        }
+
+        let value = value & self.prg(address);
+
+        self.prg_bank = (value & 0x03) as usize;
+        self.chr_bank = (value >> 4) as usize;
+    }
+
+    fn read_chr(&mut self, address: u16) -> u8 {
+        self.memory.read_chr(self.chr_bank, 0x2000, address)
+    }
+
+    fn write_chr(&mut self, address: u16, value: u8) {
+        self.memory.write_chr(self.chr_bank, 0x2000, address, value);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.memory.mirroring
+    }
+
+    fn memory(&mut self) -> &mut Memory {
+        &mut self.memory
+    }
+}
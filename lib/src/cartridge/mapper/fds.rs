@@ -0,0 +1,551 @@
+use std::io::{self, Read, Write};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use super::fds_audio::FdsAudio;
+use super::{Mapper, Memory, Mirroring};
+use crate::cartridge::CartridgeError;
+
+const FDS_MAGIC: [u8; 4] = [b'F', b'D', b'S', 0x1A];
+const FDS_HEADER_SIZE: usize = 16;
+// A side as stored in .fds files: blocks only, without gaps or CRCs
+const FDS_SIDE_SIZE: usize = 65500;
+// A side as stored in .qd files: blocks followed by their CRCs
+const QD_SIDE_SIZE: usize = 0x10000;
+
+// Gaps the drive sees on the disk, in bytes: before the first block and
+// between blocks
+const LEADING_GAP: usize = 28300 / 8;
+const BLOCK_GAP: usize = 976 / 8;
+
+// The drive moves one byte past the head roughly every 150 CPU cycles, and
+// takes a while to get from the end of the disk back to the start
+const BYTE_CYCLES: u32 = 150;
+const REWIND_CYCLES: u32 = 50000;
+
+// Block 3 holds the size of the file data in the block 4 after it
+const FILE_HEADER_BLOCK: u8 = 3;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Fds,
+    QuickDisk,
+}
+
+// Lays a side out the way the drive reads it, with the gap before each
+// block ending in a $80 start mark. The drive reports every CRC as good, so
+// .fds sides get placeholder ones.
+fn build_side(side: &[u8], format: Format) -> Vec<u8> {
+    let mut raw = vec![0; LEADING_GAP];
+    let mut position = 0;
+    let mut file_size = 0;
+
+    while position < side.len() {
+        let length = match side[position] {
+            1 => 56,
+            2 => 2,
+            FILE_HEADER_BLOCK if position + 15 < side.len() => {
+                file_size = side[position + 13] as usize | (side[position + 14] as usize) << 8;
+                16
+            }
+            4 => 1 + file_size,
+            _ => break,
+        };
+
+        let crc_length = if format == Format::QuickDisk { 2 } else { 0 };
+
+        if position + length + crc_length > side.len() {
+            break;
+        }
+
+        raw.push(0x80);
+        raw.extend_from_slice(&side[position..position + length]);
+
+        match format {
+            Format::Fds => raw.extend_from_slice(&[0, 0]),
+            Format::QuickDisk => {
+                raw.extend_from_slice(&side[position + length..position + length + 2])
+            }
+        }
+
+        raw.extend(std::iter::repeat_n(0, BLOCK_GAP));
+        position += length + crc_length;
+    }
+
+    raw.resize(raw.len().max(FDS_SIDE_SIZE), 0);
+    raw
+}
+
+// The disks in a .fds or .qd image, and which side is in the drive
+pub struct Disk {
+    sides: Vec<Vec<u8>>,
+    original: Vec<Vec<u8>>,
+    inserted: Option<usize>,
+    dirty: bool,
+}
+
+impl Disk {
+    pub fn parse(data: &[u8]) -> Result<Self, CartridgeError> {
+        let data = if data.starts_with(&FDS_MAGIC) {
+            &data[FDS_HEADER_SIZE.min(data.len())..]
+        } else {
+            data
+        };
+
+        let (format, side_size) = if !data.is_empty() && data.len() % FDS_SIDE_SIZE == 0 {
+            (Format::Fds, FDS_SIDE_SIZE)
+        } else if !data.is_empty() && data.len() % QD_SIDE_SIZE == 0 {
+            (Format::QuickDisk, QD_SIDE_SIZE)
+        } else {
+            return Err(CartridgeError::InvalidHeader);
+        };
+
+        let sides: Vec<Vec<u8>> = data
+            .chunks(side_size)
+            .map(|side| build_side(side, format))
+            .collect();
+
+        Ok(Disk {
+            original: sides.clone(),
+            sides,
+            inserted: Some(0),
+            dirty: false,
+        })
+    }
+
+    #[inline]
+    pub fn side_count(&self) -> usize {
+        self.sides.len()
+    }
+
+    #[inline]
+    pub fn inserted(&self) -> Option<usize> {
+        self.inserted
+    }
+
+    // Puts a side in the drive. Games wait to see the drive empty before
+    // they look for another side, so eject first when swapping.
+    pub fn insert(&mut self, side: usize) -> bool {
+        if side >= self.sides.len() {
+            return false;
+        }
+
+        self.inserted = Some(side);
+        true
+    }
+
+    #[inline]
+    pub fn eject(&mut self) {
+        self.inserted = None;
+    }
+
+    // Whether the disks have been written to since the last load or save
+    #[inline]
+    pub fn dirty(&self) -> bool {
+        self.dirty
+    }
+
+    #[inline]
+    pub fn mark_saved(&mut self) {
+        self.dirty = false;
+    }
+
+    fn len(&self) -> usize {
+        self.inserted.map_or(0, |side| self.sides[side].len())
+    }
+
+    fn read(&self, position: usize) -> u8 {
+        self.inserted
+            .and_then(|side| self.sides[side].get(position))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    fn write(&mut self, position: usize, value: u8) {
+        if let Some(byte) = self
+            .inserted
+            .and_then(|side| self.sides[side].get_mut(position))
+        {
+            *byte = value;
+            self.dirty = true;
+        }
+    }
+
+    // Writes every run of bytes that differs from the original image, each
+    // as its side, offset and length followed by the bytes
+    pub fn save<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        for (side, (current, original)) in self.sides.iter().zip(&self.original).enumerate() {
+            let mut position = 0;
+
+            while position < current.len() {
+                if current[position] == original[position] {
+                    position += 1;
+                    continue;
+                }
+
+                let start = position;
+
+                while position < current.len()
+                    && current[position] != original[position]
+                    && position - start < u16::MAX as usize
+                {
+                    position += 1;
+                }
+
+                writer.write_u8(side as u8)?;
+                writer.write_u32::<LittleEndian>(start as u32)?;
+                writer.write_u16::<LittleEndian>((position - start) as u16)?;
+                writer.write_all(&current[start..position])?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn load<R: Read>(&mut self, reader: &mut R) -> io::Result<()> {
+        loop {
+            let side = match reader.read_u8() {
+                Ok(side) => side as usize,
+                Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(error) => return Err(error),
+            };
+
+            let start = reader.read_u32::<LittleEndian>()? as usize;
+            let length = reader.read_u16::<LittleEndian>()? as usize;
+
+            let data = self
+                .sides
+                .get_mut(side)
+                .and_then(|side| side.get_mut(start..start + length))
+                .ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidData, "disk write out of range")
+                })?;
+
+            reader.read_exact(data)?;
+        }
+
+        self.dirty = false;
+
+        Ok(())
+    }
+}
+
+// Famicom Disk System RAM adapter and drive. The BIOS is the 8 KB PRG ROM.
+pub struct Fds {
+    memory: Memory,
+    disk: Disk,
+    audio: FdsAudio,
+    mirroring: Mirroring,
+
+    disk_registers_enabled: bool,
+    sound_registers_enabled: bool,
+
+    timer_reload: u16,
+    timer_counter: u16,
+    timer_repeat: bool,
+    timer_enabled: bool,
+    timer_irq: bool,
+
+    motor_on: bool,
+    reset_transfer: bool,
+    read_mode: bool,
+    crc_control: bool,
+    previous_crc_control: bool,
+    transfer_started: bool,
+    disk_irq_enabled: bool,
+    disk_irq: bool,
+
+    read_data: u8,
+    write_data: u8,
+    transfer_complete: bool,
+    gap_ended: bool,
+    crc: u16,
+
+    position: usize,
+    delay: u32,
+    end_of_head: bool,
+    scanning: bool,
+}
+
+impl Fds {
+    pub fn new(memory: Memory, disk: Disk) -> Self {
+        Fds {
+            memory,
+            disk,
+            audio: FdsAudio::new(),
+            mirroring: Mirroring::Horizontal,
+            disk_registers_enabled: false,
+            sound_registers_enabled: false,
+            timer_reload: 0,
+            timer_counter: 0,
+            timer_repeat: false,
+            timer_enabled: false,
+            timer_irq: false,
+            motor_on: false,
+            reset_transfer: false,
+            read_mode: true,
+            crc_control: false,
+            previous_crc_control: false,
+            transfer_started: false,
+            disk_irq_enabled: false,
+            disk_irq: false,
+            read_data: 0,
+            write_data: 0,
+            transfer_complete: false,
+            gap_ended: false,
+            crc: 0,
+            position: 0,
+            delay: 0,
+            end_of_head: true,
+            scanning: false,
+        }
+    }
+
+    fn update_crc(&mut self, value: u8) {
+        for bit in 0..8 {
+            let carry = self.crc & 0x01 != 0;
+            self.crc >>= 1;
+
+            if carry {
+                self.crc ^= 0x8408;
+            }
+
+            if value & (1 << bit) != 0 {
+                self.crc ^= 0x8000;
+            }
+        }
+    }
+
+    fn clock_timer(&mut self) {
+        if !self.timer_enabled {
+            return;
+        }
+
+        if self.timer_counter == 0 {
+            self.timer_irq = true;
+            self.timer_counter = self.timer_reload;
+
+            if !self.timer_repeat {
+                self.timer_enabled = false;
+            }
+        } else {
+            self.timer_counter -= 1;
+        }
+    }
+
+    fn clock_drive(&mut self) {
+        if self.disk.inserted().is_none() || !self.motor_on {
+            self.end_of_head = true;
+            self.scanning = false;
+            return;
+        }
+
+        if self.reset_transfer && !self.scanning {
+            return;
+        }
+
+        if self.end_of_head {
+            self.delay = REWIND_CYCLES;
+            self.end_of_head = false;
+            self.position = 0;
+            self.gap_ended = false;
+            return;
+        }
+
+        if self.delay > 0 {
+            self.delay -= 1;
+            return;
+        }
+
+        self.scanning = true;
+        self.transfer_byte();
+        self.previous_crc_control = self.crc_control;
+        self.position += 1;
+
+        if self.position >= self.disk.len() {
+            self.motor_on = false;
+        } else {
+            self.delay = BYTE_CYCLES;
+        }
+    }
+
+    fn transfer_byte(&mut self) {
+        let mut irq = self.disk_irq_enabled;
+
+        if self.read_mode {
+            let value = self.disk.read(self.position);
+
+            if !self.previous_crc_control {
+                self.update_crc(value);
+            }
+
+            // Data starts after the $80 mark at the end of a gap
+            if !self.transfer_started {
+                self.gap_ended = false;
+                self.crc = 0;
+            } else if value != 0 && !self.gap_ended {
+                self.gap_ended = true;
+                irq = false;
+            }
+
+            if self.gap_ended {
+                self.transfer_complete = true;
+                self.read_data = value;
+                self.disk_irq |= irq;
+            }
+        } else {
+            let mut value = 0;
+
+            if !self.crc_control {
+                self.transfer_complete = true;
+                value = self.write_data;
+                self.disk_irq |= irq;
+            }
+
+            if !self.transfer_started {
+                value = 0;
+            }
+
+            if !self.crc_control {
+                self.update_crc(value);
+            } else {
+                if !self.previous_crc_control {
+                    self.update_crc(0);
+                    self.update_crc(0);
+                }
+
+                value = self.crc as u8;
+                self.crc >>= 8;
+            }
+
+            self.disk.write(self.position, value);
+            self.gap_ended = false;
+        }
+    }
+}
+
+impl Mapper for Fds {
+    fn read_prg(&mut self, address: u16) -> u8 {
+        match address {
+            0x4030 if self.disk_registers_enabled => {
+                let status = self.timer_irq as u8
+                    | (self.transfer_complete as u8) << 1
+                    | (self.end_of_head as u8) << 6;
+
+                self.transfer_complete = false;
+                self.timer_irq = false;
+                self.disk_irq = false;
+
+                status
+            }
+            0x4031 if self.disk_registers_enabled => {
+                self.transfer_complete = false;
+                self.disk_irq = false;
+                self.read_data
+            }
+            0x4032 if self.disk_registers_enabled => {
+                let inserted = self.disk.inserted().is_some();
+
+                // Disk missing, not ready and write protected
+                0x40 | !inserted as u8
+                    | ((!inserted || !self.scanning) as u8) << 1
+                    | (!inserted as u8) << 2
+            }
+            // The expansion port, with the battery reported good
+            0x4033 if self.disk_registers_enabled => 0x80,
+            0x4040..=0x4097 if self.sound_registers_enabled => {
+                self.audio.read(address).unwrap_or(0)
+            }
+            0x6000..=0xDFFF => self.memory.read_prg_ram(address),
+            0xE000..=0xFFFF => self.memory.read_prg_rom(0, 0x2000, address),
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, address: u16, value: u8) {
+        match address {
+            0x4020 if self.disk_registers_enabled => {
+                self.timer_reload = (self.timer_reload & 0xFF00) | value as u16;
+            }
+            0x4021 if self.disk_registers_enabled => {
+                self.timer_reload = (self.timer_reload & 0x00FF) | (value as u16) << 8;
+            }
+            0x4022 if self.disk_registers_enabled => {
+                self.timer_repeat = value & 0x01 != 0;
+                self.timer_enabled = value & 0x02 != 0;
+
+                if self.timer_enabled {
+                    self.timer_counter = self.timer_reload;
+                } else {
+                    self.timer_irq = false;
+                }
+            }
+            0x4023 => {
+                self.disk_registers_enabled = value & 0x01 != 0;
+                self.sound_registers_enabled = value & 0x02 != 0;
+
+                if !self.disk_registers_enabled {
+                    self.timer_enabled = false;
+                    self.timer_irq = false;
+                    self.disk_irq = false;
+                }
+            }
+            0x4024 if self.disk_registers_enabled => {
+                self.write_data = value;
+                self.transfer_complete = false;
+                self.disk_irq = false;
+            }
+            0x4025 if self.disk_registers_enabled => {
+                self.motor_on = value & 0x01 != 0;
+                self.reset_transfer = value & 0x02 != 0;
+                self.read_mode = value & 0x04 != 0;
+                self.mirroring = if value & 0x08 != 0 {
+                    Mirroring::Horizontal
+                } else {
+                    Mirroring::Vertical
+                };
+                self.crc_control = value & 0x10 != 0;
+                self.transfer_started = value & 0x40 != 0;
+                self.disk_irq_enabled = value & 0x80 != 0;
+                self.disk_irq = false;
+            }
+            0x4040..=0x4097 if self.sound_registers_enabled => self.audio.write(address, value),
+            0x6000..=0xDFFF => self.memory.write_prg_ram(address, value),
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, address: u16) -> u8 {
+        self.memory.read_chr(0, 0x2000, address)
+    }
+
+    fn write_chr(&mut self, address: u16, value: u8) {
+        self.memory.write_chr(0, 0x2000, address, value);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn memory(&mut self) -> &mut Memory {
+        &mut self.memory
+    }
+
+    fn cpu_clock(&mut self) {
+        self.clock_timer();
+        self.clock_drive();
+        self.audio.clock();
+    }
+
+    fn irq(&self) -> bool {
+        self.timer_irq || self.disk_irq
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+
+    fn disk(&mut self) -> Option<&mut Disk> {
+        Some(&mut self.disk)
+    }
+}
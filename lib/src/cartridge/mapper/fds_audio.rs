@@ -0,0 +1,227 @@
+// The Famicom Disk System's wavetable channel: a 64 step, 6-bit waveform
+// with a volume envelope and a frequency modulation unit
+
+// Modulation table entries: adjustments to the modulation counter, with 4
+// resetting it
+const MOD_ADJUSTMENTS: [i8; 8] = [0, 1, 2, 4, 0, -4, -2, -1];
+
+const MASTER_VOLUMES: [f32; 4] = [1.0, 2.0 / 3.0, 2.0 / 4.0, 2.0 / 5.0];
+
+// Output at full volume and the highest wave value, relative to the APU pulses
+const OUTPUT_LEVEL: f32 = 0.6;
+
+#[derive(Default)]
+struct Envelope {
+    gain: u8,
+    speed: u8,
+    increase: bool,
+    disabled: bool,
+    timer: u32,
+}
+
+impl Envelope {
+    fn write(&mut self, value: u8) {
+        self.speed = value & 0x3F;
+        self.increase = value & 0x40 != 0;
+        self.disabled = value & 0x80 != 0;
+        self.timer = 0;
+
+        // With the envelope off, the speed bits set the gain directly
+        if self.disabled {
+            self.gain = self.speed;
+        }
+    }
+
+    fn clock(&mut self, master_speed: u8) {
+        if self.disabled {
+            return;
+        }
+
+        self.timer += 1;
+
+        if self.timer < 8 * (master_speed as u32 + 1) * (self.speed as u32 + 1) {
+            return;
+        }
+
+        self.timer = 0;
+
+        if self.increase && self.gain < 32 {
+            self.gain += 1;
+        } else if !self.increase && self.gain > 0 {
+            self.gain -= 1;
+        }
+    }
+}
+
+pub struct FdsAudio {
+    wave_table: [u8; 64],
+    wave_write: bool,
+    wave_halted: bool,
+    wave_accumulator: u32,
+    frequency: u16,
+    output: u8,
+
+    volume: Envelope,
+    modulation: Envelope,
+    envelopes_halted: bool,
+    envelope_speed: u8,
+    master_volume: u8,
+
+    mod_table: [u8; 64],
+    mod_position: usize,
+    mod_counter: i8,
+    mod_frequency: u16,
+    mod_halted: bool,
+    mod_accumulator: u32,
+}
+
+impl FdsAudio {
+    pub fn new() -> Self {
+        FdsAudio {
+            wave_table: [0; 64],
+            wave_write: false,
+            wave_halted: true,
+            wave_accumulator: 0,
+            frequency: 0,
+            output: 0,
+            volume: Envelope::default(),
+            modulation: Envelope::default(),
+            envelopes_halted: false,
+            envelope_speed: 0xE8,
+            master_volume: 0,
+            mod_table: [0; 64],
+            mod_position: 0,
+            mod_counter: 0,
+            mod_frequency: 0,
+            mod_halted: true,
+            mod_accumulator: 0,
+        }
+    }
+
+    pub fn read(&self, address: u16) -> Option<u8> {
+        match address {
+            0x4040..=0x407F => Some(self.wave_table[address as usize - 0x4040] | 0x40),
+            0x4090 => Some(self.volume.gain | 0x40),
+            0x4092 => Some(self.modulation.gain | 0x40),
+            _ => None,
+        }
+    }
+
+    pub fn write(&mut self, address: u16, value: u8) {
+        match address {
+            0x4040..=0x407F if self.wave_write => {
+                self.wave_table[address as usize - 0x4040] = value & 0x3F;
+            }
+            0x4080 => self.volume.write(value),
+            0x4082 => self.frequency = (self.frequency & 0x0F00) | value as u16,
+            0x4083 => {
+                self.frequency = (self.frequency & 0x00FF) | ((value as u16 & 0x0F) << 8);
+                self.wave_halted = value & 0x80 != 0;
+                self.envelopes_halted = value & 0x40 != 0;
+
+                if self.wave_halted {
+                    self.wave_accumulator = 0;
+                }
+            }
+            0x4084 => self.modulation.write(value),
+            // A 7-bit signed value
+            0x4085 => self.mod_counter = ((value << 1) as i8) >> 1,
+            0x4086 => self.mod_frequency = (self.mod_frequency & 0x0F00) | value as u16,
+            0x4087 => {
+                self.mod_frequency = (self.mod_frequency & 0x00FF) | ((value as u16 & 0x0F) << 8);
+                self.mod_halted = value & 0x80 != 0;
+
+                if self.mod_halted {
+                    self.mod_accumulator = 0;
+                }
+            }
+            // Each write fills two entries, and only while modulation is halted
+            0x4088 if self.mod_halted => {
+                self.mod_table[self.mod_position] = value & 0x07;
+                self.mod_table[(self.mod_position + 1) & 0x3F] = value & 0x07;
+                self.mod_position = (self.mod_position + 2) & 0x3F;
+            }
+            0x4089 => {
+                self.master_volume = value & 0x03;
+                self.wave_write = value & 0x80 != 0;
+            }
+            0x408A => self.envelope_speed = value,
+            _ => {}
+        }
+    }
+
+    // The modulated frequency, as worked out by the hardware
+    fn pitch(&self) -> u32 {
+        let mut temp = self.mod_counter as i32 * self.modulation.gain as i32;
+        let remainder = temp & 0x0F;
+        temp >>= 4;
+
+        if remainder > 0 && temp & 0x80 == 0 {
+            temp += if self.mod_counter < 0 { -1 } else { 2 };
+        }
+
+        if temp >= 192 {
+            temp -= 256;
+        } else if temp < -64 {
+            temp += 256;
+        }
+
+        temp *= self.frequency as i32;
+        let remainder = temp & 0x3F;
+        temp >>= 6;
+
+        if remainder >= 32 {
+            temp += 1;
+        }
+
+        (self.frequency as i32 + temp).max(0) as u32
+    }
+
+    fn clock_modulation(&mut self) {
+        if self.mod_halted || self.mod_frequency == 0 {
+            return;
+        }
+
+        self.mod_accumulator += self.mod_frequency as u32;
+
+        if self.mod_accumulator < 0x10000 {
+            return;
+        }
+
+        self.mod_accumulator &= 0xFFFF;
+
+        let entry = self.mod_table[self.mod_position] as usize;
+        self.mod_counter = if entry == 4 {
+            0
+        } else {
+            // Wraps within 7 bits
+            let counter = self.mod_counter as i32 + MOD_ADJUSTMENTS[entry] as i32;
+            (((counter + 64) & 0x7F) - 64) as i8
+        };
+        self.mod_position = (self.mod_position + 1) & 0x3F;
+    }
+
+    pub fn clock(&mut self) {
+        if !self.envelopes_halted && !self.wave_halted && self.envelope_speed != 0 {
+            self.volume.clock(self.envelope_speed);
+            self.modulation.clock(self.envelope_speed);
+        }
+
+        self.clock_modulation();
+
+        // The output holds while the CPU has the wave table
+        if self.wave_halted || self.wave_write {
+            return;
+        }
+
+        self.wave_accumulator = (self.wave_accumulator + self.pitch()) & 0x3F_FFFF;
+        self.output = self.wave_table[(self.wave_accumulator >> 16) as usize];
+    }
+
+    pub fn output(&self) -> f32 {
+        let gain = self.volume.gain.min(32) as f32;
+        let level = self.output as f32 * gain / (63.0 * 32.0);
+
+        level * MASTER_VOLUMES[self.master_volume as usize] * OUTPUT_LEVEL
+    }
+}
@@ -0,0 +1,176 @@
+use std::io::{self, Read, Write};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+pub const SECTOR_SIZE: usize = 0x1000;
+
+// Software ID of the SST39SF040: manufacturer, then device
+const MANUFACTURER_ID: u8 = 0xBF;
+const DEVICE_ID: u8 = 0xB7;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    Idle,
+    Unlock1,
+    Unlock2,
+    Program,
+    Erase,
+    EraseUnlock1,
+    EraseUnlock2,
+}
+
+// SST39SF0x0 flash PRG ROM, for boards that save by rewriting their own
+// program. Programs and erases complete instantly, so the status polling
+// games do always sees the operation finished.
+pub struct Flash {
+    data: Vec<u8>,
+    state: State,
+    software_id: bool,
+    modified: Vec<bool>,
+    dirty: bool,
+}
+
+impl Flash {
+    pub fn new(data: Vec<u8>) -> Self {
+        let sectors = data.len().div_ceil(SECTOR_SIZE);
+
+        Flash {
+            data,
+            state: State::Idle,
+            software_id: false,
+            modified: vec![false; sectors],
+            dirty: false,
+        }
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    // Whether sectors have been rewritten since the last save
+    #[inline]
+    pub fn dirty(&self) -> bool {
+        self.dirty
+    }
+
+    pub fn read(&self, address: usize) -> u8 {
+        if self.data.is_empty() {
+            return 0;
+        }
+
+        if self.software_id {
+            return if address & 0x01 == 0 {
+                MANUFACTURER_ID
+            } else {
+                DEVICE_ID
+            };
+        }
+
+        self.data[address % self.data.len()]
+    }
+
+    pub fn write(&mut self, address: usize, value: u8) {
+        if self.data.is_empty() {
+            return;
+        }
+
+        let address = address % self.data.len();
+        let command = address & 0x7FFF;
+
+        self.state = match (self.state, command, value) {
+            (State::Idle, 0x5555, 0xAA) => State::Unlock1,
+            (State::Idle, _, 0xF0) => {
+                self.software_id = false;
+                State::Idle
+            }
+            (State::Unlock1, 0x2AAA, 0x55) => State::Unlock2,
+            (State::Unlock2, 0x5555, 0xA0) => State::Program,
+            (State::Unlock2, 0x5555, 0x80) => State::Erase,
+            (State::Unlock2, 0x5555, 0x90) => {
+                self.software_id = true;
+                State::Idle
+            }
+            (State::Unlock2, 0x5555, 0xF0) => {
+                self.software_id = false;
+                State::Idle
+            }
+            (State::Program, _, _) => {
+                // Programming can only clear bits
+                self.data[address] &= value;
+                self.mark_modified(address);
+                State::Idle
+            }
+            (State::Erase, 0x5555, 0xAA) => State::EraseUnlock1,
+            (State::EraseUnlock1, 0x2AAA, 0x55) => State::EraseUnlock2,
+            (State::EraseUnlock2, _, 0x30) => {
+                let start = address / SECTOR_SIZE * SECTOR_SIZE;
+                let end = (start + SECTOR_SIZE).min(self.data.len());
+                self.data[start..end].fill(0xFF);
+                self.mark_modified(address);
+                State::Idle
+            }
+            (State::EraseUnlock2, 0x5555, 0x10) => {
+                self.data.fill(0xFF);
+                self.modified.fill(true);
+                self.dirty = true;
+                State::Idle
+            }
+            _ => State::Idle,
+        };
+    }
+
+    fn mark_modified(&mut self, address: usize) {
+        self.modified[address / SECTOR_SIZE] = true;
+        self.dirty = true;
+    }
+
+    #[inline]
+    pub fn mark_saved(&mut self) {
+        self.dirty = false;
+    }
+
+    // Writes every sector that differs from the original ROM, each as its
+    // index followed by its contents
+    pub fn save<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        for (sector, chunk) in self.data.chunks(SECTOR_SIZE).enumerate() {
+            if self.modified[sector] {
+                writer.write_u32::<LittleEndian>(sector as u32)?;
+                writer.write_all(chunk)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn load<R: Read>(&mut self, reader: &mut R) -> io::Result<()> {
+        loop {
+            let sector = match reader.read_u32::<LittleEndian>() {
+                Ok(sector) => sector as usize,
+                Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(error) => return Err(error),
+            };
+
+            if sector >= self.modified.len() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "flash sector out of range",
+                ));
+            }
+
+            let start = sector * SECTOR_SIZE;
+            let end = (start + SECTOR_SIZE).min(self.data.len());
+            reader.read_exact(&mut self.data[start..end])?;
+            self.modified[sector] = true;
+        }
+
+        self.dirty = false;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,339 @@
+use super::{Mapper, Memory, Mirroring};
+
+// The 5B halves the CPU clock before its own divide by 8, so a tone flips
+// every 16 * period CPU cycles. Its envelope steps 32 times per period.
+const TONE_DIVIDER: u8 = 16;
+
+// Output of a channel at full volume, relative to the APU pulses
+const CHANNEL_LEVEL: f32 = 0.12;
+
+#[derive(Default, Clone, Copy)]
+struct Tone {
+    period: u16,
+    counter: u16,
+    output: bool,
+}
+
+impl Tone {
+    fn clock(&mut self) {
+        self.counter += 1;
+
+        if self.counter >= self.period.max(1) {
+            self.counter = 0;
+            self.output = !self.output;
+        }
+    }
+}
+
+// The Sunsoft 5B's audio: a YM2149F (AY-3-8910 variant) with three square
+// channels, a noise generator and a shared envelope
+struct Audio {
+    address: u8,
+    registers: [u8; 16],
+    tones: [Tone; 3],
+    noise_period: u8,
+    noise_counter: u8,
+    noise_shift: u32,
+    envelope_period: u16,
+    envelope_counter: u16,
+    envelope_step: u8,
+    envelope_holding: bool,
+    envelope_attack: bool,
+    divider: u8,
+}
+
+impl Audio {
+    fn new() -> Self {
+        Audio {
+            address: 0,
+            registers: [0; 16],
+            tones: [Tone::default(); 3],
+            noise_period: 0,
+            noise_counter: 0,
+            noise_shift: 1,
+            envelope_period: 0,
+            envelope_counter: 0,
+            envelope_step: 0,
+            envelope_holding: false,
+            envelope_attack: false,
+            divider: 0,
+        }
+    }
+
+    fn write(&mut self, value: u8) {
+        let register = self.address as usize;
+
+        if register > 0x0F {
+            return;
+        }
+
+        self.registers[register] = value;
+
+        match register {
+            0x00..=0x05 => {
+                let tone = &mut self.tones[register / 2];
+                let low = self.registers[register & !1] as u16;
+                let high = (self.registers[register | 1] & 0x0F) as u16;
+                tone.period = high << 8 | low;
+            }
+            0x06 => self.noise_period = value & 0x1F,
+            0x0B | 0x0C => {
+                self.envelope_period =
+                    (self.registers[0x0C] as u16) << 8 | self.registers[0x0B] as u16;
+            }
+            0x0D => {
+                self.envelope_step = 0;
+                self.envelope_counter = 0;
+                self.envelope_holding = false;
+                self.envelope_attack = value & 0x04 != 0;
+            }
+            _ => {}
+        }
+    }
+
+    fn clock(&mut self) {
+        self.divider += 1;
+
+        if self.divider < TONE_DIVIDER {
+            return;
+        }
+
+        self.divider = 0;
+
+        for tone in &mut self.tones {
+            tone.clock();
+        }
+
+        // Noise runs at half the tone rate
+        self.noise_counter += 1;
+
+        if self.noise_counter >= self.noise_period.max(1) * 2 {
+            self.noise_counter = 0;
+            let feedback = (self.noise_shift ^ (self.noise_shift >> 3)) & 0x01;
+            self.noise_shift = (self.noise_shift >> 1) | (feedback << 16);
+        }
+
+        self.envelope_counter += 1;
+
+        if self.envelope_counter >= self.envelope_period.max(1) {
+            self.envelope_counter = 0;
+            self.clock_envelope();
+        }
+    }
+
+    fn clock_envelope(&mut self) {
+        if self.envelope_holding {
+            return;
+        }
+
+        self.envelope_step += 1;
+
+        if self.envelope_step < 32 {
+            return;
+        }
+
+        let shape = self.registers[0x0D];
+        let continuing = shape & 0x08 != 0;
+        let alternate = shape & 0x02 != 0;
+        let hold = shape & 0x01 != 0;
+
+        if !continuing {
+            self.envelope_attack = false;
+            self.envelope_holding = true;
+            self.envelope_step = 31;
+        } else if hold {
+            if alternate {
+                self.envelope_attack = !self.envelope_attack;
+            }
+
+            self.envelope_holding = true;
+            self.envelope_step = 31;
+        } else {
+            if alternate {
+                self.envelope_attack = !self.envelope_attack;
+            }
+
+            self.envelope_step = 0;
+        }
+    }
+
+    fn envelope_level(&self) -> u8 {
+        let shape = self.registers[0x0D];
+
+        if self.envelope_holding && shape & 0x08 == 0 {
+            return 0;
+        }
+
+        if self.envelope_attack {
+            self.envelope_step
+        } else {
+            31 - self.envelope_step
+        }
+    }
+
+    fn output(&self) -> f32 {
+        let mixer = self.registers[0x07];
+        let noise = self.noise_shift & 0x01 != 0;
+        let mut output = 0.0;
+
+        for (channel, tone) in self.tones.iter().enumerate() {
+            let tone_enabled = mixer & (0x01 << channel) == 0;
+            let noise_enabled = mixer & (0x08 << channel) == 0;
+
+            if (tone_enabled && !tone.output) || (noise_enabled && !noise) {
+                continue;
+            }
+
+            let volume = self.registers[0x08 + channel];
+
+            // The volume is logarithmic, 3 dB per step, or 1.5 dB per
+            // envelope step
+            let level = if volume & 0x10 != 0 {
+                self.envelope_level()
+            } else {
+                match volume & 0x0F {
+                    0 => 0,
+                    volume => volume * 2 + 1,
+                }
+            };
+
+            if level > 0 {
+                output += 10f32.powf(-1.5 * (31 - level) as f32 / 20.0);
+            }
+        }
+
+        output * CHANNEL_LEVEL
+    }
+}
+
+// Sunsoft FME-7, 5A and 5B (mapper 69)
+pub struct Fme7 {
+    memory: Memory,
+    command: u8,
+    chr_banks: [usize; 8],
+    prg_banks: [u8; 4],
+    mirroring: Mirroring,
+    irq_enabled: bool,
+    irq_counter_enabled: bool,
+    irq_counter: u16,
+    irq_pending: bool,
+    audio: Audio,
+}
+
+impl Fme7 {
+    pub fn new(memory: Memory) -> Self {
+        Fme7 {
+            memory,
+            command: 0,
+            chr_banks: [0; 8],
+            prg_banks: [0; 4],
+            mirroring: Mirroring::Vertical,
+            irq_enabled: false,
+            irq_counter_enabled: false,
+            irq_counter: 0,
+            irq_pending: false,
+            audio: Audio::new(),
+        }
+    }
+
+    fn write_parameter(&mut self, value: u8) {
+        match self.command {
+            0x00..=0x07 => self.chr_banks[self.command as usize] = value as usize,
+            0x08..=0x0B => self.prg_banks[self.command as usize - 0x08] = value,
+            0x0C => {
+                self.mirroring = match value & 0x03 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreenLower,
+                    _ => Mirroring::SingleScreenUpper,
+                };
+            }
+            0x0D => {
+                self.irq_enabled = value & 0x01 != 0;
+                self.irq_counter_enabled = value & 0x80 != 0;
+                self.irq_pending = false;
+            }
+            0x0E => self.irq_counter = (self.irq_counter & 0xFF00) | value as u16,
+            _ => self.irq_counter = (self.irq_counter & 0x00FF) | (value as u16) << 8,
+        }
+    }
+}
+
+impl Mapper for Fme7 {
+    fn read_prg(&mut self, address: u16) -> u8 {
+        match address {
+            0x6000..=0x7FFF => {
+                let register = self.prg_banks[0];
+
+                // Bit 6 selects RAM, bit 7 enables it
+                match register & 0xC0 {
+                    0xC0 => self.memory.read_prg_ram(address),
+                    0x40 => 0,
+                    _ => self
+                        .memory
+                        .read_prg_rom((register & 0x3F) as usize, 0x2000, address),
+                }
+            }
+            0x8000..=0xDFFF => {
+                let bank = self.prg_banks[1 + ((address - 0x8000) >> 13) as usize] & 0x3F;
+                self.memory.read_prg_rom(bank as usize, 0x2000, address)
+            }
+            0xE000..=0xFFFF => {
+                let last = self.memory.prg_banks(0x2000) - 1;
+                self.memory.read_prg_rom(last, 0x2000, address)
+            }
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, address: u16, value: u8) {
+        match address {
+            0x6000..=0x7FFF if self.prg_banks[0] & 0xC0 == 0xC0 => {
+                self.memory.write_prg_ram(address, value);
+            }
+            0x8000..=0x9FFF => self.command = value & 0x0F,
+            0xA000..=0xBFFF => self.write_parameter(value),
+            0xC000..=0xDFFF => self.audio.address = value,
+            0xE000..=0xFFFF => self.audio.write(value),
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, address: u16) -> u8 {
+        let bank = self.chr_banks[(address >> 10) as usize & 0x07];
+        self.memory.read_chr(bank, 0x0400, address)
+    }
+
+    fn write_chr(&mut self, address: u16, value: u8) {
+        let bank = self.chr_banks[(address >> 10) as usize & 0x07];
+        self.memory.write_chr(bank, 0x0400, address, value);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn memory(&mut self) -> &mut Memory {
+        &mut self.memory
+    }
+
+    fn cpu_clock(&mut self) {
+        if self.irq_counter_enabled {
+            self.irq_counter = self.irq_counter.wrapping_sub(1);
+
+            if self.irq_counter == 0xFFFF && self.irq_enabled {
+                self.irq_pending = true;
+            }
+        }
+
+        self.audio.clock();
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+}
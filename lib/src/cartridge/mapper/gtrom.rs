@@ -0,0 +1,98 @@
+use std::mem;
+
+use super::{Flash, Mapper, Memory, Mirroring};
+
+// GTROM / Cheapocabra (mapper 111)
+pub struct Gtrom {
+    memory: Memory,
+    flash: Flash,
+    prg_bank: usize,
+    chr_bank: usize,
+    nametable_page: usize,
+    // The board has 8 KB of its own nametable RAM: two pages of four screens
+    nametable_ram: [u8; 0x2000],
+}
+
+impl Gtrom {
+    pub fn new(mut memory: Memory) -> Self {
+        let flash = Flash::new(mem::take(&mut memory.prg_rom));
+
+        if memory.chr_ram {
+            memory.chr.resize(memory.chr.len().max(0x4000), 0);
+        }
+
+        Gtrom {
+            memory,
+            flash,
+            prg_bank: 0,
+            chr_bank: 0,
+            nametable_page: 0,
+            nametable_ram: [0; 0x2000],
+        }
+    }
+
+    fn flash_address(&self, address: u16) -> usize {
+        self.prg_bank * 0x8000 + (address as usize & 0x7FFF)
+    }
+
+    fn nametable_index(&self, address: u16) -> usize {
+        self.nametable_page * 0x1000 + (address as usize & 0x0FFF)
+    }
+}
+
+impl Mapper for Gtrom {
+    fn read_prg(&mut self, address: u16) -> u8 {
+        match address {
+            0x8000..=0xFFFF => self.flash.read(self.flash_address(address)),
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, address: u16, value: u8) {
+        match address {
+            // Bits 6 and 7 drive the board's two LEDs
+            0x5000..=0x5FFF | 0x7000..=0x7FFF => {
+                self.prg_bank = (value & 0x0F) as usize;
+                self.chr_bank = ((value >> 4) & 0x01) as usize;
+                self.nametable_page = ((value >> 5) & 0x01) as usize;
+            }
+            0x8000..=0xFFFF => {
+                let address = self.flash_address(address);
+                self.flash.write(address, value);
+            }
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, address: u16) -> u8 {
+        self.memory.read_chr(self.chr_bank, 0x2000, address)
+    }
+
+    fn write_chr(&mut self, address: u16, value: u8) {
+        self.memory.write_chr(self.chr_bank, 0x2000, address, value);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        Mirroring::FourScreen
+    }
+
+    fn memory(&mut self) -> &mut Memory {
+        &mut self.memory
+    }
+
+    fn nametable_ram(&self) -> bool {
+        true
+    }
+
+    fn read_nametable(&mut self, address: u16, _ciram: &[u8]) -> u8 {
+        self.nametable_ram[self.nametable_index(address)]
+    }
+
+    fn write_nametable(&mut self, address: u16, value: u8, _ciram: &mut [u8]) {
+        self.nametable_ram[self.nametable_index(address)] = value;
+    }
+
+    fn flash(&mut self) -> Option<&mut Flash> {
+        Some(&mut self.flash)
+    }
+}
@@ -0,0 +1,59 @@
+use super::{Mapper, Memory, Mirroring};
+
+// GNROM and MHROM (mapper 66)
+pub struct Gxrom {
+    memory: Memory,
+    prg_bank: usize,
+    chr_bank: usize,
+}
+
+impl Gxrom {
+    pub fn new(memory: Memory) -> Self {
+        Gxrom {
+            memory,
+            prg_bank: 0,
+            chr_bank: 0,
+        }
+    }
+
+    fn prg(&self, address: u16) -> u8 {
+        match address {
+            0x8000..=0xFFFF => self.memory.read_prg_rom(self.prg_bank, 0x8000, address),
+            _ => 0,
+        }
+    }
+}
+
+impl Mapper for Gxrom {
+    fn read_prg(&mut self, address: u16) -> u8 {
+        self.prg(address)
+    }
+
+    fn write_prg(&mut self, address: u16, value: u8) {
+        if address < 0x8000 {
+            return;
+        }
+
+        // The latch is always subject to bus conflicts on these boards
+        let value = value & self.prg(address);
+
+        self.prg_bank = ((value >> 4) & 0x03) as usize;
+        self.chr_bank = (value & 0x03) as usize;
+    }
+
+    fn read_chr(&mut self, address: u16) -> u8 {
+        self.memory.read_chr(self.chr_bank, 0x2000, address)
+    }
+
+    fn write_chr(&mut self, address: u16, value: u8) {
+        self.memory.write_chr(self.chr_bank, 0x2000, address, value);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.memory.mirroring
+    }
+
+    fn memory(&mut self) -> &mut Memory {
+        &mut self.memory
+    }
+}
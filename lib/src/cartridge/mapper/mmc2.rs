@@ -0,0 +1,128 @@
+use super::{Mapper, Memory, Mirroring};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Chip {
+    Mmc2,
+    Mmc4,
+}
+
+// PxROM (mapper 9) and FxROM (mapper 10)
+pub struct Mmc2 {
+    memory: Memory,
+    chip: Chip,
+    prg_bank: usize,
+    // CHR banks selected by each latch, for tile $FD and tile $FE
+    chr_banks: [[usize; 2]; 2],
+    latches: [usize; 2],
+    mirroring: Mirroring,
+}
+
+impl Mmc2 {
+    pub fn new(memory: Memory, mapper: u16) -> Self {
+        Mmc2 {
+            memory,
+            chip: if mapper == 10 { Chip::Mmc4 } else { Chip::Mmc2 },
+            prg_bank: 0,
+            chr_banks: [[0; 2]; 2],
+            latches: [1, 1],
+            mirroring: Mirroring::Vertical,
+        }
+    }
+
+    fn chr_bank(&self, address: u16) -> usize {
+        let half = (address >> 12) as usize & 0x01;
+        self.chr_banks[half][self.latches[half]]
+    }
+
+    fn update_latch(&mut self, address: u16) {
+        let half = (address >> 12) as usize & 0x01;
+        let row = address & 0x0FFF;
+
+        // The MMC2 only watches the first row address in the left pattern
+        // table, everything else latches on any of the eight
+        let row = if self.chip == Chip::Mmc2 && half == 0 {
+            row
+        } else {
+            row & !0x07
+        };
+
+        match row {
+            0x0FD8 => self.latches[half] = 0,
+            0x0FE8 => self.latches[half] = 1,
+            _ => {}
+        }
+    }
+}
+
+impl Mapper for Mmc2 {
+    fn read_prg(&mut self, address: u16) -> u8 {
+        match (self.chip, address) {
+            (Chip::Mmc4, 0x6000..=0x7FFF) => self.memory.read_prg_ram(address),
+            (Chip::Mmc2, 0x8000..=0x9FFF) => {
+                self.memory.read_prg_rom(self.prg_bank, 0x2000, address)
+            }
+            (Chip::Mmc2, 0xA000..=0xFFFF) => {
+                let last = self.memory.prg_banks(0x2000) - 1;
+                // The last bank stays at $E000 even with under 24 KB
+                let bank = last.saturating_sub(((0xFFFF - address) >> 13) as usize);
+                self.memory.read_prg_rom(bank, 0x2000, address)
+            }
+            (Chip::Mmc4, 0x8000..=0xBFFF) => {
+                self.memory.read_prg_rom(self.prg_bank, 0x4000, address)
+            }
+            (Chip::Mmc4, 0xC000..=0xFFFF) => {
+                let last = self.memory.prg_banks(0x4000) - 1;
+                self.memory.read_prg_rom(last, 0x4000, address)
+            }
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, address: u16, value: u8) {
+        match address {
+            0x6000..=0x7FFF if self.chip == Chip::Mmc4 => self.memory.write_prg_ram(address, value),
+            0xA000..=0xAFFF => self.prg_bank = (value & 0x0F) as usize,
+            0xB000..=0xEFFF => {
+                let register = ((address - 0xB000) >> 12) as usize;
+                self.chr_banks[register / 2][register % 2] = (value & 0x1F) as usize;
+            }
+            0xF000..=0xFFFF => {
+                self.mirroring = if value & 0x01 != 0 {
+                    Mirroring::Horizontal
+                } else {
+                    Mirroring::Vertical
+                };
+            }
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, address: u16) -> u8 {
+        let value = self
+            .memory
+            .read_chr(self.chr_bank(address), 0x1000, address);
+
+        // The fetch that trips a latch still uses the previous bank
+        self.update_latch(address);
+
+        value
+    }
+
+    fn peek_chr(&mut self, address: u16) -> u8 {
+        self.memory
+            .read_chr(self.chr_bank(address), 0x1000, address)
+    }
+
+    fn write_chr(&mut self, address: u16, value: u8) {
+        let bank = self.chr_bank(address);
+        self.memory.write_chr(bank, 0x1000, address, value);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn memory(&mut self) -> &mut Memory {
+        &mut self.memory
+    }
+}
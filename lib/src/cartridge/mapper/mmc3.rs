@@ -0,0 +1,205 @@
+use super::{Mapper, Memory, Mirroring};
+
+// Number of CPU cycles A12 has to stay low before a rising edge clocks the
+// IRQ counter. This filters out the toggling during sprite pattern fetches.
+const A12_FILTER_CYCLES: u8 = 3;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum IrqBehaviour {
+    // MMC3A and earlier Sharp chips: only fire when the counter is decremented
+    // to zero, or when a reload through $C001 leaves it at zero
+    Old,
+    // MMC3B/MMC3C: fire whenever the counter is zero after being clocked
+    New,
+}
+
+// TxROM (mapper 4)
+pub struct Mmc3 {
+    memory: Memory,
+    registers: [usize; 8],
+    bank_select: u8,
+    mirroring: Mirroring,
+    four_screen: bool,
+    prg_ram_enabled: bool,
+    prg_ram_write_protect: bool,
+    irq_behaviour: IrqBehaviour,
+    irq_latch: u8,
+    irq_counter: u8,
+    irq_reload: bool,
+    irq_enabled: bool,
+    irq_pending: bool,
+    a12: bool,
+    a12_low_cycles: u8,
+}
+
+impl Mmc3 {
+    pub fn new(memory: Memory, submapper: u8) -> Self {
+        // Submapper 4 marks boards with the older MMC3A
+        let irq_behaviour = if submapper == 4 {
+            IrqBehaviour::Old
+        } else {
+            IrqBehaviour::New
+        };
+        let four_screen = memory.mirroring == Mirroring::FourScreen;
+
+        Mmc3 {
+            mirroring: memory.mirroring,
+            memory,
+            registers: [0, 2, 4, 5, 6, 7, 0, 1],
+            bank_select: 0,
+            four_screen,
+            prg_ram_enabled: true,
+            prg_ram_write_protect: false,
+            irq_behaviour,
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_reload: false,
+            irq_enabled: false,
+            irq_pending: false,
+            a12: false,
+            a12_low_cycles: 0,
+        }
+    }
+
+    fn prg_bank(&self, address: u16) -> usize {
+        let second_last = self.memory.prg_banks(0x2000).saturating_sub(2);
+        let swapped = self.bank_select & 0x40 != 0;
+
+        match (address >> 13) & 0x03 {
+            0 if swapped => second_last,
+            0 => self.registers[6],
+            1 => self.registers[7],
+            2 if swapped => self.registers[6],
+            2 => second_last,
+            _ => second_last + 1,
+        }
+    }
+
+    fn chr_bank(&self, address: u16) -> usize {
+        let inverted = self.bank_select & 0x80 != 0;
+        let slot = ((address >> 10) & 0x07) as usize ^ if inverted { 0x04 } else { 0 };
+
+        match slot {
+            0..=3 => (self.registers[slot / 2] & !1) | (slot & 1),
+            _ => self.registers[slot - 2],
+        }
+    }
+
+    fn clock_irq_counter(&mut self) {
+        let previous = self.irq_counter;
+        let reloaded = self.irq_reload;
+
+        if self.irq_counter == 0 || self.irq_reload {
+            self.irq_counter = self.irq_latch;
+            self.irq_reload = false;
+        } else {
+            self.irq_counter -= 1;
+        }
+
+        let fire = match self.irq_behaviour {
+            IrqBehaviour::Old => self.irq_counter == 0 && (previous != 0 || reloaded),
+            IrqBehaviour::New => self.irq_counter == 0,
+        };
+
+        if fire && self.irq_enabled {
+            self.irq_pending = true;
+        }
+    }
+}
+
+impl Mapper for Mmc3 {
+    fn read_prg(&mut self, address: u16) -> u8 {
+        match address {
+            0x6000..=0x7FFF if self.prg_ram_enabled => self.memory.read_prg_ram(address),
+            0x8000..=0xFFFF => {
+                let bank = self.prg_bank(address);
+                self.memory.read_prg_rom(bank, 0x2000, address)
+            }
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, address: u16, value: u8) {
+        match (address, address & 0x01) {
+            (0x6000..=0x7FFF, _) if self.prg_ram_enabled && !self.prg_ram_write_protect => {
+                self.memory.write_prg_ram(address, value);
+            }
+            (0x8000..=0x9FFF, 0) => self.bank_select = value,
+            (0x8000..=0x9FFF, _) => {
+                let register = (self.bank_select & 0x07) as usize;
+                self.registers[register] = match register {
+                    6 | 7 => (value & 0x3F) as usize,
+                    _ => value as usize,
+                };
+            }
+            (0xA000..=0xBFFF, 0) => {
+                self.mirroring = if value & 0x01 != 0 {
+                    Mirroring::Horizontal
+                } else {
+                    Mirroring::Vertical
+                };
+            }
+            (0xA000..=0xBFFF, _) => {
+                self.prg_ram_enabled = value & 0x80 != 0;
+                self.prg_ram_write_protect = value & 0x40 != 0;
+            }
+            (0xC000..=0xDFFF, 0) => self.irq_latch = value,
+            (0xC000..=0xDFFF, _) => {
+                self.irq_counter = 0;
+                self.irq_reload = true;
+            }
+            (0xE000..=0xFFFF, 0) => {
+                self.irq_enabled = false;
+                self.irq_pending = false;
+            }
+            (0xE000..=0xFFFF, _) => self.irq_enabled = true,
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, address: u16) -> u8 {
+        let bank = self.chr_bank(address);
+        self.memory.read_chr(bank, 0x0400, address)
+    }
+
+    fn write_chr(&mut self, address: u16, value: u8) {
+        let bank = self.chr_bank(address);
+        self.memory.write_chr(bank, 0x0400, address, value);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        if self.four_screen {
+            Mirroring::FourScreen
+        } else {
+            self.mirroring
+        }
+    }
+
+    fn memory(&mut self) -> &mut Memory {
+        &mut self.memory
+    }
+
+    fn cpu_clock(&mut self) {
+        if !self.a12 {
+            self.a12_low_cycles = self.a12_low_cycles.saturating_add(1);
+        }
+    }
+
+    fn ppu_address(&mut self, address: u16) {
+        let a12 = address & 0x1000 != 0;
+
+        if a12 && !self.a12 {
+            if self.a12_low_cycles >= A12_FILTER_CYCLES {
+                self.clock_irq_counter();
+            }
+
+            self.a12_low_cycles = 0;
+        }
+
+        self.a12 = a12;
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+}
@@ -0,0 +1,623 @@
+use super::{Mapper, Memory, Mirroring};
+
+const EXRAM_SIZE: usize = 0x0400;
+
+// CPU cycles without a PPU read before the MMC5 considers rendering stopped
+const IN_FRAME_TIMEOUT: u8 = 3;
+
+// The audio frame sequencer runs at a fixed 240 Hz
+const FRAME_PERIOD: u16 = 7457;
+
+const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14, 12, 16, 24, 18, 48, 20, 96, 22,
+    192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+const DUTY_TABLE: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ChrSet {
+    A,
+    B,
+}
+
+// A 2A03-style pulse channel without the sweep unit
+#[derive(Default)]
+struct Pulse {
+    enabled: bool,
+    duty: u8,
+    step: u8,
+    period: u16,
+    timer: u16,
+    length: u8,
+    halt: bool,
+    constant_volume: bool,
+    volume: u8,
+    envelope_start: bool,
+    envelope_divider: u8,
+    envelope_decay: u8,
+}
+
+impl Pulse {
+    fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.duty = value >> 6;
+                self.halt = value & 0x20 != 0;
+                self.constant_volume = value & 0x10 != 0;
+                self.volume = value & 0x0F;
+            }
+            2 => self.period = (self.period & 0x0700) | value as u16,
+            3 => {
+                self.period = (self.period & 0x00FF) | ((value as u16 & 0x07) << 8);
+                self.step = 0;
+                self.envelope_start = true;
+
+                if self.enabled {
+                    self.length = LENGTH_TABLE[(value >> 3) as usize];
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+
+        if !enabled {
+            self.length = 0;
+        }
+    }
+
+    fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.period;
+            self.step = (self.step + 1) & 0x07;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn clock_frame(&mut self) {
+        if self.envelope_start {
+            self.envelope_start = false;
+            self.envelope_decay = 15;
+            self.envelope_divider = self.volume;
+        } else if self.envelope_divider == 0 {
+            self.envelope_divider = self.volume;
+
+            if self.envelope_decay > 0 {
+                self.envelope_decay -= 1;
+            } else if self.halt {
+                self.envelope_decay = 15;
+            }
+        } else {
+            self.envelope_divider -= 1;
+        }
+
+        if !self.halt && self.length > 0 {
+            self.length -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.length == 0 || DUTY_TABLE[self.duty as usize][self.step as usize] == 0 {
+            return 0;
+        }
+
+        if self.constant_volume {
+            self.volume
+        } else {
+            self.envelope_decay
+        }
+    }
+}
+
+// ExROM (mapper 5)
+pub struct Mmc5 {
+    memory: Memory,
+    exram: [u8; EXRAM_SIZE],
+    exram_mode: u8,
+
+    prg_mode: u8,
+    prg_banks: [u8; 5],
+    prg_ram_protect: [u8; 2],
+
+    chr_mode: u8,
+    chr_banks: [usize; 12],
+    chr_upper: u8,
+    chr_last_written: ChrSet,
+
+    nametables: u8,
+    fill_tile: u8,
+    fill_attribute: u8,
+
+    split_control: u8,
+    split_scroll: u8,
+    split_bank: u8,
+    split_tile: bool,
+    split_y: u8,
+
+    sprite_16: bool,
+    rendering_enabled: bool,
+
+    // Scanline detection, driven by the PPU's nametable fetches
+    last_nametable_address: u16,
+    nametable_matches: u8,
+    nametable_reads: u8,
+    idle_cycles: u8,
+    in_frame: bool,
+    scanline: u8,
+    tile: u8,
+    ext_attribute: u8,
+
+    irq_compare: u8,
+    irq_enabled: bool,
+    irq_pending: bool,
+
+    multiplicand: u8,
+    multiplier: u8,
+
+    pulses: [Pulse; 2],
+    frame_counter: u16,
+    apu_cycle: bool,
+    pcm: u8,
+    pcm_read_mode: bool,
+    pcm_irq_enabled: bool,
+    pcm_irq_pending: bool,
+}
+
+impl Mmc5 {
+    pub fn new(memory: Memory) -> Self {
+        Mmc5 {
+            memory,
+            exram: [0; EXRAM_SIZE],
+            exram_mode: 0,
+            prg_mode: 3,
+            prg_banks: [0, 0xFF, 0xFF, 0xFF, 0xFF],
+            prg_ram_protect: [0; 2],
+            chr_mode: 0,
+            chr_banks: [0; 12],
+            chr_upper: 0,
+            chr_last_written: ChrSet::A,
+            nametables: 0,
+            fill_tile: 0,
+            fill_attribute: 0,
+            split_control: 0,
+            split_scroll: 0,
+            split_bank: 0,
+            split_tile: false,
+            split_y: 0,
+            sprite_16: false,
+            rendering_enabled: false,
+            last_nametable_address: 0,
+            nametable_matches: 0,
+            nametable_reads: 0,
+            idle_cycles: 0,
+            in_frame: false,
+            scanline: 0,
+            tile: 0,
+            ext_attribute: 0,
+            irq_compare: 0,
+            irq_enabled: false,
+            irq_pending: false,
+            multiplicand: 0xFF,
+            multiplier: 0xFF,
+            pulses: [Pulse::default(), Pulse::default()],
+            frame_counter: 0,
+            apu_cycle: false,
+            pcm: 0,
+            pcm_read_mode: false,
+            pcm_irq_enabled: false,
+            pcm_irq_pending: false,
+        }
+    }
+
+    // Resolves a CPU address to (is ROM, 8 KB bank)
+    fn prg_bank(&self, address: u16) -> (bool, usize) {
+        if address < 0x8000 {
+            return (false, (self.prg_banks[0] & 0x0F) as usize);
+        }
+
+        let slot = ((address - 0x8000) >> 13) as usize;
+        let register = match self.prg_mode {
+            0 => 4,
+            1 => 2 + (slot >> 1) * 2,
+            2 if slot < 2 => 2,
+            _ => slot + 1,
+        };
+        let value = self.prg_banks[register];
+        let rom = register == 4 || value & 0x80 != 0;
+
+        let bank = match (self.prg_mode, register) {
+            (0, _) => (value as usize & 0x7C) | slot,
+            (1, _) | (2, 2) => (value as usize & 0x7E) | (slot & 0x01),
+            _ => value as usize & 0x7F,
+        };
+
+        if rom {
+            (true, bank)
+        } else {
+            (false, bank & 0x0F)
+        }
+    }
+
+    fn prg_ram_index(&self, bank: usize, address: u16) -> Option<usize> {
+        if self.memory.prg_ram.is_empty() {
+            return None;
+        }
+
+        let index = bank * 0x2000 + (address as usize & 0x1FFF);
+        Some(index % self.memory.prg_ram.len())
+    }
+
+    fn prg_ram_writable(&self) -> bool {
+        self.prg_ram_protect == [0x02, 0x01]
+    }
+
+    fn chr_set(&self) -> ChrSet {
+        if self.sprite_16 && self.in_frame {
+            // In 8x16 mode, sprites always use set A and the background set B
+            if self.fetching_sprites() {
+                ChrSet::A
+            } else {
+                ChrSet::B
+            }
+        } else {
+            self.chr_last_written
+        }
+    }
+
+    fn fetching_sprites(&self) -> bool {
+        // Per scanline, the PPU makes 64 background nametable fetches followed
+        // by 16 garbage ones while it loads sprite patterns
+        self.in_frame && (65..=80).contains(&self.nametable_reads)
+    }
+
+    // Resolves a PPU pattern address to (bank, bank size)
+    fn chr_bank(&self, address: u16) -> (usize, usize) {
+        let slot = (address >> 10) as usize & 0x07;
+
+        match (self.chr_set(), self.chr_mode) {
+            (ChrSet::A, 0) => (self.chr_banks[7], 0x2000),
+            (ChrSet::A, 1) => (self.chr_banks[3 + (slot & 0x04)], 0x1000),
+            (ChrSet::A, 2) => (self.chr_banks[1 + (slot & 0x06)], 0x0800),
+            (ChrSet::A, _) => (self.chr_banks[slot], 0x0400),
+            (ChrSet::B, 0) | (ChrSet::B, 1) => (self.chr_banks[11], 0x1000 << (1 - self.chr_mode)),
+            (ChrSet::B, 2) => (self.chr_banks[9 + (slot & 0x02)], 0x0800),
+            (ChrSet::B, _) => (self.chr_banks[8 + (slot & 0x03)], 0x0400),
+        }
+    }
+
+    fn detect_scanline(&mut self, address: u16) {
+        if address == self.last_nametable_address {
+            self.nametable_matches += 1;
+        } else {
+            self.nametable_matches = 0;
+        }
+
+        self.last_nametable_address = address;
+        self.idle_cycles = 0;
+
+        // Two dummy fetches at the end of a scanline followed by the first
+        // fetch of the next one all read the same address
+        if self.nametable_matches == 2 {
+            if self.in_frame {
+                self.scanline = self.scanline.wrapping_add(1);
+
+                if self.scanline == self.irq_compare {
+                    self.irq_pending = true;
+                }
+            } else {
+                self.in_frame = true;
+                self.scanline = 0;
+            }
+
+            self.nametable_reads = 0;
+            self.split_y = self.split_scroll.wrapping_add(self.scanline) % 240;
+        }
+
+        self.nametable_reads = self.nametable_reads.saturating_add(1);
+    }
+
+    fn in_split(&self, tile: u8) -> bool {
+        if self.split_control & 0x80 == 0 || self.exram_mode > 1 {
+            return false;
+        }
+
+        let threshold = self.split_control & 0x1F;
+
+        if self.split_control & 0x40 != 0 {
+            tile >= threshold
+        } else {
+            tile < threshold
+        }
+    }
+
+    // What $5105 maps a nametable address to, split screen and extended
+    // attributes aside
+    fn mapped_nametable(&self, address: u16, ciram: &[u8]) -> u8 {
+        let offset = address as usize & 0x03FF;
+        let table = (address >> 10) & 0x03;
+
+        match (self.nametables >> (table * 2)) & 0x03 {
+            0 => ciram[offset],
+            1 => ciram[0x0400 + offset],
+            2 if self.exram_mode <= 1 => self.exram[offset],
+            2 => 0,
+            _ if offset >= 0x03C0 => self.fill_attribute * 0x55,
+            _ => self.fill_tile,
+        }
+    }
+
+    fn clock_audio(&mut self) {
+        self.apu_cycle = !self.apu_cycle;
+
+        if self.apu_cycle {
+            for pulse in &mut self.pulses {
+                pulse.clock_timer();
+            }
+        }
+
+        self.frame_counter += 1;
+
+        if self.frame_counter >= FRAME_PERIOD {
+            self.frame_counter = 0;
+
+            for pulse in &mut self.pulses {
+                pulse.clock_frame();
+            }
+        }
+    }
+
+    fn read_pcm(&mut self, value: u8) {
+        if value == 0 {
+            self.pcm_irq_pending = true;
+        } else {
+            self.pcm = value;
+        }
+    }
+}
+
+impl Mapper for Mmc5 {
+    fn read_prg(&mut self, address: u16) -> u8 {
+        match address {
+            0x5010 => {
+                let value = ((self.pcm_irq_pending && self.pcm_irq_enabled) as u8) << 7
+                    | self.pcm_read_mode as u8;
+                self.pcm_irq_pending = false;
+                value
+            }
+            0x5015 => (self.pulses[0].length > 0) as u8 | ((self.pulses[1].length > 0) as u8) << 1,
+            0x5204 => {
+                let value = (self.irq_pending as u8) << 7 | (self.in_frame as u8) << 6;
+                self.irq_pending = false;
+                value
+            }
+            0x5205 => (self.multiplicand as u16 * self.multiplier as u16) as u8,
+            0x5206 => ((self.multiplicand as u16 * self.multiplier as u16) >> 8) as u8,
+            0x5C00..=0x5FFF if self.exram_mode >= 2 => self.exram[address as usize - 0x5C00],
+            0x6000..=0xFFFF => {
+                let value = match self.prg_bank(address) {
+                    (true, bank) => self.memory.read_prg_rom(bank, 0x2000, address),
+                    (false, bank) => match self.prg_ram_index(bank, address) {
+                        Some(index) => self.memory.prg_ram[index],
+                        None => 0,
+                    },
+                };
+
+                if self.pcm_read_mode && (0x8000..=0xBFFF).contains(&address) {
+                    self.read_pcm(value);
+                }
+
+                value
+            }
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, address: u16, value: u8) {
+        match address {
+            0x5000..=0x5003 => self.pulses[0].write(address - 0x5000, value),
+            0x5004..=0x5007 => self.pulses[1].write(address - 0x5004, value),
+            0x5010 => {
+                self.pcm_read_mode = value & 0x01 != 0;
+                self.pcm_irq_enabled = value & 0x80 != 0;
+            }
+            0x5011 if !self.pcm_read_mode && value != 0 => self.pcm = value,
+            0x5015 => {
+                self.pulses[0].set_enabled(value & 0x01 != 0);
+                self.pulses[1].set_enabled(value & 0x02 != 0);
+            }
+            0x5100 => self.prg_mode = value & 0x03,
+            0x5101 => self.chr_mode = value & 0x03,
+            0x5102 => self.prg_ram_protect[0] = value & 0x03,
+            0x5103 => self.prg_ram_protect[1] = value & 0x03,
+            0x5104 => self.exram_mode = value & 0x03,
+            0x5105 => self.nametables = value,
+            0x5106 => self.fill_tile = value,
+            0x5107 => self.fill_attribute = value & 0x03,
+            0x5113..=0x5117 => self.prg_banks[address as usize - 0x5113] = value,
+            0x5120..=0x512B => {
+                let register = address as usize - 0x5120;
+                self.chr_banks[register] = value as usize | (self.chr_upper as usize) << 8;
+                self.chr_last_written = if register < 8 { ChrSet::A } else { ChrSet::B };
+            }
+            0x5130 => self.chr_upper = value & 0x03,
+            0x5200 => self.split_control = value,
+            0x5201 => self.split_scroll = value,
+            0x5202 => self.split_bank = value,
+            0x5203 => self.irq_compare = value,
+            0x5204 => self.irq_enabled = value & 0x80 != 0,
+            0x5205 => self.multiplicand = value,
+            0x5206 => self.multiplier = value,
+            0x5C00..=0x5FFF => {
+                let index = address as usize - 0x5C00;
+
+                match self.exram_mode {
+                    // Only writable while rendering when the PPU uses ExRAM
+                    0 | 1 => self.exram[index] = if self.in_frame { value } else { 0 },
+                    2 => self.exram[index] = value,
+                    _ => {}
+                }
+            }
+            0x6000..=0xFFFF if self.prg_ram_writable() => {
+                if let (false, bank) = self.prg_bank(address) {
+                    if let Some(index) = self.prg_ram_index(bank, address) {
+                        self.memory.prg_ram[index] = value;
+                        self.memory.prg_ram_dirty = true;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, address: u16) -> u8 {
+        // Pattern fetches break up runs of identical nametable reads
+        self.last_nametable_address = 0;
+        self.nametable_matches = 0;
+        self.idle_cycles = 0;
+
+        if self.in_frame && !self.fetching_sprites() {
+            if self.split_tile {
+                let address = (address & 0x0FF8) | (self.split_y & 0x07) as u16;
+                return self
+                    .memory
+                    .read_chr(self.split_bank as usize, 0x1000, address);
+            }
+
+            if self.exram_mode == 1 {
+                let bank = (self.ext_attribute & 0x3F) as usize | (self.chr_upper as usize) << 6;
+                return self.memory.read_chr(bank, 0x1000, address);
+            }
+        }
+
+        let (bank, size) = self.chr_bank(address);
+        self.memory.read_chr(bank, size, address)
+    }
+
+    fn peek_chr(&mut self, address: u16) -> u8 {
+        let (bank, size) = self.chr_bank(address);
+        self.memory.read_chr(bank, size, address)
+    }
+
+    fn write_chr(&mut self, address: u16, value: u8) {
+        let (bank, size) = self.chr_bank(address);
+        self.memory.write_chr(bank, size, address, value);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        // Only meaningful for the simple cases, see `read_nametable`
+        match self.nametables {
+            0x44 => Mirroring::Vertical,
+            0x50 => Mirroring::Horizontal,
+            0x55 => Mirroring::SingleScreenUpper,
+            _ => Mirroring::SingleScreenLower,
+        }
+    }
+
+    fn memory(&mut self) -> &mut Memory {
+        &mut self.memory
+    }
+
+    fn read_nametable(&mut self, address: u16, ciram: &[u8]) -> u8 {
+        self.detect_scanline(address);
+
+        let offset = address as usize & 0x03FF;
+        let attribute = offset >= 0x03C0;
+
+        if self.in_frame && !self.fetching_sprites() {
+            if !attribute {
+                // Tiles 2..33 are fetched during the scanline, 0 and 1 at its end
+                let reads = self.nametable_reads;
+                let fetch = if reads > 80 { reads - 16 } else { reads } / 2;
+                self.tile = if fetch < 32 { fetch + 2 } else { fetch - 32 };
+                self.split_tile = self.in_split(self.tile);
+                self.ext_attribute = self.exram[offset];
+            }
+
+            if self.split_tile {
+                let row = (self.split_y / 8) as usize;
+                let column = (self.tile & 0x1F) as usize;
+
+                if !attribute {
+                    return self.exram[row * 32 + column];
+                }
+
+                let shift = ((row & 0x02) << 1) | (column & 0x02);
+                let value = self.exram[0x03C0 + (row / 4) * 8 + column / 4] >> shift;
+                return (value & 0x03) * 0x55;
+            }
+
+            if attribute && self.exram_mode == 1 {
+                return (self.ext_attribute >> 6) * 0x55;
+            }
+        }
+
+        self.mapped_nametable(address, ciram)
+    }
+
+    fn peek_nametable(&mut self, address: u16, ciram: &[u8]) -> u8 {
+        self.mapped_nametable(address, ciram)
+    }
+
+    fn write_nametable(&mut self, address: u16, value: u8, ciram: &mut [u8]) {
+        let offset = address as usize & 0x03FF;
+        let table = (address >> 10) & 0x03;
+
+        match (self.nametables >> (table * 2)) & 0x03 {
+            0 => ciram[offset] = value,
+            1 => ciram[0x0400 + offset] = value,
+            2 if self.exram_mode <= 1 => self.exram[offset] = value,
+            _ => {}
+        }
+    }
+
+    fn ppu_register_write(&mut self, address: u16, value: u8) {
+        match address & 0x2007 {
+            0x2000 => self.sprite_16 = value & 0x20 != 0,
+            0x2001 => {
+                self.rendering_enabled = value & 0x18 != 0;
+
+                if !self.rendering_enabled {
+                    self.in_frame = false;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn cpu_clock(&mut self) {
+        if self.idle_cycles < IN_FRAME_TIMEOUT {
+            self.idle_cycles += 1;
+
+            if self.idle_cycles == IN_FRAME_TIMEOUT {
+                self.in_frame = false;
+                self.last_nametable_address = 0;
+            }
+        }
+
+        self.clock_audio();
+    }
+
+    fn irq(&self) -> bool {
+        (self.irq_pending && self.irq_enabled) || (self.pcm_irq_pending && self.pcm_irq_enabled)
+    }
+
+    fn audio_output(&self) -> f32 {
+        let pulses = (self.pulses[0].output() + self.pulses[1].output()) as f32;
+        let pulse_out = if pulses == 0.0 {
+            0.0
+        } else {
+            95.88 / (8128.0 / pulses + 100.0)
+        };
+
+        pulse_out + self.pcm as f32 / 255.0 * 0.25
+    }
+}
@@ -0,0 +1,234 @@
+use super::{CartridgeError, Header, Mirroring};
+
+mod axrom;
+mod bnrom;
+mod cnrom;
+mod color_dreams;
+mod fds;
+mod fds_audio;
+mod flash;
+mod fme7;
+mod gtrom;
+mod gxrom;
+mod mmc2;
+mod mmc3;
+mod mmc5;
+mod multicart;
+mod namco163;
+mod nrom;
+mod nsf;
+mod unrom512;
+mod uxrom;
+mod vrc4;
+mod vrc6;
+mod vrc7;
+mod vrc_irq;
+
+pub use fds::{Disk, Fds};
+pub use flash::Flash;
+pub use nsf::NsfMapper;
+
+pub trait Mapper {
+    // CPU bus, $4020-$FFFF
+    fn read_prg(&mut self, address: u16) -> u8;
+    fn write_prg(&mut self, address: u16, value: u8);
+
+    // PPU bus, $0000-$1FFF. Every pattern fetch made while rendering comes
+    // through here, in the order the PPU makes them.
+    fn read_chr(&mut self, address: u16) -> u8;
+    fn write_chr(&mut self, address: u16, value: u8);
+
+    fn mirroring(&self) -> Mirroring;
+
+    // The board's ROM and RAM, for saving battery-backed PRG-RAM
+    fn memory(&mut self) -> &mut Memory;
+
+    // PPU bus, $2000-$2FFF. `ciram` is the console's 2 KB of nametable RAM.
+    fn read_nametable(&mut self, address: u16, ciram: &[u8]) -> u8 {
+        ciram[self.mirroring().ciram_index(address)]
+    }
+
+    fn write_nametable(&mut self, address: u16, value: u8, ciram: &mut [u8]) {
+        ciram[self.mirroring().ciram_index(address)] = value;
+    }
+
+    // Reads for debuggers, without the side effects a PPU fetch has on
+    // boards that watch them
+    fn peek_chr(&mut self, address: u16) -> u8 {
+        self.read_chr(address)
+    }
+
+    fn peek_nametable(&mut self, address: u16, ciram: &[u8]) -> u8 {
+        self.read_nametable(address, ciram)
+    }
+
+    // Whether the board maps its own RAM over all four nametables, bypassing
+    // the cartridge-level four-screen RAM
+    fn nametable_ram(&self) -> bool {
+        false
+    }
+
+    // Called when the CPU writes a PPU register, for mappers that snoop them
+    fn ppu_register_write(&mut self, _address: u16, _value: u8) {}
+
+    // Called once per CPU cycle (M2)
+    fn cpu_clock(&mut self) {}
+
+    // Called with every address the PPU puts on its bus
+    fn ppu_address(&mut self, _address: u16) {}
+
+    // State of the cartridge's /IRQ line
+    fn irq(&self) -> bool {
+        false
+    }
+
+    // Expansion audio output, to be mixed with the APU's
+    fn audio_output(&self) -> f32 {
+        0.0
+    }
+
+    // Flash PRG ROM, for boards that save by rewriting it
+    fn flash(&mut self) -> Option<&mut Flash> {
+        None
+    }
+
+    // Disk drive, for the Famicom Disk System
+    fn disk(&mut self) -> Option<&mut Disk> {
+        None
+    }
+
+    // Called when the console is reset. Cartridges don't see the reset line,
+    // but some multicarts detect it to return to their menu.
+    fn reset(&mut self) {}
+
+    // Number of DIP switches or solder pads the board reads
+    fn dip_switch_count(&self) -> u8 {
+        0
+    }
+
+    fn set_dip_switches(&mut self, _value: u8) {}
+}
+
+pub struct Memory {
+    pub prg_rom: Vec<u8>,
+    pub prg_ram: Vec<u8>,
+    pub chr: Vec<u8>,
+    pub chr_ram: bool,
+    pub mirroring: Mirroring,
+    // Set whenever PRG-RAM changes, and cleared once it has been saved
+    pub prg_ram_dirty: bool,
+}
+
+impl Memory {
+    pub fn new(prg_rom: Vec<u8>, chr_rom: Vec<u8>, header: &Header) -> Self {
+        let chr_ram = chr_rom.is_empty();
+        let chr = if chr_ram {
+            vec![0; header.chr_ram_size.max(0x2000)]
+        } else {
+            chr_rom
+        };
+
+        Memory {
+            prg_rom,
+            prg_ram: vec![0; header.prg_ram_size],
+            chr,
+            chr_ram,
+            mirroring: header.mirroring,
+            prg_ram_dirty: false,
+        }
+    }
+
+    #[inline]
+    pub fn prg_banks(&self, size: usize) -> usize {
+        (self.prg_rom.len() / size).max(1)
+    }
+
+    #[inline]
+    pub fn chr_banks(&self, size: usize) -> usize {
+        (self.chr.len() / size).max(1)
+    }
+
+    #[inline]
+    pub fn read_prg_rom(&self, bank: usize, size: usize, address: u16) -> u8 {
+        if self.prg_rom.is_empty() {
+            return 0;
+        }
+
+        let index = (bank % self.prg_banks(size)) * size + (address as usize & (size - 1));
+        self.prg_rom[index % self.prg_rom.len()]
+    }
+
+    #[inline]
+    pub fn read_chr(&self, bank: usize, size: usize, address: u16) -> u8 {
+        let index = (bank % self.chr_banks(size)) * size + (address as usize & (size - 1));
+        self.chr[index % self.chr.len()]
+    }
+
+    #[inline]
+    pub fn write_chr(&mut self, bank: usize, size: usize, address: u16, value: u8) {
+        if !self.chr_ram {
+            return;
+        }
+
+        let index = (bank % self.chr_banks(size)) * size + (address as usize & (size - 1));
+        let len = self.chr.len();
+        self.chr[index % len] = value;
+    }
+
+    #[inline]
+    pub fn read_prg_ram(&self, address: u16) -> u8 {
+        if self.prg_ram.is_empty() {
+            return 0;
+        }
+
+        self.prg_ram[(address as usize - 0x6000) % self.prg_ram.len()]
+    }
+
+    #[inline]
+    pub fn write_prg_ram(&mut self, address: u16, value: u8) {
+        if self.prg_ram.is_empty() {
+            return;
+        }
+
+        let len = self.prg_ram.len();
+        let index = (address as usize - 0x6000) % len;
+
+        if self.prg_ram[index] != value {
+            self.prg_ram[index] = value;
+            self.prg_ram_dirty = true;
+        }
+    }
+}
+
+pub fn create(header: &Header, memory: Memory) -> Result<Box<dyn Mapper>, CartridgeError> {
+    let mapper: Box<dyn Mapper> = match header.mapper {
+        0 => Box::new(nrom::Nrom::new(memory)),
+        2 => Box::new(uxrom::Uxrom::new(memory, header.submapper)),
+        3 => Box::new(cnrom::Cnrom::new(memory, header.submapper)),
+        4 => Box::new(mmc3::Mmc3::new(memory, header.submapper)),
+        5 => Box::new(mmc5::Mmc5::new(memory)),
+        7 => Box::new(axrom::Axrom::new(memory, header.submapper)),
+        9 | 10 => Box::new(mmc2::Mmc2::new(memory, header.mapper)),
+        11 => Box::new(color_dreams::ColorDreams::new(memory)),
+        15 => Box::new(multicart::K1029::new(memory)),
+        19 => Box::new(namco163::Namco163::new(memory)),
+        21 | 22 | 23 | 25 => Box::new(vrc4::Vrc4::new(memory, header)),
+        24 | 26 => Box::new(vrc6::Vrc6::new(memory, header.mapper)),
+        30 => Box::new(unrom512::Unrom512::new(memory, header)),
+        34 => Box::new(bnrom::Bnrom::new(memory, header.submapper)),
+        41 => Box::new(multicart::Caltron::new(memory)),
+        57 => Box::new(multicart::Gk6in1::new(memory)),
+        58 | 200 | 212 | 225 => Box::new(multicart::AddressLatch::new(memory, header.mapper)),
+        60 => Box::new(multicart::ResetMulticart::new(memory)),
+        66 => Box::new(gxrom::Gxrom::new(memory)),
+        69 => Box::new(fme7::Fme7::new(memory)),
+        85 => Box::new(vrc7::Vrc7::new(memory, header.submapper)),
+        111 => Box::new(gtrom::Gtrom::new(memory)),
+        203 => Box::new(multicart::DataLatch::new(memory)),
+        226 | 233 => Box::new(multicart::Multicart226::new(memory, header.mapper)),
+        230 => Box::new(multicart::Multicart230::new(memory)),
+        mapper => return Err(CartridgeError::UnsupportedMapper(mapper)),
+    };
+
+    Ok(mapper)
+}
@@ -0,0 +1,634 @@
+// Pirate multicarts and bootleg boards built from discrete logic. Cartridges
+// don't see the console's reset line, but most of these boards detect it and
+// either clear their latches to return to the menu or step to the next game.
+
+use super::{Mapper, Memory, Mirroring};
+
+// Reads PRG ROM as one 16 KB bank mirrored at $8000 and $C000 (NROM-128), or
+// as the 32 KB pair the bank belongs to (NROM-256)
+fn read_nrom(memory: &Memory, bank: usize, nrom128: bool, address: u16) -> u8 {
+    let bank = if nrom128 {
+        bank
+    } else {
+        (bank & !1) | ((address as usize >> 14) & 0x01)
+    };
+
+    memory.read_prg_rom(bank, 0x4000, address)
+}
+
+fn horizontal_if(horizontal: bool) -> Mirroring {
+    if horizontal {
+        Mirroring::Horizontal
+    } else {
+        Mirroring::Vertical
+    }
+}
+
+// K-1029 and K-1030P, "100-in-1 Contra Function 16" (mapper 15)
+pub struct K1029 {
+    memory: Memory,
+    mode: u8,
+    bank: usize,
+    half: usize,
+    mirroring: Mirroring,
+}
+
+impl K1029 {
+    pub fn new(memory: Memory) -> Self {
+        K1029 {
+            memory,
+            mode: 0,
+            bank: 0,
+            half: 0,
+            mirroring: Mirroring::Vertical,
+        }
+    }
+}
+
+impl Mapper for K1029 {
+    fn read_prg(&mut self, address: u16) -> u8 {
+        let upper = address >= 0xC000;
+
+        match (address, self.mode) {
+            (0x6000..=0x7FFF, _) => self.memory.read_prg_ram(address),
+            (0x8000..=0xFFFF, 0) => read_nrom(&self.memory, self.bank, false, address),
+            (0x8000..=0xFFFF, 1) => {
+                let bank = if upper { self.bank | 0x07 } else { self.bank };
+                self.memory.read_prg_rom(bank, 0x4000, address)
+            }
+            // NROM-64: one 8 KB bank mirrored four times
+            (0x8000..=0xFFFF, 2) => {
+                let bank = self.bank << 1 | self.half;
+                self.memory.read_prg_rom(bank, 0x2000, address)
+            }
+            (0x8000..=0xFFFF, _) => read_nrom(&self.memory, self.bank, true, address),
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, address: u16, value: u8) {
+        match address {
+            0x6000..=0x7FFF => self.memory.write_prg_ram(address, value),
+            0x8000..=0xFFFF => {
+                self.mode = (address & 0x03) as u8;
+                self.bank = (value & 0x3F) as usize;
+                self.half = (value >> 7) as usize;
+                self.mirroring = horizontal_if(value & 0x40 != 0);
+            }
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, address: u16) -> u8 {
+        self.memory.read_chr(0, 0x2000, address)
+    }
+
+    fn write_chr(&mut self, address: u16, value: u8) {
+        // CHR-RAM is write-protected in the NROM modes
+        if self.mode == 1 || self.mode == 2 {
+            self.memory.write_chr(0, 0x2000, address, value);
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn memory(&mut self) -> &mut Memory {
+        &mut self.memory
+    }
+
+    fn reset(&mut self) {
+        self.mode = 0;
+        self.bank = 0;
+        self.half = 0;
+    }
+}
+
+// Caltron 6-in-1 (mapper 41)
+pub struct Caltron {
+    memory: Memory,
+    outer: u16,
+    inner_chr: usize,
+}
+
+impl Caltron {
+    pub fn new(memory: Memory) -> Self {
+        Caltron {
+            memory,
+            outer: 0,
+            inner_chr: 0,
+        }
+    }
+
+    fn chr_bank(&self) -> usize {
+        ((self.outer as usize >> 1) & 0x0C) | self.inner_chr
+    }
+}
+
+impl Mapper for Caltron {
+    fn read_prg(&mut self, address: u16) -> u8 {
+        match address {
+            0x8000..=0xFFFF => {
+                let bank = (self.outer & 0x07) as usize;
+                self.memory.read_prg_rom(bank, 0x8000, address)
+            }
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, address: u16, mut value: u8) {
+        match address {
+            // The outer bank is latched from the address
+            0x6000..=0x67FF => self.outer = address & 0x3F,
+            // The inner CHR bank only responds while a game in the last four
+            // PRG banks is selected, and has bus conflicts
+            0x8000..=0xFFFF if self.outer & 0x04 != 0 => {
+                value &= self.read_prg(address);
+                self.inner_chr = (value & 0x03) as usize;
+            }
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, address: u16) -> u8 {
+        self.memory.read_chr(self.chr_bank(), 0x2000, address)
+    }
+
+    fn write_chr(&mut self, address: u16, value: u8) {
+        self.memory
+            .write_chr(self.chr_bank(), 0x2000, address, value);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        horizontal_if(self.outer & 0x20 != 0)
+    }
+
+    fn memory(&mut self) -> &mut Memory {
+        &mut self.memory
+    }
+
+    fn reset(&mut self) {
+        self.outer = 0;
+        self.inner_chr = 0;
+    }
+}
+
+// GK 6-in-1 and similar (mapper 57)
+pub struct Gk6in1 {
+    memory: Memory,
+    registers: [u8; 2],
+    dip_switches: u8,
+}
+
+impl Gk6in1 {
+    pub fn new(memory: Memory) -> Self {
+        Gk6in1 {
+            memory,
+            registers: [0; 2],
+            dip_switches: 0,
+        }
+    }
+
+    fn chr_bank(&self) -> usize {
+        let high = (self.registers[0] & 0x40) >> 3;
+        let low = (self.registers[0] | self.registers[1]) & 0x07;
+        (high | low) as usize
+    }
+}
+
+impl Mapper for Gk6in1 {
+    fn read_prg(&mut self, address: u16) -> u8 {
+        match address {
+            // The menu reads the switches to pick which set of games to list
+            0x6000..=0x7FFF => self.dip_switches,
+            0x8000..=0xFFFF => {
+                let bank = (self.registers[1] >> 5) as usize;
+                let nrom128 = self.registers[1] & 0x10 == 0;
+                read_nrom(&self.memory, bank, nrom128, address)
+            }
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, address: u16, value: u8) {
+        if address >= 0x8000 {
+            self.registers[(address >> 11) as usize & 0x01] = value;
+        }
+    }
+
+    fn read_chr(&mut self, address: u16) -> u8 {
+        self.memory.read_chr(self.chr_bank(), 0x2000, address)
+    }
+
+    fn write_chr(&mut self, address: u16, value: u8) {
+        self.memory
+            .write_chr(self.chr_bank(), 0x2000, address, value);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        horizontal_if(self.registers[1] & 0x08 != 0)
+    }
+
+    fn memory(&mut self) -> &mut Memory {
+        &mut self.memory
+    }
+
+    fn reset(&mut self) {
+        self.registers = [0; 2];
+    }
+
+    fn dip_switch_count(&self) -> u8 {
+        2
+    }
+
+    fn set_dip_switches(&mut self, value: u8) {
+        self.dip_switches = value & 0x03;
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum AddressLatchWiring {
+    // Mapper 58: A0-A2 PRG bank, A3-A5 CHR bank, A6 NROM-128, A7 mirroring
+    Mapper58,
+    // Mapper 200: A0-A2 PRG and CHR bank, A3 mirroring
+    Mapper200,
+    // Mapper 212: A0-A2 PRG and CHR bank, A3 mirroring, A14 NROM-256
+    Mapper212,
+    // Mapper 225: A0-A5 CHR bank, A6-A11 PRG bank, A12 NROM-128,
+    // A13 mirroring, A14 bank high bit
+    Mapper225,
+}
+
+// The many NROM multicarts that latch the address of any write to
+// $8000-$FFFF (mappers 58, 200, 212 and 225)
+pub struct AddressLatch {
+    memory: Memory,
+    wiring: AddressLatchWiring,
+    latch: u16,
+    // Mapper 225 boards have four nibbles of RAM at $5800-$5FFF
+    nibble_ram: [u8; 4],
+}
+
+impl AddressLatch {
+    pub fn new(memory: Memory, mapper: u16) -> Self {
+        let wiring = match mapper {
+            58 => AddressLatchWiring::Mapper58,
+            200 => AddressLatchWiring::Mapper200,
+            212 => AddressLatchWiring::Mapper212,
+            _ => AddressLatchWiring::Mapper225,
+        };
+
+        AddressLatch {
+            memory,
+            wiring,
+            latch: 0,
+            nibble_ram: [0; 4],
+        }
+    }
+
+    // 16 KB PRG bank, whether it's mirrored, 8 KB CHR bank and mirroring
+    fn decode(&self) -> (usize, bool, usize, Mirroring) {
+        let latch = self.latch as usize;
+
+        match self.wiring {
+            AddressLatchWiring::Mapper58 => (
+                latch & 0x07,
+                latch & 0x40 != 0,
+                (latch >> 3) & 0x07,
+                horizontal_if(latch & 0x80 != 0),
+            ),
+            AddressLatchWiring::Mapper200 => (
+                latch & 0x07,
+                true,
+                latch & 0x07,
+                horizontal_if(latch & 0x08 != 0),
+            ),
+            AddressLatchWiring::Mapper212 => (
+                latch & 0x07,
+                latch & 0x4000 == 0,
+                latch & 0x07,
+                horizontal_if(latch & 0x08 != 0),
+            ),
+            AddressLatchWiring::Mapper225 => {
+                let high = (latch >> 14) & 0x01;
+                (
+                    high << 6 | (latch >> 6) & 0x3F,
+                    latch & 0x1000 != 0,
+                    high << 6 | latch & 0x3F,
+                    horizontal_if(latch & 0x2000 != 0),
+                )
+            }
+        }
+    }
+}
+
+impl Mapper for AddressLatch {
+    fn read_prg(&mut self, address: u16) -> u8 {
+        match (address, self.wiring) {
+            (0x5800..=0x5FFF, AddressLatchWiring::Mapper225) => {
+                self.nibble_ram[address as usize & 0x03]
+            }
+            // The menu tells the 300-in-1 and 1000-in-1 versions apart by D7
+            (0x6000..=0x7FFF, AddressLatchWiring::Mapper212) if address & 0x10 == 0 => 0x80,
+            (0x8000..=0xFFFF, _) => {
+                let (bank, nrom128, _, _) = self.decode();
+                read_nrom(&self.memory, bank, nrom128, address)
+            }
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, address: u16, value: u8) {
+        match (address, self.wiring) {
+            (0x5800..=0x5FFF, AddressLatchWiring::Mapper225) => {
+                self.nibble_ram[address as usize & 0x03] = value & 0x0F;
+            }
+            (0x8000..=0xFFFF, _) => self.latch = address,
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, address: u16) -> u8 {
+        let (_, _, bank, _) = self.decode();
+        self.memory.read_chr(bank, 0x2000, address)
+    }
+
+    fn write_chr(&mut self, address: u16, value: u8) {
+        let (_, _, bank, _) = self.decode();
+        self.memory.write_chr(bank, 0x2000, address, value);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        let (_, _, _, mirroring) = self.decode();
+        mirroring
+    }
+
+    fn memory(&mut self) -> &mut Memory {
+        &mut self.memory
+    }
+
+    fn reset(&mut self) {
+        self.latch = 0;
+    }
+}
+
+// Reset-based NROM-128 4-in-1 (mapper 60). Every reset moves to the next
+// game, and there is nothing to write to.
+pub struct ResetMulticart {
+    memory: Memory,
+    game: usize,
+}
+
+impl ResetMulticart {
+    pub fn new(memory: Memory) -> Self {
+        ResetMulticart { memory, game: 0 }
+    }
+}
+
+impl Mapper for ResetMulticart {
+    fn read_prg(&mut self, address: u16) -> u8 {
+        match address {
+            0x8000..=0xFFFF => read_nrom(&self.memory, self.game, true, address),
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, _address: u16, _value: u8) {}
+
+    fn read_chr(&mut self, address: u16) -> u8 {
+        self.memory.read_chr(self.game, 0x2000, address)
+    }
+
+    fn write_chr(&mut self, address: u16, value: u8) {
+        self.memory.write_chr(self.game, 0x2000, address, value);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.memory.mirroring
+    }
+
+    fn memory(&mut self) -> &mut Memory {
+        &mut self.memory
+    }
+
+    fn reset(&mut self) {
+        self.game = (self.game + 1) & 0x03;
+    }
+}
+
+// 35-in-1 and similar (mapper 203)
+pub struct DataLatch {
+    memory: Memory,
+    latch: u8,
+}
+
+impl DataLatch {
+    pub fn new(memory: Memory) -> Self {
+        DataLatch { memory, latch: 0 }
+    }
+}
+
+impl Mapper for DataLatch {
+    fn read_prg(&mut self, address: u16) -> u8 {
+        match address {
+            0x8000..=0xFFFF => {
+                let bank = (self.latch >> 2) as usize;
+                read_nrom(&self.memory, bank, true, address)
+            }
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, address: u16, value: u8) {
+        if address >= 0x8000 {
+            self.latch = value;
+        }
+    }
+
+    fn read_chr(&mut self, address: u16) -> u8 {
+        let bank = (self.latch & 0x03) as usize;
+        self.memory.read_chr(bank, 0x2000, address)
+    }
+
+    fn write_chr(&mut self, address: u16, value: u8) {
+        let bank = (self.latch & 0x03) as usize;
+        self.memory.write_chr(bank, 0x2000, address, value);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.memory.mirroring
+    }
+
+    fn memory(&mut self) -> &mut Memory {
+        &mut self.memory
+    }
+
+    fn reset(&mut self) {
+        self.latch = 0;
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mapper226Family {
+    // Mapper 226: the second register holds PRG bank bit 6
+    Mapper226,
+    // Mapper 233: a flip-flop toggled by reset selects the 512 KB half
+    Mapper233,
+}
+
+// 76-in-1 and 42-in-1 (mappers 226 and 233)
+pub struct Multicart226 {
+    memory: Memory,
+    family: Mapper226Family,
+    registers: [u8; 2],
+    reset_half: bool,
+}
+
+impl Multicart226 {
+    pub fn new(memory: Memory, mapper: u16) -> Self {
+        Multicart226 {
+            memory,
+            family: if mapper == 233 {
+                Mapper226Family::Mapper233
+            } else {
+                Mapper226Family::Mapper226
+            },
+            registers: [0; 2],
+            reset_half: false,
+        }
+    }
+
+    fn prg_bank(&self) -> usize {
+        let register = self.registers[0] as usize;
+
+        match self.family {
+            Mapper226Family::Mapper226 => {
+                (register & 0x1F)
+                    | ((register >> 7) & 0x01) << 5
+                    | (self.registers[1] as usize & 0x01) << 6
+            }
+            Mapper226Family::Mapper233 => (register & 0x1F) | (self.reset_half as usize) << 5,
+        }
+    }
+}
+
+impl Mapper for Multicart226 {
+    fn read_prg(&mut self, address: u16) -> u8 {
+        match address {
+            0x8000..=0xFFFF => {
+                let nrom128 = self.registers[0] & 0x20 != 0;
+                read_nrom(&self.memory, self.prg_bank(), nrom128, address)
+            }
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, address: u16, value: u8) {
+        if address < 0x8000 {
+            return;
+        }
+
+        match self.family {
+            Mapper226Family::Mapper226 => self.registers[address as usize & 0x01] = value,
+            Mapper226Family::Mapper233 => self.registers[0] = value,
+        }
+    }
+
+    fn read_chr(&mut self, address: u16) -> u8 {
+        self.memory.read_chr(0, 0x2000, address)
+    }
+
+    fn write_chr(&mut self, address: u16, value: u8) {
+        self.memory.write_chr(0, 0x2000, address, value);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match (self.family, self.registers[0] >> 6) {
+            (Mapper226Family::Mapper226, bits) => horizontal_if(bits & 0x01 == 0),
+            (Mapper226Family::Mapper233, 0) => Mirroring::SingleScreenLower,
+            (Mapper226Family::Mapper233, 1) => Mirroring::Vertical,
+            (Mapper226Family::Mapper233, 2) => Mirroring::Horizontal,
+            (Mapper226Family::Mapper233, _) => Mirroring::SingleScreenUpper,
+        }
+    }
+
+    fn memory(&mut self) -> &mut Memory {
+        &mut self.memory
+    }
+
+    fn reset(&mut self) {
+        self.registers = [0; 2];
+
+        if self.family == Mapper226Family::Mapper233 {
+            self.reset_half = !self.reset_half;
+        }
+    }
+}
+
+// 22-in-1 (mapper 230). Powers on into Contra in an UNROM layout over the
+// first 128 KB, and each reset toggles between it and the menu.
+pub struct Multicart230 {
+    memory: Memory,
+    contra: bool,
+    register: u8,
+}
+
+impl Multicart230 {
+    pub fn new(memory: Memory) -> Self {
+        Multicart230 {
+            memory,
+            contra: true,
+            register: 0,
+        }
+    }
+}
+
+impl Mapper for Multicart230 {
+    fn read_prg(&mut self, address: u16) -> u8 {
+        match address {
+            0x8000..=0xBFFF if self.contra => {
+                let bank = (self.register & 0x07) as usize;
+                self.memory.read_prg_rom(bank, 0x4000, address)
+            }
+            0xC000..=0xFFFF if self.contra => self.memory.read_prg_rom(7, 0x4000, address),
+            0x8000..=0xFFFF => {
+                let bank = (self.register & 0x1F) as usize + 8;
+                let nrom128 = self.register & 0x20 != 0;
+                read_nrom(&self.memory, bank, nrom128, address)
+            }
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, address: u16, value: u8) {
+        if address >= 0x8000 {
+            self.register = value;
+        }
+    }
+
+    fn read_chr(&mut self, address: u16) -> u8 {
+        self.memory.read_chr(0, 0x2000, address)
+    }
+
+    fn write_chr(&mut self, address: u16, value: u8) {
+        self.memory.write_chr(0, 0x2000, address, value);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        if self.contra {
+            Mirroring::Vertical
+        } else {
+            horizontal_if(self.register & 0x40 == 0)
+        }
+    }
+
+    fn memory(&mut self) -> &mut Memory {
+        &mut self.memory
+    }
+
+    fn reset(&mut self) {
+        self.contra = !self.contra;
+        self.register = 0;
+    }
+}
@@ -0,0 +1,262 @@
+use super::{Mapper, Memory, Mirroring};
+
+const SOUND_RAM_SIZE: usize = 0x80;
+
+// Each active channel is updated in turn, one every 15 CPU cycles
+const CHANNEL_CYCLES: u8 = 15;
+
+// Output level of one step of a single channel, relative to the APU pulses
+const OUTPUT_STEP: f32 = 0.004;
+
+// Namco 129 and 163 (mapper 19)
+pub struct Namco163 {
+    memory: Memory,
+    prg_banks: [usize; 3],
+    chr_banks: [u8; 8],
+    nametable_banks: [u8; 4],
+    prg_ram_protect: u8,
+
+    irq_counter: u16,
+    irq_enabled: bool,
+    irq_pending: bool,
+
+    sound_ram: [u8; SOUND_RAM_SIZE],
+    sound_address: u8,
+    sound_increment: bool,
+    sound_disabled: bool,
+    channel_cycles: u8,
+    current_channel: u8,
+    outputs: [i8; 8],
+}
+
+impl Namco163 {
+    pub fn new(memory: Memory) -> Self {
+        Namco163 {
+            memory,
+            prg_banks: [0, 1, 2],
+            chr_banks: [0; 8],
+            nametable_banks: [0xE0; 4],
+            prg_ram_protect: 0,
+            irq_counter: 0,
+            irq_enabled: false,
+            irq_pending: false,
+            sound_ram: [0; SOUND_RAM_SIZE],
+            sound_address: 0,
+            sound_increment: false,
+            sound_disabled: false,
+            channel_cycles: 0,
+            current_channel: 7,
+            outputs: [0; 8],
+        }
+    }
+
+    fn active_channels(&self) -> u8 {
+        ((self.sound_ram[0x7F] >> 4) & 0x07) + 1
+    }
+
+    fn prg_ram_writable(&self, address: u16) -> bool {
+        // Writes need the enable pattern in the upper bits, and each of the
+        // low bits protects one 2 KB quarter of the RAM
+        let quarter = (address - 0x6000) >> 11;
+        self.prg_ram_protect & 0xF0 == 0x40 && self.prg_ram_protect & (1 << quarter) == 0
+    }
+
+    fn update_channel(&mut self, channel: u8) {
+        let base = 0x40 + channel as usize * 8;
+        let registers = &self.sound_ram[base..base + 8];
+
+        let frequency =
+            registers[0] as u32 | (registers[2] as u32) << 8 | ((registers[4] & 0x03) as u32) << 16;
+        let mut phase =
+            registers[1] as u32 | (registers[3] as u32) << 8 | (registers[5] as u32) << 16;
+        let length = 256 - (registers[4] & 0xFC) as u32;
+        let offset = registers[6] as u32;
+        let volume = (registers[7] & 0x0F) as i8;
+
+        phase = (phase + frequency) % (length << 16);
+
+        let index = ((offset + (phase >> 16)) & 0xFF) as usize;
+        let byte = self.sound_ram[index >> 1];
+        let sample = if index & 0x01 == 0 {
+            byte & 0x0F
+        } else {
+            byte >> 4
+        };
+
+        self.outputs[channel as usize] = (sample as i8 - 8) * volume;
+
+        self.sound_ram[base + 1] = phase as u8;
+        self.sound_ram[base + 3] = (phase >> 8) as u8;
+        self.sound_ram[base + 5] = (phase >> 16) as u8;
+    }
+
+    fn clock_audio(&mut self) {
+        self.channel_cycles += 1;
+
+        if self.channel_cycles < CHANNEL_CYCLES {
+            return;
+        }
+
+        self.channel_cycles = 0;
+
+        // Channels run from 7 downwards
+        let lowest = 8 - self.active_channels();
+
+        if self.current_channel < lowest {
+            self.current_channel = 7;
+        }
+
+        self.update_channel(self.current_channel);
+
+        self.current_channel = if self.current_channel == lowest {
+            7
+        } else {
+            self.current_channel - 1
+        };
+    }
+
+    fn chr_page(&self, address: u16) -> u8 {
+        self.chr_banks[(address >> 10) as usize & 0x07]
+    }
+}
+
+impl Mapper for Namco163 {
+    fn read_prg(&mut self, address: u16) -> u8 {
+        match address {
+            0x4800..=0x4FFF => {
+                let value = self.sound_ram[self.sound_address as usize];
+
+                if self.sound_increment {
+                    self.sound_address = (self.sound_address + 1) & 0x7F;
+                }
+
+                value
+            }
+            0x5000..=0x57FF => self.irq_counter as u8,
+            0x5800..=0x5FFF => (self.irq_enabled as u8) << 7 | (self.irq_counter >> 8) as u8,
+            0x6000..=0x7FFF => self.memory.read_prg_ram(address),
+            0x8000..=0xDFFF => {
+                let bank = self.prg_banks[((address - 0x8000) >> 13) as usize];
+                self.memory.read_prg_rom(bank, 0x2000, address)
+            }
+            0xE000..=0xFFFF => {
+                let last = self.memory.prg_banks(0x2000) - 1;
+                self.memory.read_prg_rom(last, 0x2000, address)
+            }
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, address: u16, value: u8) {
+        match address {
+            0x4800..=0x4FFF => {
+                self.sound_ram[self.sound_address as usize] = value;
+
+                if self.sound_increment {
+                    self.sound_address = (self.sound_address + 1) & 0x7F;
+                }
+            }
+            0x5000..=0x57FF => {
+                self.irq_counter = (self.irq_counter & 0x7F00) | value as u16;
+                self.irq_pending = false;
+            }
+            0x5800..=0x5FFF => {
+                self.irq_counter = (self.irq_counter & 0x00FF) | ((value as u16 & 0x7F) << 8);
+                self.irq_enabled = value & 0x80 != 0;
+                self.irq_pending = false;
+            }
+            0x6000..=0x7FFF if self.prg_ram_writable(address) => {
+                self.memory.write_prg_ram(address, value);
+            }
+            0x8000..=0xBFFF => self.chr_banks[((address - 0x8000) >> 11) as usize] = value,
+            0xC000..=0xDFFF => self.nametable_banks[((address - 0xC000) >> 11) as usize] = value,
+            0xE000..=0xE7FF => {
+                self.prg_banks[0] = (value & 0x3F) as usize;
+                self.sound_disabled = value & 0x40 != 0;
+            }
+            0xE800..=0xEFFF => {
+                self.prg_banks[1] = (value & 0x3F) as usize;
+            }
+            0xF000..=0xF7FF => self.prg_banks[2] = (value & 0x3F) as usize,
+            0xF800..=0xFFFF => {
+                self.prg_ram_protect = value;
+                self.sound_address = value & 0x7F;
+                self.sound_increment = value & 0x80 != 0;
+            }
+            _ => {}
+        }
+    }
+
+    // CHR bank values of $E0 and above can select nametable RAM for patterns,
+    // unless disabled through $E800. No known game relies on that, so they
+    // always read CHR here.
+    fn read_chr(&mut self, address: u16) -> u8 {
+        let bank = self.chr_page(address) as usize;
+        self.memory.read_chr(bank, 0x0400, address)
+    }
+
+    fn write_chr(&mut self, address: u16, value: u8) {
+        let bank = self.chr_page(address) as usize;
+        self.memory.write_chr(bank, 0x0400, address, value);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        Mirroring::Vertical
+    }
+
+    fn memory(&mut self) -> &mut Memory {
+        &mut self.memory
+    }
+
+    fn read_nametable(&mut self, address: u16, ciram: &[u8]) -> u8 {
+        let bank = self.nametable_banks[(address >> 10) as usize & 0x03];
+
+        if bank >= 0xE0 {
+            ciram[(bank as usize & 0x01) * 0x0400 + (address as usize & 0x03FF)]
+        } else {
+            self.memory.read_chr(bank as usize, 0x0400, address)
+        }
+    }
+
+    fn write_nametable(&mut self, address: u16, value: u8, ciram: &mut [u8]) {
+        let bank = self.nametable_banks[(address >> 10) as usize & 0x03];
+
+        if bank >= 0xE0 {
+            ciram[(bank as usize & 0x01) * 0x0400 + (address as usize & 0x03FF)] = value;
+        } else {
+            self.memory.write_chr(bank as usize, 0x0400, address, value);
+        }
+    }
+
+    fn cpu_clock(&mut self) {
+        if self.irq_enabled && self.irq_counter < 0x7FFF {
+            self.irq_counter += 1;
+
+            if self.irq_counter == 0x7FFF {
+                self.irq_pending = true;
+            }
+        }
+
+        self.clock_audio();
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn audio_output(&self) -> f32 {
+        if self.sound_disabled {
+            return 0.0;
+        }
+
+        // The chip multiplexes its channels, so more channels means each one
+        // is heard for a shorter time
+        let active = self.active_channels();
+        let sum: i32 = self.outputs[(8 - active) as usize..]
+            .iter()
+            .map(|&output| output as i32)
+            .sum();
+
+        sum as f32 / active as f32 * OUTPUT_STEP
+    }
+}
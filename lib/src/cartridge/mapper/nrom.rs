@@ -0,0 +1,43 @@
+use super::{Mapper, Memory, Mirroring};
+
+pub struct Nrom {
+    memory: Memory,
+}
+
+impl Nrom {
+    pub fn new(memory: Memory) -> Self {
+        Nrom { memory }
+    }
+}
+
+impl Mapper for Nrom {
+    fn read_prg(&mut self, address: u16) -> u8 {
+        match address {
+            0x6000..=0x7FFF => self.memory.read_prg_ram(address),
+            0x8000..=0xFFFF => self.memory.read_prg_rom(0, 0x8000, address),
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, address: u16, value: u8) {
+        if let 0x6000..=0x7FFF = address {
+            self.memory.write_prg_ram(address, value);
+        }
+    }
+
+    fn read_chr(&mut self, address: u16) -> u8 {
+        self.memory.read_chr(0, 0x2000, address)
+    }
+
+    fn write_chr(&mut self, address: u16, value: u8) {
+        self.memory.write_chr(0, 0x2000, address, value);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.memory.mirroring
+    }
+
+    fn memory(&mut self) -> &mut Memory {
+        &mut self.memory
+    }
+}
@@ -0,0 +1,330 @@
+use super::fds_audio::FdsAudio;
+use super::{create, Mapper, Memory, Mirroring};
+use crate::cartridge::{CartridgeError, Header};
+use crate::nsf::{ExpansionChips, Nsf, Nsf2Flags};
+
+const BANK_SIZE: usize = 0x1000;
+
+// The FDS maps RAM over $6000-$DFFF, with two more bank registers at
+// $5FF6/$5FF7 for $6000-$7FFF
+const FDS_RAM_SIZE: usize = 0xA000;
+
+// NSF2 tunes whose INIT doesn't return have PLAY called from NMI, by a
+// driver only the CPU can see
+const PLAY_DRIVER: u16 = 0x4100;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Chip {
+    Vrc6,
+    Vrc7,
+    Mmc5,
+    Namco163,
+    Sunsoft5b,
+}
+
+impl Chip {
+    fn mapper(self) -> u16 {
+        match self {
+            Chip::Vrc6 => 24,
+            Chip::Vrc7 => 85,
+            Chip::Mmc5 => 5,
+            Chip::Namco163 => 19,
+            Chip::Sunsoft5b => 69,
+        }
+    }
+
+    // The registers the chip has in an NSF player, which leaves out all of
+    // its banking
+    fn decodes(self, address: u16) -> bool {
+        match self {
+            Chip::Vrc6 => matches!(address, 0x9000..=0x9003 | 0xA000..=0xA002 | 0xB000..=0xB002),
+            Chip::Vrc7 => matches!(address, 0x9010 | 0x9030),
+            Chip::Mmc5 => matches!(address, 0x5000..=0x5015 | 0x5205..=0x5206 | 0x5C00..=0x5FF5),
+            Chip::Namco163 => matches!(address, 0x4800..=0x4FFF | 0xF800..=0xFFFF),
+            Chip::Sunsoft5b => matches!(address, 0xC000..=0xFFFF),
+        }
+    }
+}
+
+// The hardware an NSF player would be built on: 4 KB PRG banks at
+// $5FF8-$5FFF, 8 KB of PRG-RAM and whichever audio chips the tune uses
+pub struct NsfMapper {
+    memory: Memory,
+    initial_banks: [u8; 10],
+    // $6000-$FFFF in 4 KB slots. The first two only hold banks on the FDS.
+    banks: [u8; 10],
+    fds: Option<FdsAudio>,
+    chips: Vec<(Chip, Box<dyn Mapper>)>,
+    // The NSF2 IRQ timer and the writable IRQ vector that goes with it
+    irq_support: bool,
+    irq_vector: [u8; 2],
+    irq_reload: u16,
+    irq_counter: u16,
+    irq_enabled: bool,
+    irq_pending: bool,
+    // PHA, TXA, PHA, TYA, PHA, JSR PLAY, PLA, TAY, PLA, TAX, PLA, RTI
+    play_driver: Option<[u8; 14]>,
+}
+
+impl NsfMapper {
+    pub fn new(nsf: &Nsf, header: &Header) -> Result<Self, CartridgeError> {
+        let fds = nsf.chips.contains(ExpansionChips::FDS);
+        let base = if fds { 0x6000 } else { 0x8000 };
+
+        // Bankswitched tunes keep the load address' offset within the first
+        // bank. The others load linearly into fixed banks.
+        let (padding, initial_banks) = match nsf.banks {
+            Some(banks) => {
+                let mut initial = [0; 10];
+                initial[2..].copy_from_slice(&banks);
+                initial[0] = banks[6];
+                initial[1] = banks[7];
+                (nsf.load_address as usize & 0x0FFF, initial)
+            }
+            None => {
+                let mut initial = [0; 10];
+                let first = (base - 0x6000) / BANK_SIZE;
+
+                for (slot, bank) in initial.iter_mut().enumerate().skip(first) {
+                    *bank = (slot - first) as u8;
+                }
+
+                (nsf.load_address as usize - base, initial)
+            }
+        };
+
+        let mut prg_rom = vec![0; padding];
+        prg_rom.extend_from_slice(&nsf.data);
+        prg_rom.resize(prg_rom.len().div_ceil(BANK_SIZE) * BANK_SIZE, 0);
+
+        let mut memory = Memory::new(prg_rom, Vec::new(), header);
+        memory.prg_ram = vec![0; if fds { FDS_RAM_SIZE } else { 0x2000 }];
+
+        let mut chips = Vec::new();
+
+        for (flag, chip) in [
+            (ExpansionChips::VRC6, Chip::Vrc6),
+            (ExpansionChips::VRC7, Chip::Vrc7),
+            (ExpansionChips::MMC5, Chip::Mmc5),
+            (ExpansionChips::NAMCO163, Chip::Namco163),
+            (ExpansionChips::SUNSOFT5B, Chip::Sunsoft5b),
+        ] {
+            if nsf.chips.contains(flag) {
+                let header = Header {
+                    mapper: chip.mapper(),
+                    ..header.clone()
+                };
+                let memory = Memory::new(vec![0; 0x8000], Vec::new(), &header);
+                chips.push((chip, create(&header, memory)?));
+            }
+        }
+
+        let mut mapper = NsfMapper {
+            memory,
+            initial_banks,
+            banks: initial_banks,
+            fds: if fds { Some(FdsAudio::new()) } else { None },
+            chips,
+            irq_support: nsf.flags.contains(Nsf2Flags::IRQ),
+            irq_vector: [0; 2],
+            irq_reload: 0,
+            irq_counter: 0,
+            irq_enabled: false,
+            irq_pending: false,
+            play_driver: if nsf.flags.contains(Nsf2Flags::NON_RETURNING_INIT) {
+                let [low, high] = nsf.play_address.to_le_bytes();
+                Some([
+                    0x48, 0x8A, 0x48, 0x98, 0x48, 0x20, low, high, 0x68, 0xA8, 0x68, 0xAA, 0x68,
+                    0x40,
+                ])
+            } else {
+                None
+            },
+        };
+        mapper.reset();
+
+        Ok(mapper)
+    }
+
+    fn fds(&self) -> bool {
+        self.fds.is_some()
+    }
+
+    // PRG-RAM and the banks, without any registers
+    fn read_memory(&self, address: u16) -> u8 {
+        match address {
+            0x6000..=0xFFFF if self.fds() => self.memory.prg_ram[address as usize - 0x6000],
+            0x6000..=0x7FFF => self.memory.read_prg_ram(address),
+            0x8000..=0xFFFF => {
+                let bank = self.banks[(address as usize - 0x6000) / BANK_SIZE];
+                self.memory.read_prg_rom(bank as usize, BANK_SIZE, address)
+            }
+            _ => 0,
+        }
+    }
+
+    // The FDS has RAM where the banks go, so switching copies a bank in
+    fn switch_bank(&mut self, slot: usize, bank: u8) {
+        self.banks[slot] = bank;
+
+        if self.fds() {
+            let start = slot * BANK_SIZE;
+
+            for offset in 0..BANK_SIZE {
+                self.memory.prg_ram[start + offset] =
+                    self.memory
+                        .read_prg_rom(bank as usize, BANK_SIZE, offset as u16);
+            }
+        }
+    }
+}
+
+impl Mapper for NsfMapper {
+    fn read_prg(&mut self, address: u16) -> u8 {
+        if let Some(driver) = &self.play_driver {
+            let [low, high] = PLAY_DRIVER.to_le_bytes();
+
+            match address {
+                0xFFFA => return low,
+                0xFFFB => return high,
+                _ => {
+                    if let Some(&value) = driver.get(address.wrapping_sub(PLAY_DRIVER) as usize) {
+                        return value;
+                    }
+                }
+            }
+        }
+
+        if let (true, 0xFFFE..=0xFFFF) = (self.irq_support, address) {
+            return self.irq_vector[address as usize - 0xFFFE];
+        }
+
+        if let Some(fds) = &self.fds {
+            if let Some(value) = fds.read(address) {
+                return value;
+            }
+        }
+
+        // Only the MMC5 and Namco 163 have registers to read back, all below
+        // $6000
+        if address < 0x6000 {
+            if let Some((_, chip)) = self
+                .chips
+                .iter_mut()
+                .find(|(chip, _)| chip.decodes(address))
+            {
+                return chip.read_prg(address);
+            }
+        }
+
+        self.read_memory(address)
+    }
+
+    fn write_prg(&mut self, address: u16, value: u8) {
+        for (chip, mapper) in &mut self.chips {
+            if chip.decodes(address) {
+                mapper.write_prg(address, value);
+            }
+        }
+
+        if let Some(fds) = &mut self.fds {
+            fds.write(address, value);
+        }
+
+        match address {
+            0x401B if self.irq_support => {
+                self.irq_reload = (self.irq_reload & 0xFF00) | value as u16;
+            }
+            0x401C if self.irq_support => {
+                self.irq_reload = (self.irq_reload & 0x00FF) | (value as u16) << 8;
+            }
+            // Acknowledges the IRQ and restarts the timer
+            0x401D if self.irq_support => {
+                self.irq_enabled = value & 0x01 != 0;
+                self.irq_counter = self.irq_reload;
+                self.irq_pending = false;
+            }
+            0xFFFE..=0xFFFF if self.irq_support => {
+                self.irq_vector[address as usize - 0xFFFE] = value;
+            }
+            0x5FF6..=0x5FF7 if self.fds() => self.switch_bank(address as usize - 0x5FF6, value),
+            0x5FF8..=0x5FFF => self.switch_bank(address as usize - 0x5FF6, value),
+            0x6000..=0xDFFF if self.fds() => {
+                self.memory.prg_ram[address as usize - 0x6000] = value;
+            }
+            0x6000..=0x7FFF => self.memory.write_prg_ram(address, value),
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, _address: u16) -> u8 {
+        0
+    }
+
+    fn write_chr(&mut self, _address: u16, _value: u8) {}
+
+    fn mirroring(&self) -> Mirroring {
+        Mirroring::Horizontal
+    }
+
+    fn memory(&mut self) -> &mut Memory {
+        &mut self.memory
+    }
+
+    fn cpu_clock(&mut self) {
+        for (_, chip) in &mut self.chips {
+            chip.cpu_clock();
+        }
+
+        if let Some(fds) = &mut self.fds {
+            fds.clock();
+        }
+
+        if self.irq_enabled {
+            if self.irq_counter == 0 {
+                self.irq_counter = self.irq_reload;
+                self.irq_pending = true;
+            } else {
+                self.irq_counter -= 1;
+            }
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn audio_output(&self) -> f32 {
+        let chips: f32 = self.chips.iter().map(|(_, chip)| chip.audio_output()).sum();
+        let fds = self.fds.as_ref().map_or(0.0, |fds| fds.output());
+
+        chips + fds
+    }
+
+    // Back to the state INIT expects: the header's banks, cleared RAM and
+    // the MMC5's ExRAM usable as plain RAM
+    fn reset(&mut self) {
+        self.memory.prg_ram.fill(0);
+
+        for slot in 0..self.banks.len() {
+            if self.fds() || slot >= 2 {
+                self.switch_bank(slot, self.initial_banks[slot]);
+            }
+        }
+
+        for (chip, mapper) in &mut self.chips {
+            if *chip == Chip::Mmc5 {
+                mapper.write_prg(0x5104, 0x02);
+            }
+        }
+
+        if let Some(fds) = &mut self.fds {
+            *fds = FdsAudio::new();
+        }
+
+        // The vector starts out as whatever the tune's ROM has there
+        self.irq_vector = [self.read_memory(0xFFFE), self.read_memory(0xFFFF)];
+        self.irq_enabled = false;
+        self.irq_pending = false;
+    }
+}
@@ -0,0 +1,112 @@
+use std::mem;
+
+use super::{Flash, Header, Mapper, Memory, Mirroring};
+
+// UNROM 512 (mapper 30)
+pub struct Unrom512 {
+    memory: Memory,
+    flash: Flash,
+    flashable: bool,
+    prg_bank: usize,
+    chr_bank: usize,
+    mirroring: Mirroring,
+    one_screen: bool,
+}
+
+impl Unrom512 {
+    pub fn new(mut memory: Memory, header: &Header) -> Self {
+        let flash = Flash::new(mem::take(&mut memory.prg_rom));
+
+        // Old iNES headers can't describe the board's 32 KB of CHR-RAM
+        if memory.chr_ram && !header.nes2 {
+            memory.chr.resize(0x8000, 0);
+        }
+
+        // The four-screen bit on its own selects mapper-controlled one-screen
+        // mirroring, and together with the vertical bit real four-screen
+        let one_screen = header.mirroring == Mirroring::FourScreen
+            && header.hardwired_mirroring == Mirroring::Horizontal;
+        let mirroring = if one_screen {
+            Mirroring::SingleScreenLower
+        } else {
+            header.mirroring
+        };
+
+        Unrom512 {
+            memory,
+            flash,
+            // Only boards with the battery bit set can rewrite their flash
+            flashable: header.battery,
+            prg_bank: 0,
+            chr_bank: 0,
+            mirroring,
+            one_screen,
+        }
+    }
+
+    fn prg(&self, address: u16) -> u8 {
+        let bank = match address {
+            0x8000..=0xBFFF => self.prg_bank,
+            0xC000..=0xFFFF => (self.flash.len() / 0x4000).max(1) - 1,
+            _ => return 0,
+        };
+
+        self.flash.read(bank * 0x4000 + (address as usize & 0x3FFF))
+    }
+}
+
+impl Mapper for Unrom512 {
+    fn read_prg(&mut self, address: u16) -> u8 {
+        self.prg(address)
+    }
+
+    fn write_prg(&mut self, address: u16, mut value: u8) {
+        match address {
+            0x8000..=0xBFFF if self.flashable => {
+                let address = self.prg_bank * 0x4000 + (address as usize & 0x3FFF);
+                self.flash.write(address, value);
+            }
+            0x8000..=0xFFFF => {
+                if !self.flashable {
+                    value &= self.prg(address);
+                }
+
+                self.prg_bank = (value & 0x1F) as usize;
+                self.chr_bank = ((value >> 5) & 0x03) as usize;
+
+                if self.one_screen {
+                    self.mirroring = if value & 0x80 != 0 {
+                        Mirroring::SingleScreenUpper
+                    } else {
+                        Mirroring::SingleScreenLower
+                    };
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, address: u16) -> u8 {
+        self.memory.read_chr(self.chr_bank, 0x2000, address)
+    }
+
+    fn write_chr(&mut self, address: u16, value: u8) {
+        self.memory.write_chr(self.chr_bank, 0x2000, address, value);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn memory(&mut self) -> &mut Memory {
+        &mut self.memory
+    }
+
+    fn flash(&mut self) -> Option<&mut Flash> {
+        if self.flashable {
+            Some(&mut self.flash)
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use super::{Mapper, Memory, Mirroring};
+
+// UNROM, UOROM and compatible boards (mapper 2)
+pub struct Uxrom {
+    memory: Memory,
+    bank: usize,
+    bus_conflicts: bool,
+}
+
+impl Uxrom {
+    pub fn new(memory: Memory, submapper: u8) -> Self {
+        Uxrom {
+            memory,
+            bank: 0,
+            bus_conflicts: submapper == 2,
+        }
+    }
+
+    fn prg(&self, address: u16) -> u8 {
+        match address {
+            0x8000..=0xBFFF => self.memory.read_prg_rom(self.bank, 0x4000, address),
+            0xC000..=0xFFFF => {
+                let last = self.memory.prg_banks(0x4000) - 1;
+                self.memory.read_prg_rom(last, 0x4000, address)
+            }
+            _ => 0,
+        }
+    }
+}
+
+impl Mapper for Uxrom {
+    fn read_prg(&mut self, address: u16) -> u8 {
+        self.prg(address)
+    }
+
+    fn write_prg(&mut self, address: u16, mut value: u8) {
+        if address < 0x8000 {
+            return;
+        }
+
+        if self.bus_conflicts {
+            value &= self.prg(address);
+        }
+
+        self.bank = value as usize;
+    }
+
+    fn read_chr(&mut self, address: u16) -> u8 {
+        self.memory.read_chr(0, 0x2000, address)
+    }
+
+    fn write_chr(&mut self, address: u16, value: u8) {
+        self.memory.write_chr(0, 0x2000, address, value);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.memory.mirroring
+    }
+
+    fn memory(&mut self) -> &mut Memory {
+        &mut self.memory
+    }
+}
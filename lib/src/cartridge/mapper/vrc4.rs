@@ -0,0 +1,180 @@
+use super::vrc_irq::VrcIrq;
+use super::{Header, Mapper, Memory, Mirroring};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Chip {
+    Vrc2,
+    Vrc4,
+}
+
+// Konami VRC2 and VRC4 (mappers 21, 22, 23 and 25)
+pub struct Vrc4 {
+    memory: Memory,
+    chip: Chip,
+    // CPU address lines wired to the chip's A0 and A1 inputs
+    a0: u16,
+    a1: u16,
+    // VRC2a ignores the lowest bit of its CHR bank numbers
+    chr_shift: u8,
+    prg_banks: [usize; 2],
+    prg_swap: bool,
+    chr_banks: [usize; 8],
+    mirroring: Mirroring,
+    microwire: u8,
+    irq: VrcIrq,
+}
+
+impl Vrc4 {
+    pub fn new(mut memory: Memory, header: &Header) -> Self {
+        let mapper = header.mapper;
+        let (chip, a0, a1) = match (mapper, header.submapper) {
+            (21, 1) => (Chip::Vrc4, 0x02, 0x04),
+            (21, 2) => (Chip::Vrc4, 0x40, 0x80),
+            (21, _) => (Chip::Vrc4, 0x42, 0x84),
+            (22, _) => (Chip::Vrc2, 0x02, 0x01),
+            (23, 1) => (Chip::Vrc4, 0x01, 0x02),
+            (23, 2) => (Chip::Vrc4, 0x04, 0x08),
+            (23, 3) => (Chip::Vrc2, 0x01, 0x02),
+            (23, _) => (Chip::Vrc4, 0x05, 0x0A),
+            (25, 1) => (Chip::Vrc4, 0x02, 0x01),
+            (25, 2) => (Chip::Vrc4, 0x08, 0x04),
+            (25, 3) => (Chip::Vrc2, 0x02, 0x01),
+            (_, _) => (Chip::Vrc4, 0x0A, 0x05),
+        };
+
+        // Old iNES headers always get PRG-RAM, which would hide the latch.
+        // Only VRC2 boards with a battery have any.
+        if chip == Chip::Vrc2 && !header.nes2 && !header.battery {
+            memory.prg_ram.clear();
+        }
+
+        Vrc4 {
+            memory,
+            chip,
+            a0,
+            a1,
+            chr_shift: (mapper == 22) as u8,
+            prg_banks: [0, 1],
+            prg_swap: false,
+            chr_banks: [0; 8],
+            mirroring: Mirroring::Vertical,
+            microwire: 0,
+            irq: VrcIrq::default(),
+        }
+    }
+
+    fn register(&self, address: u16) -> u16 {
+        let a0 = (address & self.a0 != 0) as u16;
+        let a1 = (address & self.a1 != 0) as u16;
+
+        (address & 0xF000) | (a1 << 1) | a0
+    }
+
+    fn prg_bank(&self, address: u16) -> usize {
+        let second_last = self.memory.prg_banks(0x2000).saturating_sub(2);
+
+        match (address >> 13) & 0x03 {
+            0 if self.prg_swap => second_last,
+            0 => self.prg_banks[0],
+            1 => self.prg_banks[1],
+            2 if self.prg_swap => self.prg_banks[0],
+            2 => second_last,
+            _ => second_last + 1,
+        }
+    }
+
+    fn chr_bank(&self, address: u16) -> usize {
+        self.chr_banks[(address >> 10) as usize & 0x07] >> self.chr_shift
+    }
+}
+
+impl Mapper for Vrc4 {
+    fn read_prg(&mut self, address: u16) -> u8 {
+        match address {
+            0x6000..=0x7FFF if !self.memory.prg_ram.is_empty() => self.memory.read_prg_ram(address),
+            // Without RAM, the VRC2 exposes a one bit latch meant for an EEPROM
+            0x6000..=0x6FFF if self.chip == Chip::Vrc2 => self.microwire,
+            0x8000..=0xFFFF => {
+                let bank = self.prg_bank(address);
+                self.memory.read_prg_rom(bank, 0x2000, address)
+            }
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, address: u16, value: u8) {
+        if address < 0x8000 {
+            if self.memory.prg_ram.is_empty() {
+                if self.chip == Chip::Vrc2 && address < 0x7000 {
+                    self.microwire = value & 0x01;
+                }
+            } else if address >= 0x6000 {
+                self.memory.write_prg_ram(address, value);
+            }
+
+            return;
+        }
+
+        match (self.chip, self.register(address)) {
+            (_, 0x8000..=0x8003) => self.prg_banks[0] = (value & 0x1F) as usize,
+            (Chip::Vrc2, 0x9000..=0x9003) => {
+                self.mirroring = if value & 0x01 != 0 {
+                    Mirroring::Horizontal
+                } else {
+                    Mirroring::Vertical
+                };
+            }
+            (Chip::Vrc4, 0x9000..=0x9001) => {
+                self.mirroring = match value & 0x03 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreenLower,
+                    _ => Mirroring::SingleScreenUpper,
+                };
+            }
+            (Chip::Vrc4, 0x9002) => self.prg_swap = value & 0x02 != 0,
+            (_, 0xA000..=0xA003) => self.prg_banks[1] = (value & 0x1F) as usize,
+            (_, register @ 0xB000..=0xE003) => {
+                let index = (((register >> 12) - 0x0B) * 2 + ((register >> 1) & 0x01)) as usize;
+                let bank = self.chr_banks[index];
+
+                self.chr_banks[index] = if register & 0x01 == 0 {
+                    (bank & 0x1F0) | (value & 0x0F) as usize
+                } else {
+                    (bank & 0x00F) | ((value & 0x1F) as usize) << 4
+                };
+            }
+            (Chip::Vrc4, 0xF000) => self.irq.write_latch_low(value),
+            (Chip::Vrc4, 0xF001) => self.irq.write_latch_high(value),
+            (Chip::Vrc4, 0xF002) => self.irq.write_control(value),
+            (Chip::Vrc4, 0xF003) => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, address: u16) -> u8 {
+        let bank = self.chr_bank(address);
+        self.memory.read_chr(bank, 0x0400, address)
+    }
+
+    fn write_chr(&mut self, address: u16, value: u8) {
+        let bank = self.chr_bank(address);
+        self.memory.write_chr(bank, 0x0400, address, value);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn memory(&mut self) -> &mut Memory {
+        &mut self.memory
+    }
+
+    fn cpu_clock(&mut self) {
+        self.irq.clock();
+    }
+
+    fn irq(&self) -> bool {
+        self.irq.pending()
+    }
+}
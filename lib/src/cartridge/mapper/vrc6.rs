@@ -0,0 +1,253 @@
+use super::vrc_irq::VrcIrq;
+use super::{Mapper, Memory, Mirroring};
+
+// Output level of one step of the VRC6 channels, relative to the APU pulses
+const OUTPUT_STEP: f32 = 0.0086;
+
+#[derive(Default)]
+struct Pulse {
+    enabled: bool,
+    volume: u8,
+    duty: u8,
+    ignore_duty: bool,
+    period: u16,
+    counter: u16,
+    step: u8,
+}
+
+impl Pulse {
+    fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.ignore_duty = value & 0x80 != 0;
+                self.duty = (value >> 4) & 0x07;
+                self.volume = value & 0x0F;
+            }
+            1 => self.period = (self.period & 0x0F00) | value as u16,
+            _ => {
+                self.period = (self.period & 0x00FF) | ((value as u16 & 0x0F) << 8);
+                self.enabled = value & 0x80 != 0;
+
+                if !self.enabled {
+                    self.step = 0;
+                }
+            }
+        }
+    }
+
+    fn clock(&mut self, shift: u8) {
+        if !self.enabled {
+            return;
+        }
+
+        if self.counter == 0 {
+            self.counter = self.period >> shift;
+            self.step = (self.step + 1) & 0x0F;
+        } else {
+            self.counter -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.enabled && (self.ignore_duty || self.step <= self.duty) {
+            self.volume
+        } else {
+            0
+        }
+    }
+}
+
+#[derive(Default)]
+struct Sawtooth {
+    enabled: bool,
+    rate: u8,
+    period: u16,
+    counter: u16,
+    step: u8,
+    accumulator: u8,
+}
+
+impl Sawtooth {
+    fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => self.rate = value & 0x3F,
+            1 => self.period = (self.period & 0x0F00) | value as u16,
+            _ => {
+                self.period = (self.period & 0x00FF) | ((value as u16 & 0x0F) << 8);
+                self.enabled = value & 0x80 != 0;
+
+                if !self.enabled {
+                    self.step = 0;
+                    self.accumulator = 0;
+                }
+            }
+        }
+    }
+
+    fn clock(&mut self, shift: u8) {
+        if !self.enabled {
+            return;
+        }
+
+        if self.counter > 0 {
+            self.counter -= 1;
+            return;
+        }
+
+        self.counter = self.period >> shift;
+        self.step += 1;
+
+        // The accumulator grows on every other step and resets after seven
+        if self.step == 14 {
+            self.step = 0;
+            self.accumulator = 0;
+        } else if self.step & 0x01 == 0 {
+            self.accumulator = self.accumulator.wrapping_add(self.rate);
+        }
+    }
+
+    fn output(&self) -> u8 {
+        self.accumulator >> 3
+    }
+}
+
+// Konami VRC6 (mappers 24 and 26)
+pub struct Vrc6 {
+    memory: Memory,
+    // VRC6b swaps the A0 and A1 lines
+    swapped_lines: bool,
+    prg_banks: [usize; 2],
+    chr_banks: [usize; 8],
+    mirroring: Mirroring,
+    prg_ram_enabled: bool,
+    irq: VrcIrq,
+    pulses: [Pulse; 2],
+    sawtooth: Sawtooth,
+    halt: bool,
+    shift: u8,
+}
+
+impl Vrc6 {
+    pub fn new(memory: Memory, mapper: u16) -> Self {
+        Vrc6 {
+            memory,
+            swapped_lines: mapper == 26,
+            prg_banks: [0, 0],
+            chr_banks: [0; 8],
+            mirroring: Mirroring::Vertical,
+            prg_ram_enabled: false,
+            irq: VrcIrq::default(),
+            pulses: [Pulse::default(), Pulse::default()],
+            sawtooth: Sawtooth::default(),
+            halt: false,
+            shift: 0,
+        }
+    }
+
+    fn register(&self, address: u16) -> u16 {
+        let lines = address & 0x03;
+
+        if self.swapped_lines {
+            (address & 0xF000) | ((lines & 0x01) << 1) | (lines >> 1)
+        } else {
+            (address & 0xF000) | lines
+        }
+    }
+}
+
+impl Mapper for Vrc6 {
+    fn read_prg(&mut self, address: u16) -> u8 {
+        match address {
+            0x6000..=0x7FFF if self.prg_ram_enabled => self.memory.read_prg_ram(address),
+            0x8000..=0xBFFF => self.memory.read_prg_rom(self.prg_banks[0], 0x4000, address),
+            0xC000..=0xDFFF => self.memory.read_prg_rom(self.prg_banks[1], 0x2000, address),
+            0xE000..=0xFFFF => {
+                let last = self.memory.prg_banks(0x2000) - 1;
+                self.memory.read_prg_rom(last, 0x2000, address)
+            }
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, address: u16, value: u8) {
+        if address < 0x8000 {
+            if self.prg_ram_enabled && address >= 0x6000 {
+                self.memory.write_prg_ram(address, value);
+            }
+
+            return;
+        }
+
+        match self.register(address) {
+            0x8000..=0x8003 => self.prg_banks[0] = (value & 0x0F) as usize,
+            register @ 0x9000..=0x9002 => self.pulses[0].write(register & 0x03, value),
+            0x9003 => {
+                self.halt = value & 0x01 != 0;
+                self.shift = if value & 0x04 != 0 {
+                    8
+                } else if value & 0x02 != 0 {
+                    4
+                } else {
+                    0
+                };
+            }
+            register @ 0xA000..=0xA002 => self.pulses[1].write(register & 0x03, value),
+            register @ 0xB000..=0xB002 => self.sawtooth.write(register & 0x03, value),
+            0xB003 => {
+                self.prg_ram_enabled = value & 0x80 != 0;
+                self.mirroring = match (value >> 2) & 0x03 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreenLower,
+                    _ => Mirroring::SingleScreenUpper,
+                };
+            }
+            0xC000..=0xC003 => self.prg_banks[1] = (value & 0x1F) as usize,
+            register @ 0xD000..=0xE003 => {
+                let index = (((register >> 12) - 0x0D) * 4 + (register & 0x03)) as usize;
+                self.chr_banks[index] = value as usize;
+            }
+            0xF000 => self.irq.write_latch(value),
+            0xF001 => self.irq.write_control(value),
+            0xF002 => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, address: u16) -> u8 {
+        let bank = self.chr_banks[(address >> 10) as usize & 0x07];
+        self.memory.read_chr(bank, 0x0400, address)
+    }
+
+    fn write_chr(&mut self, address: u16, value: u8) {
+        let bank = self.chr_banks[(address >> 10) as usize & 0x07];
+        self.memory.write_chr(bank, 0x0400, address, value);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn memory(&mut self) -> &mut Memory {
+        &mut self.memory
+    }
+
+    fn cpu_clock(&mut self) {
+        self.irq.clock();
+
+        if !self.halt {
+            self.pulses[0].clock(self.shift);
+            self.pulses[1].clock(self.shift);
+            self.sawtooth.clock(self.shift);
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.irq.pending()
+    }
+
+    fn audio_output(&self) -> f32 {
+        let sum = self.pulses[0].output() + self.pulses[1].output() + self.sawtooth.output();
+        sum as f32 * OUTPUT_STEP
+    }
+}
@@ -0,0 +1,500 @@
+use std::f32::consts::TAU;
+
+use super::vrc_irq::VrcIrq;
+use super::{Mapper, Memory, Mirroring};
+
+// The FM core produces one sample every 36 CPU cycles (~49.7 kHz)
+const SAMPLE_CYCLES: u8 = 36;
+const SAMPLE_RATE: f32 = 1_789_773.0 / SAMPLE_CYCLES as f32;
+
+const CHANNELS: usize = 6;
+const SILENCE: f32 = 96.0;
+
+// Built-in instruments 1-15, as dumped from the chip
+const PATCHES: [[u8; 8]; 15] = [
+    [0x03, 0x21, 0x05, 0x06, 0xE8, 0x81, 0x42, 0x27],
+    [0x13, 0x41, 0x14, 0x0D, 0xD8, 0xF6, 0x23, 0x12],
+    [0x11, 0x11, 0x08, 0x08, 0xFA, 0xB2, 0x20, 0x12],
+    [0x31, 0x61, 0x0C, 0x07, 0xA8, 0x64, 0x61, 0x27],
+    [0x32, 0x21, 0x1E, 0x06, 0xE1, 0x76, 0x01, 0x28],
+    [0x02, 0x01, 0x06, 0x00, 0xA3, 0xE2, 0xF4, 0xF4],
+    [0x21, 0x61, 0x1D, 0x07, 0x82, 0x81, 0x11, 0x07],
+    [0x23, 0x21, 0x22, 0x17, 0xA2, 0x72, 0x01, 0x17],
+    [0x35, 0x11, 0x25, 0x00, 0x40, 0x73, 0x72, 0x01],
+    [0xB5, 0x01, 0x0F, 0x0F, 0xA8, 0xA5, 0x51, 0x02],
+    [0x17, 0xC1, 0x24, 0x07, 0xF8, 0xF8, 0x22, 0x12],
+    [0x71, 0x23, 0x11, 0x06, 0x65, 0x74, 0x18, 0x16],
+    [0x01, 0x02, 0xD3, 0x05, 0xC9, 0x95, 0x03, 0x02],
+    [0x61, 0x63, 0x0C, 0x00, 0x94, 0xC0, 0x33, 0xF6],
+    [0x21, 0x72, 0x0D, 0x00, 0xC1, 0xD5, 0x56, 0x06],
+];
+
+const MULTIPLIERS: [f32; 16] = [
+    0.5, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0, 10.0, 12.0, 12.0, 15.0, 15.0,
+];
+
+// Time in milliseconds for a full 96 dB sweep at rate 1. Each step of the
+// effective rate (4 per rate, plus key scaling) shortens it by 2^(1/4).
+const ATTACK_TIME: f32 = 3460.0;
+const DECAY_TIME: f32 = 41852.0;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Envelope {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+    Off,
+}
+
+// One half of an instrument, as laid out in the patch registers
+#[derive(Clone, Copy)]
+struct OperatorPatch {
+    tremolo: bool,
+    vibrato: bool,
+    sustained: bool,
+    key_scale_rate: bool,
+    multiplier: f32,
+    rectified: bool,
+    attack: u8,
+    decay: u8,
+    sustain_level: f32,
+    release: u8,
+}
+
+impl OperatorPatch {
+    fn new(patch: &[u8; 8], carrier: bool) -> Self {
+        let index = carrier as usize;
+        let flags = patch[index];
+        let rates = patch[4 + index];
+        let levels = patch[6 + index];
+
+        OperatorPatch {
+            tremolo: flags & 0x80 != 0,
+            vibrato: flags & 0x40 != 0,
+            sustained: flags & 0x20 != 0,
+            key_scale_rate: flags & 0x10 != 0,
+            multiplier: MULTIPLIERS[(flags & 0x0F) as usize],
+            rectified: patch[3] & (0x08 << index) != 0,
+            attack: rates >> 4,
+            decay: rates & 0x0F,
+            sustain_level: (levels >> 4) as f32 * 3.0,
+            release: levels & 0x0F,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Operator {
+    phase: f32,
+    envelope: Envelope,
+    attenuation: f32,
+    output: f32,
+}
+
+impl Default for Operator {
+    fn default() -> Self {
+        Operator {
+            phase: 0.0,
+            envelope: Envelope::Off,
+            attenuation: SILENCE,
+            output: 0.0,
+        }
+    }
+}
+
+impl Operator {
+    fn key_on(&mut self) {
+        self.phase = 0.0;
+        self.envelope = Envelope::Attack;
+    }
+
+    fn key_off(&mut self) {
+        if self.envelope != Envelope::Off {
+            self.envelope = Envelope::Release;
+        }
+    }
+
+    // Change in attenuation per sample for a rate, in dB
+    fn step(rate: u8, key_scale: u8, time: f32) -> f32 {
+        if rate == 0 {
+            return 0.0;
+        }
+
+        let effective = (rate * 4 + key_scale).min(63) as f32;
+        let milliseconds = time * 2f32.powf(-(effective - 4.0) / 4.0);
+
+        SILENCE / (milliseconds / 1000.0 * SAMPLE_RATE).max(1.0)
+    }
+
+    fn clock_envelope(&mut self, patch: &OperatorPatch, key_scale: u8, channel_sustain: bool) {
+        let key_scale = if patch.key_scale_rate {
+            key_scale
+        } else {
+            key_scale >> 2
+        };
+
+        match self.envelope {
+            Envelope::Attack => {
+                if patch.attack == 15 {
+                    self.attenuation = 0.0;
+                } else {
+                    self.attenuation -= Self::step(patch.attack, key_scale, ATTACK_TIME);
+                }
+
+                if self.attenuation <= 0.0 {
+                    self.attenuation = 0.0;
+                    self.envelope = Envelope::Decay;
+                }
+            }
+            Envelope::Decay => {
+                self.attenuation += Self::step(patch.decay, key_scale, DECAY_TIME);
+
+                if self.attenuation >= patch.sustain_level {
+                    self.attenuation = patch.sustain_level;
+                    self.envelope = Envelope::Sustain;
+                }
+            }
+            Envelope::Sustain => {
+                // Percussive instruments keep decaying at the release rate
+                if !patch.sustained {
+                    self.attenuation += Self::step(patch.release, key_scale, DECAY_TIME);
+                }
+            }
+            Envelope::Release => {
+                let rate = if channel_sustain {
+                    5
+                } else if patch.sustained {
+                    patch.release
+                } else {
+                    7
+                };
+
+                self.attenuation += Self::step(rate, key_scale, DECAY_TIME);
+            }
+            Envelope::Off => {}
+        }
+
+        if self.attenuation >= SILENCE {
+            self.attenuation = SILENCE;
+
+            if self.envelope != Envelope::Attack {
+                self.envelope = Envelope::Off;
+            }
+        }
+    }
+
+    fn generate(
+        &mut self,
+        patch: &OperatorPatch,
+        increment: f32,
+        level: f32,
+        modulation: f32,
+    ) -> f32 {
+        self.phase = (self.phase + increment * patch.multiplier).fract();
+
+        let mut wave = (TAU * (self.phase + modulation)).sin();
+
+        if patch.rectified && wave < 0.0 {
+            wave = 0.0;
+        }
+
+        self.output = wave * 10f32.powf(-(self.attenuation + level) / 20.0);
+        self.output
+    }
+}
+
+#[derive(Default, Clone, Copy)]
+struct Channel {
+    fnum: u16,
+    block: u8,
+    key: bool,
+    sustain: bool,
+    instrument: u8,
+    volume: u8,
+    modulator: Operator,
+    carrier: Operator,
+    feedback: [f32; 2],
+}
+
+// The VRC7's cut-down YM2413 (OPLL): six two-operator FM channels
+struct Opll {
+    address: u8,
+    custom: [u8; 8],
+    channels: [Channel; CHANNELS],
+    lfo: f32,
+}
+
+impl Opll {
+    fn new() -> Self {
+        Opll {
+            address: 0,
+            custom: [0; 8],
+            channels: [Channel::default(); CHANNELS],
+            lfo: 0.0,
+        }
+    }
+
+    fn write(&mut self, value: u8) {
+        let register = self.address;
+        let index = (register & 0x0F) as usize;
+
+        match register {
+            0x00..=0x07 => self.custom[index] = value,
+            0x10..=0x15 => {
+                let channel = &mut self.channels[index];
+                channel.fnum = (channel.fnum & 0x100) | value as u16;
+            }
+            0x20..=0x25 => {
+                let channel = &mut self.channels[index];
+                let key = value & 0x10 != 0;
+
+                channel.fnum = (channel.fnum & 0xFF) | ((value as u16 & 0x01) << 8);
+                channel.block = (value >> 1) & 0x07;
+                channel.sustain = value & 0x20 != 0;
+
+                if key && !channel.key {
+                    channel.modulator.key_on();
+                    channel.carrier.key_on();
+                } else if !key && channel.key {
+                    channel.modulator.key_off();
+                    channel.carrier.key_off();
+                }
+
+                channel.key = key;
+            }
+            0x30..=0x35 => {
+                let channel = &mut self.channels[index];
+                channel.instrument = value >> 4;
+                channel.volume = value & 0x0F;
+            }
+            _ => {}
+        }
+    }
+
+    fn patch(&self, instrument: u8) -> [u8; 8] {
+        match instrument {
+            0 => self.custom,
+            _ => PATCHES[instrument as usize - 1],
+        }
+    }
+
+    fn sample(&mut self) -> f32 {
+        // Tremolo at ~3.7 Hz with 4.8 dB depth, vibrato at ~6.4 Hz
+        self.lfo = (self.lfo + 1.0 / SAMPLE_RATE).fract();
+        let tremolo = (1.0 + (TAU * 3.7 * self.lfo).sin()) * 2.4;
+        let vibrato = 1.0 + (TAU * 6.4 * self.lfo).sin() * 0.004;
+
+        let mut output = 0.0;
+
+        for index in 0..CHANNELS {
+            let patch = self.patch(self.channels[index].instrument);
+            let modulator_patch = OperatorPatch::new(&patch, false);
+            let carrier_patch = OperatorPatch::new(&patch, true);
+            let channel = &mut self.channels[index];
+
+            let key_scale = (channel.block << 1) | (channel.fnum >> 8) as u8;
+            let increment = channel.fnum as f32 * (1 << channel.block) as f32 / (1 << 19) as f32;
+
+            channel
+                .modulator
+                .clock_envelope(&modulator_patch, key_scale, channel.sustain);
+            channel
+                .carrier
+                .clock_envelope(&carrier_patch, key_scale, channel.sustain);
+
+            if channel.carrier.envelope == Envelope::Off {
+                continue;
+            }
+
+            let feedback = match patch[3] & 0x07 {
+                0 => 0.0,
+                level => {
+                    (channel.feedback[0] + channel.feedback[1]) / 2.0 * (1 << (level - 1)) as f32
+                        / 32.0
+                }
+            };
+
+            let operator_increment = |patch: &OperatorPatch| {
+                if patch.vibrato {
+                    increment * vibrato
+                } else {
+                    increment
+                }
+            };
+            let operator_level = |patch: &OperatorPatch, level: f32| {
+                if patch.tremolo {
+                    level + tremolo
+                } else {
+                    level
+                }
+            };
+
+            let modulator_level = operator_level(&modulator_patch, (patch[2] & 0x3F) as f32 * 0.75);
+            let modulation = channel.modulator.generate(
+                &modulator_patch,
+                operator_increment(&modulator_patch),
+                modulator_level,
+                feedback,
+            );
+            channel.feedback = [channel.feedback[1], modulation];
+
+            let carrier_level = operator_level(&carrier_patch, channel.volume as f32 * 3.0);
+            output += channel.carrier.generate(
+                &carrier_patch,
+                operator_increment(&carrier_patch),
+                carrier_level,
+                modulation,
+            );
+        }
+
+        output / CHANNELS as f32
+    }
+}
+
+// Konami VRC7 (mapper 85)
+pub struct Vrc7 {
+    memory: Memory,
+    // CPU address line wired to the chip's A4 input
+    a4: u16,
+    prg_banks: [usize; 3],
+    chr_banks: [usize; 8],
+    mirroring: Mirroring,
+    prg_ram_enabled: bool,
+    irq: VrcIrq,
+    opll: Opll,
+    silenced: bool,
+    sample_cycles: u8,
+    output: f32,
+}
+
+impl Vrc7 {
+    pub fn new(memory: Memory, submapper: u8) -> Self {
+        let a4 = match submapper {
+            1 => 0x08,
+            2 => 0x10,
+            _ => 0x18,
+        };
+
+        Vrc7 {
+            memory,
+            a4,
+            prg_banks: [0; 3],
+            chr_banks: [0; 8],
+            mirroring: Mirroring::Vertical,
+            prg_ram_enabled: false,
+            irq: VrcIrq::default(),
+            opll: Opll::new(),
+            silenced: false,
+            sample_cycles: 0,
+            output: 0.0,
+        }
+    }
+}
+
+impl Mapper for Vrc7 {
+    fn read_prg(&mut self, address: u16) -> u8 {
+        match address {
+            0x6000..=0x7FFF if self.prg_ram_enabled => self.memory.read_prg_ram(address),
+            0x8000..=0xDFFF => {
+                let bank = self.prg_banks[((address - 0x8000) >> 13) as usize];
+                self.memory.read_prg_rom(bank, 0x2000, address)
+            }
+            0xE000..=0xFFFF => {
+                let last = self.memory.prg_banks(0x2000) - 1;
+                self.memory.read_prg_rom(last, 0x2000, address)
+            }
+            _ => 0,
+        }
+    }
+
+    fn write_prg(&mut self, address: u16, value: u8) {
+        if address < 0x8000 {
+            if self.prg_ram_enabled && address >= 0x6000 {
+                self.memory.write_prg_ram(address, value);
+            }
+
+            return;
+        }
+
+        // The audio ports are decoded from fixed address lines on every board
+        match address & 0xF030 {
+            0x9010 => {
+                self.opll.address = value;
+                return;
+            }
+            0x9030 => {
+                self.opll.write(value);
+                return;
+            }
+            _ => {}
+        }
+
+        let register = (address & 0xF000) | if address & self.a4 != 0 { 0x10 } else { 0 };
+
+        match register {
+            0x8000 => self.prg_banks[0] = (value & 0x3F) as usize,
+            0x8010 => self.prg_banks[1] = (value & 0x3F) as usize,
+            0x9000 => self.prg_banks[2] = (value & 0x3F) as usize,
+            0xA000..=0xD010 => {
+                let index = (((register >> 12) - 0x0A) * 2 + (register >> 4 & 0x01)) as usize;
+                self.chr_banks[index] = value as usize;
+            }
+            0xE000 => {
+                self.mirroring = match value & 0x03 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreenLower,
+                    _ => Mirroring::SingleScreenUpper,
+                };
+                self.silenced = value & 0x40 != 0;
+                self.prg_ram_enabled = value & 0x80 != 0;
+            }
+            0xE010 => self.irq.write_latch(value),
+            0xF000 => self.irq.write_control(value),
+            0xF010 => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+
+    fn read_chr(&mut self, address: u16) -> u8 {
+        let bank = self.chr_banks[(address >> 10) as usize & 0x07];
+        self.memory.read_chr(bank, 0x0400, address)
+    }
+
+    fn write_chr(&mut self, address: u16, value: u8) {
+        let bank = self.chr_banks[(address >> 10) as usize & 0x07];
+        self.memory.write_chr(bank, 0x0400, address, value);
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn memory(&mut self) -> &mut Memory {
+        &mut self.memory
+    }
+
+    fn cpu_clock(&mut self) {
+        self.irq.clock();
+
+        self.sample_cycles += 1;
+
+        if self.sample_cycles == SAMPLE_CYCLES {
+            self.sample_cycles = 0;
+            self.output = self.opll.sample();
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.irq.pending()
+    }
+
+    fn audio_output(&self) -> f32 {
+        if self.silenced {
+            0.0
+        } else {
+            self.output
+        }
+    }
+}
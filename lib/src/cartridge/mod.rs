@@ -0,0 +1,794 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use database::Database;
+use hash::RomHash;
+use mapper::{Disk, Fds, Mapper, Memory, NsfMapper};
+use patch::PatchError;
+
+use crate::nsf::Nsf;
+use crate::zip::{self, ZipError, ZIP_MAGIC};
+
+pub mod database;
+pub mod hash;
+pub mod mapper;
+pub mod patch;
+mod unif;
+
+const INES_MAGIC: [u8; 4] = [b'N', b'E', b'S', 0x1A];
+const HEADER_SIZE: usize = 16;
+const TRAINER_SIZE: usize = 512;
+const PRG_ROM_PAGE_SIZE: usize = 0x4000;
+const CHR_ROM_PAGE_SIZE: usize = 0x2000;
+const FDS_BIOS_SIZE: usize = 0x2000;
+
+// How often `autosave` writes changed saves to disk
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mirroring {
+    Horizontal,
+    Vertical,
+    SingleScreenLower,
+    SingleScreenUpper,
+    FourScreen,
+}
+
+impl Mirroring {
+    // Maps a nametable address onto the console's 2 KB of nametable RAM
+    pub fn ciram_index(self, address: u16) -> usize {
+        let table = (address as usize >> 10) & 0x03;
+        let page = match self {
+            Mirroring::Horizontal => table >> 1,
+            Mirroring::Vertical | Mirroring::FourScreen => table & 0x01,
+            Mirroring::SingleScreenLower => 0,
+            Mirroring::SingleScreenUpper => 1,
+        };
+
+        page * 0x0400 + (address as usize & 0x03FF)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Region {
+    Ntsc,
+    Pal,
+    // Runs on either, e.g. by checking the timing at startup
+    Multiple,
+    Dendy,
+}
+
+// Input device the game expects, from the NES 2.0 expansion device field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputDevice {
+    Unspecified,
+    StandardControllers,
+    FourScore,
+    FamicomFourPlayers,
+    Zapper,
+    PowerPad,
+    ArkanoidNes,
+    ArkanoidFamicom,
+    FamilyBasicKeyboard,
+    Other(u8),
+}
+
+impl InputDevice {
+    fn from_nes2(value: u8) -> Self {
+        match value {
+            0x00 => InputDevice::Unspecified,
+            0x01 => InputDevice::StandardControllers,
+            0x02 => InputDevice::FourScore,
+            0x03 => InputDevice::FamicomFourPlayers,
+            0x08 => InputDevice::Zapper,
+            0x0B => InputDevice::PowerPad,
+            0x0F => InputDevice::ArkanoidNes,
+            0x10 => InputDevice::ArkanoidFamicom,
+            0x23 => InputDevice::FamilyBasicKeyboard,
+            value => InputDevice::Other(value),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum CartridgeError {
+    InvalidHeader,
+    Truncated,
+    UnsupportedMapper(u16),
+    UnsupportedBoard(String),
+    InvalidBios,
+    MissingBios,
+    Patch(PatchError),
+    Zip(ZipError),
+    Io(io::Error),
+}
+
+impl fmt::Display for CartridgeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CartridgeError::InvalidHeader => write!(f, "invalid ROM header"),
+            CartridgeError::Truncated => write!(f, "ROM data is shorter than the header claims"),
+            CartridgeError::UnsupportedMapper(mapper) => {
+                write!(f, "unsupported mapper: {}", mapper)
+            }
+            CartridgeError::UnsupportedBoard(board) => write!(f, "unsupported board: {}", board),
+            CartridgeError::InvalidBios => write!(f, "the FDS BIOS must be 8 KB"),
+            CartridgeError::MissingBios => write!(f, "disk images need the FDS BIOS"),
+            CartridgeError::Patch(error) => write!(f, "{}", error),
+            CartridgeError::Zip(error) => write!(f, "{}", error),
+            CartridgeError::Io(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for CartridgeError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    pub mapper: u16,
+    pub submapper: u8,
+    pub mirroring: Mirroring,
+    // The layout selected by the vertical bit, even when four-screen
+    // overrides it. Some boards give the combination its own meaning.
+    pub hardwired_mirroring: Mirroring,
+    pub battery: bool,
+    pub trainer: bool,
+    pub nes2: bool,
+    pub prg_rom_size: usize,
+    pub chr_rom_size: usize,
+    pub prg_ram_size: usize,
+    pub chr_ram_size: usize,
+    pub region: Region,
+    pub input_device: InputDevice,
+}
+
+impl Header {
+    pub fn parse(data: &[u8]) -> Result<Self, CartridgeError> {
+        if data.len() < HEADER_SIZE || data[0..4] != INES_MAGIC {
+            return Err(CartridgeError::InvalidHeader);
+        }
+
+        let flags6 = data[6];
+        let flags7 = data[7];
+        let nes2 = flags7 & 0x0C == 0x08;
+
+        let hardwired_mirroring = if flags6 & 0x01 != 0 {
+            Mirroring::Vertical
+        } else {
+            Mirroring::Horizontal
+        };
+        let mirroring = if flags6 & 0x08 != 0 {
+            Mirroring::FourScreen
+        } else {
+            hardwired_mirroring
+        };
+
+        let mut mapper = ((flags7 & 0xF0) | (flags6 >> 4)) as u16;
+        let mut submapper = 0;
+        let mut prg_rom_size = data[4] as usize * PRG_ROM_PAGE_SIZE;
+        let mut chr_rom_size = data[5] as usize * CHR_ROM_PAGE_SIZE;
+        let prg_ram_size;
+        let chr_ram_size;
+        let region;
+        let input_device;
+
+        if nes2 {
+            mapper |= ((data[8] & 0x0F) as u16) << 8;
+            submapper = data[8] >> 4;
+            prg_rom_size = nes2_rom_size(data[4], data[9] & 0x0F, PRG_ROM_PAGE_SIZE)
+                .ok_or(CartridgeError::InvalidHeader)?;
+            chr_rom_size = nes2_rom_size(data[5], data[9] >> 4, CHR_ROM_PAGE_SIZE)
+                .ok_or(CartridgeError::InvalidHeader)?;
+            prg_ram_size = nes2_ram_size(data[10] & 0x0F) + nes2_ram_size(data[10] >> 4);
+            chr_ram_size = nes2_ram_size(data[11] & 0x0F) + nes2_ram_size(data[11] >> 4);
+            region = match data[12] & 0x03 {
+                0 => Region::Ntsc,
+                1 => Region::Pal,
+                2 => Region::Multiple,
+                _ => Region::Dendy,
+            };
+            input_device = InputDevice::from_nes2(data[15] & 0x3F);
+        } else {
+            prg_ram_size = data[8].max(1) as usize * 0x2000;
+            chr_ram_size = if chr_rom_size == 0 { 0x2000 } else { 0 };
+            region = if data[9] & 0x01 != 0 {
+                Region::Pal
+            } else {
+                Region::Ntsc
+            };
+            input_device = InputDevice::Unspecified;
+        }
+
+        Ok(Header {
+            mapper,
+            submapper,
+            mirroring,
+            hardwired_mirroring,
+            battery: flags6 & 0x02 != 0,
+            trainer: flags6 & 0x04 != 0,
+            nes2,
+            prg_rom_size,
+            chr_rom_size,
+            prg_ram_size,
+            chr_ram_size,
+            region,
+            input_device,
+        })
+    }
+}
+
+// None for sizes too big to address
+fn nes2_rom_size(lsb: u8, msb: u8, page_size: usize) -> Option<usize> {
+    if msb == 0x0F {
+        // Exponent-multiplier notation: 2^E * (MM * 2 + 1)
+        let exponent = (lsb >> 2) as u32;
+        let multiplier = (lsb & 0x03) as usize * 2 + 1;
+        1usize
+            .checked_shl(exponent)
+            .and_then(|size| size.checked_mul(multiplier))
+    } else {
+        (((msb as usize) << 8) | lsb as usize).checked_mul(page_size)
+    }
+}
+
+fn nes2_ram_size(shift: u8) -> usize {
+    if shift == 0 {
+        0
+    } else {
+        64 << shift
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct OpenOptions {
+    // An IPS, BPS or UPS patch to apply before the ROM is parsed
+    pub patch: Option<PathBuf>,
+    // Applies a patch named like the ROM, e.g. game.ips for game.nes, when
+    // `patch` isn't given
+    pub auto_patch: bool,
+    // Which file to take from a zip archive holding several ROMs
+    pub entry: Option<String>,
+    // Lets disk images be opened along with cartridges
+    pub fds_bios: Option<Vec<u8>>,
+}
+
+// Reads a ROM file, or the ROM inside it if it's a zip archive. Returns the
+// name of the file the data came from, which is the archive entry's for zips.
+pub fn read_rom_file<P: AsRef<Path>>(
+    path: P,
+    entry: Option<&str>,
+) -> Result<(String, Vec<u8>), CartridgeError> {
+    let data = fs::read(&path).map_err(CartridgeError::Io)?;
+
+    if data.starts_with(&ZIP_MAGIC) {
+        return zip::extract_rom(&data, entry).map_err(CartridgeError::Zip);
+    }
+
+    let name = path
+        .as_ref()
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    Ok((name, data))
+}
+
+fn is_disk_image(name: &str) -> bool {
+    let extension = Path::new(name)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_ascii_lowercase());
+
+    matches!(extension.as_deref(), Some("fds" | "qd"))
+}
+
+pub struct Cartridge {
+    pub header: Header,
+    pub hash: RomHash,
+    mapper: Box<dyn Mapper>,
+    // Four-screen boards carry their own nametable RAM and ignore the console's
+    four_screen_ram: [u8; 0x1000],
+    // Where battery-backed RAM or rewritten flash is kept between sessions
+    save_path: Option<PathBuf>,
+    last_flush: Instant,
+}
+
+impl Cartridge {
+    // Loads an iNES or UNIF image, going by its magic number
+    pub fn load(data: &[u8]) -> Result<Self, CartridgeError> {
+        if data.starts_with(&unif::UNIF_MAGIC) {
+            Cartridge::from_unif(data)
+        } else {
+            Cartridge::from_ines(data)
+        }
+    }
+
+    pub fn from_ines(data: &[u8]) -> Result<Self, CartridgeError> {
+        Cartridge::from_ines_with_database(data, Database::builtin())
+    }
+
+    // Loads an iNES image, correcting its header from `database` if the dump
+    // is listed there
+    pub fn from_ines_with_database(
+        data: &[u8],
+        database: &Database,
+    ) -> Result<Self, CartridgeError> {
+        let header = Header::parse(data)?;
+
+        let prg_start = HEADER_SIZE + if header.trainer { TRAINER_SIZE } else { 0 };
+        let chr_start = prg_start
+            .checked_add(header.prg_rom_size)
+            .ok_or(CartridgeError::Truncated)?;
+        let chr_end = chr_start
+            .checked_add(header.chr_rom_size)
+            .ok_or(CartridgeError::Truncated)?;
+
+        if data.len() < chr_end {
+            return Err(CartridgeError::Truncated);
+        }
+
+        Cartridge::from_parts(
+            header,
+            data[prg_start..chr_start].to_vec(),
+            data[chr_start..chr_end].to_vec(),
+            database,
+        )
+    }
+
+    pub fn from_unif(data: &[u8]) -> Result<Self, CartridgeError> {
+        Cartridge::from_unif_with_database(data, Database::builtin())
+    }
+
+    pub fn from_unif_with_database(
+        data: &[u8],
+        database: &Database,
+    ) -> Result<Self, CartridgeError> {
+        let (header, prg_rom, chr_rom) = unif::parse(data)?;
+        Cartridge::from_parts(header, prg_rom, chr_rom, database)
+    }
+
+    fn from_parts(
+        mut header: Header,
+        prg_rom: Vec<u8>,
+        chr_rom: Vec<u8>,
+        database: &Database,
+    ) -> Result<Self, CartridgeError> {
+        let hash = RomHash::new(&[prg_rom.as_slice(), chr_rom.as_slice()].concat());
+
+        if let Some(correction) = database.lookup(&hash) {
+            correction.apply(&mut header);
+        }
+
+        let memory = Memory::new(prg_rom, chr_rom, &header);
+        let mapper = mapper::create(&header, memory)?;
+
+        Ok(Cartridge::new(header, hash, mapper))
+    }
+
+    // Loads a .fds or .qd disk image into a Famicom Disk System, with the
+    // side A of the first disk inserted. The BIOS isn't ours to ship.
+    pub fn from_fds(data: &[u8], bios: &[u8]) -> Result<Self, CartridgeError> {
+        if bios.len() != FDS_BIOS_SIZE {
+            return Err(CartridgeError::InvalidBios);
+        }
+
+        let disk = Disk::parse(data)?;
+
+        // Mapper 20 is reserved for the FDS
+        let header = Header {
+            mapper: 20,
+            submapper: 0,
+            mirroring: Mirroring::Horizontal,
+            hardwired_mirroring: Mirroring::Horizontal,
+            battery: false,
+            trainer: false,
+            nes2: false,
+            prg_rom_size: FDS_BIOS_SIZE,
+            chr_rom_size: 0,
+            prg_ram_size: 0x8000,
+            chr_ram_size: 0x2000,
+            region: Region::Ntsc,
+            input_device: InputDevice::Unspecified,
+        };
+        let memory = Memory::new(bios.to_vec(), Vec::new(), &header);
+
+        Ok(Cartridge::new(
+            header,
+            RomHash::new(data),
+            Box::new(Fds::new(memory, disk)),
+        ))
+    }
+
+    // Builds the board an NSF player runs on. NSF files have no mapper
+    // number, so the header only describes the memory.
+    pub fn from_nsf(nsf: &Nsf) -> Result<Self, CartridgeError> {
+        let header = Header {
+            mapper: 0,
+            submapper: 0,
+            mirroring: Mirroring::Horizontal,
+            hardwired_mirroring: Mirroring::Horizontal,
+            battery: false,
+            trainer: false,
+            nes2: false,
+            prg_rom_size: nsf.data.len(),
+            chr_rom_size: 0,
+            prg_ram_size: 0x2000,
+            chr_ram_size: 0x2000,
+            region: nsf.region,
+            input_device: InputDevice::Unspecified,
+        };
+        let mapper = NsfMapper::new(nsf, &header)?;
+
+        Ok(Cartridge::new(
+            header,
+            RomHash::new(&nsf.data),
+            Box::new(mapper),
+        ))
+    }
+
+    fn new(header: Header, hash: RomHash, mapper: Box<dyn Mapper>) -> Self {
+        Cartridge {
+            header,
+            hash,
+            mapper,
+            four_screen_ram: [0; 0x1000],
+            save_path: None,
+            last_flush: Instant::now(),
+        }
+    }
+
+    // Loads a ROM file, along with the `.sav` file next to it if the board
+    // keeps a save
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, CartridgeError> {
+        Cartridge::open_with(path, &OpenOptions::default())
+    }
+
+    // Loads a ROM file, or a zipped one, patched first if `options` asks for
+    // it. Disk images need `options` to have the FDS BIOS.
+    pub fn open_with<P: AsRef<Path>>(
+        path: P,
+        options: &OpenOptions,
+    ) -> Result<Self, CartridgeError> {
+        let (name, mut data) = read_rom_file(&path, options.entry.as_deref())?;
+
+        let patch_path = match &options.patch {
+            Some(patch_path) => Some(patch_path.clone()),
+            None if options.auto_patch => patch::find_next_to(path.as_ref()),
+            None => None,
+        };
+
+        if let Some(patch_path) = patch_path {
+            let patch = fs::read(patch_path).map_err(CartridgeError::Io)?;
+            data = patch::apply(&data, &patch).map_err(CartridgeError::Patch)?;
+        }
+
+        if is_disk_image(&name) {
+            let bios = options
+                .fds_bios
+                .as_ref()
+                .ok_or(CartridgeError::MissingBios)?;
+            let mut cartridge = Cartridge::from_fds(&data, bios)?;
+            cartridge.use_save_next_to(path.as_ref())?;
+
+            return Ok(cartridge);
+        }
+
+        let mut cartridge = Cartridge::load(&data)?;
+
+        if cartridge.header.battery || cartridge.has_flash() {
+            cartridge.use_save_next_to(path.as_ref())?;
+        }
+
+        Ok(cartridge)
+    }
+
+    // Loads a disk image file, along with the writes saved next to it
+    pub fn open_fds<P: AsRef<Path>>(path: P, bios: &[u8]) -> Result<Self, CartridgeError> {
+        let (_, data) = read_rom_file(&path, None)?;
+        let mut cartridge = Cartridge::from_fds(&data, bios)?;
+        cartridge.use_save_next_to(path.as_ref())?;
+
+        Ok(cartridge)
+    }
+
+    fn use_save_next_to(&mut self, path: &Path) -> Result<(), CartridgeError> {
+        let save_path = path.with_extension("sav");
+        self.load_save(&save_path).map_err(CartridgeError::Io)?;
+        self.save_path = Some(save_path);
+
+        Ok(())
+    }
+
+    #[inline]
+    pub fn read_prg(&mut self, address: u16) -> u8 {
+        self.mapper.read_prg(address)
+    }
+
+    #[inline]
+    pub fn write_prg(&mut self, address: u16, value: u8) {
+        self.mapper.write_prg(address, value);
+    }
+
+    #[inline]
+    pub fn read_chr(&mut self, address: u16) -> u8 {
+        self.mapper.read_chr(address)
+    }
+
+    #[inline]
+    pub fn write_chr(&mut self, address: u16, value: u8) {
+        self.mapper.write_chr(address, value);
+    }
+
+    #[inline]
+    pub fn mirroring(&self) -> Mirroring {
+        self.mapper.mirroring()
+    }
+
+    pub fn read_nametable(&mut self, address: u16, ciram: &[u8]) -> u8 {
+        if self.four_screen() {
+            return self.four_screen_ram[address as usize & 0x0FFF];
+        }
+
+        self.mapper.read_nametable(address, ciram)
+    }
+
+    pub fn write_nametable(&mut self, address: u16, value: u8, ciram: &mut [u8]) {
+        if self.four_screen() {
+            self.four_screen_ram[address as usize & 0x0FFF] = value;
+            return;
+        }
+
+        self.mapper.write_nametable(address, value, ciram);
+    }
+
+    pub fn peek_chr(&mut self, address: u16) -> u8 {
+        self.mapper.peek_chr(address)
+    }
+
+    pub fn peek_nametable(&mut self, address: u16, ciram: &[u8]) -> u8 {
+        if self.four_screen() {
+            return self.four_screen_ram[address as usize & 0x0FFF];
+        }
+
+        self.mapper.peek_nametable(address, ciram)
+    }
+
+    #[inline]
+    pub fn ppu_register_write(&mut self, address: u16, value: u8) {
+        self.mapper.ppu_register_write(address, value);
+    }
+
+    #[inline]
+    pub fn cpu_clock(&mut self) {
+        self.mapper.cpu_clock();
+    }
+
+    #[inline]
+    pub fn ppu_address(&mut self, address: u16) {
+        self.mapper.ppu_address(address);
+    }
+
+    #[inline]
+    pub fn irq(&self) -> bool {
+        self.mapper.irq()
+    }
+
+    #[inline]
+    pub fn audio_output(&self) -> f32 {
+        self.mapper.audio_output()
+    }
+
+    // Soft reset through the console's reset button
+    #[inline]
+    pub fn reset(&mut self) {
+        self.mapper.reset();
+    }
+
+    #[inline]
+    pub fn dip_switch_count(&self) -> u8 {
+        self.mapper.dip_switch_count()
+    }
+
+    // Sets the board's DIP switches, one bit per switch
+    #[inline]
+    pub fn set_dip_switches(&mut self, value: u8) {
+        self.mapper.set_dip_switches(value);
+    }
+
+    fn four_screen(&self) -> bool {
+        self.mapper.mirroring() == Mirroring::FourScreen && !self.mapper.nametable_ram()
+    }
+
+    #[inline]
+    pub fn save_path(&self) -> Option<&Path> {
+        self.save_path.as_deref()
+    }
+
+    // Changes where `flush` writes the save, or disables saving with `None`
+    pub fn set_save_path(&mut self, path: Option<PathBuf>) {
+        self.save_path = path;
+    }
+
+    // Whether PRG-RAM has changed since the last load or save
+    pub fn battery_ram_dirty(&mut self) -> bool {
+        self.header.battery && self.mapper.memory().prg_ram_dirty
+    }
+
+    // Fills PRG-RAM from a raw save file. A missing file leaves it as is.
+    pub fn load_battery_ram<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(error) => return Err(error),
+        };
+
+        let memory = self.mapper.memory();
+        let len = data.len().min(memory.prg_ram.len());
+        memory.prg_ram[..len].copy_from_slice(&data[..len]);
+        memory.prg_ram_dirty = false;
+
+        Ok(())
+    }
+
+    // Writes PRG-RAM to `path`, if it changed since the last load or save
+    pub fn save_battery_ram<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        if !self.battery_ram_dirty() {
+            return Ok(());
+        }
+
+        let memory = self.mapper.memory();
+        fs::write(path, &memory.prg_ram)?;
+        memory.prg_ram_dirty = false;
+
+        Ok(())
+    }
+
+    fn load_save(&mut self, path: &Path) -> io::Result<()> {
+        if self.disk_side_count() > 0 {
+            self.load_disk_writes(path)
+        } else if self.has_flash() {
+            self.load_flash(path)
+        } else {
+            self.load_battery_ram(path)
+        }
+    }
+
+    // Writes the save to the save path, if it changed
+    pub fn flush(&mut self) -> io::Result<()> {
+        let path = match self.save_path.clone() {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+
+        self.last_flush = Instant::now();
+
+        if self.disk_side_count() > 0 {
+            self.save_disk_writes(path)
+        } else if self.has_flash() {
+            self.save_flash(path)
+        } else {
+            self.save_battery_ram(path)
+        }
+    }
+
+    // Meant to be called regularly, e.g. once per frame: flushes the save
+    // every few seconds so a crash loses little progress
+    pub fn autosave(&mut self) -> io::Result<()> {
+        if self.last_flush.elapsed() < AUTOSAVE_INTERVAL {
+            return Ok(());
+        }
+
+        self.flush()
+    }
+
+    // Number of disk sides, or 0 for anything but the Famicom Disk System
+    pub fn disk_side_count(&mut self) -> usize {
+        self.mapper.disk().map_or(0, |disk| disk.side_count())
+    }
+
+    pub fn inserted_disk_side(&mut self) -> Option<usize> {
+        self.mapper.disk().and_then(|disk| disk.inserted())
+    }
+
+    // Puts a disk side in the drive. Eject the current one and let the game
+    // notice before inserting another.
+    pub fn insert_disk_side(&mut self, side: usize) -> bool {
+        self.mapper.disk().is_some_and(|disk| disk.insert(side))
+    }
+
+    pub fn eject_disk(&mut self) {
+        if let Some(disk) = self.mapper.disk() {
+            disk.eject();
+        }
+    }
+
+    pub fn disk_dirty(&mut self) -> bool {
+        self.mapper.disk().is_some_and(|disk| disk.dirty())
+    }
+
+    // Applies disk writes saved by `save_disk_writes`. A missing file leaves
+    // the disks as the image had them.
+    pub fn load_disk_writes<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        let disk = match self.mapper.disk() {
+            Some(disk) => disk,
+            None => return Ok(()),
+        };
+
+        match fs::read(path) {
+            Ok(data) => disk.load(&mut data.as_slice()),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+
+    // Writes the difference between the disks and the original image to
+    // `path`, if they were written since the last load or save
+    pub fn save_disk_writes<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        let disk = match self.mapper.disk() {
+            Some(disk) if disk.dirty() => disk,
+            _ => return Ok(()),
+        };
+
+        let mut data = Vec::new();
+        disk.save(&mut data)?;
+        fs::write(path, data)?;
+        disk.mark_saved();
+
+        Ok(())
+    }
+
+    // Whether the board saves to its flash PRG ROM
+    pub fn has_flash(&mut self) -> bool {
+        self.mapper.flash().is_some()
+    }
+
+    // Whether flash sectors have been rewritten since the last load or save
+    pub fn flash_dirty(&mut self) -> bool {
+        self.mapper.flash().is_some_and(|flash| flash.dirty())
+    }
+
+    // Applies flash sectors saved by `save_flash`. A missing file leaves the
+    // flash as the ROM shipped it.
+    pub fn load_flash<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        let flash = match self.mapper.flash() {
+            Some(flash) => flash,
+            None => return Ok(()),
+        };
+
+        match fs::read(path) {
+            Ok(data) => flash.load(&mut data.as_slice()),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+
+    // Writes every rewritten flash sector to `path`, if any changed since the
+    // last load or save
+    pub fn save_flash<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        let flash = match self.mapper.flash() {
+            Some(flash) if flash.dirty() => flash,
+            _ => return Ok(()),
+        };
+
+        let mut data = Vec::new();
+        flash.save(&mut data)?;
+        fs::write(path, data)?;
+        flash.mark_saved();
+
+        Ok(())
+    }
+}
+
+impl Drop for Cartridge {
+    fn drop(&mut self) {
+        // Nowhere to report a failure here. Frontends that care call `flush`
+        // themselves before exiting.
+        let _ = self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests;
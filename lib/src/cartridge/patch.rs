@@ -0,0 +1,328 @@
+// IPS, BPS and UPS patches, applied to a whole ROM file before it's parsed
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use super::hash::crc32;
+
+const IPS_MAGIC: &[u8] = b"PATCH";
+const IPS_EOF: &[u8] = b"EOF";
+const BPS_MAGIC: &[u8] = b"BPS1";
+const UPS_MAGIC: &[u8] = b"UPS1";
+
+// BPS and UPS end with the CRC32s of the source, the target and the patch
+const FOOTER_SIZE: usize = 12;
+
+// Far beyond any NES ROM, so a corrupt size can't exhaust memory
+const MAX_TARGET_SIZE: usize = 0x400_0000;
+
+// Extensions looked for next to a ROM when patching it automatically
+pub const PATCH_EXTENSIONS: [&str; 3] = ["ips", "bps", "ups"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PatchError {
+    UnknownFormat,
+    Malformed,
+    // The ROM isn't the one the patch was made for
+    SourceMismatch,
+    // The patch was made for the ROM but produced something else
+    TargetMismatch,
+    PatchChecksum,
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PatchError::UnknownFormat => write!(f, "not an IPS, BPS or UPS patch"),
+            PatchError::Malformed => write!(f, "malformed patch"),
+            PatchError::SourceMismatch => write!(f, "the patch is for a different ROM"),
+            PatchError::TargetMismatch => write!(f, "the patched ROM fails its checksum"),
+            PatchError::PatchChecksum => write!(f, "the patch is corrupt"),
+        }
+    }
+}
+
+impl std::error::Error for PatchError {}
+
+// Applies `patch` to `rom`, going by the patch's magic number
+pub fn apply(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if patch.starts_with(IPS_MAGIC) {
+        apply_ips(rom, patch)
+    } else if patch.starts_with(BPS_MAGIC) {
+        apply_bps(rom, patch)
+    } else if patch.starts_with(UPS_MAGIC) {
+        apply_ups(rom, patch)
+    } else {
+        Err(PatchError::UnknownFormat)
+    }
+}
+
+// The first patch file named like `rom`, e.g. game.ips for game.nes
+pub fn find_next_to(rom: &Path) -> Option<PathBuf> {
+    PATCH_EXTENSIONS
+        .iter()
+        .map(|extension| rom.with_extension(extension))
+        .find(|path| path.is_file())
+}
+
+fn big_endian(bytes: &[u8]) -> usize {
+    bytes
+        .iter()
+        .fold(0, |value, &byte| value << 8 | byte as usize)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8], offset: usize) -> Self {
+        Reader { data, offset }
+    }
+
+    fn bytes(&mut self, length: usize) -> Result<&'a [u8], PatchError> {
+        let end = self
+            .offset
+            .checked_add(length)
+            .ok_or(PatchError::Malformed)?;
+        let bytes = self
+            .data
+            .get(self.offset..end)
+            .ok_or(PatchError::Malformed)?;
+        self.offset = end;
+
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, PatchError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn big_endian(&mut self, length: usize) -> Result<usize, PatchError> {
+        Ok(big_endian(self.bytes(length)?))
+    }
+
+    // BPS and UPS numbers: 7 bits a byte, least significant first, with
+    // the top bit ending the number. Each continuation also adds one, so
+    // every number has a single encoding.
+    fn number(&mut self) -> Result<usize, PatchError> {
+        let mut value: usize = 0;
+        let mut shift: usize = 1;
+
+        loop {
+            let byte = self.byte()?;
+            let digit = (byte & 0x7F) as usize;
+            value = digit
+                .checked_mul(shift)
+                .and_then(|digit| value.checked_add(digit))
+                .ok_or(PatchError::Malformed)?;
+
+            if byte & 0x80 != 0 {
+                return Ok(value);
+            }
+
+            shift = shift.checked_shl(7).ok_or(PatchError::Malformed)?;
+            value = value.checked_add(shift).ok_or(PatchError::Malformed)?;
+        }
+    }
+
+    // The sign is in the lowest bit
+    fn signed_number(&mut self) -> Result<isize, PatchError> {
+        let value = self.number()?;
+        let magnitude = (value >> 1) as isize;
+
+        Ok(if value & 0x01 != 0 {
+            -magnitude
+        } else {
+            magnitude
+        })
+    }
+}
+
+// Records of a 24-bit offset and a 16-bit length followed by that many
+// bytes, or by a 16-bit count and a byte to repeat when the length is 0. A
+// 24-bit size may follow the EOF marker to truncate the file to.
+fn apply_ips(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let mut output = rom.to_vec();
+    let mut reader = Reader::new(patch, IPS_MAGIC.len());
+
+    loop {
+        let offset = reader.bytes(3)?;
+
+        if offset == IPS_EOF {
+            break;
+        }
+
+        let offset = big_endian(offset);
+        let length = reader.big_endian(2)?;
+
+        let (length, value) = if length == 0 {
+            (reader.big_endian(2)?, Some(reader.byte()?))
+        } else {
+            (length, None)
+        };
+
+        if output.len() < offset + length {
+            output.resize(offset + length, 0);
+        }
+
+        match value {
+            Some(value) => output[offset..offset + length].fill(value),
+            None => output[offset..offset + length].copy_from_slice(reader.bytes(length)?),
+        }
+    }
+
+    if let Ok(size) = reader.big_endian(3) {
+        output.truncate(size);
+    }
+
+    Ok(output)
+}
+
+// Checks the CRCs at the end of a BPS or UPS patch that don't need the
+// output, returning the target's and the actions in between
+fn check_footer(rom: &[u8], patch: &[u8]) -> Result<(u32, usize), PatchError> {
+    if patch.len() < UPS_MAGIC.len() + FOOTER_SIZE {
+        return Err(PatchError::Malformed);
+    }
+
+    let footer = patch.len() - FOOTER_SIZE;
+    let crc = |offset: usize| {
+        let bytes = &patch[footer + offset..footer + offset + 4];
+        u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+    };
+
+    if crc32(&patch[..patch.len() - 4]) != crc(8) {
+        return Err(PatchError::PatchChecksum);
+    }
+
+    if crc32(rom) != crc(0) {
+        return Err(PatchError::SourceMismatch);
+    }
+
+    Ok((crc(4), footer))
+}
+
+// Copies runs from the source, the patch or the output so far, tracking
+// separate read positions in the source and output
+fn apply_bps(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let (target_crc, footer) = check_footer(rom, patch)?;
+    let mut reader = Reader::new(&patch[..footer], BPS_MAGIC.len());
+
+    let source_size = reader.number()?;
+    let target_size = reader.number()?;
+    let metadata_size = reader.number()?;
+    reader.bytes(metadata_size)?;
+
+    if source_size != rom.len() {
+        return Err(PatchError::SourceMismatch);
+    }
+
+    if target_size > MAX_TARGET_SIZE {
+        return Err(PatchError::Malformed);
+    }
+
+    let mut output = Vec::with_capacity(target_size);
+    let mut source_offset: usize = 0;
+    let mut target_offset: usize = 0;
+
+    let relative = |offset: usize, delta: isize| {
+        offset
+            .checked_add_signed(delta)
+            .ok_or(PatchError::Malformed)
+    };
+
+    while reader.offset < footer {
+        let action = reader.number()?;
+        let length = (action >> 2) + 1;
+
+        if output.len() + length > target_size {
+            return Err(PatchError::Malformed);
+        }
+
+        match action & 0x03 {
+            // SourceRead, from the same position in the source
+            0 => {
+                let start = output.len();
+                let bytes = rom
+                    .get(start..start + length)
+                    .ok_or(PatchError::Malformed)?;
+                output.extend_from_slice(bytes);
+            }
+            // TargetRead
+            1 => output.extend_from_slice(reader.bytes(length)?),
+            // SourceCopy
+            2 => {
+                source_offset = relative(source_offset, reader.signed_number()?)?;
+                let bytes = rom
+                    .get(source_offset..source_offset + length)
+                    .ok_or(PatchError::Malformed)?;
+                output.extend_from_slice(bytes);
+                source_offset += length;
+            }
+            // TargetCopy, a byte at a time since the run can overlap what
+            // it's writing
+            _ => {
+                target_offset = relative(target_offset, reader.signed_number()?)?;
+
+                for _ in 0..length {
+                    let byte = *output.get(target_offset).ok_or(PatchError::Malformed)?;
+                    output.push(byte);
+                    target_offset += 1;
+                }
+            }
+        }
+    }
+
+    if output.len() != target_size || crc32(&output) != target_crc {
+        return Err(PatchError::TargetMismatch);
+    }
+
+    Ok(output)
+}
+
+// Hunks of XORed bytes after a relative skip, each ended by a zero byte
+// that also skips a byte of output
+fn apply_ups(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let (target_crc, footer) = check_footer(rom, patch)?;
+    let mut reader = Reader::new(&patch[..footer], UPS_MAGIC.len());
+
+    let source_size = reader.number()?;
+    let target_size = reader.number()?;
+
+    if source_size != rom.len() {
+        return Err(PatchError::SourceMismatch);
+    }
+
+    if target_size > MAX_TARGET_SIZE {
+        return Err(PatchError::Malformed);
+    }
+
+    let mut output = rom.to_vec();
+    output.resize(target_size, 0);
+    let mut offset: usize = 0;
+
+    while reader.offset < footer {
+        offset = offset
+            .checked_add(reader.number()?)
+            .ok_or(PatchError::Malformed)?;
+
+        loop {
+            let byte = reader.byte()?;
+            offset += 1;
+
+            if byte == 0 {
+                break;
+            }
+
+            *output.get_mut(offset - 1).ok_or(PatchError::Malformed)? ^= byte;
+        }
+    }
+
+    if crc32(&output) != target_crc {
+        return Err(PatchError::TargetMismatch);
+    }
+
+    Ok(output)
+}
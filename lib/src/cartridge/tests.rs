@@ -0,0 +1,1811 @@
+use super::*;
+
+// Builds an iNES image where every 16 KB PRG bank and every 8 KB CHR bank is
+// filled with its own index.
+fn rom(mapper: u16, submapper: u8, prg_banks: u8, chr_banks: u8) -> Vec<u8> {
+    let mut data = vec![
+        b'N',
+        b'E',
+        b'S',
+        0x1A,
+        prg_banks,
+        chr_banks,
+        ((mapper as u8) & 0x0F) << 4,
+        ((mapper as u8) & 0xF0) | 0x08,
+        (submapper << 4) | ((mapper >> 8) as u8 & 0x0F),
+        0,
+        0x07,
+        if chr_banks == 0 { 0x07 } else { 0 },
+        0,
+        0,
+        0,
+        0,
+    ];
+
+    for bank in 0..prg_banks {
+        data.extend(vec![bank; PRG_ROM_PAGE_SIZE]);
+    }
+
+    for bank in 0..chr_banks {
+        data.extend(vec![bank; CHR_ROM_PAGE_SIZE]);
+    }
+
+    data
+}
+
+#[test]
+fn test_header_ines() {
+    let mut data = rom(0, 0, 2, 1);
+    data[6] = 0x13;
+    data[7] = 0x40;
+    data[8] = 0;
+
+    let header = Header::parse(&data).unwrap();
+    assert_eq!(header.mapper, 0x41);
+    assert!(!header.nes2);
+    assert!(header.battery);
+    assert_eq!(header.mirroring, Mirroring::Vertical);
+    assert_eq!(header.prg_rom_size, 0x8000);
+    assert_eq!(header.chr_rom_size, 0x2000);
+    assert_eq!(header.prg_ram_size, 0x2000);
+    assert_eq!(header.region, Region::Ntsc);
+}
+
+#[test]
+fn test_header_nes2() {
+    let mut data = rom(0x123, 5, 2, 0);
+    data[12] = 0x03;
+    data[15] = 0x08;
+
+    let header = Header::parse(&data).unwrap();
+    assert!(header.nes2);
+    assert_eq!(header.mapper, 0x123);
+    assert_eq!(header.submapper, 5);
+    assert_eq!(header.prg_ram_size, 0x2000);
+    assert_eq!(header.chr_ram_size, 0x2000);
+    assert_eq!(header.region, Region::Dendy);
+    assert_eq!(header.input_device, InputDevice::Zapper);
+}
+
+#[test]
+fn test_header_nes2_overflow() {
+    // 2^63 * 7 bytes of PRG-ROM and CHR-ROM in exponent-multiplier form
+    let mut data = vec![b'N', b'E', b'S', 0x1A, 0xFF, 0xFF, 0x00, 0x08, 0x00, 0xFF];
+    data.extend([0; 6]);
+    assert!(matches!(
+        Cartridge::load(&data),
+        Err(CartridgeError::InvalidHeader)
+    ));
+
+    // 2^62 * 3 bytes each fit, but not both together
+    data[4] = 0xF9;
+    data[5] = 0xF9;
+    assert!(matches!(
+        Cartridge::load(&data),
+        Err(CartridgeError::Truncated)
+    ));
+}
+
+#[test]
+fn test_invalid_header() {
+    assert!(matches!(
+        Cartridge::from_ines(&[0; 16]),
+        Err(CartridgeError::InvalidHeader)
+    ));
+}
+
+#[test]
+fn test_truncated_rom() {
+    let mut data = rom(0, 0, 2, 1);
+    data.truncate(0x4000);
+    assert!(matches!(
+        Cartridge::from_ines(&data),
+        Err(CartridgeError::Truncated)
+    ));
+}
+
+#[test]
+fn test_unsupported_mapper() {
+    assert!(matches!(
+        Cartridge::from_ines(&rom(0xFFF, 0, 1, 1)),
+        Err(CartridgeError::UnsupportedMapper(0xFFF))
+    ));
+}
+
+#[test]
+fn test_uxrom() {
+    let mut cartridge = Cartridge::from_ines(&rom(2, 1, 8, 0)).unwrap();
+    assert_eq!(cartridge.read_prg(0x8000), 0);
+    assert_eq!(cartridge.read_prg(0xC000), 7);
+
+    cartridge.write_prg(0x8000, 3);
+    assert_eq!(cartridge.read_prg(0xBFFF), 3);
+    assert_eq!(cartridge.read_prg(0xFFFF), 7);
+
+    cartridge.write_chr(0x0010, 0x42);
+    assert_eq!(cartridge.read_chr(0x0010), 0x42);
+}
+
+#[test]
+fn test_uxrom_bus_conflicts() {
+    let mut data = rom(2, 2, 8, 0);
+    // $C000 in the fixed bank reads back $07, so writing $05 there selects 5 & 7
+    data[16 + 7 * PRG_ROM_PAGE_SIZE] = 0x07;
+    // $C001 reads back $01
+    data[16 + 7 * PRG_ROM_PAGE_SIZE + 1] = 0x01;
+
+    let mut cartridge = Cartridge::from_ines(&data).unwrap();
+    cartridge.write_prg(0xC001, 0x06);
+    assert_eq!(cartridge.read_prg(0x8000), 0);
+
+    cartridge.write_prg(0xC000, 0x05);
+    assert_eq!(cartridge.read_prg(0x8000), 5);
+}
+
+#[test]
+fn test_cnrom() {
+    let mut cartridge = Cartridge::from_ines(&rom(3, 1, 1, 4)).unwrap();
+    assert_eq!(cartridge.read_prg(0x8000), 0);
+    assert_eq!(cartridge.read_prg(0xC000), 0);
+
+    cartridge.write_prg(0x8000, 2);
+    assert_eq!(cartridge.read_chr(0x1FFF), 2);
+
+    // CHR ROM is not writable
+    cartridge.write_chr(0x0000, 0x42);
+    assert_eq!(cartridge.read_chr(0x0000), 2);
+}
+
+#[test]
+fn test_axrom() {
+    let mut cartridge = Cartridge::from_ines(&rom(7, 1, 8, 0)).unwrap();
+    assert_eq!(cartridge.mirroring(), Mirroring::SingleScreenLower);
+
+    cartridge.write_prg(0x8000, 0x12);
+    assert_eq!(cartridge.read_prg(0x8000), 4);
+    assert_eq!(cartridge.read_prg(0xC000), 5);
+    assert_eq!(cartridge.mirroring(), Mirroring::SingleScreenUpper);
+}
+
+#[test]
+fn test_gxrom() {
+    let mut data = rom(66, 0, 8, 4);
+    for byte in &mut data[16..16 + 8 * PRG_ROM_PAGE_SIZE] {
+        *byte = 0xFF;
+    }
+
+    let mut cartridge = Cartridge::from_ines(&data).unwrap();
+    cartridge.write_prg(0x8000, 0x23);
+    assert_eq!(cartridge.read_chr(0x0000), 3);
+
+    let mut cartridge = Cartridge::from_ines(&rom(66, 0, 8, 4)).unwrap();
+    cartridge.write_prg(0x8000, 0x23);
+    assert_eq!(cartridge.read_chr(0x0000), 0);
+}
+
+#[test]
+fn test_bnrom() {
+    let mut data = rom(34, 2, 8, 0);
+    for byte in &mut data[16..16 + 2 * PRG_ROM_PAGE_SIZE] {
+        *byte = 0xFF;
+    }
+
+    let mut cartridge = Cartridge::from_ines(&data).unwrap();
+    cartridge.write_prg(0x8000, 3);
+    assert_eq!(cartridge.read_prg(0x8000), 6);
+    assert_eq!(cartridge.read_prg(0xC000), 7);
+}
+
+#[test]
+fn test_nina001() {
+    let mut cartridge = Cartridge::from_ines(&rom(34, 1, 4, 4)).unwrap();
+
+    cartridge.write_prg(0x6000, 0x42);
+    assert_eq!(cartridge.read_prg(0x6000), 0x42);
+
+    cartridge.write_prg(0x7FFD, 1);
+    assert_eq!(cartridge.read_prg(0x8000), 2);
+
+    cartridge.write_prg(0x7FFE, 5);
+    cartridge.write_prg(0x7FFF, 2);
+    assert_eq!(cartridge.read_chr(0x0000), 2);
+    assert_eq!(cartridge.read_chr(0x1000), 1);
+}
+
+#[test]
+fn test_color_dreams() {
+    let mut data = rom(11, 0, 8, 16);
+    for byte in &mut data[16..16 + 8 * PRG_ROM_PAGE_SIZE] {
+        *byte = 0xFF;
+    }
+
+    let mut cartridge = Cartridge::from_ines(&data).unwrap();
+    cartridge.write_prg(0x8000, 0x52);
+    assert_eq!(cartridge.read_chr(0x0000), 5);
+    assert_eq!(cartridge.read_prg(0x8000), 0xFF);
+}
+
+fn clock_scanline(cartridge: &mut Cartridge) {
+    for _ in 0..4 {
+        cartridge.cpu_clock();
+    }
+    cartridge.ppu_address(0x0000);
+    for _ in 0..4 {
+        cartridge.cpu_clock();
+    }
+    cartridge.ppu_address(0x1000);
+}
+
+#[test]
+fn test_mmc3_prg_banks() {
+    let mut cartridge = Cartridge::from_ines(&rom(4, 0, 8, 8)).unwrap();
+
+    cartridge.write_prg(0x8000, 0x06);
+    cartridge.write_prg(0x8001, 0x04);
+    cartridge.write_prg(0x8000, 0x07);
+    cartridge.write_prg(0x8001, 0x07);
+    assert_eq!(cartridge.read_prg(0x8000), 2);
+    assert_eq!(cartridge.read_prg(0xA000), 3);
+    assert_eq!(cartridge.read_prg(0xC000), 7);
+    assert_eq!(cartridge.read_prg(0xE000), 7);
+
+    // PRG mode 1 swaps $8000 and $C000
+    cartridge.write_prg(0x8000, 0x40);
+    assert_eq!(cartridge.read_prg(0x8000), 7);
+    assert_eq!(cartridge.read_prg(0xC000), 2);
+}
+
+// A NES 2.0 image with a single 8 KB bank of PRG-ROM, filled with 0x42
+fn small_prg_rom(mapper: u16) -> Vec<u8> {
+    let mut data = rom(mapper, 0, 0, 1);
+    data[4] = 13 << 2;
+    data[9] = 0x0F;
+    data.splice(HEADER_SIZE..HEADER_SIZE, vec![0x42; 0x2000]);
+    data
+}
+
+#[test]
+fn test_mmc3_small_prg_rom() {
+    let mut cartridge = Cartridge::from_ines(&small_prg_rom(4)).unwrap();
+    assert_eq!(cartridge.read_prg(0xFFFC), 0x42);
+    assert_eq!(cartridge.read_prg(0x8000), 0x42);
+
+    // With no PRG-ROM at all, reads come back as 0
+    let mut cartridge = Cartridge::from_ines(&rom(4, 0, 0, 1)).unwrap();
+    assert_eq!(cartridge.read_prg(0xFFFC), 0);
+}
+
+#[test]
+fn test_mmc3_chr_banks() {
+    let mut cartridge = Cartridge::from_ines(&rom(4, 0, 2, 8)).unwrap();
+
+    // R0 = 2 KB bank at 1 KB index 16 (8 KB bank 2)
+    cartridge.write_prg(0x8000, 0x00);
+    cartridge.write_prg(0x8001, 0x11);
+    // R2 = 1 KB bank at index 40 (8 KB bank 5)
+    cartridge.write_prg(0x8000, 0x02);
+    cartridge.write_prg(0x8001, 40);
+    assert_eq!(cartridge.read_chr(0x0000), 2);
+    assert_eq!(cartridge.read_chr(0x1000), 5);
+
+    // A12 inversion
+    cartridge.write_prg(0x8000, 0x80);
+    assert_eq!(cartridge.read_chr(0x0000), 5);
+    assert_eq!(cartridge.read_chr(0x1000), 2);
+}
+
+#[test]
+fn test_mmc3_mirroring_and_prg_ram() {
+    let mut cartridge = Cartridge::from_ines(&rom(4, 0, 2, 1)).unwrap();
+
+    cartridge.write_prg(0xA000, 0x01);
+    assert_eq!(cartridge.mirroring(), Mirroring::Horizontal);
+    cartridge.write_prg(0xA000, 0x00);
+    assert_eq!(cartridge.mirroring(), Mirroring::Vertical);
+
+    cartridge.write_prg(0xA001, 0x80);
+    cartridge.write_prg(0x6000, 0x42);
+    assert_eq!(cartridge.read_prg(0x6000), 0x42);
+
+    cartridge.write_prg(0xA001, 0xC0);
+    cartridge.write_prg(0x6000, 0x24);
+    assert_eq!(cartridge.read_prg(0x6000), 0x42);
+
+    cartridge.write_prg(0xA001, 0x00);
+    assert_eq!(cartridge.read_prg(0x6000), 0);
+}
+
+#[test]
+fn test_mmc3_irq() {
+    let mut cartridge = Cartridge::from_ines(&rom(4, 0, 2, 1)).unwrap();
+    cartridge.write_prg(0xC000, 2);
+    cartridge.write_prg(0xC001, 0);
+    cartridge.write_prg(0xE001, 0);
+
+    clock_scanline(&mut cartridge);
+    clock_scanline(&mut cartridge);
+    assert!(!cartridge.irq());
+    clock_scanline(&mut cartridge);
+    assert!(cartridge.irq());
+
+    cartridge.write_prg(0xE000, 0);
+    assert!(!cartridge.irq());
+}
+
+#[test]
+fn test_mmc3_irq_a12_filter() {
+    let mut cartridge = Cartridge::from_ines(&rom(4, 0, 2, 1)).unwrap();
+    cartridge.write_prg(0xC000, 0);
+    cartridge.write_prg(0xC001, 0);
+    cartridge.write_prg(0xE001, 0);
+
+    // Rapid toggles like the ones during sprite fetches are ignored
+    cartridge.ppu_address(0x0000);
+    cartridge.ppu_address(0x1000);
+    assert!(!cartridge.irq());
+}
+
+#[test]
+fn test_mmc3_irq_behaviour() {
+    // With a latch of zero, the new behaviour fires on every clock
+    let mut cartridge = Cartridge::from_ines(&rom(4, 0, 2, 1)).unwrap();
+    cartridge.write_prg(0xC000, 0);
+    cartridge.write_prg(0xE001, 0);
+    clock_scanline(&mut cartridge);
+    assert!(cartridge.irq());
+    cartridge.write_prg(0xE000, 0);
+    cartridge.write_prg(0xE001, 0);
+    clock_scanline(&mut cartridge);
+    assert!(cartridge.irq());
+
+    // The old behaviour only fires on the clock following a $C001 reload
+    let mut cartridge = Cartridge::from_ines(&rom(4, 4, 2, 1)).unwrap();
+    cartridge.write_prg(0xC000, 0);
+    cartridge.write_prg(0xC001, 0);
+    cartridge.write_prg(0xE001, 0);
+    clock_scanline(&mut cartridge);
+    assert!(cartridge.irq());
+    cartridge.write_prg(0xE000, 0);
+    cartridge.write_prg(0xE001, 0);
+    clock_scanline(&mut cartridge);
+    assert!(!cartridge.irq());
+}
+
+// Replays the PPU's fetches for one rendered scanline
+fn fetch_scanline(cartridge: &mut Cartridge, ciram: &[u8]) -> Vec<u8> {
+    let mut tiles = Vec::new();
+
+    for tile in 0..32 {
+        tiles.push(cartridge.read_nametable(0x2000 + tile, ciram));
+        cartridge.read_nametable(0x23C0, ciram);
+        cartridge.read_chr(0x0000);
+        cartridge.read_chr(0x0008);
+    }
+
+    for _ in 0..8 {
+        cartridge.read_nametable(0x2000, ciram);
+        cartridge.read_nametable(0x2000, ciram);
+        cartridge.read_chr(0x1000);
+        cartridge.read_chr(0x1008);
+    }
+
+    for _ in 0..2 {
+        cartridge.read_nametable(0x2000, ciram);
+        cartridge.read_nametable(0x23C0, ciram);
+        cartridge.read_chr(0x0000);
+        cartridge.read_chr(0x0008);
+    }
+
+    cartridge.read_nametable(0x2000, ciram);
+    cartridge.read_nametable(0x2000, ciram);
+
+    tiles
+}
+
+#[test]
+fn test_mmc5_prg_modes() {
+    let mut cartridge = Cartridge::from_ines(&rom(5, 0, 8, 1)).unwrap();
+
+    // Mode 3 at power-on, with $5117 pointing at the last bank
+    assert_eq!(cartridge.read_prg(0xE000), 7);
+
+    cartridge.write_prg(0x5100, 0);
+    cartridge.write_prg(0x5117, 0x04);
+    assert_eq!(cartridge.read_prg(0x8000), 2);
+    assert_eq!(cartridge.read_prg(0xFFFF), 3);
+
+    cartridge.write_prg(0x5100, 2);
+    cartridge.write_prg(0x5115, 0x82);
+    cartridge.write_prg(0x5116, 0x8A);
+    assert_eq!(cartridge.read_prg(0x8000), 1);
+    assert_eq!(cartridge.read_prg(0xC000), 5);
+
+    // RAM mapped into $C000 is only writable once unlocked
+    cartridge.write_prg(0x5116, 0x00);
+    cartridge.write_prg(0xC000, 0x42);
+    assert_eq!(cartridge.read_prg(0xC000), 0);
+    cartridge.write_prg(0x5102, 0x02);
+    cartridge.write_prg(0x5103, 0x01);
+    cartridge.write_prg(0xC000, 0x42);
+    assert_eq!(cartridge.read_prg(0xC000), 0x42);
+    assert_eq!(cartridge.read_prg(0x6000), 0x42);
+}
+
+#[test]
+fn test_mmc5_chr_modes() {
+    let mut cartridge = Cartridge::from_ines(&rom(5, 0, 2, 4)).unwrap();
+
+    cartridge.write_prg(0x5101, 3);
+    cartridge.write_prg(0x5124, 8 * 3);
+    assert_eq!(cartridge.read_chr(0x1000), 3);
+
+    cartridge.write_prg(0x5101, 1);
+    cartridge.write_prg(0x5123, 2);
+    assert_eq!(cartridge.read_chr(0x0000), 1);
+}
+
+#[test]
+fn test_mmc5_multiplier() {
+    let mut cartridge = Cartridge::from_ines(&rom(5, 0, 2, 1)).unwrap();
+    cartridge.write_prg(0x5205, 200);
+    cartridge.write_prg(0x5206, 100);
+    assert_eq!(cartridge.read_prg(0x5205), 0x20);
+    assert_eq!(cartridge.read_prg(0x5206), 0x4E);
+}
+
+#[test]
+fn test_mmc5_nametables() {
+    let mut cartridge = Cartridge::from_ines(&rom(5, 0, 2, 1)).unwrap();
+    let mut ciram = [0; 0x800];
+
+    // $2000 CIRAM page 1, $2400 ExRAM, $2800 fill mode
+    cartridge.write_prg(0x5105, 0b00_11_10_01);
+    cartridge.write_prg(0x5106, 0x24);
+    cartridge.write_prg(0x5107, 0x02);
+
+    cartridge.write_nametable(0x2000, 0x11, &mut ciram);
+    assert_eq!(ciram[0x400], 0x11);
+
+    cartridge.write_nametable(0x2410, 0x22, &mut ciram);
+    assert_eq!(cartridge.read_nametable(0x2410, &ciram), 0x22);
+
+    assert_eq!(cartridge.read_nametable(0x2800, &ciram), 0x24);
+    assert_eq!(cartridge.read_nametable(0x2BC0, &ciram), 0xAA);
+
+    // ExRAM as general purpose RAM
+    cartridge.write_prg(0x5104, 2);
+    cartridge.write_prg(0x5C00, 0x33);
+    assert_eq!(cartridge.read_prg(0x5C00), 0x33);
+}
+
+#[test]
+fn test_mmc5_scanline_irq() {
+    let mut cartridge = Cartridge::from_ines(&rom(5, 0, 2, 1)).unwrap();
+    let ciram = [0; 0x800];
+    cartridge.write_prg(0x5203, 2);
+    cartridge.write_prg(0x5204, 0x80);
+
+    for _ in 0..3 {
+        fetch_scanline(&mut cartridge, &ciram);
+        assert!(!cartridge.irq());
+    }
+
+    fetch_scanline(&mut cartridge, &ciram);
+    assert!(cartridge.irq());
+    assert_eq!(cartridge.read_prg(0x5204), 0xC0);
+    assert!(!cartridge.irq());
+
+    // Rendering stops, so the next frame starts over
+    for _ in 0..3 {
+        cartridge.cpu_clock();
+    }
+    assert_eq!(cartridge.read_prg(0x5204), 0x00);
+}
+
+#[test]
+fn test_mmc5_split_screen() {
+    let mut cartridge = Cartridge::from_ines(&rom(5, 0, 2, 1)).unwrap();
+    let ciram = [0; 0x800];
+
+    cartridge.write_prg(0x5104, 2);
+    for column in 0..32 {
+        cartridge.write_prg(0x5C00 + column, 0x80 + column as u8);
+    }
+    cartridge.write_prg(0x5104, 0);
+    cartridge.write_prg(0x5200, 0x80 | 4);
+
+    fetch_scanline(&mut cartridge, &ciram);
+    let tiles = fetch_scanline(&mut cartridge, &ciram);
+
+    // Tiles left of the threshold come from ExRAM
+    assert_eq!(tiles[0], 0x82);
+    assert_eq!(tiles[1], 0x83);
+    assert_eq!(tiles[2], 0);
+}
+
+#[test]
+fn test_mmc5_audio() {
+    let mut cartridge = Cartridge::from_ines(&rom(5, 0, 2, 1)).unwrap();
+    assert_eq!(cartridge.audio_output(), 0.0);
+
+    cartridge.write_prg(0x5011, 0x80);
+    assert!(cartridge.audio_output() > 0.0);
+
+    cartridge.write_prg(0x5015, 0x01);
+    cartridge.write_prg(0x5003, 0x08);
+    assert_eq!(cartridge.read_prg(0x5015), 0x01);
+}
+
+#[test]
+fn test_vrc4_wiring() {
+    // VRC4e (mapper 23, submapper 2) uses A2 and A3
+    let mut cartridge = Cartridge::from_ines(&rom(23, 2, 8, 8)).unwrap();
+
+    cartridge.write_prg(0x8000, 4);
+    assert_eq!(cartridge.read_prg(0x8000), 2);
+
+    // $B004 and $B00C: low and high nibble of CHR bank 0 at $0000, $B008 for bank 1
+    cartridge.write_prg(0xB000, 0x08);
+    cartridge.write_prg(0xB004, 0x01);
+    assert_eq!(cartridge.read_chr(0x0000), 3);
+
+    // Swap mode moves the second-last bank to $8000
+    cartridge.write_prg(0x9008, 0x02);
+    assert_eq!(cartridge.read_prg(0x8000), 7);
+    assert_eq!(cartridge.read_prg(0xC000), 2);
+
+    cartridge.write_prg(0x9000, 0x03);
+    assert_eq!(cartridge.mirroring(), Mirroring::SingleScreenUpper);
+}
+
+#[test]
+fn test_vrc4_small_prg_rom() {
+    for mapper in [21, 23, 25] {
+        let mut cartridge = Cartridge::from_ines(&small_prg_rom(mapper)).unwrap();
+        assert_eq!(cartridge.read_prg(0xFFFC), 0x42);
+        assert_eq!(cartridge.read_prg(0xC000), 0x42);
+    }
+}
+
+#[test]
+fn test_vrc2a_chr_banks() {
+    let mut cartridge = Cartridge::from_ines(&rom(22, 0, 8, 8)).unwrap();
+
+    // VRC2a ignores the low bit of CHR bank numbers
+    cartridge.write_prg(0xB000, 0x01);
+    cartridge.write_prg(0xB002, 0x05);
+    assert_eq!(cartridge.read_chr(0x0000), 5);
+
+    cartridge.write_prg(0x9000, 0x01);
+    assert_eq!(cartridge.mirroring(), Mirroring::Horizontal);
+}
+
+#[test]
+fn test_vrc2_microwire() {
+    // An iNES 1.0 header, which always asks for PRG-RAM
+    let mut data = rom(22, 0, 8, 8);
+    data[7] &= 0xF0;
+    data[8..16].fill(0);
+
+    // Without a battery, $6000-$6FFF is a one bit latch
+    let mut cartridge = Cartridge::from_ines(&data).unwrap();
+    cartridge.write_prg(0x6000, 0xFF);
+    assert_eq!(cartridge.read_prg(0x6000), 0x01);
+    cartridge.write_prg(0x6FFF, 0xFE);
+    assert_eq!(cartridge.read_prg(0x6000), 0x00);
+
+    // With one, it's RAM
+    data[6] |= 0x02;
+    let mut cartridge = Cartridge::from_ines(&data).unwrap();
+    cartridge.write_prg(0x6000, 0xFF);
+    assert_eq!(cartridge.read_prg(0x6000), 0xFF);
+}
+
+#[test]
+fn test_vrc_irq() {
+    let mut cartridge = Cartridge::from_ines(&rom(21, 1, 8, 8)).unwrap();
+
+    // Cycle mode, firing after 16 cycles
+    cartridge.write_prg(0xF000, 0x00);
+    cartridge.write_prg(0xF002, 0x0F);
+    cartridge.write_prg(0xF004, 0x06);
+
+    for _ in 0..15 {
+        cartridge.cpu_clock();
+    }
+    assert!(!cartridge.irq());
+    cartridge.cpu_clock();
+    assert!(cartridge.irq());
+
+    cartridge.write_prg(0xF006, 0);
+    assert!(!cartridge.irq());
+
+    // Scanline mode divides by 113 2/3
+    cartridge.write_prg(0xF000, 0x0E);
+    cartridge.write_prg(0xF002, 0x0F);
+    cartridge.write_prg(0xF004, 0x02);
+    for _ in 0..341 {
+        cartridge.cpu_clock();
+    }
+    assert!(cartridge.irq());
+}
+
+#[test]
+fn test_vrc6() {
+    let mut cartridge = Cartridge::from_ines(&rom(26, 0, 8, 8)).unwrap();
+
+    cartridge.write_prg(0x8000, 2);
+    cartridge.write_prg(0xC000, 7);
+    assert_eq!(cartridge.read_prg(0x8000), 2);
+    assert_eq!(cartridge.read_prg(0xC000), 3);
+    assert_eq!(cartridge.read_prg(0xE000), 7);
+
+    // VRC6b swaps A0 and A1, so $D002 selects CHR bank 1
+    cartridge.write_prg(0xD002, 8 * 4);
+    assert_eq!(cartridge.read_chr(0x0400), 4);
+
+    cartridge.write_prg(0xB003, 0x84);
+    assert_eq!(cartridge.mirroring(), Mirroring::Horizontal);
+    cartridge.write_prg(0x6000, 0x42);
+    assert_eq!(cartridge.read_prg(0x6000), 0x42);
+}
+
+#[test]
+fn test_vrc6_audio() {
+    let mut cartridge = Cartridge::from_ines(&rom(24, 0, 8, 8)).unwrap();
+
+    cartridge.write_prg(0x9000, 0x8F);
+    cartridge.write_prg(0x9001, 0x10);
+    cartridge.write_prg(0x9002, 0x80);
+    cartridge.cpu_clock();
+    assert!(cartridge.audio_output() > 0.0);
+
+    cartridge.write_prg(0x9002, 0x00);
+    assert_eq!(cartridge.audio_output(), 0.0);
+}
+
+#[test]
+fn test_vrc7() {
+    let mut cartridge = Cartridge::from_ines(&rom(85, 2, 8, 8)).unwrap();
+
+    cartridge.write_prg(0x8010, 5);
+    assert_eq!(cartridge.read_prg(0xA000), 2);
+
+    cartridge.write_prg(0xA010, 8 * 6);
+    assert_eq!(cartridge.read_chr(0x0400), 6);
+
+    cartridge.write_prg(0xE000, 0x01);
+    assert_eq!(cartridge.mirroring(), Mirroring::Horizontal);
+}
+
+#[test]
+fn test_vrc7_audio() {
+    let mut cartridge = Cartridge::from_ines(&rom(85, 2, 8, 8)).unwrap();
+
+    // Instrument 3 at full volume, A4 on channel 0
+    let writes = [(0x30, 0x30), (0x10, 0xAC), (0x20, 0x14 | 0x08)];
+    for (register, value) in writes {
+        cartridge.write_prg(0x9010, register);
+        cartridge.write_prg(0x9030, value);
+    }
+
+    let mut peak: f32 = 0.0;
+    for _ in 0..36 * 200 {
+        cartridge.cpu_clock();
+        peak = peak.max(cartridge.audio_output().abs());
+    }
+    assert!(peak > 0.01);
+
+    cartridge.write_prg(0xE000, 0x40);
+    assert_eq!(cartridge.audio_output(), 0.0);
+}
+
+#[test]
+fn test_namco163() {
+    let mut cartridge = Cartridge::from_ines(&rom(19, 0, 8, 8)).unwrap();
+    let mut ciram = [0; 0x800];
+
+    cartridge.write_prg(0xE000, 4);
+    cartridge.write_prg(0xF000, 9);
+    assert_eq!(cartridge.read_prg(0x8000), 2);
+    assert_eq!(cartridge.read_prg(0xC000), 4);
+    assert_eq!(cartridge.read_prg(0xE000), 7);
+
+    cartridge.write_prg(0x8800, 8 * 3);
+    assert_eq!(cartridge.read_chr(0x0400), 3);
+
+    // Nametables can come from either CIRAM page or from CHR ROM
+    cartridge.write_prg(0xC000, 0xE1);
+    cartridge.write_prg(0xC800, 8 * 6);
+    cartridge.write_nametable(0x2000, 0x42, &mut ciram);
+    assert_eq!(ciram[0x400], 0x42);
+    assert_eq!(cartridge.read_nametable(0x2400, &ciram), 6);
+
+    // PRG RAM is write protected unless enabled through $F800
+    cartridge.write_prg(0x6000, 0x42);
+    assert_eq!(cartridge.read_prg(0x6000), 0);
+    cartridge.write_prg(0xF800, 0x40);
+    cartridge.write_prg(0x6000, 0x42);
+    assert_eq!(cartridge.read_prg(0x6000), 0x42);
+}
+
+#[test]
+fn test_namco163_irq() {
+    let mut cartridge = Cartridge::from_ines(&rom(19, 0, 8, 8)).unwrap();
+
+    cartridge.write_prg(0x5000, 0xFD);
+    cartridge.write_prg(0x5800, 0xFF);
+    cartridge.cpu_clock();
+    assert!(!cartridge.irq());
+    cartridge.cpu_clock();
+    assert!(cartridge.irq());
+    assert_eq!(cartridge.read_prg(0x5800), 0xFF);
+
+    cartridge.write_prg(0x5000, 0);
+    assert!(!cartridge.irq());
+}
+
+#[test]
+fn test_namco163_audio() {
+    let mut cartridge = Cartridge::from_ines(&rom(19, 0, 8, 8)).unwrap();
+
+    // A square wave in the first 16 samples of sound RAM
+    cartridge.write_prg(0xF800, 0x80);
+    for value in [0xFF; 4].iter().chain([0x00; 4].iter()) {
+        cartridge.write_prg(0x4800, *value);
+    }
+
+    // Channel 7: frequency, length 16, wave at 0, volume 15, one channel
+    cartridge.write_prg(0xF800, 0x80 | 0x78);
+    for value in [0x00, 0x00, 0x40, 0x00, 0xF0, 0x00, 0x00, 0x0F] {
+        cartridge.write_prg(0x4800, value);
+    }
+
+    let mut outputs = Vec::new();
+    for _ in 0..15 * 32 {
+        cartridge.cpu_clock();
+        outputs.push(cartridge.audio_output());
+    }
+    assert!(outputs.iter().any(|&output| output > 0.0));
+    assert!(outputs.iter().any(|&output| output < 0.0));
+}
+
+#[test]
+fn test_fme7() {
+    let mut cartridge = Cartridge::from_ines(&rom(69, 0, 8, 8)).unwrap();
+
+    cartridge.write_prg(0x8000, 0x09);
+    cartridge.write_prg(0xA000, 5);
+    assert_eq!(cartridge.read_prg(0x8000), 2);
+
+    cartridge.write_prg(0x8000, 0x03);
+    cartridge.write_prg(0xA000, 8 * 4);
+    assert_eq!(cartridge.read_chr(0x0C00), 4);
+
+    // $6000 maps ROM, then RAM once selected and enabled
+    cartridge.write_prg(0x8000, 0x08);
+    cartridge.write_prg(0xA000, 0x02);
+    assert_eq!(cartridge.read_prg(0x6000), 1);
+    cartridge.write_prg(0xA000, 0xC0);
+    cartridge.write_prg(0x6000, 0x42);
+    assert_eq!(cartridge.read_prg(0x6000), 0x42);
+
+    cartridge.write_prg(0x8000, 0x0C);
+    cartridge.write_prg(0xA000, 0x01);
+    assert_eq!(cartridge.mirroring(), Mirroring::Horizontal);
+}
+
+#[test]
+fn test_fme7_irq() {
+    let mut cartridge = Cartridge::from_ines(&rom(69, 0, 8, 8)).unwrap();
+
+    cartridge.write_prg(0x8000, 0x0E);
+    cartridge.write_prg(0xA000, 0x01);
+    cartridge.write_prg(0x8000, 0x0F);
+    cartridge.write_prg(0xA000, 0x00);
+    cartridge.write_prg(0x8000, 0x0D);
+    cartridge.write_prg(0xA000, 0x81);
+
+    cartridge.cpu_clock();
+    assert!(!cartridge.irq());
+    cartridge.cpu_clock();
+    assert!(cartridge.irq());
+
+    cartridge.write_prg(0xA000, 0x00);
+    assert!(!cartridge.irq());
+}
+
+#[test]
+fn test_sunsoft5b_audio() {
+    let mut cartridge = Cartridge::from_ines(&rom(69, 0, 8, 8)).unwrap();
+
+    // Channel A tone only, full volume
+    for (register, value) in [(0x00, 0x10), (0x01, 0x00), (0x07, 0x3E), (0x08, 0x0F)] {
+        cartridge.write_prg(0xC000, register);
+        cartridge.write_prg(0xE000, value);
+    }
+
+    let mut outputs = Vec::new();
+    for _ in 0..256 * 8 {
+        cartridge.cpu_clock();
+        outputs.push(cartridge.audio_output());
+    }
+    assert!(outputs.iter().any(|&output| output > 0.1));
+    assert!(outputs.contains(&0.0));
+
+    // A period of $10 flips every 256 CPU cycles
+    let flips: Vec<usize> = outputs
+        .windows(2)
+        .enumerate()
+        .filter(|(_, pair)| pair[0] != pair[1])
+        .map(|(cycle, _)| cycle)
+        .collect();
+    assert!(flips.len() >= 6);
+    assert!(flips.windows(2).all(|pair| pair[1] - pair[0] == 256));
+}
+
+#[test]
+fn test_mmc2() {
+    let mut cartridge = Cartridge::from_ines(&rom(9, 0, 8, 16)).unwrap();
+
+    cartridge.write_prg(0xA000, 3);
+    assert_eq!(cartridge.read_prg(0x8000), 1);
+    assert_eq!(cartridge.read_prg(0xA000), 6);
+    assert_eq!(cartridge.read_prg(0xE000), 7);
+
+    // $0000: bank 4 for $FD, bank 6 for $FE
+    cartridge.write_prg(0xB000, 4);
+    cartridge.write_prg(0xC000, 6);
+    assert_eq!(cartridge.read_chr(0x0000), 3);
+
+    // The latch flips after the fetch of tile $FD's last row
+    assert_eq!(cartridge.read_chr(0x0FD8), 3);
+    assert_eq!(cartridge.read_chr(0x0000), 2);
+
+    // The MMC2 ignores the rest of the row on the left pattern table
+    cartridge.read_chr(0x0FE9);
+    assert_eq!(cartridge.read_chr(0x0000), 2);
+    cartridge.read_chr(0x0FE8);
+    assert_eq!(cartridge.read_chr(0x0000), 3);
+
+    // The right pattern table latches on the whole row
+    cartridge.write_prg(0xD000, 2);
+    cartridge.write_prg(0xE000, 8);
+    assert_eq!(cartridge.read_chr(0x1000), 4);
+    cartridge.read_chr(0x1FDA);
+    assert_eq!(cartridge.read_chr(0x1000), 1);
+
+    // Peeking leaves the latches alone
+    assert_eq!(cartridge.peek_chr(0x1FE8), 1);
+    assert_eq!(cartridge.read_chr(0x1000), 1);
+}
+
+#[test]
+fn test_mmc2_small_prg_rom() {
+    // 16 KB of PRG-ROM, with the second 8 KB bank filled with 0x42
+    let mut data = rom(9, 0, 1, 16);
+    data[HEADER_SIZE + 0x2000..HEADER_SIZE + 0x4000].fill(0x42);
+
+    let mut cartridge = Cartridge::from_ines(&data).unwrap();
+    assert_eq!(cartridge.read_prg(0xA000), 0);
+    assert_eq!(cartridge.read_prg(0xC000), 0);
+    assert_eq!(cartridge.read_prg(0xE000), 0x42);
+    assert_eq!(cartridge.read_prg(0xFFFC), 0x42);
+}
+
+#[test]
+fn test_mmc4() {
+    let mut cartridge = Cartridge::from_ines(&rom(10, 0, 8, 16)).unwrap();
+
+    cartridge.write_prg(0xA000, 3);
+    assert_eq!(cartridge.read_prg(0x8000), 3);
+    assert_eq!(cartridge.read_prg(0xC000), 7);
+
+    cartridge.write_prg(0x6000, 0x42);
+    assert_eq!(cartridge.read_prg(0x6000), 0x42);
+
+    cartridge.write_prg(0xB000, 4);
+    cartridge.read_chr(0x0FDC);
+    assert_eq!(cartridge.read_chr(0x0000), 2);
+
+    cartridge.write_prg(0xF000, 1);
+    assert_eq!(cartridge.mirroring(), Mirroring::Horizontal);
+}
+
+fn program_flash(cartridge: &mut Cartridge, bank: u8, address: u16, value: u8) {
+    for (bank, address, value) in [
+        (1, 0x9555, 0xAA),
+        (0, 0xAAAA, 0x55),
+        (1, 0x9555, 0xA0),
+        (bank, address, value),
+    ] {
+        cartridge.write_prg(0xC000, bank);
+        cartridge.write_prg(address, value);
+    }
+}
+
+#[test]
+fn test_unrom512() {
+    let mut data = rom(30, 0, 32, 0);
+    data[6] |= 0x08;
+    let mut cartridge = Cartridge::from_ines(&data).unwrap();
+
+    // Without the battery bit the board has bus conflicts and no flash
+    assert!(!cartridge.has_flash());
+    cartridge.write_prg(0xC000, 0xFF);
+    assert_eq!(cartridge.read_prg(0x8000), 31);
+    cartridge.write_prg(0x8000, 0x83);
+    assert_eq!(cartridge.read_prg(0x8000), 3);
+    assert_eq!(cartridge.read_prg(0xC000), 31);
+
+    // The four-screen bit alone means mapper-controlled one-screen
+    assert_eq!(cartridge.mirroring(), Mirroring::SingleScreenLower);
+    data[6] |= 0x02;
+    let mut cartridge = Cartridge::from_ines(&data).unwrap();
+    cartridge.write_prg(0xC000, 0xA0);
+    assert_eq!(cartridge.mirroring(), Mirroring::SingleScreenUpper);
+
+    data[6] |= 0x01;
+    let cartridge = Cartridge::from_ines(&data).unwrap();
+    assert_eq!(cartridge.mirroring(), Mirroring::FourScreen);
+
+    // CHR-RAM is 32 KB in 8 KB banks
+    let mut data = rom(30, 0, 32, 0);
+    data[6] |= 0x02;
+    data[11] = 0x09;
+    let mut cartridge = Cartridge::from_ines(&data).unwrap();
+    cartridge.write_chr(0x0000, 0x11);
+    cartridge.write_prg(0xC000, 0x40);
+    assert_eq!(cartridge.read_chr(0x0000), 0);
+    cartridge.write_chr(0x0000, 0x22);
+    cartridge.write_prg(0xC000, 0x60);
+    assert_eq!(cartridge.read_chr(0x0000), 0);
+    cartridge.write_prg(0xC000, 0x00);
+    assert_eq!(cartridge.read_chr(0x0000), 0x11);
+}
+
+#[test]
+fn test_unrom512_flash() {
+    let mut data = rom(30, 0, 32, 0);
+    data[6] |= 0x02;
+    let mut cartridge = Cartridge::from_ines(&data).unwrap();
+
+    assert!(cartridge.has_flash());
+    assert!(!cartridge.flash_dirty());
+
+    // Programming can only clear bits
+    program_flash(&mut cartridge, 5, 0x8123, 0x0F);
+    cartridge.write_prg(0xC000, 5);
+    assert_eq!(cartridge.read_prg(0x8123), 0x05);
+    assert!(cartridge.flash_dirty());
+
+    // Sector erase
+    for (bank, address, value) in [
+        (1, 0x9555, 0xAA),
+        (0, 0xAAAA, 0x55),
+        (1, 0x9555, 0x80),
+        (1, 0x9555, 0xAA),
+        (0, 0xAAAA, 0x55),
+        (5, 0x8000, 0x30),
+    ] {
+        cartridge.write_prg(0xC000, bank);
+        cartridge.write_prg(address, value);
+    }
+
+    cartridge.write_prg(0xC000, 5);
+    assert_eq!(cartridge.read_prg(0x8123), 0xFF);
+    assert_eq!(cartridge.read_prg(0x8FFF), 0xFF);
+    assert_eq!(cartridge.read_prg(0x9000), 5);
+
+    // Software ID
+    for (bank, address, value) in [(1, 0x9555, 0xAA), (0, 0xAAAA, 0x55), (1, 0x9555, 0x90)] {
+        cartridge.write_prg(0xC000, bank);
+        cartridge.write_prg(address, value);
+    }
+
+    assert_eq!(cartridge.read_prg(0x8000), 0xBF);
+    assert_eq!(cartridge.read_prg(0x8001), 0xB7);
+    cartridge.write_prg(0x8000, 0xF0);
+    assert_eq!(cartridge.read_prg(0x8000), 1);
+}
+
+#[test]
+fn test_flash_persistence() {
+    let mut data = rom(30, 0, 32, 0);
+    data[6] |= 0x02;
+    let path = std::env::temp_dir().join(format!("nesemu-flash-{}.bin", std::process::id()));
+
+    let mut cartridge = Cartridge::from_ines(&data).unwrap();
+    program_flash(&mut cartridge, 7, 0xA000, 0x03);
+    cartridge.save_flash(&path).unwrap();
+    assert!(!cartridge.flash_dirty());
+
+    // Only the rewritten sector is stored
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), 4 + 0x1000);
+
+    let mut cartridge = Cartridge::from_ines(&data).unwrap();
+    cartridge.load_flash(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    cartridge.write_prg(0xC000, 7);
+    assert_eq!(cartridge.read_prg(0xA000), 0x03);
+    assert_eq!(cartridge.read_prg(0xA001), 7);
+    assert!(!cartridge.flash_dirty());
+
+    // A missing file is not an error
+    cartridge.load_flash(&path).unwrap();
+}
+
+#[test]
+fn test_gtrom() {
+    let mut cartridge = Cartridge::from_ines(&rom(111, 0, 32, 0)).unwrap();
+    let mut ciram = [0; 0x0800];
+
+    cartridge.write_prg(0x5000, 0x03);
+    assert_eq!(cartridge.read_prg(0x8000), 6);
+    assert_eq!(cartridge.read_prg(0xC000), 7);
+
+    // Two pages of four-screen nametables on the board
+    cartridge.write_nametable(0x2C00, 0x11, &mut ciram);
+    cartridge.write_prg(0x7000, 0x20);
+    assert_eq!(cartridge.read_nametable(0x2C00, &ciram), 0);
+    cartridge.write_nametable(0x2C00, 0x22, &mut ciram);
+    cartridge.write_prg(0x5000, 0x00);
+    assert_eq!(cartridge.read_nametable(0x2C00, &ciram), 0x11);
+    assert_eq!(ciram, [0; 0x0800]);
+
+    cartridge.write_chr(0x0000, 0x33);
+    cartridge.write_prg(0x5000, 0x10);
+    assert_eq!(cartridge.read_chr(0x0000), 0);
+
+    // The flash command addresses are $5555 and $2AAA of the chip, so
+    // $D555 and $AAAA with bank 0 mapped
+    cartridge.write_prg(0x5000, 0x00);
+    cartridge.write_prg(0xD555, 0xAA);
+    cartridge.write_prg(0xAAAA, 0x55);
+    cartridge.write_prg(0xD555, 0xA0);
+    cartridge.write_prg(0x8000, 0x00);
+    assert_eq!(cartridge.read_prg(0x8000), 0x00);
+    assert!(cartridge.flash_dirty());
+}
+
+#[test]
+fn test_k1029() {
+    let mut cartridge = Cartridge::from_ines(&rom(15, 0, 16, 0)).unwrap();
+
+    // UNROM mode with the fixed bank at B | 7
+    cartridge.write_prg(0x8001, 0x0A);
+    assert_eq!(cartridge.read_prg(0x8000), 10);
+    assert_eq!(cartridge.read_prg(0xC000), 15);
+    assert_eq!(cartridge.mirroring(), Mirroring::Vertical);
+
+    cartridge.write_chr(0x0000, 0x11);
+    assert_eq!(cartridge.read_chr(0x0000), 0x11);
+
+    // NROM-128, with CHR-RAM write-protected
+    cartridge.write_prg(0x8003, 0x45);
+    assert_eq!(cartridge.read_prg(0x8000), 5);
+    assert_eq!(cartridge.read_prg(0xC000), 5);
+    assert_eq!(cartridge.mirroring(), Mirroring::Horizontal);
+    cartridge.write_chr(0x0000, 0x22);
+    assert_eq!(cartridge.read_chr(0x0000), 0x11);
+
+    // Reset returns to the menu
+    cartridge.reset();
+    assert_eq!(cartridge.read_prg(0x8000), 0);
+    assert_eq!(cartridge.read_prg(0xC000), 1);
+}
+
+#[test]
+fn test_caltron() {
+    let mut cartridge = Cartridge::from_ines(&rom(41, 0, 16, 16)).unwrap();
+
+    // The inner CHR register is ignored until a high outer bank is chosen
+    cartridge.write_prg(0x8000, 0x03);
+    assert_eq!(cartridge.read_chr(0x0000), 0);
+
+    cartridge.write_prg(0x602D, 0);
+    assert_eq!(cartridge.read_prg(0x8000), 10);
+    assert_eq!(cartridge.read_prg(0xC000), 11);
+    assert_eq!(cartridge.mirroring(), Mirroring::Horizontal);
+    assert_eq!(cartridge.read_chr(0x0000), 4);
+
+    // Bus conflicts with the ROM's contents
+    cartridge.write_prg(0x8000, 0x03);
+    assert_eq!(cartridge.read_chr(0x0000), 6);
+}
+
+#[test]
+fn test_gk6in1_dip_switches() {
+    let mut cartridge = Cartridge::from_ines(&rom(57, 0, 8, 16)).unwrap();
+
+    assert_eq!(cartridge.dip_switch_count(), 2);
+    assert_eq!(cartridge.read_prg(0x6000), 0);
+    cartridge.set_dip_switches(0x02);
+    assert_eq!(cartridge.read_prg(0x6000), 0x02);
+
+    cartridge.write_prg(0x8800, 0x6A);
+    assert_eq!(cartridge.read_prg(0x8000), 3);
+    assert_eq!(cartridge.read_prg(0xC000), 3);
+    assert_eq!(cartridge.mirroring(), Mirroring::Horizontal);
+
+    // Low CHR bits from both registers, high bit from the first
+    cartridge.write_prg(0x8000, 0x41);
+    assert_eq!(cartridge.read_chr(0x0000), 11);
+
+    // NROM-256
+    cartridge.write_prg(0x8800, 0x70);
+    assert_eq!(cartridge.read_prg(0x8000), 2);
+    assert_eq!(cartridge.read_prg(0xC000), 3);
+}
+
+#[test]
+fn test_address_latch_multicarts() {
+    let mut cartridge = Cartridge::from_ines(&rom(58, 0, 8, 8)).unwrap();
+    cartridge.write_prg(0x80D5, 0);
+    assert_eq!(cartridge.read_prg(0x8000), 5);
+    assert_eq!(cartridge.read_prg(0xC000), 5);
+    assert_eq!(cartridge.read_chr(0x0000), 2);
+    assert_eq!(cartridge.mirroring(), Mirroring::Horizontal);
+    cartridge.write_prg(0x8005, 0);
+    assert_eq!(cartridge.read_prg(0x8000), 4);
+    assert_eq!(cartridge.read_prg(0xC000), 5);
+
+    let mut cartridge = Cartridge::from_ines(&rom(225, 0, 128, 128)).unwrap();
+    cartridge.write_prg(0x5801, 0xF3);
+    assert_eq!(cartridge.read_prg(0x5801), 0x03);
+    cartridge.write_prg(0xF0C5, 0);
+    assert_eq!(cartridge.read_prg(0x8000), 67);
+    assert_eq!(cartridge.read_prg(0xC000), 67);
+    assert_eq!(cartridge.read_chr(0x0000), 69);
+    assert_eq!(cartridge.mirroring(), Mirroring::Horizontal);
+
+    cartridge.reset();
+    assert_eq!(cartridge.read_prg(0x8000), 0);
+    assert_eq!(cartridge.read_prg(0xC000), 1);
+}
+
+#[test]
+fn test_reset_multicarts() {
+    let mut cartridge = Cartridge::from_ines(&rom(60, 0, 4, 4)).unwrap();
+
+    for game in [0, 1, 2, 3, 0] {
+        assert_eq!(cartridge.read_prg(0x8000), game);
+        assert_eq!(cartridge.read_prg(0xC000), game);
+        assert_eq!(cartridge.read_chr(0x0000), game);
+        cartridge.reset();
+    }
+
+    // 22-in-1: Contra at power on, the menu after a reset
+    let mut cartridge = Cartridge::from_ines(&rom(230, 0, 64, 0)).unwrap();
+    cartridge.write_prg(0x8000, 0x03);
+    assert_eq!(cartridge.read_prg(0x8000), 3);
+    assert_eq!(cartridge.read_prg(0xC000), 7);
+
+    cartridge.reset();
+    assert_eq!(cartridge.read_prg(0x8000), 8);
+    assert_eq!(cartridge.read_prg(0xC000), 9);
+    cartridge.write_prg(0x8000, 0x25);
+    assert_eq!(cartridge.read_prg(0x8000), 13);
+    assert_eq!(cartridge.read_prg(0xC000), 13);
+
+    // 42-in-1: reset flips the 512 KB half
+    let mut cartridge = Cartridge::from_ines(&rom(233, 0, 64, 0)).unwrap();
+    cartridge.write_prg(0x8000, 0x62);
+    assert_eq!(cartridge.read_prg(0x8000), 2);
+    assert_eq!(cartridge.mirroring(), Mirroring::Vertical);
+
+    cartridge.reset();
+    cartridge.write_prg(0x8000, 0x22);
+    assert_eq!(cartridge.read_prg(0x8000), 34);
+    assert_eq!(cartridge.mirroring(), Mirroring::SingleScreenLower);
+}
+
+#[test]
+fn test_battery_ram() {
+    let mut data = rom(4, 0, 2, 1);
+    data[6] |= 0x02;
+    let dir = std::env::temp_dir().join(format!("nesemu-battery-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let rom_path = dir.join("game.nes");
+    let save_path = dir.join("game.sav");
+    std::fs::write(&rom_path, &data).unwrap();
+
+    let mut cartridge = Cartridge::open(&rom_path).unwrap();
+    assert_eq!(cartridge.save_path(), Some(save_path.as_path()));
+    assert!(!cartridge.battery_ram_dirty());
+
+    // Rewriting the same value doesn't need a save
+    cartridge.write_prg(0x6000, 0x00);
+    assert!(!cartridge.battery_ram_dirty());
+    cartridge.write_prg(0x6001, 0x42);
+    assert!(cartridge.battery_ram_dirty());
+
+    cartridge.flush().unwrap();
+    assert!(!cartridge.battery_ram_dirty());
+    assert_eq!(std::fs::read(&save_path).unwrap()[..2], [0x00, 0x42]);
+
+    // Dropping the cartridge flushes it too
+    cartridge.write_prg(0x7FFF, 0x24);
+    drop(cartridge);
+
+    let mut cartridge = Cartridge::open(&rom_path).unwrap();
+    assert_eq!(cartridge.read_prg(0x6001), 0x42);
+    assert_eq!(cartridge.read_prg(0x7FFF), 0x24);
+
+    // Frontends can keep saves elsewhere
+    let other_path = dir.join("other.sav");
+    cartridge.set_save_path(Some(other_path.clone()));
+    cartridge.write_prg(0x6000, 0x11);
+    cartridge.flush().unwrap();
+    assert_eq!(std::fs::read(&other_path).unwrap()[0], 0x11);
+
+    drop(cartridge);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_no_battery() {
+    let mut cartridge = Cartridge::from_ines(&rom(4, 0, 2, 1)).unwrap();
+
+    cartridge.write_prg(0x6000, 0x42);
+    assert!(!cartridge.battery_ram_dirty());
+    assert_eq!(cartridge.save_path(), None);
+    cartridge.flush().unwrap();
+}
+
+#[test]
+fn test_hashes() {
+    assert_eq!(hash::crc32(b""), 0);
+    assert_eq!(hash::crc32(b"123456789"), 0xCBF43926);
+
+    assert_eq!(
+        hash::sha1(b"abc"),
+        [
+            0xA9, 0x99, 0x3E, 0x36, 0x47, 0x06, 0x81, 0x6A, 0xBA, 0x3E, 0x25, 0x71, 0x78, 0x50,
+            0xC2, 0x6C, 0x9C, 0xD0, 0xD8, 0x9D
+        ]
+    );
+
+    // Two blocks of padding
+    assert_eq!(hash::sha1(&[b'a'; 56])[..4], [0xC2, 0xDB, 0x33, 0x0F]);
+}
+
+#[test]
+fn test_header_correction() {
+    let data = rom(0, 0, 2, 1);
+    let hash = RomHash::new(&data[HEADER_SIZE..]);
+    let sha1: String = hash
+        .sha1
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+
+    let database = Database::parse(&format!(
+        "# comment\n\n{:08X} mapper=3 mirroring=vertical battery=yes # by CRC32\n",
+        hash.crc32
+    ))
+    .unwrap();
+    assert_eq!(database.len(), 1);
+
+    let cartridge = Cartridge::from_ines_with_database(&data, &database).unwrap();
+    assert_eq!(cartridge.hash, hash);
+    assert_eq!(cartridge.header.mapper, 3);
+    assert_eq!(cartridge.header.mirroring, Mirroring::Vertical);
+    assert!(cartridge.header.battery);
+    assert_eq!(cartridge.header.region, Region::Ntsc);
+
+    // SHA-1 entries win over CRC32 ones
+    let database = Database::parse(&format!(
+        "{:08x} mapper=3\n{} mapper=66 region=pal input=zapper prg-ram=0\n",
+        hash.crc32, sha1
+    ))
+    .unwrap();
+
+    let cartridge = Cartridge::from_ines_with_database(&data, &database).unwrap();
+    assert_eq!(cartridge.header.mapper, 66);
+    assert_eq!(cartridge.header.region, Region::Pal);
+    assert_eq!(cartridge.header.input_device, InputDevice::Zapper);
+    assert_eq!(cartridge.header.prg_ram_size, 0);
+
+    // Unlisted dumps keep their header
+    let cartridge = Cartridge::from_ines_with_database(&rom(0, 0, 1, 1), &database).unwrap();
+    assert_eq!(cartridge.header.mapper, 0);
+}
+
+// Four bytes that bring the CRC32 of `data` followed by them to `target`,
+// by running the CRC backwards
+fn crc32_suffix(data: &[u8], target: u32) -> [u8; 4] {
+    let mut register = !target;
+
+    for _ in 0..32 {
+        register = if register & 0x8000_0000 != 0 {
+            (register ^ 0xEDB8_8320) << 1 | 1
+        } else {
+            register << 1
+        };
+    }
+
+    (register ^ !hash::crc32(data)).to_le_bytes()
+}
+
+#[test]
+fn test_builtin_database() {
+    // A dump of Just Breed with a header claiming NROM
+    let mut data = rom(0, 0, 2, 1);
+    let end = data.len() - 4;
+    let suffix = crc32_suffix(&data[HEADER_SIZE..end], 0x9CBA_DC25);
+    data[end..].copy_from_slice(&suffix);
+
+    let cartridge = Cartridge::from_ines(&data).unwrap();
+    assert_eq!(cartridge.hash.crc32, 0x9CBA_DC25);
+    assert_eq!(cartridge.header.mapper, 5);
+    assert_eq!(cartridge.header.mirroring, Mirroring::Horizontal);
+}
+
+#[test]
+fn test_database_errors() {
+    assert!(!Database::builtin().is_empty());
+
+    let error = Database::parse("12345678 mapper=1\nnothex mapper=1").unwrap_err();
+    assert_eq!(error.line, 2);
+    assert!(Database::parse("12345678 mapper").is_err());
+    assert!(Database::parse("12345678 mirroring=diagonal").is_err());
+    assert!(Database::parse("12345678 colour=blue").is_err());
+}
+
+fn unif(chunks: &[(&[u8; 4], Vec<u8>)]) -> Vec<u8> {
+    let mut data = b"UNIF".to_vec();
+    data.extend([7, 0, 0, 0]);
+    data.resize(32, 0);
+
+    for (id, chunk) in chunks {
+        data.extend(*id);
+        data.extend((chunk.len() as u32).to_le_bytes());
+        data.extend(chunk);
+    }
+
+    data
+}
+
+#[test]
+fn test_unif() {
+    let ines = rom(2, 2, 8, 0);
+    let prg = &ines[HEADER_SIZE..];
+
+    let data = unif(&[
+        (b"MAPR", b"NES-UNROM\0".to_vec()),
+        (b"NAME", b"Test\0".to_vec()),
+        // Chunks are put together in PRG0..PRGF order, not file order
+        (b"PRG1", prg[0x10000..].to_vec()),
+        (b"PRG0", prg[..0x10000].to_vec()),
+        (b"MIRR", vec![1]),
+        (b"BATR", vec![0]),
+        (b"TVCI", vec![1]),
+    ]);
+
+    let mut cartridge = Cartridge::load(&data).unwrap();
+    let mut expected = Cartridge::from_ines(&ines).unwrap();
+
+    assert_eq!(cartridge.header.mapper, 2);
+    assert_eq!(cartridge.header.submapper, 2);
+    assert_eq!(cartridge.header.mirroring, Mirroring::Vertical);
+    assert_eq!(cartridge.header.region, Region::Pal);
+    assert_eq!(cartridge.header.prg_rom_size, 0x20000);
+    assert_eq!(cartridge.header.chr_ram_size, 0x2000);
+    assert!(cartridge.header.battery);
+    assert_eq!(cartridge.hash, expected.hash);
+
+    for bank in [0, 3, 5] {
+        cartridge.write_prg(0x8000, bank);
+        expected.write_prg(0x8000, bank);
+        assert_eq!(cartridge.read_prg(0x8000), expected.read_prg(0x8000));
+        assert_eq!(cartridge.read_prg(0xC000), expected.read_prg(0xC000));
+    }
+}
+
+#[test]
+fn test_unif_errors() {
+    let data = unif(&[(b"MAPR", b"UNL-SOMETHING\0".to_vec())]);
+    assert!(matches!(
+        Cartridge::load(&data),
+        Err(CartridgeError::UnsupportedBoard(board)) if board == "UNL-SOMETHING"
+    ));
+
+    let data = unif(&[(b"PRG0", vec![0; 0x8000])]);
+    assert!(matches!(
+        Cartridge::load(&data),
+        Err(CartridgeError::InvalidHeader)
+    ));
+
+    let mut data = unif(&[
+        (b"MAPR", b"NES-NROM-256\0".to_vec()),
+        (b"PRG0", vec![0; 0x8000]),
+    ]);
+    data.truncate(data.len() - 1);
+    assert!(matches!(
+        Cartridge::load(&data),
+        Err(CartridgeError::Truncated)
+    ));
+}
+
+// Builds a .fds image with the given number of sides, each holding a disk
+// info block, a file count block and one 4-byte file.
+fn fds_image(sides: usize) -> Vec<u8> {
+    let mut data = vec![b'F', b'D', b'S', 0x1A, sides as u8];
+    data.resize(16, 0);
+
+    for side in 0..sides {
+        let mut blocks = vec![0x01];
+        blocks.extend(b"*NINTENDO-HVC*");
+        blocks.resize(56, side as u8);
+        blocks.extend([0x02, 0x01]);
+        blocks.extend([
+            0x03, 0, 0, b'F', b'I', b'L', b'E', 0, 0, 0, 0, 0, 0, 4, 0, 0,
+        ]);
+        blocks.extend([0x04, 0x11, 0x22, 0x33, 0x44]);
+        blocks.resize(65500, 0);
+        data.extend(blocks);
+    }
+
+    data
+}
+
+fn fds(sides: usize) -> Cartridge {
+    let mut cartridge = Cartridge::from_fds(&fds_image(sides), &[0xEA; 0x2000]).unwrap();
+    cartridge.write_prg(0x4023, 0x01);
+    cartridge
+}
+
+fn clock_until_irq(cartridge: &mut Cartridge) {
+    for _ in 0..1_000_000 {
+        cartridge.cpu_clock();
+
+        if cartridge.irq() {
+            return;
+        }
+    }
+
+    panic!("no IRQ");
+}
+
+#[test]
+fn test_fds_memory() {
+    let mut cartridge = fds(1);
+    assert_eq!(cartridge.header.mapper, 20);
+    assert_eq!(cartridge.read_prg(0xE000), 0xEA);
+    assert_eq!(cartridge.read_prg(0xFFFF), 0xEA);
+
+    // 32 KB of PRG-RAM and 8 KB of CHR-RAM
+    cartridge.write_prg(0x6000, 0x12);
+    cartridge.write_prg(0xDFFF, 0x34);
+    assert_eq!(cartridge.read_prg(0x6000), 0x12);
+    assert_eq!(cartridge.read_prg(0xDFFF), 0x34);
+    cartridge.write_chr(0x1FFF, 0x56);
+    assert_eq!(cartridge.read_chr(0x1FFF), 0x56);
+
+    cartridge.write_prg(0x4025, 0x08);
+    assert_eq!(cartridge.mirroring(), Mirroring::Horizontal);
+    cartridge.write_prg(0x4025, 0x00);
+    assert_eq!(cartridge.mirroring(), Mirroring::Vertical);
+
+    assert!(matches!(
+        Cartridge::from_fds(&fds_image(1), &[0; 0x1000]),
+        Err(CartridgeError::InvalidBios)
+    ));
+    assert!(matches!(
+        Cartridge::from_fds(&[0; 1000], &[0; 0x2000]),
+        Err(CartridgeError::InvalidHeader)
+    ));
+}
+
+#[test]
+fn test_fds_timer_irq() {
+    let mut cartridge = fds(1);
+    cartridge.write_prg(0x4020, 10);
+    cartridge.write_prg(0x4021, 0);
+    cartridge.write_prg(0x4022, 0x02);
+
+    for _ in 0..10 {
+        cartridge.cpu_clock();
+    }
+
+    assert!(!cartridge.irq());
+    cartridge.cpu_clock();
+    assert!(cartridge.irq());
+    assert_eq!(cartridge.read_prg(0x4030) & 0x01, 0x01);
+    assert!(!cartridge.irq());
+
+    // Without repeat, the timer stops after firing
+    for _ in 0..100 {
+        cartridge.cpu_clock();
+    }
+
+    assert!(!cartridge.irq());
+}
+
+#[test]
+fn test_fds_disk_read() {
+    let mut cartridge = fds(1);
+    assert_eq!(cartridge.read_prg(0x4032) & 0x01, 0);
+
+    // Motor on, reading, with the transfer and IRQs enabled
+    cartridge.write_prg(0x4025, 0xC5);
+
+    // The gap and its $80 mark are skipped
+    clock_until_irq(&mut cartridge);
+    assert_eq!(cartridge.read_prg(0x4031), 0x01);
+    assert!(!cartridge.irq());
+
+    for &expected in b"*NINTENDO-HVC*" {
+        clock_until_irq(&mut cartridge);
+        assert_eq!(cartridge.read_prg(0x4031), expected);
+    }
+
+    assert_eq!(cartridge.read_prg(0x4032) & 0x02, 0);
+}
+
+#[test]
+fn test_fds_sides() {
+    let mut cartridge = fds(2);
+    assert_eq!(cartridge.disk_side_count(), 2);
+    assert_eq!(cartridge.inserted_disk_side(), Some(0));
+
+    cartridge.eject_disk();
+    assert_eq!(cartridge.inserted_disk_side(), None);
+    assert_eq!(cartridge.read_prg(0x4032) & 0x07, 0x07);
+
+    assert!(!cartridge.insert_disk_side(2));
+    assert!(cartridge.insert_disk_side(1));
+    assert_eq!(cartridge.inserted_disk_side(), Some(1));
+    assert_eq!(cartridge.read_prg(0x4032) & 0x05, 0);
+
+    // Other cartridges have no disk
+    let mut cartridge = Cartridge::from_ines(&rom(0, 0, 2, 1)).unwrap();
+    assert_eq!(cartridge.disk_side_count(), 0);
+    assert!(!cartridge.insert_disk_side(0));
+}
+
+#[test]
+fn test_fds_disk_writes() {
+    let path = std::env::temp_dir().join(format!("nesemu-disk-{}.sav", std::process::id()));
+    let mut cartridge = fds(2);
+    cartridge.eject_disk();
+    cartridge.insert_disk_side(1);
+
+    // Write a single byte at the start of the side, then stop the motor
+    cartridge.write_prg(0x4024, 0x5A);
+    cartridge.write_prg(0x4025, 0x41);
+
+    for _ in 0..50002 {
+        cartridge.cpu_clock();
+    }
+
+    cartridge.write_prg(0x4025, 0x00);
+    assert!(cartridge.disk_dirty());
+
+    cartridge.save_disk_writes(&path).unwrap();
+    assert!(!cartridge.disk_dirty());
+
+    // Side, offset and length, then the byte
+    assert_eq!(std::fs::read(&path).unwrap(), [1, 0, 0, 0, 0, 1, 0, 0x5A]);
+
+    let mut cartridge = fds(2);
+    cartridge.load_disk_writes(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(!cartridge.disk_dirty());
+
+    cartridge.eject_disk();
+    cartridge.insert_disk_side(1);
+    cartridge.write_prg(0x4025, 0x45);
+
+    for _ in 0..50002 {
+        cartridge.cpu_clock();
+    }
+
+    assert_eq!(cartridge.read_prg(0x4030) & 0x02, 0x02);
+    assert_eq!(cartridge.read_prg(0x4031), 0x5A);
+
+    // A missing file is not an error
+    cartridge.load_disk_writes(&path).unwrap();
+}
+
+#[test]
+fn test_fds_audio() {
+    let mut cartridge = fds(1);
+    cartridge.write_prg(0x4023, 0x03);
+
+    // The wave table is only writable while $4089 bit 7 is set
+    cartridge.write_prg(0x4040, 0x3F);
+    assert_eq!(cartridge.read_prg(0x4040), 0x40);
+    cartridge.write_prg(0x4089, 0x80);
+
+    for address in 0x4040..0x4080 {
+        cartridge.write_prg(address, 0x3F);
+    }
+
+    assert_eq!(cartridge.read_prg(0x4040), 0x7F);
+    cartridge.write_prg(0x4089, 0x00);
+
+    // Fixed gain of 32 and a running channel
+    cartridge.write_prg(0x4080, 0xA0);
+    cartridge.write_prg(0x4082, 0xFF);
+    cartridge.write_prg(0x4083, 0x0F);
+    assert_eq!(cartridge.read_prg(0x4090), 0x60);
+
+    for _ in 0..10 {
+        cartridge.cpu_clock();
+    }
+
+    assert!(cartridge.audio_output() > 0.0);
+
+    // Halting the channel holds its output
+    cartridge.write_prg(0x4083, 0x80);
+    let output = cartridge.audio_output();
+    cartridge.cpu_clock();
+    assert_eq!(cartridge.audio_output(), output);
+}
+
+// BPS and UPS numbers
+fn patch_number(mut value: usize) -> Vec<u8> {
+    let mut bytes = Vec::new();
+
+    loop {
+        let digit = (value & 0x7F) as u8;
+        value >>= 7;
+
+        if value == 0 {
+            bytes.push(digit | 0x80);
+            return bytes;
+        }
+
+        bytes.push(digit);
+        value -= 1;
+    }
+}
+
+fn patch_footer(mut patch: Vec<u8>, source: &[u8], target: &[u8]) -> Vec<u8> {
+    patch.extend(hash::crc32(source).to_le_bytes());
+    patch.extend(hash::crc32(target).to_le_bytes());
+    patch.extend(hash::crc32(&patch).to_le_bytes());
+    patch
+}
+
+#[test]
+fn test_ips_patch() {
+    let mut ips = b"PATCH".to_vec();
+    ips.extend([0x00, 0x00, 0x02, 0x00, 0x02, b'X', b'Y']);
+    // Run-length encoded, past the end of the ROM
+    ips.extend([0x00, 0x00, 0x0A, 0x00, 0x00, 0x00, 0x03, b'Z']);
+    ips.extend(b"EOF");
+
+    let patched = patch::apply(b"ABCDEFGH", &ips).unwrap();
+    assert_eq!(patched, b"ABXYEFGH\0\0ZZZ");
+
+    // Truncated to the size after EOF
+    ips.extend([0x00, 0x00, 0x04]);
+    assert_eq!(patch::apply(b"ABCDEFGH", &ips).unwrap(), b"ABXY");
+
+    ips.truncate(10);
+    assert_eq!(
+        patch::apply(b"ABCDEFGH", &ips),
+        Err(patch::PatchError::Malformed)
+    );
+    assert_eq!(
+        patch::apply(b"ABCDEFGH", b"PATC"),
+        Err(patch::PatchError::UnknownFormat)
+    );
+}
+
+#[test]
+fn test_bps_patch() {
+    let source = b"ABCDEFGH";
+    let target = b"ABCDxyxyxyEF";
+
+    let mut bps = b"BPS1".to_vec();
+    bps.extend(patch_number(source.len()));
+    bps.extend(patch_number(target.len()));
+    bps.extend(patch_number(4));
+    bps.extend(b"meta");
+    // SourceRead 4, TargetRead 2, an overlapping TargetCopy 4 from +4 and a
+    // SourceCopy 2 from +4
+    bps.extend(patch_number(3 << 2));
+    bps.extend(patch_number((1 << 2) | 1));
+    bps.extend(b"xy");
+    bps.extend(patch_number((3 << 2) | 3));
+    bps.extend(patch_number(4 << 1));
+    bps.extend(patch_number((1 << 2) | 2));
+    bps.extend(patch_number(4 << 1));
+    let bps = patch_footer(bps, source, target);
+
+    assert_eq!(patch::apply(source, &bps).unwrap(), target);
+    assert_eq!(
+        patch::apply(b"ABCDEFGX", &bps),
+        Err(patch::PatchError::SourceMismatch)
+    );
+
+    let mut corrupt = bps.clone();
+    corrupt[8] ^= 0x01;
+    assert_eq!(
+        patch::apply(source, &corrupt),
+        Err(patch::PatchError::PatchChecksum)
+    );
+
+    // A patch that's consistent with itself but produces the wrong target
+    let wrong = patch_footer(bps[..bps.len() - 12].to_vec(), source, b"ABCDxyxyxyEG");
+    assert_eq!(
+        patch::apply(source, &wrong),
+        Err(patch::PatchError::TargetMismatch)
+    );
+}
+
+#[test]
+fn test_ups_patch() {
+    let source = b"ABCDEFGH";
+    let target = b"ABXDEFGHIJ";
+
+    let mut ups = b"UPS1".to_vec();
+    ups.extend(patch_number(source.len()));
+    ups.extend(patch_number(target.len()));
+    ups.extend(patch_number(2));
+    ups.extend([b'C' ^ b'X', 0x00]);
+    // The terminator skipped D, leaving 4 bytes to go
+    ups.extend(patch_number(4));
+    ups.extend([b'I', b'J', 0x00]);
+    let ups = patch_footer(ups, source, target);
+
+    assert_eq!(patch::apply(source, &ups).unwrap(), target);
+    assert_eq!(
+        patch::apply(target, &ups),
+        Err(patch::PatchError::SourceMismatch)
+    );
+}
+
+#[test]
+fn test_open_patched() {
+    let dir = std::env::temp_dir().join(format!("nesemu-patch-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let rom_path = dir.join("game.nes");
+    std::fs::write(&rom_path, rom(0, 0, 1, 1)).unwrap();
+
+    // Rewrites the first byte of PRG-ROM
+    let mut ips = b"PATCH".to_vec();
+    ips.extend([0x00, 0x00, 0x10, 0x00, 0x01, 0x42]);
+    ips.extend(b"EOF");
+    let patch_path = dir.join("other.ips");
+    std::fs::write(&patch_path, &ips).unwrap();
+
+    let options = OpenOptions {
+        patch: Some(patch_path.clone()),
+        auto_patch: false,
+        entry: None,
+        fds_bios: None,
+    };
+    let mut cartridge = Cartridge::open_with(&rom_path, &options).unwrap();
+    assert_eq!(cartridge.read_prg(0x8000), 0x42);
+
+    // Only patches named like the ROM are found
+    let options = OpenOptions {
+        patch: None,
+        auto_patch: true,
+        entry: None,
+        fds_bios: None,
+    };
+    let mut cartridge = Cartridge::open_with(&rom_path, &options).unwrap();
+    assert_eq!(cartridge.read_prg(0x8000), 0x00);
+
+    std::fs::rename(&patch_path, dir.join("game.ips")).unwrap();
+    let mut cartridge = Cartridge::open_with(&rom_path, &options).unwrap();
+    assert_eq!(cartridge.read_prg(0x8000), 0x42);
+
+    let mut cartridge = Cartridge::open(&rom_path).unwrap();
+    assert_eq!(cartridge.read_prg(0x8000), 0x00);
+
+    std::fs::write(dir.join("game.ips"), b"PATCH").unwrap();
+    assert!(matches!(
+        Cartridge::open_with(&rom_path, &options),
+        Err(CartridgeError::Patch(patch::PatchError::Malformed))
+    ));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,159 @@
+// UNIF images: a 32 byte header followed by tagged chunks, with the board
+// named instead of numbered
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use super::{CartridgeError, Header, InputDevice, Mirroring, Region};
+
+pub const UNIF_MAGIC: [u8; 4] = *b"UNIF";
+const UNIF_HEADER_SIZE: usize = 32;
+const CHUNK_HEADER_SIZE: usize = 8;
+
+// Prefixes naming the board's maker, which don't change how it works
+const BOARD_PREFIXES: [&str; 8] = [
+    "NES-", "HVC-", "UNL-", "BTL-", "BMC-", "IREM-", "KONAMI-", "AVE-",
+];
+
+pub fn parse(data: &[u8]) -> Result<(Header, Vec<u8>, Vec<u8>), CartridgeError> {
+    if data.len() < UNIF_HEADER_SIZE || data[0..4] != UNIF_MAGIC {
+        return Err(CartridgeError::InvalidHeader);
+    }
+
+    let mut board = None;
+    let mut prg_chunks: [&[u8]; 16] = [&[]; 16];
+    let mut chr_chunks: [&[u8]; 16] = [&[]; 16];
+    let mut mirroring = None;
+    let mut battery = false;
+    let mut region = Region::Ntsc;
+
+    let mut offset = UNIF_HEADER_SIZE;
+
+    while offset < data.len() {
+        if data.len() < offset + CHUNK_HEADER_SIZE {
+            return Err(CartridgeError::Truncated);
+        }
+
+        let id = &data[offset..offset + 4];
+        let length = LittleEndian::read_u32(&data[offset + 4..]) as usize;
+        let start = offset + CHUNK_HEADER_SIZE;
+        let end = start.checked_add(length).ok_or(CartridgeError::Truncated)?;
+
+        if data.len() < end {
+            return Err(CartridgeError::Truncated);
+        }
+
+        let chunk = &data[start..end];
+
+        match id {
+            b"MAPR" => {
+                let name = chunk.split(|&byte| byte == 0).next().unwrap_or(&[]);
+                board = Some(String::from_utf8_lossy(name).trim().to_string());
+            }
+            [b'P', b'R', b'G', index] => prg_chunks[chunk_index(*index)?] = chunk,
+            [b'C', b'H', b'R', index] => chr_chunks[chunk_index(*index)?] = chunk,
+            b"MIRR" => {
+                mirroring = chunk.first().map(|&value| match value {
+                    1 => Mirroring::Vertical,
+                    2 => Mirroring::SingleScreenLower,
+                    3 => Mirroring::SingleScreenUpper,
+                    4 => Mirroring::FourScreen,
+                    // 0 is horizontal, 5 is left to the mapper
+                    _ => Mirroring::Horizontal,
+                });
+            }
+            b"BATR" => battery = true,
+            b"TVCI" => {
+                region = match chunk.first() {
+                    Some(1) => Region::Pal,
+                    Some(2) => Region::Multiple,
+                    _ => Region::Ntsc,
+                };
+            }
+            // NAME, READ, DINF, CTRL and the checksums don't affect emulation
+            _ => {}
+        }
+
+        offset = end;
+    }
+
+    let board = board.ok_or(CartridgeError::InvalidHeader)?;
+    let (mapper, submapper) =
+        board_mapper(&board).ok_or(CartridgeError::UnsupportedBoard(board.clone()))?;
+
+    let prg_rom = prg_chunks.concat();
+    let chr_rom = chr_chunks.concat();
+    let mirroring = mirroring.unwrap_or(Mirroring::Horizontal);
+
+    let header = Header {
+        mapper,
+        submapper,
+        mirroring,
+        hardwired_mirroring: if mirroring == Mirroring::Vertical {
+            Mirroring::Vertical
+        } else {
+            Mirroring::Horizontal
+        },
+        battery,
+        trainer: false,
+        nes2: false,
+        prg_rom_size: prg_rom.len(),
+        chr_rom_size: chr_rom.len(),
+        prg_ram_size: 0x2000,
+        chr_ram_size: if chr_rom.is_empty() {
+            chr_ram_size(&board)
+        } else {
+            0
+        },
+        region,
+        input_device: InputDevice::Unspecified,
+    };
+
+    Ok((header, prg_rom, chr_rom))
+}
+
+fn chunk_index(digit: u8) -> Result<usize, CartridgeError> {
+    (digit as char)
+        .to_digit(16)
+        .map(|index| index as usize)
+        .ok_or(CartridgeError::InvalidHeader)
+}
+
+fn strip_prefix(board: &str) -> &str {
+    BOARD_PREFIXES
+        .iter()
+        .find_map(|prefix| board.strip_prefix(prefix))
+        .unwrap_or(board)
+}
+
+// Mapper and submapper numbers of the boards we support
+fn board_mapper(board: &str) -> Option<(u16, u8)> {
+    let mapper = match strip_prefix(board) {
+        "NROM" | "NROM-128" | "NROM-256" | "RROM" | "RROM-128" => (0, 0),
+        "UNROM" | "UOROM" => (2, 2),
+        "CNROM" => (3, 2),
+        "TBROM" | "TEROM" | "TFROM" | "TGROM" | "TKROM" | "TLROM" | "TNROM" | "TR1ROM"
+        | "TSROM" | "TVROM" => (4, 0),
+        "EKROM" | "ELROM" | "ETROM" | "EWROM" => (5, 0),
+        "ANROM" | "AN1ROM" => (7, 1),
+        "AMROM" | "AOROM" => (7, 2),
+        "PNROM" | "PEEOROM" => (9, 0),
+        "FJROM" | "FKROM" => (10, 0),
+        "UNROM-512-8" | "UNROM-512-16" | "UNROM-512-32" => (30, 0),
+        "BNROM" => (34, 2),
+        "NINA-001" | "NINA-01" => (34, 1),
+        "GK-192" => (58, 0),
+        "GNROM" | "MHROM" => (66, 0),
+        "JLROM" | "JSROM" | "BTR" => (69, 0),
+        _ => return None,
+    };
+
+    Some(mapper)
+}
+
+fn chr_ram_size(board: &str) -> usize {
+    match strip_prefix(board) {
+        "UNROM-512-16" => 0x4000,
+        "UNROM-512-32" => 0x8000,
+        _ => 0x2000,
+    }
+}
@@ -1,5 +1,13 @@
 use bitflags::bitflags;
-use byteorder::{ByteOrder, LittleEndian};
+
+use crate::apu::Apu;
+use crate::cartridge::{Cartridge, Region};
+use crate::ppu::Ppu;
+use crate::VideoInterface;
+
+// Where a subroutine started by `call` returns to. Nothing runs code from
+// the PPU registers.
+const CALL_RETURN: u16 = 0x3FF0;
 
 const INITIAL_STATUS_FLAGS: StatusFlags = StatusFlags::from_bits_truncate(0b0010_0100);
 
@@ -29,7 +37,25 @@ pub struct CPU {
     status: StatusFlags,
     addressed: u16,
     implied: bool,
-    memory: [u8; 0xFFFF],
+    memory: [u8; 0x10000],
+    apu: Apu,
+    ppu: Ppu,
+    // Takes over $4020-$FFFF when inserted
+    cartridge: Option<Cartridge>,
+    cycles: u64,
+    nmi_pending: bool,
+    // Cycles the CPU is halted for by OAM DMA
+    dma_stall: u16,
+    region: Region,
+    // PAL runs 16 PPU dots every 5 CPU cycles, so this counts to the extra
+    // one
+    dot_phase: u8,
+}
+
+impl Default for CPU {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl CPU {
@@ -45,10 +71,81 @@ impl CPU {
             status: INITIAL_STATUS_FLAGS,
             addressed: 0,
             implied: false,
-            memory: [0; 0xFFFF],
+            memory: [0; 0x10000],
+            apu: Apu::new(),
+            ppu: Ppu::new(),
+            cartridge: None,
+            cycles: 0,
+            nmi_pending: false,
+            dma_stall: 0,
+            region: Region::Ntsc,
+            dot_phase: 0,
         }
     }
 
+    // Takes the region from the cartridge's header, or the database's
+    // correction of it
+    pub fn insert_cartridge(&mut self, cartridge: Cartridge) {
+        self.set_region(cartridge.header.region);
+        self.cartridge = Some(cartridge);
+    }
+
+    // Dendy has PAL's frame with NTSC's 3 dots a cycle, and APU tables
+    // nearer NTSC's
+    pub fn set_region(&mut self, region: Region) {
+        self.region = match region {
+            Region::Multiple => Region::Ntsc,
+            region => region,
+        };
+        self.apu.set_region(self.region);
+        self.ppu.set_region(self.region);
+        self.dot_phase = 0;
+    }
+
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    pub fn eject_cartridge(&mut self) -> Option<Cartridge> {
+        self.cartridge.take()
+    }
+
+    pub fn cartridge_mut(&mut self) -> Option<&mut Cartridge> {
+        self.cartridge.as_mut()
+    }
+
+    pub fn apu_mut(&mut self) -> &mut Apu {
+        &mut self.apu
+    }
+
+    pub fn ppu(&self) -> &Ppu {
+        &self.ppu
+    }
+
+    // The PPU along with the cartridge on its bus, for peeking at VRAM
+    pub fn ppu_bus(&mut self) -> (&Ppu, Option<&mut Cartridge>) {
+        (&self.ppu, self.cartridge.as_mut())
+    }
+
+    pub fn ppu_mut(&mut self) -> &mut Ppu {
+        &mut self.ppu
+    }
+
+    // Number of CPU cycles run so far
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    // The APU mixed with the cartridge's expansion audio
+    pub fn audio_output(&self) -> f32 {
+        let expansion = self
+            .cartridge
+            .as_ref()
+            .map_or(0.0, |cartridge| cartridge.audio_output());
+
+        self.apu.output() + expansion
+    }
+
     pub fn reset(&mut self) {
         self.registers = Registers {
             a: 0,
@@ -66,19 +163,138 @@ impl CPU {
     }
 
     pub fn run(&mut self) {
-        loop {
-            let opcode = self.read(self.registers.pc);
-            let instruction = self.fetch_instruction(opcode);
+        while self.read(self.registers.pc) != 0x00 {
+            self.step();
+        }
+    }
+
+    // Runs one instruction and clocks the rest of the console for as long
+    // as it takes. Returns the number of cycles it took.
+    pub fn step(&mut self) -> u16 {
+        // Interrupts are taken between instructions, in place of the next one
+        if self.nmi_pending {
+            self.nmi_pending = false;
+            return self.interrupt(0xFFFA);
+        }
+
+        if self.irq_pending() {
+            return self.interrupt(0xFFFE);
+        }
+
+        let opcode = self.read(self.registers.pc);
+        let instruction = self.fetch_instruction(opcode);
+
+        self.registers.pc = self.registers.pc.wrapping_add(1);
+        self.implied = false;
+
+        (instruction.mode)(self);
+        (instruction.operate)(self);
+
+        for _ in 0..instruction.cycles {
+            self.clock();
+        }
+
+        // OAM DMA takes over the bus once the write that started it is done
+        let stall = std::mem::take(&mut self.dma_stall);
+
+        for _ in 0..stall {
+            self.clock();
+        }
+
+        instruction.cycles as u16 + stall
+    }
+
+    // Runs until the PPU finishes a frame, then hands it to `video`
+    pub fn run_frame(&mut self, video: &mut dyn VideoInterface) {
+        while !self.ppu.poll_frame() {
+            self.step();
+        }
+
+        video.frame(self.ppu.framebuffer());
+    }
+
+    // Raises an NMI for the next `step` to take
+    pub fn nmi(&mut self) {
+        self.nmi_pending = true;
+    }
+
+    // Whether an IRQ is asserted and not masked, so `step` will take it
+    pub fn irq_pending(&self) -> bool {
+        let asserted = self.apu.irq()
+            || self
+                .cartridge
+                .as_ref()
+                .is_some_and(|cartridge| cartridge.irq());
+
+        asserted && !self.status.contains(StatusFlags::INTERRUPT_DISABLE)
+    }
+
+    fn interrupt(&mut self, vector: u16) -> u16 {
+        const CYCLES: u16 = 7;
+
+        self.push_word(self.registers.pc);
+        self.push(((self.status - StatusFlags::BREAK) | StatusFlags::UNUSED).bits());
+        self.status.insert(StatusFlags::INTERRUPT_DISABLE);
+        self.registers.pc = self.read_word(vector);
+
+        for _ in 0..CYCLES {
+            self.clock();
+        }
+
+        CYCLES
+    }
 
-            if opcode == 0x00 {
-                break;
+    // Sets up a call to the subroutine at `address`, as if from JSR, for
+    // `step` to run. `returned` tells when it's done.
+    pub fn call(&mut self, address: u16, a: u8, x: u8) {
+        self.registers.a = a;
+        self.registers.x = x;
+        self.registers.y = 0;
+        self.registers.sp = 0xFD;
+        self.push_word(CALL_RETURN.wrapping_sub(1));
+        self.registers.pc = address;
+    }
+
+    pub fn returned(&self) -> bool {
+        self.registers.pc == CALL_RETURN
+    }
+
+    // Lets one cycle pass with the CPU doing nothing
+    pub fn idle(&mut self) {
+        self.clock();
+    }
+
+    fn clock(&mut self) {
+        self.cycles += 1;
+        self.apu.clock();
+
+        if let Some(cartridge) = &mut self.cartridge {
+            cartridge.cpu_clock();
+        }
+
+        let mut dots = 3;
+
+        if self.region == Region::Pal {
+            self.dot_phase = (self.dot_phase + 1) % 5;
+
+            if self.dot_phase == 0 {
+                dots += 1;
             }
+        }
 
-            self.registers.pc += 1;
-            self.implied = false;
+        for _ in 0..dots {
+            self.ppu.clock(self.cartridge.as_mut());
+        }
+
+        if self.ppu.poll_nmi() {
+            self.nmi_pending = true;
+        }
 
-            (instruction.mode)(self);
-            (instruction.operate)(self);
+        // The DMC steals the bus to fetch its samples. The stalled cycles
+        // aren't emulated.
+        if let Some(address) = self.apu.dmc_request() {
+            let value = self.read(address);
+            self.apu.fill_dmc(value);
         }
     }
 
@@ -89,23 +305,75 @@ impl CPU {
     }
 
     #[inline]
-    fn read(&self, address: u16) -> u8 {
-        self.memory[address as usize]
+    pub fn read(&mut self, address: u16) -> u8 {
+        if let 0x2000..=0x3FFF = address {
+            return self.ppu.read_register(address, self.cartridge.as_mut());
+        }
+
+        if address == 0x4015 {
+            return self.apu.read_status();
+        }
+
+        match &mut self.cartridge {
+            Some(cartridge) if address >= 0x4020 => cartridge.read_prg(address),
+            _ => self.memory[address as usize],
+        }
     }
 
     #[inline]
-    fn write(&mut self, address: u16, value: u8) {
-        self.memory[address as usize] = value;
+    pub fn write(&mut self, address: u16, value: u8) {
+        if let 0x2000..=0x3FFF = address {
+            return self
+                .ppu
+                .write_register(address, value, self.cartridge.as_mut());
+        }
+
+        if address == 0x4014 {
+            return self.oam_dma(value);
+        }
+
+        // The APU registers are write-only, so memory keeps what was last
+        // written for reads
+        if let 0x4000..=0x4013 | 0x4015 | 0x4017 = address {
+            self.apu.write(address, value);
+        }
+
+        // Nothing on the console answers at $4018-$401F, which leaves room
+        // for NSF players' IRQ timer
+        if let (Some(cartridge), 0x4018..=0x401F) = (&mut self.cartridge, address) {
+            cartridge.write_prg(address, value);
+        }
+
+        match &mut self.cartridge {
+            Some(cartridge) if address >= 0x4020 => cartridge.write_prg(address, value),
+            _ => self.memory[address as usize] = value,
+        }
+    }
+
+    // Copies a page of CPU memory into OAM. The CPU is halted for 513
+    // cycles, or 514 when the DMA has to wait for an odd cycle to start.
+    fn oam_dma(&mut self, page: u8) {
+        let start = (page as u16) << 8;
+
+        for offset in 0..256 {
+            let value = self.read(start + offset);
+            self.ppu.write_oam(value);
+        }
+
+        self.dma_stall = 513 + (self.cycles & 1) as u16;
     }
 
     #[inline]
-    fn read_word(&self, address: u16) -> u16 {
-        LittleEndian::read_u16(&self.memory[address as usize..])
+    fn read_word(&mut self, address: u16) -> u16 {
+        let low = self.read(address) as u16;
+        let high = self.read(address.wrapping_add(1)) as u16;
+        high << 8 | low
     }
 
     #[inline]
     fn write_word(&mut self, address: u16, value: u16) {
-        LittleEndian::write_u16(&mut self.memory[address as usize..], value);
+        self.write(address, value as u8);
+        self.write(address.wrapping_add(1), (value >> 8) as u8);
     }
 
     #[inline]
@@ -122,14 +390,16 @@ impl CPU {
 
     #[inline]
     fn pull_word(&mut self) -> u16 {
-        self.registers.sp = self.registers.sp.wrapping_add(2);
-        self.read_word(0x0100 + self.registers.sp as u16)
+        let low = self.pull() as u16;
+        let high = self.pull() as u16;
+        high << 8 | low
     }
 
+    // The high byte goes first, so the word sits little-endian on the stack
     #[inline]
     fn push_word(&mut self, value: u16) {
-        self.write_word(0x0100 + self.registers.sp as u16, value);
-        self.registers.sp = self.registers.sp.wrapping_sub(2);
+        self.push((value >> 8) as u8);
+        self.push(value as u8);
     }
 
     #[inline]
@@ -182,7 +452,8 @@ impl CPU {
     }
 
     fn indirect(&mut self) {
-        self.addressed = self.read_word(self.read_word(self.registers.pc));
+        let ptr = self.read_word(self.registers.pc);
+        self.addressed = self.read_word(ptr);
     }
 
     fn indirect_x(&mut self) {
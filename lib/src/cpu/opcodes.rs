@@ -385,7 +385,11 @@ impl CPU {
     }
 
     fn brk(&mut self) {
-        todo!();
+        // BRK skips a padding byte
+        self.push_word(self.registers.pc.wrapping_add(1));
+        self.push((self.status | StatusFlags::BREAK | StatusFlags::UNUSED).bits());
+        self.status.insert(StatusFlags::INTERRUPT_DISABLE);
+        self.registers.pc = self.read_word(0xFFFE);
     }
 
     fn clc(&mut self) {
@@ -634,7 +638,7 @@ impl CPU {
     }
 
     fn rts(&mut self) {
-        self.registers.pc = self.pull_word() + 1;
+        self.registers.pc = self.pull_word().wrapping_add(1);
     }
 
     fn sbc(&mut self) {
@@ -730,3 +730,154 @@ fn test_tya() {
     ]);
     assert_eq!(cpu.registers.a, 0x42);
 }
+
+#[test]
+fn test_cartridge_bus() {
+    // NROM with 8 KB of PRG-RAM and a reset vector pointing at $8000
+    let mut rom = vec![b'N', b'E', b'S', 0x1A, 1, 1, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0];
+    rom.extend(vec![0xEA; 0x4000]);
+    rom[16 + 0x3FFC] = 0x00;
+    rom[16 + 0x3FFD] = 0x80;
+    rom.extend(vec![0; 0x2000]);
+
+    let mut cpu = CPU::new();
+    cpu.insert_cartridge(Cartridge::from_ines(&rom).unwrap());
+
+    cpu.write(0x6000, 0x42);
+    assert_eq!(cpu.read(0x6000), 0x42);
+    assert_eq!(cpu.cartridge_mut().unwrap().read_prg(0x6000), 0x42);
+
+    // Everything below $4020 stays on the console
+    cpu.write(0x0200, 0x24);
+    assert_eq!(cpu.read(0x0200), 0x24);
+    assert_eq!(cpu.cartridge_mut().unwrap().read_prg(0x0200), 0);
+
+    cpu.reset();
+    assert_eq!(cpu.registers.pc, 0x8000);
+    assert_eq!(cpu.read(0x8000), 0xEA);
+
+    assert!(cpu.eject_cartridge().is_some());
+    assert_eq!(cpu.read(0x6000), 0);
+}
+
+#[test]
+fn test_jsr_stack_order() {
+    let mut cpu = CPU::new();
+    cpu.write(0x4000, 0x68); // PLA
+    cpu.load_and_run(vec![
+        0xA9, 0x42, // LDA #$42
+        0x48, // PHA
+        0x20, 0x00, 0x40, // JSR $4000
+    ]);
+
+    // The return address goes high byte first, under the pushed value
+    assert_eq!(cpu.read(0x01FD), 0x42);
+    assert_eq!(cpu.read(0x01FC), 0x80);
+    assert_eq!(cpu.read(0x01FB), 0x05);
+    assert_eq!(cpu.registers.a, 0x05);
+}
+
+#[test]
+fn test_brk() {
+    let mut cpu = CPU::new();
+    cpu.write_word(0xFFFE, 0x4000);
+    cpu.write(0x4000, 0x40); // RTI
+    cpu.registers.pc = 0x0200;
+    cpu.write(0x0200, 0x00); // BRK
+    cpu.write(0x0202, 0xEA); // NOP
+
+    cpu.step();
+    assert_eq!(cpu.registers.pc, 0x4000);
+    assert_eq!(cpu.read(0x01FB) & 0x30, 0x30);
+    assert!(cpu.status.contains(StatusFlags::INTERRUPT_DISABLE));
+
+    // BRK returns past its padding byte
+    cpu.step();
+    assert_eq!(cpu.registers.pc, 0x0202);
+}
+
+#[test]
+fn test_call() {
+    let mut cpu = CPU::new();
+    cpu.write(0x0300, 0x86); // STX
+    cpu.write(0x0301, 0x10); // $10
+    cpu.write(0x0302, 0x60); // RTS
+
+    cpu.call(0x0300, 0x01, 0x02);
+    assert!(!cpu.returned());
+
+    // STX zero page takes 3 cycles, RTS 6
+    assert_eq!(cpu.step(), 3);
+    assert_eq!(cpu.step(), 6);
+    assert!(cpu.returned());
+    assert_eq!(cpu.read(0x10), 0x02);
+    assert_eq!(cpu.registers.a, 0x01);
+    assert_eq!(cpu.cycles(), 9);
+
+    cpu.idle();
+    assert_eq!(cpu.cycles(), 10);
+}
+
+#[test]
+fn test_apu_bus() {
+    let mut cpu = CPU::new();
+
+    // Pulse 1's length counter shows in $4015
+    cpu.write(0x4015, 0x01);
+    cpu.write(0x4003, 0x08);
+    assert_eq!(cpu.read(0x4015) & 0x01, 0x01);
+
+    // The DMC fetches its sample through the CPU
+    cpu.write(0xC000, 0xFF);
+    cpu.write(0x4012, 0x00);
+    cpu.write(0x4013, 0x00);
+    cpu.write(0x4015, 0x11);
+    assert_eq!(cpu.read(0x4015) & 0x10, 0x10);
+    cpu.idle();
+    assert_eq!(cpu.read(0x4015) & 0x10, 0);
+}
+
+#[test]
+fn test_irq() {
+    let mut cpu = CPU::new();
+    cpu.write_word(0xFFFE, 0x4000);
+    cpu.write(0x4000, 0x40); // RTI
+    cpu.registers.pc = 0x0200;
+    cpu.write(0x0200, 0x58); // CLI
+    cpu.write(0x0201, 0xEA); // NOP
+
+    // The DMC's IRQ stays masked until CLI
+    cpu.write(0x4010, 0x80);
+    cpu.write(0x4013, 0x00);
+    cpu.write(0x4015, 0x10);
+    cpu.idle();
+    assert!(cpu.apu.irq());
+    assert!(!cpu.irq_pending());
+
+    cpu.step();
+    assert!(cpu.irq_pending());
+    assert_eq!(cpu.step(), 7);
+    assert_eq!(cpu.registers.pc, 0x4000);
+    assert_eq!(cpu.read(0x01FB) & 0x30, 0x20);
+    assert!(!cpu.irq_pending());
+
+    // RTI goes back to the NOP the IRQ took the place of
+    cpu.write(0x4015, 0x00);
+    cpu.step();
+    assert_eq!(cpu.registers.pc, 0x0201);
+}
+
+#[test]
+fn test_nmi() {
+    let mut cpu = CPU::new();
+    cpu.write_word(0xFFFA, 0x4000);
+    cpu.registers.pc = 0x0200;
+
+    // Masking IRQs doesn't stop it
+    cpu.status.insert(StatusFlags::INTERRUPT_DISABLE);
+    cpu.nmi();
+    assert_eq!(cpu.step(), 7);
+    assert_eq!(cpu.registers.pc, 0x4000);
+    assert_eq!(cpu.pull() & 0x30, 0x20);
+    assert_eq!(cpu.pull_word(), 0x0200);
+}
@@ -1,5 +1,21 @@
+pub mod apu;
+pub mod cartridge;
 pub mod cpu;
+pub mod nsf;
+pub mod ppu;
+pub mod video;
+pub mod zip;
 
-trait VideoInterface {}
+// Where finished frames go. `frame` is called once per frame with
+// ppu::WIDTH * ppu::HEIGHT pixels, row by row from the top left. Each pixel
+// is a palette index in the low 6 bits with the red, green and blue
+// emphasis bits of $2001 above them, which `video::Palette` turns into a
+// colour.
+pub trait VideoInterface {
+    fn frame(&mut self, pixels: &[u16]);
+}
+
+#[allow(dead_code)]
 trait AudioInterface {}
+#[allow(dead_code)]
 trait InputInterface {}
@@ -0,0 +1,454 @@
+use bitflags::bitflags;
+
+use crate::cartridge::{Cartridge, CartridgeError, Region};
+use crate::cpu::CPU;
+
+mod nsfe;
+
+const NSF_MAGIC: [u8; 5] = [b'N', b'E', b'S', b'M', 0x1A];
+const NSF_HEADER_SIZE: usize = 0x80;
+
+pub const NTSC_CLOCK_RATE: f64 = 1_789_773.0;
+pub const PAL_CLOCK_RATE: f64 = 1_662_607.0;
+
+// Used when the header leaves the play rate at 0
+const NTSC_DEFAULT_SPEED: u16 = 16639;
+const PAL_DEFAULT_SPEED: u16 = 19997;
+
+// INIT gets this long to return before the tune starts anyway
+const INIT_MAX_SECONDS: f64 = 1.0;
+
+// For tracks the file gives no length
+const DEFAULT_DURATION_MS: u32 = 150_000;
+const DEFAULT_FADE_MS: u32 = 10_000;
+
+// The console's output stage blocks DC, leaving the DMC level and idle
+// channels silent
+const HIGH_PASS_FACTOR: f32 = 0.996;
+
+bitflags! {
+    pub struct ExpansionChips: u8 {
+        const VRC6 = 1 << 0;
+        const VRC7 = 1 << 1;
+        const FDS = 1 << 2;
+        const MMC5 = 1 << 3;
+        const NAMCO163 = 1 << 4;
+        const SUNSOFT5B = 1 << 5;
+    }
+}
+
+bitflags! {
+    pub struct Nsf2Flags: u8 {
+        // PLAY can be driven by IRQs from the timer at $401B-$401D
+        const IRQ = 1 << 4;
+        // INIT may never return, with PLAY then called from NMI
+        const NON_RETURNING_INIT = 1 << 5;
+        const NO_PLAY = 1 << 6;
+        const MANDATORY_METADATA = 1 << 7;
+    }
+}
+
+// NSFe track information. Lengths are in milliseconds.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Track {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub duration: Option<u32>,
+    pub fade: Option<u32>,
+}
+
+// An NSF, NSF2 or NSFe music file: 6502 code and data, plus the routines
+// that play it
+pub struct Nsf {
+    pub version: u8,
+    pub song_count: u8,
+    // Zero-based, unlike in the file
+    pub first_song: u8,
+    pub load_address: u16,
+    pub init_address: u16,
+    pub play_address: u16,
+    pub title: String,
+    pub artist: String,
+    pub copyright: String,
+    pub ripper: String,
+    // PLAY rates, in microseconds between calls
+    pub ntsc_speed: u16,
+    pub pal_speed: u16,
+    // Initial 4 KB banks for $8000-$FFFF, or None if the tune isn't
+    // bankswitched
+    pub banks: Option<[u8; 8]>,
+    pub region: Region,
+    pub chips: ExpansionChips,
+    pub flags: Nsf2Flags,
+    // One per song
+    pub tracks: Vec<Track>,
+    // Zero-based songs in the order the soundtrack plays them
+    pub playlist: Option<Vec<u8>>,
+    pub data: Vec<u8>,
+}
+
+fn text(field: &[u8]) -> String {
+    let end = field
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+fn region(value: u8) -> Region {
+    match value & 0x03 {
+        0 => Region::Ntsc,
+        1 => Region::Pal,
+        _ => Region::Multiple,
+    }
+}
+
+impl Nsf {
+    pub fn parse(data: &[u8]) -> Result<Self, CartridgeError> {
+        let nsf = if data.starts_with(&nsfe::NSFE_MAGIC) {
+            nsfe::parse(data)?
+        } else {
+            Nsf::parse_nsf(data)?
+        };
+
+        // Only the FDS has RAM to load code into below $8000
+        let lowest = if nsf.chips.contains(ExpansionChips::FDS) {
+            0x6000
+        } else {
+            0x8000
+        };
+
+        if nsf.load_address < lowest {
+            return Err(CartridgeError::InvalidHeader);
+        }
+
+        Ok(nsf)
+    }
+
+    fn parse_nsf(data: &[u8]) -> Result<Self, CartridgeError> {
+        if data.len() < NSF_HEADER_SIZE || data[0..5] != NSF_MAGIC {
+            return Err(CartridgeError::InvalidHeader);
+        }
+
+        let word = |offset: usize| data[offset] as u16 | (data[offset + 1] as u16) << 8;
+
+        let mut banks = [0; 8];
+        banks.copy_from_slice(&data[0x70..0x78]);
+        let banks = if banks.iter().any(|&bank| bank != 0) {
+            Some(banks)
+        } else {
+            None
+        };
+
+        // NSF2 files can end their program early and follow it with NSFe
+        // chunks
+        let version = data[0x05];
+        let (flags, length) = if version >= 2 {
+            let length =
+                data[0x7D] as usize | (data[0x7E] as usize) << 8 | (data[0x7F] as usize) << 16;
+            (Nsf2Flags::from_bits_truncate(data[0x7C]), length)
+        } else {
+            (Nsf2Flags::empty(), 0)
+        };
+
+        let program = &data[NSF_HEADER_SIZE..];
+        let (program, metadata) = if length == 0 {
+            (program, None)
+        } else if length <= program.len() {
+            (&program[..length], Some(&program[length..]))
+        } else {
+            return Err(CartridgeError::Truncated);
+        };
+
+        let song_count = data[0x06];
+
+        let mut nsf = Nsf {
+            version,
+            song_count,
+            first_song: data[0x07].saturating_sub(1),
+            load_address: word(0x08),
+            init_address: word(0x0A),
+            play_address: word(0x0C),
+            title: text(&data[0x0E..0x2E]),
+            artist: text(&data[0x2E..0x4E]),
+            copyright: text(&data[0x4E..0x6E]),
+            ripper: String::new(),
+            ntsc_speed: word(0x6E),
+            pal_speed: word(0x78),
+            banks,
+            region: region(data[0x7A]),
+            chips: ExpansionChips::from_bits_truncate(data[0x7B]),
+            flags,
+            tracks: vec![Track::default(); song_count as usize],
+            playlist: None,
+            data: program.to_vec(),
+        };
+
+        if let Some(metadata) = metadata {
+            nsfe::read_metadata(&mut nsf, metadata)?;
+        }
+
+        Ok(nsf)
+    }
+
+    // The songs of the soundtrack in playing order
+    pub fn track_order(&self) -> Vec<u8> {
+        match &self.playlist {
+            Some(playlist) => playlist.clone(),
+            None => (0..self.song_count).collect(),
+        }
+    }
+
+    // How long a song plays before fading out, in milliseconds
+    pub fn duration(&self, song: u8) -> u32 {
+        self.tracks
+            .get(song as usize)
+            .and_then(|track| track.duration)
+            .unwrap_or(DEFAULT_DURATION_MS)
+    }
+
+    pub fn fade(&self, song: u8) -> u32 {
+        self.tracks
+            .get(song as usize)
+            .and_then(|track| track.fade)
+            .unwrap_or(DEFAULT_FADE_MS)
+    }
+}
+
+// Plays an NSF on a CPU and APU alone, calling INIT once per song and then
+// PLAY at the rate the header asks for
+pub struct Player {
+    cpu: CPU,
+    init_address: u16,
+    play_address: u16,
+    ntsc_speed: u16,
+    pal_speed: u16,
+    song_count: u8,
+    flags: Nsf2Flags,
+    // Duration and fade of each song, in milliseconds
+    lengths: Vec<(u32, u32)>,
+    region: Region,
+    sample_rate: u32,
+
+    // Samples rendered since the song started, and where its fade out
+    // starts and ends
+    position: u64,
+    fade_start: u64,
+    fade_end: u64,
+
+    play_cycles: f64,
+    next_play: f64,
+    sample_cycles: f64,
+    cycles_per_sample: f64,
+    sample_sum: f32,
+    previous_input: f32,
+    previous_output: f32,
+}
+
+impl Player {
+    pub fn new(nsf: &Nsf, sample_rate: u32) -> Result<Self, CartridgeError> {
+        let mut cpu = CPU::new();
+        cpu.insert_cartridge(Cartridge::from_nsf(nsf)?);
+
+        // PAL-only tunes play at PAL rates, everything else at NTSC ones
+        let region = if nsf.region == Region::Pal {
+            Region::Pal
+        } else {
+            Region::Ntsc
+        };
+
+        let mut player = Player {
+            cpu,
+            init_address: nsf.init_address,
+            play_address: nsf.play_address,
+            ntsc_speed: nsf.ntsc_speed,
+            pal_speed: nsf.pal_speed,
+            song_count: nsf.song_count,
+            flags: nsf.flags,
+            lengths: (0..nsf.song_count)
+                .map(|song| (nsf.duration(song), nsf.fade(song)))
+                .collect(),
+            region,
+            sample_rate,
+            position: 0,
+            fade_start: u64::MAX,
+            fade_end: u64::MAX,
+            play_cycles: 0.0,
+            next_play: 0.0,
+            sample_cycles: 0.0,
+            cycles_per_sample: 0.0,
+            sample_sum: 0.0,
+            previous_input: 0.0,
+            previous_output: 0.0,
+        };
+        player.set_region(region, sample_rate);
+
+        Ok(player)
+    }
+
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    // Switches between NTSC and PAL timing. Tunes only see the change when
+    // the next song starts.
+    pub fn set_region(&mut self, region: Region, sample_rate: u32) {
+        let (clock_rate, speed, default_speed) = if region == Region::Pal {
+            (PAL_CLOCK_RATE, self.pal_speed, PAL_DEFAULT_SPEED)
+        } else {
+            (NTSC_CLOCK_RATE, self.ntsc_speed, NTSC_DEFAULT_SPEED)
+        };
+        let speed = if speed == 0 { default_speed } else { speed };
+
+        self.region = if region == Region::Pal {
+            Region::Pal
+        } else {
+            Region::Ntsc
+        };
+        self.cpu.set_region(self.region);
+        self.sample_rate = sample_rate;
+        self.play_cycles = speed as f64 * clock_rate / 1_000_000.0;
+        self.cycles_per_sample = clock_rate / sample_rate as f64;
+    }
+
+    pub fn cpu_mut(&mut self) -> &mut CPU {
+        &mut self.cpu
+    }
+
+    // Resets the console and runs INIT for a zero-based song number, or
+    // leaves it running if it never returns. Returns false for songs the
+    // file doesn't have.
+    pub fn start(&mut self, song: u8) -> bool {
+        if song >= self.song_count {
+            return false;
+        }
+
+        if let Some(cartridge) = self.cpu.cartridge_mut() {
+            cartridge.reset();
+        }
+
+        // The state the NSF spec promises INIT
+        for address in 0x0000..0x0800 {
+            self.cpu.write(address, 0);
+        }
+
+        for address in 0x4000..0x4014 {
+            self.cpu.write(address, 0);
+        }
+
+        self.cpu.write(0x4015, 0x00);
+        self.cpu.write(0x4015, 0x0F);
+        self.cpu.write(0x4017, 0x40);
+
+        let pal = (self.region == Region::Pal) as u8;
+        let max_cycles = (INIT_MAX_SECONDS * NTSC_CLOCK_RATE) as u64;
+
+        self.cpu.call(self.init_address, song, pal);
+
+        if self.flags.contains(Nsf2Flags::NON_RETURNING_INIT) {
+            self.next_play = self.cpu.cycles() as f64 + self.play_cycles;
+        } else {
+            let end = self.cpu.cycles() + max_cycles;
+
+            while !self.cpu.returned() && self.cpu.cycles() < end {
+                self.cpu.step();
+            }
+
+            self.next_play = self.cpu.cycles() as f64;
+        }
+
+        let samples = |ms: u32| ms as u64 * self.sample_rate as u64 / 1000;
+        let (duration, fade) = self.lengths[song as usize];
+        self.position = 0;
+        self.fade_start = samples(duration);
+        self.fade_end = self.fade_start + samples(fade);
+
+        true
+    }
+
+    // Whether the song has played for its full length and faded out
+    pub fn finished(&self) -> bool {
+        self.position >= self.fade_end
+    }
+
+    // Fills `samples` with the tune, from -1.0 to 1.0
+    pub fn render(&mut self, samples: &mut [f32]) {
+        for sample in samples.iter_mut() {
+            while self.sample_cycles < self.cycles_per_sample {
+                let cycles = self.run() as f64;
+                self.sample_sum += self.cpu.audio_output() * cycles as f32;
+                self.sample_cycles += cycles;
+            }
+
+            // The cycles run past the sample carry over into the next one
+            let overrun = self.sample_cycles - self.cycles_per_sample;
+            let level = self.cpu.audio_output();
+            let input = (self.sample_sum - level * overrun as f32) / self.cycles_per_sample as f32;
+            let output = input - self.previous_input + HIGH_PASS_FACTOR * self.previous_output;
+
+            self.previous_input = input;
+            self.previous_output = output;
+            self.sample_sum = level * overrun as f32;
+            self.sample_cycles = overrun;
+
+            *sample = (output * self.gain()).clamp(-1.0, 1.0);
+            self.position += 1;
+        }
+    }
+
+    fn gain(&self) -> f32 {
+        if self.position < self.fade_start {
+            1.0
+        } else if self.position >= self.fade_end {
+            0.0
+        } else {
+            let fade = (self.fade_end - self.fade_start) as f32;
+            1.0 - (self.position - self.fade_start) as f32 / fade
+        }
+    }
+
+    // Runs an instruction of INIT or PLAY, or idles until PLAY is due.
+    // Returns the number of cycles that took.
+    fn run(&mut self) -> u16 {
+        let cycles = self.cpu.cycles() as f64;
+        let play_due = cycles >= self.next_play && !self.flags.contains(Nsf2Flags::NO_PLAY);
+
+        if !self.cpu.returned() {
+            // An INIT that doesn't return gets PLAY called from NMI, through
+            // the driver the mapper puts at the NMI vector
+            if play_due && self.flags.contains(Nsf2Flags::NON_RETURNING_INIT) {
+                self.schedule_play(cycles);
+                self.cpu.nmi();
+            }
+
+            return self.cpu.step();
+        }
+
+        // IRQs interrupt the wait too, returning to it with RTI
+        if self.cpu.irq_pending() {
+            return self.cpu.step();
+        }
+
+        if !play_due {
+            self.cpu.idle();
+            return 1;
+        }
+
+        self.schedule_play(cycles);
+        self.cpu.call(self.play_address, 0, 0);
+
+        self.cpu.step()
+    }
+
+    fn schedule_play(&mut self, cycles: f64) {
+        self.next_play += self.play_cycles;
+
+        // Skip the calls a slow PLAY routine missed
+        if self.next_play <= cycles {
+            self.next_play = cycles + self.play_cycles;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,187 @@
+// NSFe files: the NSF header and data split into tagged chunks, plus track
+// names, lengths and a playlist. NSF2 files append the same chunks after
+// their data as metadata.
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use super::{region, ExpansionChips, Nsf, Nsf2Flags, Track};
+use crate::cartridge::CartridgeError;
+
+pub const NSFE_MAGIC: [u8; 4] = *b"NSFE";
+const CHUNK_HEADER_SIZE: usize = 8;
+
+// The smallest INFO chunk leaves out the song count and first song
+const INFO_MIN_SIZE: usize = 8;
+
+pub fn parse(data: &[u8]) -> Result<Nsf, CartridgeError> {
+    if data.len() < NSFE_MAGIC.len() || data[0..4] != NSFE_MAGIC {
+        return Err(CartridgeError::InvalidHeader);
+    }
+
+    let mut info = None;
+    let mut program = None;
+    let mut banks = None;
+    let mut flags = Nsf2Flags::empty();
+
+    for chunk in chunks(&data[4..]) {
+        let (id, chunk) = chunk?;
+
+        match &id {
+            b"INFO" if chunk.len() >= INFO_MIN_SIZE => info = Some(chunk),
+            b"INFO" => return Err(CartridgeError::InvalidHeader),
+            b"DATA" => program = Some(chunk),
+            b"BANK" => {
+                let mut initial = [0; 8];
+                let length = chunk.len().min(8);
+                initial[..length].copy_from_slice(&chunk[..length]);
+                banks = Some(initial);
+            }
+            b"NSF2" => {
+                let value = chunk.first().copied().unwrap_or(0);
+                flags = Nsf2Flags::from_bits_truncate(value);
+            }
+            _ => {}
+        }
+    }
+
+    let info = info.ok_or(CartridgeError::InvalidHeader)?;
+    let program = program.ok_or(CartridgeError::InvalidHeader)?;
+    let song_count = info.get(8).copied().unwrap_or(1);
+
+    let mut nsf = Nsf {
+        version: 0,
+        song_count,
+        first_song: info.get(9).copied().unwrap_or(0),
+        load_address: LittleEndian::read_u16(&info[0..2]),
+        init_address: LittleEndian::read_u16(&info[2..4]),
+        play_address: LittleEndian::read_u16(&info[4..6]),
+        title: String::new(),
+        artist: String::new(),
+        copyright: String::new(),
+        ripper: String::new(),
+        ntsc_speed: 0,
+        pal_speed: 0,
+        banks,
+        region: region(info[6]),
+        chips: ExpansionChips::from_bits_truncate(info[7]),
+        flags,
+        tracks: vec![Track::default(); song_count as usize],
+        playlist: None,
+        data: program.to_vec(),
+    };
+
+    read_metadata(&mut nsf, &data[4..])?;
+
+    Ok(nsf)
+}
+
+// Fills in everything but the header and data from NSFe chunks. Chunks
+// whose name starts in uppercase must be understood, the rest can be
+// skipped.
+pub fn read_metadata(nsf: &mut Nsf, data: &[u8]) -> Result<(), CartridgeError> {
+    for chunk in chunks(data) {
+        let (id, chunk) = chunk?;
+
+        match &id {
+            b"INFO" | b"DATA" | b"BANK" | b"NSF2" => {}
+            b"RATE" => {
+                let speed = |offset: usize| {
+                    chunk
+                        .get(offset..offset + 2)
+                        .map_or(0, LittleEndian::read_u16)
+                };
+
+                nsf.ntsc_speed = speed(0);
+                nsf.pal_speed = speed(2);
+            }
+            b"auth" => {
+                let mut fields = strings(chunk);
+                nsf.title = fields.next().unwrap_or_default();
+                nsf.artist = fields.next().unwrap_or_default();
+                nsf.copyright = fields.next().unwrap_or_default();
+                nsf.ripper = fields.next().unwrap_or_default();
+            }
+            b"tlbl" => {
+                for (track, title) in nsf.tracks.iter_mut().zip(strings(chunk)) {
+                    track.title = Some(title);
+                }
+            }
+            b"taut" => {
+                for (track, author) in nsf.tracks.iter_mut().zip(strings(chunk)) {
+                    track.author = Some(author);
+                }
+            }
+            b"time" => {
+                for (track, time) in nsf.tracks.iter_mut().zip(milliseconds(chunk)) {
+                    track.duration = time;
+                }
+            }
+            b"fade" => {
+                for (track, time) in nsf.tracks.iter_mut().zip(milliseconds(chunk)) {
+                    track.fade = time;
+                }
+            }
+            b"plst" => {
+                let playlist = chunk
+                    .iter()
+                    .copied()
+                    .filter(|&track| track < nsf.song_count)
+                    .collect();
+                nsf.playlist = Some(playlist);
+            }
+            b"NEND" => break,
+            [first, ..] if first.is_ascii_uppercase() => {
+                return Err(CartridgeError::InvalidHeader);
+            }
+            // psfx, text, regn and anything newer
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+fn chunks(data: &[u8]) -> impl Iterator<Item = Result<([u8; 4], &[u8]), CartridgeError>> {
+    let mut offset = 0;
+
+    std::iter::from_fn(move || {
+        if offset >= data.len() {
+            return None;
+        }
+
+        if data.len() < offset + CHUNK_HEADER_SIZE {
+            offset = data.len();
+            return Some(Err(CartridgeError::Truncated));
+        }
+
+        let length = LittleEndian::read_u32(&data[offset..]) as usize;
+        let mut id = [0; 4];
+        id.copy_from_slice(&data[offset + 4..offset + 8]);
+
+        let start = offset + CHUNK_HEADER_SIZE;
+        let end = match start.checked_add(length) {
+            Some(end) if end <= data.len() => end,
+            _ => {
+                offset = data.len();
+                return Some(Err(CartridgeError::Truncated));
+            }
+        };
+
+        offset = end;
+        Some(Ok((id, &data[start..end])))
+    })
+}
+
+fn strings(chunk: &[u8]) -> impl Iterator<Item = String> + '_ {
+    chunk
+        .split(|&byte| byte == 0)
+        .map(|field| String::from_utf8_lossy(field).into_owned())
+}
+
+// Negative times mean the track has none
+fn milliseconds(chunk: &[u8]) -> impl Iterator<Item = Option<u32>> + '_ {
+    chunk.chunks_exact(4).map(|time| {
+        let time = LittleEndian::read_i32(time);
+        u32::try_from(time).ok()
+    })
+}
@@ -0,0 +1,438 @@
+use super::*;
+
+// Builds an NSF header for `data`, loaded at $8000 with INIT at $8000 and
+// PLAY at $8010
+fn nsf_file(songs: u8, banks: [u8; 8], chips: u8, data: &[u8]) -> Vec<u8> {
+    let mut file = vec![0; NSF_HEADER_SIZE];
+    file[0..5].copy_from_slice(&NSF_MAGIC);
+    file[0x05] = 1;
+    file[0x06] = songs;
+    file[0x07] = 1;
+    file[0x08..0x0E].copy_from_slice(&[0x00, 0x80, 0x00, 0x80, 0x10, 0x80]);
+    file[0x0E..0x13].copy_from_slice(b"Title");
+    file[0x2E..0x34].copy_from_slice(b"Artist");
+    file[0x6E..0x70].copy_from_slice(&16639u16.to_le_bytes());
+    file[0x70..0x78].copy_from_slice(&banks);
+    file[0x7B] = chips;
+    file.extend_from_slice(data);
+    file
+}
+
+// INIT stores A and X in $00 and $01, and PLAY counts its calls in $02
+fn counting_tune() -> Vec<u8> {
+    let mut code = vec![
+        0x85, 0x00, // STA $00
+        0x86, 0x01, // STX $01
+        0x60, // RTS
+    ];
+    code.resize(0x10, 0xEA);
+    code.extend([
+        0xE6, 0x02, // INC $02
+        0x60, // RTS
+    ]);
+    code
+}
+
+#[test]
+fn test_parse() {
+    let nsf = Nsf::parse(&nsf_file(3, [0; 8], 0x21, &counting_tune())).unwrap();
+    assert_eq!(nsf.song_count, 3);
+    assert_eq!(nsf.first_song, 0);
+    assert_eq!(nsf.load_address, 0x8000);
+    assert_eq!(nsf.play_address, 0x8010);
+    assert_eq!(nsf.title, "Title");
+    assert_eq!(nsf.artist, "Artist");
+    assert_eq!(nsf.copyright, "");
+    assert_eq!(nsf.banks, None);
+    assert_eq!(nsf.region, Region::Ntsc);
+    assert_eq!(nsf.chips, ExpansionChips::VRC6 | ExpansionChips::SUNSOFT5B);
+
+    assert!(matches!(
+        Nsf::parse(b"NESM"),
+        Err(CartridgeError::InvalidHeader)
+    ));
+
+    // Code below $8000 needs the FDS's RAM
+    let mut file = nsf_file(1, [0; 8], 0, &[]);
+    file[0x09] = 0x60;
+    assert!(Nsf::parse(&file).is_err());
+    file[0x7B] = 0x04;
+    assert!(Nsf::parse(&file).is_ok());
+}
+
+#[test]
+fn test_init_and_play() {
+    let nsf = Nsf::parse(&nsf_file(3, [0; 8], 0, &counting_tune())).unwrap();
+    let mut player = Player::new(&nsf, 44100).unwrap();
+
+    assert!(!player.start(3));
+    assert!(player.start(2));
+    assert_eq!(player.cpu_mut().read(0x00), 2);
+    assert_eq!(player.cpu_mut().read(0x01), 0);
+
+    // About 60 calls a second
+    let mut samples = vec![0.0; 44100];
+    player.render(&mut samples);
+    assert!((59..=61).contains(&player.cpu_mut().read(0x02)));
+
+    // Starting over clears RAM
+    player.start(0);
+    assert_eq!(player.cpu_mut().read(0x02), 0);
+
+    // PAL tunes are told so in X, and play at 50 Hz
+    player.set_region(Region::Pal, 44100);
+    player.start(1);
+    assert_eq!(player.cpu_mut().read(0x00), 1);
+    assert_eq!(player.cpu_mut().read(0x01), 1);
+    player.render(&mut samples);
+    assert!((49..=51).contains(&player.cpu_mut().read(0x02)));
+}
+
+#[test]
+fn test_bankswitching() {
+    // INIT switches bank 2 into $9000 and copies a byte from it
+    let mut data = vec![
+        0xA9, 0x02, // LDA #$02
+        0x8D, 0xF9, 0x5F, // STA $5FF9
+        0xAD, 0x00, 0x90, // LDA $9000
+        0x85, 0x00, // STA $00
+        0x60, // RTS
+    ];
+    data.resize(0x1000, 0xEA);
+    data.extend(vec![0x11; 0x1000]);
+    data.extend(vec![0x22; 0x1000]);
+
+    let nsf = Nsf::parse(&nsf_file(1, [0, 1, 0, 0, 0, 0, 0, 0], 0, &data)).unwrap();
+    assert_eq!(nsf.banks, Some([0, 1, 0, 0, 0, 0, 0, 0]));
+
+    let mut player = Player::new(&nsf, 44100).unwrap();
+    player.start(0);
+    assert_eq!(player.cpu_mut().read(0x00), 0x22);
+    assert_eq!(player.cpu_mut().read(0x9000), 0x22);
+
+    // Each song starts from the header's banks
+    player.cpu_mut().write(0x5FFA, 0x01);
+    assert_eq!(player.cpu_mut().read(0xA000), 0x11);
+    player.start(0);
+    assert_eq!(player.cpu_mut().read(0xA000), 0xA9);
+
+    // Without bankswitching, a load address past $8000 leaves a gap
+    let mut file = nsf_file(1, [0; 8], 0, &[0x33]);
+    file[0x09] = 0x90;
+    let nsf = Nsf::parse(&file).unwrap();
+    let mut player = Player::new(&nsf, 44100).unwrap();
+    assert_eq!(player.cpu_mut().read(0x9000), 0x33);
+    assert_eq!(player.cpu_mut().read(0x8000), 0x00);
+}
+
+fn energy(player: &mut Player) -> f32 {
+    let mut samples = vec![0.0; 4410];
+    player.render(&mut samples);
+    samples[2205..].iter().map(|sample| sample * sample).sum()
+}
+
+#[test]
+fn test_apu_audio() {
+    let nsf = Nsf::parse(&nsf_file(1, [0; 8], 0, &counting_tune())).unwrap();
+    let mut player = Player::new(&nsf, 44100).unwrap();
+    player.start(0);
+    assert!(energy(&mut player) < 0.001);
+
+    // A square wave on pulse 1
+    let tune = [
+        0xA9, 0xBF, // LDA #$BF
+        0x8D, 0x00, 0x40, // STA $4000
+        0xA9, 0xFD, // LDA #$FD
+        0x8D, 0x02, 0x40, // STA $4002
+        0xA9, 0x00, // LDA #$00
+        0x8D, 0x03, 0x40, // STA $4003
+        0x60, // RTS
+        0x60, // RTS
+    ];
+    let nsf = Nsf::parse(&nsf_file(1, [0; 8], 0, &tune)).unwrap();
+    let mut player = Player::new(&nsf, 44100).unwrap();
+    player.start(0);
+    assert!(energy(&mut player) > 1.0);
+}
+
+#[test]
+fn test_expansion_audio() {
+    // A VRC6 pulse in its constant output mode
+    let tune = [
+        0xA9, 0x8F, // LDA #$8F
+        0x8D, 0x00, 0x90, // STA $9000
+        0x8D, 0x02, 0x90, // STA $9002
+        0x60, // RTS
+    ];
+    let file = nsf_file(1, [0; 8], 0x01, &tune);
+    let mut player = Player::new(&Nsf::parse(&file).unwrap(), 44100).unwrap();
+    player.start(0);
+
+    let mut samples = vec![0.0; 16];
+    player.render(&mut samples);
+    assert!(samples[0] > 0.0);
+
+    // The chip's registers don't reach the ROM
+    assert_eq!(player.cpu_mut().read(0x9000), 0xA9);
+
+    // The MMC5's ExRAM and multiplier
+    let nsf = Nsf::parse(&nsf_file(1, [0; 8], 0x08, &counting_tune())).unwrap();
+    let mut player = Player::new(&nsf, 44100).unwrap();
+    player.start(0);
+    let cpu = player.cpu_mut();
+    cpu.write(0x5C00, 0x42);
+    assert_eq!(cpu.read(0x5C00), 0x42);
+    cpu.write(0x5205, 12);
+    cpu.write(0x5206, 11);
+    assert_eq!(cpu.read(0x5205), 132);
+}
+
+#[test]
+fn test_fds_tune() {
+    // Loaded into the FDS's RAM at $6000
+    let mut file = nsf_file(1, [0; 8], 0x04, &counting_tune());
+    file[0x08..0x0E].copy_from_slice(&[0x00, 0x60, 0x00, 0x60, 0x10, 0x60]);
+    let nsf = Nsf::parse(&file).unwrap();
+    let mut player = Player::new(&nsf, 44100).unwrap();
+    player.start(0);
+
+    let cpu = player.cpu_mut();
+    assert_eq!(cpu.read(0x6000), 0x85);
+    cpu.write(0x6000, 0x12);
+    assert_eq!(cpu.read(0x6000), 0x12);
+    cpu.write(0xA000, 0x34);
+    assert_eq!(cpu.read(0xA000), 0x34);
+
+    // The wave table is readable
+    cpu.write(0x4089, 0x80);
+    cpu.write(0x4040, 0x3F);
+    assert_eq!(cpu.read(0x4040), 0x7F);
+
+    let mut samples = vec![0.0; 441];
+    player.render(&mut samples);
+    assert!(player.cpu_mut().read(0x02) > 0);
+}
+
+fn chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut chunk = (data.len() as u32).to_le_bytes().to_vec();
+    chunk.extend_from_slice(id);
+    chunk.extend_from_slice(data);
+    chunk
+}
+
+// Three tracks played last first, with names and lengths for two
+fn metadata() -> Vec<u8> {
+    let mut times = Vec::new();
+    times.extend(1000i32.to_le_bytes());
+    times.extend((-1i32).to_le_bytes());
+
+    [
+        chunk(b"auth", b"Game\0Composer\0Company\0Ripper"),
+        chunk(b"tlbl", b"Intro\0Ending"),
+        chunk(b"time", &times),
+        chunk(b"fade", &500i32.to_le_bytes()),
+        chunk(b"plst", &[2, 0, 7]),
+        chunk(b"text", b"skipped"),
+        chunk(b"NEND", &[]),
+    ]
+    .concat()
+}
+
+#[test]
+fn test_nsfe() {
+    let mut info = vec![0x00, 0x80, 0x00, 0x80, 0x10, 0x80, 0x01, 0x00, 3, 1];
+    let mut file = b"NSFE".to_vec();
+    file.extend(chunk(b"INFO", &info));
+    file.extend(chunk(b"DATA", &counting_tune()));
+    file.extend(chunk(b"RATE", &20000u16.to_le_bytes()));
+    file.extend(metadata());
+
+    let nsf = Nsf::parse(&file).unwrap();
+    assert_eq!(nsf.song_count, 3);
+    assert_eq!(nsf.first_song, 1);
+    assert_eq!(nsf.play_address, 0x8010);
+    assert_eq!(nsf.region, Region::Pal);
+    assert_eq!(nsf.ntsc_speed, 20000);
+    assert_eq!(nsf.data, counting_tune());
+    assert_eq!(nsf.title, "Game");
+    assert_eq!(nsf.ripper, "Ripper");
+    assert_eq!(nsf.tracks[1].title.as_deref(), Some("Ending"));
+    assert_eq!(nsf.tracks[2].title, None);
+
+    // Out of range songs are left out of the playlist
+    assert_eq!(nsf.track_order(), vec![2, 0]);
+    assert_eq!(nsf.duration(0), 1000);
+    assert_eq!(nsf.fade(0), 500);
+    assert_eq!(nsf.duration(1), DEFAULT_DURATION_MS);
+    assert_eq!(nsf.fade(1), DEFAULT_FADE_MS);
+
+    // The short INFO chunk has a single song
+    info.truncate(8);
+    let mut short = b"NSFE".to_vec();
+    short.extend(chunk(b"INFO", &info));
+    short.extend(chunk(b"DATA", &[0x60]));
+    let nsf = Nsf::parse(&short).unwrap();
+    assert_eq!(nsf.song_count, 1);
+    assert_eq!(nsf.track_order(), vec![0]);
+
+    // Unknown chunks can only be skipped if they start in lowercase
+    let mut unknown = short.clone();
+    unknown.extend(chunk(b"ABCD", &[]));
+    assert!(matches!(
+        Nsf::parse(&unknown),
+        Err(CartridgeError::InvalidHeader)
+    ));
+
+    short.extend(&chunk(b"tlbl", b"Title")[..10]);
+    assert!(matches!(Nsf::parse(&short), Err(CartridgeError::Truncated)));
+}
+
+#[test]
+fn test_nsf2_metadata() {
+    let tune = counting_tune();
+    let mut file = nsf_file(3, [0; 8], 0, &tune);
+    file.extend(metadata());
+
+    // Version 1 files have no room for metadata
+    assert_eq!(Nsf::parse(&file).unwrap().data.len(), file.len() - 0x80);
+
+    file[0x05] = 2;
+    file[0x7C] = 0x30;
+    file[0x7D..0x80].copy_from_slice(&(tune.len() as u32).to_le_bytes()[..3]);
+
+    let nsf = Nsf::parse(&file).unwrap();
+    assert_eq!(nsf.data, tune);
+    assert_eq!(nsf.flags, Nsf2Flags::IRQ | Nsf2Flags::NON_RETURNING_INIT);
+
+    // The auth chunk takes over from the header's fields
+    assert_eq!(nsf.title, "Game");
+    assert_eq!(nsf.artist, "Composer");
+    assert_eq!(nsf.tracks[0].title.as_deref(), Some("Intro"));
+    assert_eq!(nsf.track_order(), vec![2, 0]);
+}
+
+#[test]
+fn test_fade() {
+    // Pulse 1 at a constant level
+    let tune = [
+        0xA9, 0xBF, // LDA #$BF
+        0x8D, 0x00, 0x40, // STA $4000
+        0xA9, 0xFD, // LDA #$FD
+        0x8D, 0x02, 0x40, // STA $4002
+        0xA9, 0x00, // LDA #$00
+        0x8D, 0x03, 0x40, // STA $4003
+        0x60, // RTS
+        0x60, // RTS
+    ];
+    let mut nsf = Nsf::parse(&nsf_file(1, [0; 8], 0, &tune)).unwrap();
+    nsf.tracks[0].duration = Some(100);
+    nsf.tracks[0].fade = Some(100);
+
+    let mut player = Player::new(&nsf, 44100).unwrap();
+    player.start(0);
+
+    let full = energy(&mut player);
+    assert!(full > 1.0);
+    assert!(!player.finished());
+
+    // Halfway through the fade out, and then past it
+    let fading = energy(&mut player);
+    assert!(fading < full / 2.0 && fading > 0.0);
+    assert!(player.finished());
+    assert_eq!(energy(&mut player), 0.0);
+
+    // Starting over plays it again
+    player.start(0);
+    assert!(energy(&mut player) > 1.0);
+}
+
+#[test]
+fn test_non_returning_init() {
+    let mut code = vec![
+        0x85, 0x00, // STA $00
+        0xE6, 0x03, // INC $03
+        0x4C, 0x02, 0x80, // JMP $8002
+    ];
+    code.resize(0x10, 0xEA);
+    code.extend([
+        0xE6, 0x02, // INC $02
+        0x60, // RTS
+    ]);
+
+    let mut file = nsf_file(2, [0; 8], 0, &code);
+    file[0x05] = 2;
+    file[0x7C] = 0x20;
+    let nsf = Nsf::parse(&file).unwrap();
+
+    let mut player = Player::new(&nsf, 44100).unwrap();
+    assert!(player.start(1));
+    assert_eq!(player.cpu_mut().read(0xFFFA), 0x00);
+    assert_eq!(player.cpu_mut().read(0xFFFB), 0x41);
+
+    // PLAY runs from NMI while INIT keeps looping
+    let mut samples = vec![0.0; 44100];
+    player.render(&mut samples);
+    let cpu = player.cpu_mut();
+    assert_eq!(cpu.read(0x00), 1);
+    assert!((59..=61).contains(&cpu.read(0x02)));
+    assert!(cpu.read(0x03) > 0);
+}
+
+#[test]
+fn test_irq_timer() {
+    // INIT at $8030 points the IRQ vector at $8020 and starts the timer,
+    // and the handler counts IRQs in $04. PLAY is never called.
+    let mut code = vec![0x60]; // RTS
+    code.resize(0x10, 0xEA);
+    code.extend([
+        0xE6, 0x02, // INC $02
+        0x60, // RTS
+    ]);
+    code.resize(0x20, 0xEA);
+    code.extend([
+        0x48, // PHA
+        0xA9, 0x01, // LDA #$01
+        0x8D, 0x1D, 0x40, // STA $401D
+        0xE6, 0x04, // INC $04
+        0x68, // PLA
+        0x40, // RTI
+    ]);
+    code.resize(0x30, 0xEA);
+    code.extend([
+        0xA9, 0x20, // LDA #$20
+        0x8D, 0xFE, 0xFF, // STA $FFFE
+        0xA9, 0x80, // LDA #$80
+        0x8D, 0xFF, 0xFF, // STA $FFFF
+        0xA9, 0xFF, // LDA #$FF
+        0x8D, 0x1B, 0x40, // STA $401B
+        0x8D, 0x1C, 0x40, // STA $401C
+        0xA9, 0x01, // LDA #$01
+        0x8D, 0x1D, 0x40, // STA $401D
+        0x58, // CLI
+        0x60, // RTS
+    ]);
+
+    let mut file = nsf_file(1, [0; 8], 0, &code);
+    file[0x05] = 2;
+    file[0x0A] = 0x30;
+    file[0x7C] = 0x50;
+    let nsf = Nsf::parse(&file).unwrap();
+
+    let mut player = Player::new(&nsf, 44100).unwrap();
+    player.start(0);
+    assert_eq!(player.cpu_mut().read(0xFFFE), 0x20);
+
+    // One IRQ every 65536 cycles
+    let mut samples = vec![0.0; 44100];
+    player.render(&mut samples);
+    let cpu = player.cpu_mut();
+    assert!((26..=28).contains(&cpu.read(0x04)));
+    assert_eq!(cpu.read(0x02), 0);
+
+    // Without the flag, the registers and vector are left alone
+    file[0x7C] = 0;
+    let nsf = Nsf::parse(&file).unwrap();
+    let mut player = Player::new(&nsf, 44100).unwrap();
+    player.start(0);
+    assert_eq!(player.cpu_mut().read(0xFFFE), 0x00);
+}
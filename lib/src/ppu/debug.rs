@@ -0,0 +1,265 @@
+// Pictures of the PPU's memory, for finding out why graphics went wrong.
+// Everything is drawn as RGBA in the colours palette RAM holds now, and read
+// with `Ppu::peek` so looking doesn't disturb the mapper.
+
+use super::{Ppu, PpuCtrl, HEIGHT, WIDTH};
+use crate::cartridge::Cartridge;
+use crate::video::{Palette, Rgb};
+
+// All four nametables, laid out as the scroll registers see them
+pub const NAMETABLES_WIDTH: usize = WIDTH * 2;
+pub const NAMETABLES_HEIGHT: usize = HEIGHT * 2;
+
+// 16 by 16 tiles
+pub const PATTERN_TABLE_SIZE: usize = 128;
+
+// 8 rows of 8 sprites, each in a cell tall enough for 8x16 sprites
+pub const OAM_SHEET_WIDTH: usize = 8 * 8;
+pub const OAM_SHEET_HEIGHT: usize = 8 * 16;
+
+// The 32 entries in two rows, as squares
+const SWATCH_SIZE: usize = 16;
+pub const PALETTE_WIDTH: usize = 16 * SWATCH_SIZE;
+pub const PALETTE_HEIGHT: usize = 2 * SWATCH_SIZE;
+
+// The outline of what the next frame shows, over the nametables
+const SCROLL_COLOR: Rgb = [255, 0, 255];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OamEntry {
+    pub x: u8,
+    // One less than the first line the sprite is on
+    pub y: u8,
+    pub tile: u8,
+    // 0-3, for the sprite palettes at $3F10
+    pub palette: u8,
+    pub behind_background: bool,
+    pub flip_horizontal: bool,
+    pub flip_vertical: bool,
+}
+
+impl OamEntry {
+    fn new(bytes: &[u8]) -> Self {
+        OamEntry {
+            y: bytes[0],
+            tile: bytes[1],
+            palette: bytes[2] & 0x03,
+            behind_background: bytes[2] & 0x20 != 0,
+            flip_horizontal: bytes[2] & 0x40 != 0,
+            flip_vertical: bytes[2] & 0x80 != 0,
+            x: bytes[3],
+        }
+    }
+}
+
+pub fn oam_entries(ppu: &Ppu) -> Vec<OamEntry> {
+    ppu.oam.chunks_exact(4).map(OamEntry::new).collect()
+}
+
+// The 2-bit pixels of one row of a tile, leftmost first
+fn tile_row(ppu: &Ppu, mut cartridge: Option<&mut Cartridge>, address: u16) -> [u8; 8] {
+    let low = ppu.peek(address, cartridge.as_deref_mut());
+    let high = ppu.peek(address + 8, cartridge);
+    let mut row = [0; 8];
+
+    for (x, pixel) in row.iter_mut().enumerate() {
+        let shift = 7 - x;
+        *pixel = (low >> shift) & 0x01 | ((high >> shift) & 0x01) << 1;
+    }
+
+    row
+}
+
+// A pixel in one of the 8 palettes, background ones first. Pixel 0 is the
+// backdrop in all of them.
+fn color(ppu: &Ppu, palette: &Palette, number: u8, pixel: u8) -> Rgb {
+    let address = if pixel == 0 {
+        0x3F00
+    } else {
+        0x3F00 | (number as u16) << 2 | pixel as u16
+    };
+
+    palette.color(ppu.peek(address, None) as u16 & 0x3F)
+}
+
+fn put(output: &mut [u8], width: usize, x: usize, y: usize, [r, g, b]: Rgb) {
+    let offset = (y * width + x) * 4;
+    output[offset..offset + 4].copy_from_slice(&[r, g, b, 0xFF]);
+}
+
+// NAMETABLES_WIDTH * NAMETABLES_HEIGHT pixels, with the background pattern
+// table $2000 selects
+pub fn nametables(ppu: &Ppu, mut cartridge: Option<&mut Cartridge>, palette: &Palette) -> Vec<u8> {
+    let mut output = vec![0; NAMETABLES_WIDTH * NAMETABLES_HEIGHT * 4];
+    let table = if ppu.ctrl.contains(PpuCtrl::BACKGROUND_TABLE) {
+        0x1000
+    } else {
+        0x0000
+    };
+
+    for nametable in 0..4 {
+        let base = 0x2000 + nametable as u16 * 0x0400;
+        let left = (nametable & 0x01) * WIDTH;
+        let top = (nametable >> 1) * HEIGHT;
+
+        for row in 0..HEIGHT / 8 {
+            for column in 0..WIDTH / 8 {
+                let tile = ppu.peek(base + (row * 32 + column) as u16, cartridge.as_deref_mut());
+                let attribute = ppu.peek(
+                    base + 0x03C0 + (row / 4 * 8 + column / 4) as u16,
+                    cartridge.as_deref_mut(),
+                );
+                let shift = (row & 0x02) << 1 | (column & 0x02);
+                let number = (attribute >> shift) & 0x03;
+
+                for y in 0..8 {
+                    let address = table + tile as u16 * 16 + y as u16;
+                    let pixels = tile_row(ppu, cartridge.as_deref_mut(), address);
+
+                    for (x, &pixel) in pixels.iter().enumerate() {
+                        let rgb = color(ppu, palette, number, pixel);
+                        put(
+                            &mut output,
+                            NAMETABLES_WIDTH,
+                            left + column * 8 + x,
+                            top + row * 8 + y,
+                            rgb,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    outline_scroll(ppu, &mut output);
+    output
+}
+
+// The scroll position is in t and fine X between frames, wrapping round
+// the nametables like the picture does
+fn outline_scroll(ppu: &Ppu, output: &mut [u8]) {
+    let t = ppu.t as usize;
+    let left = (t & 0x1F) * 8 + ppu.x as usize + (t >> 10 & 0x01) * WIDTH;
+    let top = (t >> 5 & 0x1F) * 8 + (t >> 12 & 0x07) + (t >> 11 & 0x01) * HEIGHT;
+
+    let mut put_wrapped = |x: usize, y: usize| {
+        put(
+            output,
+            NAMETABLES_WIDTH,
+            x % NAMETABLES_WIDTH,
+            y % NAMETABLES_HEIGHT,
+            SCROLL_COLOR,
+        );
+    };
+
+    for x in left..left + WIDTH {
+        put_wrapped(x, top);
+        put_wrapped(x, top + HEIGHT - 1);
+    }
+
+    for y in top..top + HEIGHT {
+        put_wrapped(left, y);
+        put_wrapped(left + WIDTH - 1, y);
+    }
+}
+
+// One of the two pattern tables in one of the 8 palettes, as
+// PATTERN_TABLE_SIZE pixels square
+pub fn pattern_table(
+    ppu: &Ppu,
+    mut cartridge: Option<&mut Cartridge>,
+    table: usize,
+    number: u8,
+    palette: &Palette,
+) -> Vec<u8> {
+    let mut output = vec![0; PATTERN_TABLE_SIZE * PATTERN_TABLE_SIZE * 4];
+    let base = (table as u16 & 0x01) * 0x1000;
+
+    for tile in 0..256 {
+        let (left, top) = (tile % 16 * 8, tile / 16 * 8);
+
+        for y in 0..8 {
+            let address = base + tile as u16 * 16 + y as u16;
+            let pixels = tile_row(ppu, cartridge.as_deref_mut(), address);
+
+            for (x, &pixel) in pixels.iter().enumerate() {
+                let rgb = color(ppu, palette, number & 0x07, pixel);
+                put(&mut output, PATTERN_TABLE_SIZE, left + x, top + y, rgb);
+            }
+        }
+    }
+
+    output
+}
+
+// The 64 sprites in OAM order, flipped and coloured as they'd be drawn,
+// with transparent pixels left transparent. 8x8 sprites take the top of
+// their cell.
+pub fn oam_sheet(ppu: &Ppu, mut cartridge: Option<&mut Cartridge>, palette: &Palette) -> Vec<u8> {
+    let mut output = vec![0; OAM_SHEET_WIDTH * OAM_SHEET_HEIGHT * 4];
+    let tall = ppu.ctrl.contains(PpuCtrl::TALL_SPRITES);
+    let height = if tall { 16 } else { 8 };
+    let table = if ppu.ctrl.contains(PpuCtrl::SPRITE_TABLE) {
+        0x1000
+    } else {
+        0x0000
+    };
+
+    for (index, sprite) in oam_entries(ppu).iter().enumerate() {
+        let (left, top) = (index % 8 * 8, index / 8 * 16);
+
+        for y in 0..height {
+            let row = if sprite.flip_vertical {
+                height - 1 - y
+            } else {
+                y
+            };
+
+            // 8x16 sprites take their pattern table from bit 0 of the tile
+            // and run on into the next tile
+            let address = if tall {
+                (sprite.tile as u16 & 0x01) * 0x1000
+                    + (sprite.tile as u16 & 0xFE) * 16
+                    + (row as u16 / 8) * 16
+                    + row as u16 % 8
+            } else {
+                table + sprite.tile as u16 * 16 + row as u16
+            };
+            let pixels = tile_row(ppu, cartridge.as_deref_mut(), address);
+
+            for x in 0..8 {
+                let pixel = if sprite.flip_horizontal {
+                    pixels[7 - x]
+                } else {
+                    pixels[x]
+                };
+
+                if pixel != 0 {
+                    let rgb = color(ppu, palette, 4 + sprite.palette, pixel);
+                    put(&mut output, OAM_SHEET_WIDTH, left + x, top + y, rgb);
+                }
+            }
+        }
+    }
+
+    output
+}
+
+// The 32 entries of palette RAM, background palettes on the top row and
+// sprite palettes below, as PALETTE_WIDTH * PALETTE_HEIGHT pixels
+pub fn palette_ram(ppu: &Ppu, palette: &Palette) -> Vec<u8> {
+    let mut output = vec![0; PALETTE_WIDTH * PALETTE_HEIGHT * 4];
+
+    for entry in 0..32 {
+        let rgb = palette.color(ppu.peek(0x3F00 + entry as u16, None) as u16 & 0x3F);
+        let (left, top) = (entry % 16 * SWATCH_SIZE, entry / 16 * SWATCH_SIZE);
+
+        for y in top..top + SWATCH_SIZE {
+            for x in left..left + SWATCH_SIZE {
+                put(&mut output, PALETTE_WIDTH, x, y, rgb);
+            }
+        }
+    }
+
+    output
+}
@@ -0,0 +1,817 @@
+use bitflags::bitflags;
+
+use crate::cartridge::{Cartridge, Mirroring, Region};
+use crate::video::EMPHASIS_SHIFT;
+
+pub mod debug;
+
+pub const WIDTH: usize = 256;
+pub const HEIGHT: usize = 240;
+pub const DOTS_PER_SCANLINE: u16 = 341;
+pub const SCANLINES_PER_FRAME: u16 = 262;
+pub const PAL_SCANLINES_PER_FRAME: u16 = 312;
+const VISIBLE_SCANLINES: u16 = 240;
+const VBLANK_SCANLINE: u16 = 241;
+// The Dendy holds off vblank until 51 lines after the picture, leaving the
+// same time for NMI handlers as on NTSC
+const DENDY_VBLANK_SCANLINE: u16 = 291;
+const MAX_SPRITES_PER_LINE: usize = 8;
+
+bitflags! {
+    pub struct PpuCtrl: u8 {
+        const NAMETABLE_X = 1 << 0;
+        const NAMETABLE_Y = 1 << 1;
+        const INCREMENT_32 = 1 << 2;
+        const SPRITE_TABLE = 1 << 3;
+        const BACKGROUND_TABLE = 1 << 4;
+        const TALL_SPRITES = 1 << 5;
+        const MASTER_SLAVE = 1 << 6;
+        const NMI = 1 << 7;
+    }
+}
+
+bitflags! {
+    pub struct PpuMask: u8 {
+        const GRAYSCALE = 1 << 0;
+        const BACKGROUND_LEFT = 1 << 1;
+        const SPRITES_LEFT = 1 << 2;
+        const BACKGROUND = 1 << 3;
+        const SPRITES = 1 << 4;
+        const EMPHASIZE_RED = 1 << 5;
+        const EMPHASIZE_GREEN = 1 << 6;
+        const EMPHASIZE_BLUE = 1 << 7;
+    }
+}
+
+bitflags! {
+    pub struct PpuStatus: u8 {
+        const SPRITE_OVERFLOW = 1 << 5;
+        const SPRITE_ZERO_HIT = 1 << 6;
+        const VBLANK = 1 << 7;
+    }
+}
+
+// The 2C02: registers at $2000-$2007, nametable RAM, palette RAM and OAM.
+// Pattern tables and nametable mirroring come from the cartridge, which the
+// CPU hands over with every access.
+pub struct Ppu {
+    ctrl: PpuCtrl,
+    mask: PpuMask,
+    status: PpuStatus,
+    oam_address: u8,
+    oam: [u8; 256],
+
+    // Loopy's internal registers: the current and temporary VRAM addresses,
+    // fine X scroll and the $2005/$2006 write toggle
+    v: u16,
+    t: u16,
+    x: u8,
+    w: bool,
+
+    // $2007 reads come out one read late, except for the palette
+    read_buffer: u8,
+    // The data bus between the CPU and the PPU, which write-only registers
+    // and unused status bits read back
+    latch: u8,
+
+    ciram: [u8; 0x800],
+    palette: [u8; 32],
+
+    // What the background fetches latch for the next tile, and the shift
+    // registers the tiles are drawn out of. The attribute bits are spread
+    // over a byte per pixel like the pattern.
+    tile: u8,
+    attribute: u8,
+    pattern_low: u8,
+    pattern_high: u8,
+    pattern_shifters: [u16; 2],
+    attribute_shifters: [u16; 2],
+
+    // Secondary OAM holds the sprites found on this line for the next, and
+    // the sprite fetches fill in what's drawn from them
+    secondary_oam: [u8; MAX_SPRITES_PER_LINE * 4],
+    sprites: [Sprite; MAX_SPRITES_PER_LINE],
+    sprite_count: usize,
+    sprite_zero_found: bool,
+    sprite_zero_line: bool,
+
+    // The last frame drawn, a palette entry per pixel with the emphasis
+    // bits from $2001 above it
+    framebuffer: Box<[u16; WIDTH * HEIGHT]>,
+    frame_ready: bool,
+
+    scanline: u16,
+    dot: u16,
+    frame: u64,
+    odd_frame: bool,
+    nmi_pending: bool,
+
+    region: Region,
+    scanlines_per_frame: u16,
+    vblank_scanline: u16,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Sprite {
+    x: u8,
+    attributes: u8,
+    // Flipped as needed, so the leftmost pixel is always in bit 7
+    pattern: [u8; 2],
+}
+
+impl Default for Ppu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Ppu {
+    pub fn new() -> Self {
+        Ppu {
+            ctrl: PpuCtrl::empty(),
+            mask: PpuMask::empty(),
+            status: PpuStatus::empty(),
+            oam_address: 0,
+            oam: [0; 256],
+            v: 0,
+            t: 0,
+            x: 0,
+            w: false,
+            read_buffer: 0,
+            latch: 0,
+            ciram: [0; 0x800],
+            palette: [0; 32],
+            tile: 0,
+            attribute: 0,
+            pattern_low: 0,
+            pattern_high: 0,
+            pattern_shifters: [0; 2],
+            attribute_shifters: [0; 2],
+            secondary_oam: [0xFF; MAX_SPRITES_PER_LINE * 4],
+            sprites: [Sprite::default(); MAX_SPRITES_PER_LINE],
+            sprite_count: 0,
+            sprite_zero_found: false,
+            sprite_zero_line: false,
+            framebuffer: Box::new([0; WIDTH * HEIGHT]),
+            frame_ready: false,
+            scanline: 0,
+            dot: 0,
+            frame: 0,
+            odd_frame: false,
+            nmi_pending: false,
+            region: Region::Ntsc,
+            scanlines_per_frame: SCANLINES_PER_FRAME,
+            vblank_scanline: VBLANK_SCANLINE,
+        }
+    }
+
+    // Cartridges for several regions run as NTSC
+    pub fn set_region(&mut self, region: Region) {
+        let region = match region {
+            Region::Multiple => Region::Ntsc,
+            region => region,
+        };
+
+        self.region = region;
+        (self.scanlines_per_frame, self.vblank_scanline) = match region {
+            Region::Pal => (PAL_SCANLINES_PER_FRAME, VBLANK_SCANLINE),
+            Region::Dendy => (PAL_SCANLINES_PER_FRAME, DENDY_VBLANK_SCANLINE),
+            _ => (SCANLINES_PER_FRAME, VBLANK_SCANLINE),
+        };
+
+        if self.scanline >= self.scanlines_per_frame {
+            self.scanline = 0;
+        }
+    }
+
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    pub fn scanlines_per_frame(&self) -> u16 {
+        self.scanlines_per_frame
+    }
+
+    pub fn ctrl(&self) -> PpuCtrl {
+        self.ctrl
+    }
+
+    pub fn mask(&self) -> PpuMask {
+        self.mask
+    }
+
+    pub fn scanline(&self) -> u16 {
+        self.scanline
+    }
+
+    pub fn dot(&self) -> u16 {
+        self.dot
+    }
+
+    // Number of frames completed so far
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    // The picture, WIDTH by HEIGHT pixels from the top left. See
+    // `VideoInterface` for what's in them.
+    pub fn framebuffer(&self) -> &[u16] {
+        &self.framebuffer[..]
+    }
+
+    pub fn oam(&self) -> &[u8; 256] {
+        &self.oam
+    }
+
+    pub fn palette_ram(&self) -> &[u8; 32] {
+        &self.palette
+    }
+
+    // Whether a frame was finished since the last call
+    pub fn poll_frame(&mut self) -> bool {
+        let ready = self.frame_ready;
+        self.frame_ready = false;
+        ready
+    }
+
+    // Whether an NMI was raised since the last call
+    pub fn poll_nmi(&mut self) -> bool {
+        let nmi = self.nmi_pending;
+        self.nmi_pending = false;
+        nmi
+    }
+
+    // CPU reads of $2000-$3FFF, mirrored every 8 bytes
+    pub fn read_register(&mut self, address: u16, mut cartridge: Option<&mut Cartridge>) -> u8 {
+        match address & 0x0007 {
+            // Reading the status acknowledges vblank and resets the toggle
+            2 => {
+                self.latch = self.status.bits() | (self.latch & 0x1F);
+                self.status.remove(PpuStatus::VBLANK);
+                self.w = false;
+            }
+            4 => self.latch = self.oam[self.oam_address as usize],
+            7 => {
+                let address = self.v & 0x3FFF;
+
+                self.latch = if address >= 0x3F00 {
+                    // The buffer gets the nametable byte under the palette
+                    self.read_buffer = self.read(address & 0x2FFF, cartridge.as_deref_mut());
+                    self.read_palette(address) | (self.latch & 0xC0)
+                } else {
+                    let value = self.read_buffer;
+                    self.read_buffer = self.read(address, cartridge.as_deref_mut());
+                    value
+                };
+
+                self.increment_v(cartridge);
+            }
+            _ => {}
+        }
+
+        self.latch
+    }
+
+    // CPU writes of $2000-$3FFF, mirrored every 8 bytes
+    pub fn write_register(
+        &mut self,
+        address: u16,
+        value: u8,
+        mut cartridge: Option<&mut Cartridge>,
+    ) {
+        let register = address & 0x0007;
+        self.latch = value;
+
+        if let Some(cartridge) = cartridge.as_deref_mut() {
+            cartridge.ppu_register_write(0x2000 | register, value);
+        }
+
+        match register {
+            0 => {
+                let enabling = !self.ctrl.contains(PpuCtrl::NMI) && value & 0x80 != 0;
+                self.ctrl = PpuCtrl::from_bits_truncate(value);
+                self.t = (self.t & !0x0C00) | ((value as u16 & 0x03) << 10);
+
+                // Turning NMIs on during vblank raises one straight away
+                if enabling && self.status.contains(PpuStatus::VBLANK) {
+                    self.nmi_pending = true;
+                }
+            }
+            1 => self.mask = PpuMask::from_bits_truncate(value),
+            3 => self.oam_address = value,
+            4 => self.write_oam(value),
+            5 => {
+                if self.w {
+                    self.t = (self.t & !0x73E0)
+                        | ((value as u16 & 0x07) << 12)
+                        | ((value as u16 & 0xF8) << 2);
+                } else {
+                    self.t = (self.t & !0x001F) | (value as u16 >> 3);
+                    self.x = value & 0x07;
+                }
+
+                self.w = !self.w;
+            }
+            6 => {
+                if self.w {
+                    self.t = (self.t & 0xFF00) | value as u16;
+                    self.v = self.t;
+
+                    // Boards watching A12 see the new address
+                    if let Some(cartridge) = cartridge {
+                        cartridge.ppu_address(self.v & 0x3FFF);
+                    }
+                } else {
+                    self.t = (self.t & 0x00FF) | ((value as u16 & 0x3F) << 8);
+                }
+
+                self.w = !self.w;
+            }
+            7 => {
+                self.write(self.v & 0x3FFF, value, cartridge.as_deref_mut());
+                self.increment_v(cartridge);
+            }
+            _ => {}
+        }
+    }
+
+    // $2004 writes, and where OAM DMA puts its bytes
+    pub fn write_oam(&mut self, value: u8) {
+        // The attribute bytes have no bits 2-4
+        let value = if self.oam_address & 0x03 == 0x02 {
+            value & 0xE3
+        } else {
+            value
+        };
+
+        self.oam[self.oam_address as usize] = value;
+        self.oam_address = self.oam_address.wrapping_add(1);
+    }
+
+    fn increment_v(&mut self, cartridge: Option<&mut Cartridge>) {
+        // While rendering, the access bumps the scroll the way fetches do
+        if self.rendering() && self.rendering_scanline() {
+            self.increment_x();
+            self.increment_y();
+            return;
+        }
+
+        let increment = if self.ctrl.contains(PpuCtrl::INCREMENT_32) {
+            32
+        } else {
+            1
+        };
+        self.v = self.v.wrapping_add(increment) & 0x7FFF;
+
+        if let Some(cartridge) = cartridge {
+            cartridge.ppu_address(self.v & 0x3FFF);
+        }
+    }
+
+    // The PPU bus: pattern tables from the cartridge, nametables, then the
+    // palette
+    fn read(&mut self, address: u16, cartridge: Option<&mut Cartridge>) -> u8 {
+        match (address, cartridge) {
+            (0x3F00..=0x3FFF, _) => self.read_palette(address),
+            (0x0000..=0x1FFF, Some(cartridge)) => {
+                cartridge.ppu_address(address);
+                cartridge.read_chr(address)
+            }
+            (_, Some(cartridge)) => {
+                cartridge.ppu_address(address);
+                cartridge.read_nametable(address & 0x2FFF, &self.ciram)
+            }
+            (0x0000..=0x1FFF, None) => 0,
+            (_, None) => self.ciram[Mirroring::Horizontal.ciram_index(address)],
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8, cartridge: Option<&mut Cartridge>) {
+        match (address, cartridge) {
+            (0x3F00..=0x3FFF, _) => self.palette[palette_index(address)] = value & 0x3F,
+            (0x0000..=0x1FFF, Some(cartridge)) => {
+                cartridge.ppu_address(address);
+                cartridge.write_chr(address, value);
+            }
+            (_, Some(cartridge)) => {
+                cartridge.ppu_address(address);
+                cartridge.write_nametable(address & 0x2FFF, value, &mut self.ciram);
+            }
+            (0x0000..=0x1FFF, None) => {}
+            (_, None) => self.ciram[Mirroring::Horizontal.ciram_index(address)] = value,
+        }
+    }
+
+    // A read of the PPU bus that leaves the PPU and the mapper as they
+    // were, for debuggers
+    pub fn peek(&self, address: u16, cartridge: Option<&mut Cartridge>) -> u8 {
+        let address = address & 0x3FFF;
+
+        match (address, cartridge) {
+            (0x3F00..=0x3FFF, _) => self.palette[palette_index(address)],
+            (0x0000..=0x1FFF, Some(cartridge)) => cartridge.peek_chr(address),
+            (_, Some(cartridge)) => cartridge.peek_nametable(address & 0x2FFF, &self.ciram),
+            (0x0000..=0x1FFF, None) => 0,
+            (_, None) => self.ciram[Mirroring::Horizontal.ciram_index(address)],
+        }
+    }
+
+    fn read_palette(&self, address: u16) -> u8 {
+        let value = self.palette[palette_index(address)];
+
+        if self.mask.contains(PpuMask::GRAYSCALE) {
+            value & 0x30
+        } else {
+            value
+        }
+    }
+
+    fn rendering(&self) -> bool {
+        self.mask.intersects(PpuMask::BACKGROUND | PpuMask::SPRITES)
+    }
+
+    // The scanlines that fetch, including the pre-render one that sets up
+    // the first
+    fn rendering_scanline(&self) -> bool {
+        self.scanline < VISIBLE_SCANLINES || self.pre_render_scanline()
+    }
+
+    // The last line of the frame, whichever region
+    fn pre_render_scanline(&self) -> bool {
+        self.scanline == self.scanlines_per_frame - 1
+    }
+
+    // Runs one dot
+    pub fn clock(&mut self, mut cartridge: Option<&mut Cartridge>) {
+        if self.rendering() && self.rendering_scanline() {
+            self.fetch_background(cartridge.as_deref_mut());
+            self.fetch_sprites(cartridge);
+        }
+
+        if self.scanline < VISIBLE_SCANLINES && (1..=256).contains(&self.dot) {
+            self.render_pixel();
+        }
+
+        if self.dot == 1 {
+            if self.scanline == self.vblank_scanline {
+                self.status.insert(PpuStatus::VBLANK);
+                self.frame_ready = true;
+
+                if self.ctrl.contains(PpuCtrl::NMI) {
+                    self.nmi_pending = true;
+                }
+            } else if self.pre_render_scanline() {
+                self.status = PpuStatus::empty();
+            }
+        }
+
+        self.dot += 1;
+
+        // Odd frames skip the last dot of the pre-render line when
+        // rendering, on NTSC only
+        if self.region == Region::Ntsc
+            && self.pre_render_scanline()
+            && self.dot == DOTS_PER_SCANLINE - 1
+            && self.odd_frame
+            && self.rendering()
+        {
+            self.dot = DOTS_PER_SCANLINE;
+        }
+
+        if self.dot == DOTS_PER_SCANLINE {
+            self.dot = 0;
+            self.scanline += 1;
+
+            if self.scanline == self.scanlines_per_frame {
+                self.scanline = 0;
+                self.frame += 1;
+                self.odd_frame = !self.odd_frame;
+            }
+        }
+    }
+
+    // Each tile takes 8 dots: nametable, attribute and both pattern bytes
+    // at 2 dots each, then a step right. Dots 1-256 fetch the rest of this
+    // line and 321-336 the first two tiles of the next, with the shift
+    // registers reloaded every 8 dots in between.
+    fn fetch_background(&mut self, mut cartridge: Option<&mut Cartridge>) {
+        let dot = self.dot;
+
+        if (2..=257).contains(&dot) || (322..=337).contains(&dot) {
+            self.shift_background();
+
+            if dot % 8 == 1 {
+                self.load_background();
+            }
+        }
+
+        if (1..=256).contains(&dot) || (321..=336).contains(&dot) {
+            match dot % 8 {
+                1 => self.fetch_tile(cartridge.as_deref_mut()),
+                3 => {
+                    let v = self.v;
+                    let address = 0x23C0 | (v & 0x0C00) | ((v >> 4) & 0x38) | ((v >> 2) & 0x07);
+                    // Each attribute byte covers 4x4 tiles, 2 bits per 2x2
+                    let shift = ((v >> 4) & 0x04) | (v & 0x02);
+                    self.attribute = (self.read(address, cartridge.as_deref_mut()) >> shift) & 0x03;
+                }
+                5 => self.pattern_low = self.read(self.pattern_address(), cartridge.as_deref_mut()),
+                7 => {
+                    self.pattern_high =
+                        self.read(self.pattern_address() + 8, cartridge.as_deref_mut())
+                }
+                0 => self.increment_x(),
+                _ => {}
+            }
+        }
+
+        match dot {
+            256 => self.increment_y(),
+            257 => self.v = (self.v & !0x041F) | (self.t & 0x041F),
+            // Two unused nametable fetches end the line
+            337 | 339 => self.fetch_tile(cartridge),
+            280..=304 if self.pre_render_scanline() => {
+                self.v = (self.v & !0x7BE0) | (self.t & 0x7BE0);
+            }
+            _ => {}
+        }
+    }
+
+    fn fetch_tile(&mut self, cartridge: Option<&mut Cartridge>) {
+        self.tile = self.read(0x2000 | (self.v & 0x0FFF), cartridge);
+    }
+
+    fn pattern_address(&self) -> u16 {
+        let table = if self.ctrl.contains(PpuCtrl::BACKGROUND_TABLE) {
+            0x1000
+        } else {
+            0x0000
+        };
+
+        table | (self.tile as u16) << 4 | (self.v >> 12) & 0x07
+    }
+
+    fn shift_background(&mut self) {
+        for shifter in self
+            .pattern_shifters
+            .iter_mut()
+            .chain(self.attribute_shifters.iter_mut())
+        {
+            *shifter <<= 1;
+        }
+    }
+
+    fn load_background(&mut self) {
+        let bits = [self.pattern_low, self.pattern_high];
+
+        for (plane, &bits) in bits.iter().enumerate() {
+            let fill = if self.attribute >> plane & 0x01 != 0 {
+                0xFF
+            } else {
+                0x00
+            };
+
+            self.pattern_shifters[plane] = (self.pattern_shifters[plane] & 0xFF00) | bits as u16;
+            self.attribute_shifters[plane] = (self.attribute_shifters[plane] & 0xFF00) | fill;
+        }
+    }
+
+    // Coarse X, wrapping into the next nametable across
+    fn increment_x(&mut self) {
+        if self.v & 0x001F == 31 {
+            self.v = (self.v & !0x001F) ^ 0x0400;
+        } else {
+            self.v += 1;
+        }
+    }
+
+    // Fine Y, then coarse Y, wrapping into the nametable below after row
+    // 29. Rows 30 and 31 are attribute bytes and wrap without switching.
+    fn increment_y(&mut self) {
+        if self.v & 0x7000 != 0x7000 {
+            self.v += 0x1000;
+            return;
+        }
+
+        self.v &= !0x7000;
+
+        let coarse_y = match (self.v >> 5) & 0x1F {
+            29 => {
+                self.v ^= 0x0800;
+                0
+            }
+            31 => 0,
+            coarse_y => coarse_y + 1,
+        };
+
+        self.v = (self.v & !0x03E0) | (coarse_y << 5);
+    }
+
+    fn sprite_height(&self) -> u16 {
+        if self.ctrl.contains(PpuCtrl::TALL_SPRITES) {
+            16
+        } else {
+            8
+        }
+    }
+
+    // The row of a sprite at `y` on this line, if it's on it
+    fn sprite_row(&self, y: u8) -> Option<u16> {
+        let row = self.scanline.wrapping_sub(y as u16);
+        (row < self.sprite_height()).then_some(row)
+    }
+
+    // Sprites are found during dots 65-256 and their patterns fetched over
+    // dots 257-320, a sprite every 8 dots like the background tiles. Slots
+    // without a sprite still fetch tile $FF, which boards counting A12
+    // rises rely on.
+    fn fetch_sprites(&mut self, cartridge: Option<&mut Cartridge>) {
+        let dot = self.dot;
+
+        if dot == 257 {
+            // Sprites drawn on the first line would have been found on the
+            // pre-render one, which doesn't look
+            if self.pre_render_scanline() {
+                self.secondary_oam = [0xFF; MAX_SPRITES_PER_LINE * 4];
+                self.sprite_count = 0;
+                self.sprite_zero_found = false;
+            } else {
+                self.evaluate_sprites();
+            }
+
+            self.sprite_zero_line = self.sprite_zero_found;
+        }
+
+        if !(257..=320).contains(&dot) {
+            return;
+        }
+
+        self.oam_address = 0;
+
+        let slot = (dot - 257) as usize / 8;
+        let plane = match dot % 8 {
+            5 => 0,
+            7 => 1,
+            _ => return,
+        };
+
+        let entry = &self.secondary_oam[slot * 4..slot * 4 + 4];
+        let (y, tile, attributes, x) = (entry[0], entry[1], entry[2], entry[3]);
+        let filled = slot < self.sprite_count;
+
+        let mut row = if filled {
+            self.sprite_row(y).unwrap_or(0)
+        } else {
+            0
+        };
+
+        if attributes & 0x80 != 0 {
+            row = self.sprite_height() - 1 - row;
+        }
+
+        let address = if self.ctrl.contains(PpuCtrl::TALL_SPRITES) {
+            let table = (tile as u16 & 0x01) << 12;
+            let tile = (tile as u16 & 0xFE) | (row >> 3);
+            table | tile << 4 | (row & 0x07)
+        } else {
+            let table = if self.ctrl.contains(PpuCtrl::SPRITE_TABLE) {
+                0x1000
+            } else {
+                0x0000
+            };
+            table | (tile as u16) << 4 | row
+        };
+
+        let mut pattern = self.read(address + plane * 8, cartridge);
+
+        if !filled {
+            pattern = 0;
+        } else if attributes & 0x40 != 0 {
+            pattern = pattern.reverse_bits();
+        }
+
+        let sprite = &mut self.sprites[slot];
+        sprite.x = x;
+        sprite.attributes = attributes;
+        sprite.pattern[plane as usize] = pattern;
+    }
+
+    // Copies the first 8 sprites on this line into secondary OAM. Looking
+    // for a ninth, the hardware also steps through the bytes of each entry
+    // as if they were Y, so the overflow flag is often wrong both ways.
+    fn evaluate_sprites(&mut self) {
+        self.secondary_oam = [0xFF; MAX_SPRITES_PER_LINE * 4];
+        self.sprite_count = 0;
+        self.sprite_zero_found = false;
+
+        let mut n = 0;
+
+        while n < 64 && self.sprite_count < MAX_SPRITES_PER_LINE {
+            let entry = &self.oam[n * 4..n * 4 + 4];
+
+            if self.sprite_row(entry[0]).is_some() {
+                let slot = self.sprite_count * 4;
+                self.secondary_oam[slot..slot + 4].copy_from_slice(entry);
+                self.sprite_count += 1;
+                self.sprite_zero_found |= n == 0;
+            }
+
+            n += 1;
+        }
+
+        let mut m = 0;
+
+        while n < 64 {
+            if self.sprite_row(self.oam[n * 4 + m]).is_some() {
+                self.status.insert(PpuStatus::SPRITE_OVERFLOW);
+                break;
+            }
+
+            n += 1;
+            m = (m + 1) & 0x03;
+        }
+    }
+
+    fn render_pixel(&mut self) {
+        let x = self.dot as usize - 1;
+        let mut background = 0;
+        let mut address = 0x3F00;
+
+        if self.mask.contains(PpuMask::BACKGROUND)
+            && (x >= 8 || self.mask.contains(PpuMask::BACKGROUND_LEFT))
+        {
+            let bit = 0x8000 >> self.x;
+            let plane = |shifter: u16, shift: u16| ((shifter & bit != 0) as u16) << shift;
+            background = plane(self.pattern_shifters[0], 0) | plane(self.pattern_shifters[1], 1);
+            let palette =
+                plane(self.attribute_shifters[0], 0) | plane(self.attribute_shifters[1], 1);
+
+            if background != 0 {
+                address |= palette << 2 | background;
+            }
+        }
+
+        if self.mask.contains(PpuMask::SPRITES)
+            && (x >= 8 || self.mask.contains(PpuMask::SPRITES_LEFT))
+        {
+            // The first opaque sprite wins, even behind the background
+            let sprite = self.sprites[..self.sprite_count]
+                .iter()
+                .enumerate()
+                .find_map(|(index, sprite)| {
+                    let column = x
+                        .checked_sub(sprite.x as usize)
+                        .filter(|&column| column < 8)?;
+                    let bit = 0x80 >> column;
+                    let pixel = (sprite.pattern[0] & bit != 0) as u16
+                        | ((sprite.pattern[1] & bit != 0) as u16) << 1;
+
+                    (pixel != 0).then_some((index, sprite.attributes, pixel))
+                });
+
+            if let Some((index, attributes, pixel)) = sprite {
+                // Sprite 0 hits on any opaque overlap but at the last
+                // column, whatever the priority
+                if index == 0 && self.sprite_zero_line && background != 0 && x != 255 {
+                    self.status.insert(PpuStatus::SPRITE_ZERO_HIT);
+                }
+
+                if background == 0 || attributes & 0x20 == 0 {
+                    address = 0x3F10 | (attributes as u16 & 0x03) << 2 | pixel;
+                }
+            }
+        }
+
+        // With rendering off, the backdrop is whatever palette entry v
+        // points at, if any
+        if !self.rendering() && self.v & 0x3F00 == 0x3F00 {
+            address = self.v;
+        }
+
+        // The 2C07 and the Dendy's PPU swap the red and green emphasis
+        // bits, so they're swapped back to keep pixels the same everywhere
+        let mut emphasis = self.mask.bits() as u16 >> 5;
+
+        if self.region != Region::Ntsc {
+            emphasis = emphasis & 0x04 | (emphasis & 0x01) << 1 | (emphasis & 0x02) >> 1;
+        }
+
+        let emphasis = emphasis << EMPHASIS_SHIFT;
+        self.framebuffer[self.scanline as usize * WIDTH + x] =
+            self.read_palette(address) as u16 | emphasis;
+    }
+}
+
+// $3F10, $3F14, $3F18 and $3F1C mirror the background entries below them
+fn palette_index(address: u16) -> usize {
+    let index = address as usize & 0x1F;
+
+    if index & 0x13 == 0x10 {
+        index & 0x0F
+    } else {
+        index
+    }
+}
+
+#[cfg(test)]
+mod tests;
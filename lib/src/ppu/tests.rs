@@ -0,0 +1,652 @@
+use super::*;
+use crate::cpu::CPU;
+use crate::video::Palette;
+
+// NROM with CHR-RAM and vertical mirroring
+fn cartridge() -> Cartridge {
+    let mut rom = vec![
+        b'N', b'E', b'S', 0x1A, 1, 0, 0x01, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    ];
+    rom.extend(vec![0; 0x4000]);
+    Cartridge::load(&rom).unwrap()
+}
+
+fn set_address(ppu: &mut Ppu, address: u16, cartridge: Option<&mut Cartridge>) {
+    let mut cartridge = cartridge;
+    ppu.write_register(0x2006, (address >> 8) as u8, cartridge.as_deref_mut());
+    ppu.write_register(0x2006, address as u8, cartridge);
+}
+
+fn run_to(ppu: &mut Ppu, scanline: u16, dot: u16) {
+    while ppu.scanline() != scanline || ppu.dot() != dot {
+        ppu.clock(None);
+    }
+}
+
+fn write_vram(ppu: &mut Ppu, cartridge: &mut Cartridge, address: u16, data: &[u8]) {
+    set_address(ppu, address, Some(cartridge));
+
+    for &value in data {
+        ppu.write_register(0x2007, value, Some(cartridge));
+    }
+}
+
+// Whatever $2006 left in t gets replaced
+fn reset_scroll(ppu: &mut Ppu, cartridge: &mut Cartridge) {
+    ppu.write_register(0x2000, 0x00, Some(cartridge));
+    ppu.write_register(0x2005, 0x00, Some(cartridge));
+    ppu.write_register(0x2005, 0x00, Some(cartridge));
+}
+
+fn run_cartridge_to(ppu: &mut Ppu, cartridge: &mut Cartridge, scanline: u16, dot: u16) {
+    while ppu.scanline() != scanline || ppu.dot() != dot {
+        ppu.clock(Some(cartridge));
+    }
+}
+
+// Tile 1 is solid colour 1, tile 2 solid colour 2 and tile 3 colour 3 in
+// its left column only. The left nametable is filled with tile 1, the right
+// one with tile 2.
+fn background() -> (Ppu, Cartridge) {
+    let mut ppu = Ppu::new();
+    let mut cartridge = cartridge();
+
+    let mut patterns = vec![0; 16];
+    patterns.extend([0xFF; 8].iter().chain(&[0x00; 8]));
+    patterns.extend([0x00; 8].iter().chain(&[0xFF; 8]));
+    patterns.extend([0x80; 16]);
+    write_vram(&mut ppu, &mut cartridge, 0x0000, &patterns);
+
+    write_vram(&mut ppu, &mut cartridge, 0x2000, &[1; 0x3C0]);
+    write_vram(&mut ppu, &mut cartridge, 0x2400, &[2; 0x3C0]);
+    write_vram(&mut ppu, &mut cartridge, 0x3F00, &[0x0F, 0x11, 0x22, 0x33]);
+    write_vram(&mut ppu, &mut cartridge, 0x3F04, &[0x0F, 0x14, 0x24, 0x34]);
+
+    reset_scroll(&mut ppu, &mut cartridge);
+    ppu.write_register(0x2001, 0x0A, Some(&mut cartridge));
+
+    (ppu, cartridge)
+}
+
+fn pixel(ppu: &Ppu, x: usize, y: usize) -> u16 {
+    ppu.framebuffer()[y * WIDTH + x]
+}
+
+#[test]
+fn test_vram_access() {
+    let mut ppu = Ppu::new();
+    let mut cartridge = cartridge();
+
+    set_address(&mut ppu, 0x0010, Some(&mut cartridge));
+    ppu.write_register(0x2007, 0x12, Some(&mut cartridge));
+    ppu.write_register(0x2007, 0x34, Some(&mut cartridge));
+    assert_eq!(cartridge.read_chr(0x0011), 0x34);
+
+    // Reads come out of the buffer one late
+    set_address(&mut ppu, 0x0010, Some(&mut cartridge));
+    assert_eq!(ppu.read_register(0x2007, Some(&mut cartridge)), 0x00);
+    assert_eq!(ppu.read_register(0x2007, Some(&mut cartridge)), 0x12);
+    assert_eq!(ppu.read_register(0x2007, Some(&mut cartridge)), 0x34);
+
+    // Vertical mirroring puts $2800 over $2000
+    set_address(&mut ppu, 0x2005, Some(&mut cartridge));
+    ppu.write_register(0x2007, 0x56, Some(&mut cartridge));
+    set_address(&mut ppu, 0x2805, Some(&mut cartridge));
+    ppu.read_register(0x2007, Some(&mut cartridge));
+    assert_eq!(ppu.read_register(0x2007, Some(&mut cartridge)), 0x56);
+
+    // Incrementing by 32 walks down a column
+    ppu.write_register(0x2000, 0x04, Some(&mut cartridge));
+    set_address(&mut ppu, 0x2400, Some(&mut cartridge));
+    ppu.write_register(0x2007, 0x01, Some(&mut cartridge));
+    ppu.write_register(0x2007, 0x02, Some(&mut cartridge));
+    ppu.write_register(0x2000, 0x00, Some(&mut cartridge));
+    set_address(&mut ppu, 0x2C20, Some(&mut cartridge));
+    ppu.read_register(0x2007, Some(&mut cartridge));
+    assert_eq!(ppu.read_register(0x2007, Some(&mut cartridge)), 0x02);
+}
+
+#[test]
+fn test_palette() {
+    let mut ppu = Ppu::new();
+
+    set_address(&mut ppu, 0x2F00, None);
+    ppu.write_register(0x2007, 0x99, None);
+
+    set_address(&mut ppu, 0x3F00, None);
+    ppu.write_register(0x2007, 0xFF, None);
+    ppu.write_register(0x2007, 0x21, None);
+
+    // Sprite backdrop entries mirror the background ones, everywhere up to
+    // $3FFF
+    set_address(&mut ppu, 0x3F10, None);
+    ppu.write_register(0x2007, 0x0F, None);
+    assert_eq!(ppu.palette_ram()[0], 0x0F);
+
+    set_address(&mut ppu, 0x3FE1, None);
+    ppu.write_register(0x2007, 0x16, None);
+    assert_eq!(ppu.palette_ram()[1], 0x16);
+
+    // Palette reads skip the buffer, which gets the nametable underneath
+    set_address(&mut ppu, 0x3F00, None);
+    assert_eq!(ppu.read_register(0x2007, None), 0x0F);
+    assert_eq!(ppu.read_buffer, 0x99);
+
+    ppu.write_register(0x2001, 0x01, None);
+    assert_eq!(ppu.read_register(0x2007, None), 0x10);
+}
+
+#[test]
+fn test_status() {
+    let mut ppu = Ppu::new();
+
+    run_to(&mut ppu, 241, 2);
+    ppu.write_register(0x2005, 0x1F, None);
+    assert!(!ppu.poll_nmi());
+
+    // The low bits are whatever was last on the bus
+    assert_eq!(ppu.read_register(0x2002, None), 0x9F);
+    assert_eq!(ppu.read_register(0x2002, None), 0x1F);
+
+    // Reading the status resets the write toggle
+    ppu.write_register(0x2005, 0x08, None);
+    ppu.read_register(0x2002, None);
+    ppu.write_register(0x2005, 0x10, None);
+    assert_eq!(ppu.t & 0x001F, 0x02);
+
+    // Enabling NMIs in vblank raises one
+    run_to(&mut ppu, 260, 0);
+    ppu.status.insert(PpuStatus::VBLANK);
+    ppu.write_register(0x2000, 0x80, None);
+    assert!(ppu.poll_nmi());
+    ppu.write_register(0x2000, 0x80, None);
+    assert!(!ppu.poll_nmi());
+
+    run_to(&mut ppu, 261, 2);
+    assert_eq!(ppu.read_register(0x2002, None) & 0xE0, 0x00);
+
+    run_to(&mut ppu, 241, 2);
+    assert!(ppu.poll_nmi());
+    assert_eq!(ppu.frame(), 1);
+}
+
+#[test]
+fn test_scroll_registers() {
+    let mut ppu = Ppu::new();
+
+    ppu.write_register(0x2000, 0x03, None);
+    ppu.write_register(0x2005, 0x7D, None);
+    assert_eq!((ppu.t, ppu.x), (0x0C0F, 0x05));
+    ppu.write_register(0x2005, 0x5E, None);
+    assert_eq!(ppu.t, 0x6D6F);
+
+    // The first $2006 write clears bit 14
+    ppu.write_register(0x2006, 0xFD, None);
+    assert_eq!(ppu.t, 0x3D6F);
+    ppu.write_register(0x2006, 0x42, None);
+    assert_eq!((ppu.t, ppu.v), (0x3D42, 0x3D42));
+}
+
+#[test]
+fn test_oam() {
+    let mut ppu = Ppu::new();
+
+    ppu.write_register(0x2003, 0xFD, None);
+    for value in [0x10, 0xFF, 0x20, 0x40] {
+        ppu.write_register(0x2004, value, None);
+    }
+
+    // The attribute byte has no bits 2-4, and the address wraps
+    assert_eq!(ppu.oam()[0xFD], 0x10);
+    assert_eq!(ppu.oam()[0xFE], 0xE3);
+    ppu.write_register(0x2003, 0x00, None);
+    assert_eq!(ppu.read_register(0x2004, None), 0x40);
+}
+
+#[test]
+fn test_cpu_bus() {
+    let mut cpu = CPU::new();
+
+    for offset in 0..256 {
+        cpu.write(0x0200 + offset, offset as u8);
+    }
+
+    // The registers repeat every 8 bytes
+    cpu.write(0x3FF3, 0x10);
+    cpu.write(0x4014, 0x02);
+    assert_eq!(cpu.ppu().oam()[0x10], 0x00);
+    assert_eq!(cpu.ppu().oam()[0x0F], 0xFF);
+    assert_eq!(cpu.ppu().oam()[0x16], 0x02);
+
+    // The stall comes after the write's own cycles
+    cpu.write(0x0000, 0x8D);
+    cpu.write(0x0001, 0x14);
+    cpu.write(0x0002, 0x40);
+    cpu.write(0x0003, 0xEA);
+    cpu.write(0xFFFA, 0x03);
+    cpu.reset();
+    let cycles = cpu.step();
+    assert!(cycles == 4 + 513 || cycles == 4 + 514);
+
+    // Vblank raises an NMI once it's enabled
+    cpu.write(0x2000, 0x80);
+    while cpu.ppu().scanline() != 241 || cpu.ppu().dot() < 2 {
+        cpu.idle();
+    }
+    assert_eq!(cpu.read(0x2002) & 0x80, 0x80);
+    assert_eq!(cpu.step(), 7);
+}
+
+#[test]
+fn test_background() {
+    let (mut ppu, mut cartridge) = background();
+
+    // A column of tile 3 at the left of row 2, and attributes giving the
+    // second 2x2 group its own palette
+    ppu.write_register(0x2001, 0x00, Some(&mut cartridge));
+    write_vram(&mut ppu, &mut cartridge, 0x2040, &[3]);
+    write_vram(&mut ppu, &mut cartridge, 0x23C0, &[0x04]);
+    reset_scroll(&mut ppu, &mut cartridge);
+    ppu.write_register(0x2001, 0x0A, Some(&mut cartridge));
+
+    run_cartridge_to(&mut ppu, &mut cartridge, 261, 0);
+    run_cartridge_to(&mut ppu, &mut cartridge, 241, 0);
+
+    assert_eq!(pixel(&ppu, 0, 0), 0x11);
+    assert_eq!(pixel(&ppu, 255, 239), 0x11);
+    assert_eq!(pixel(&ppu, 16, 0), 0x14);
+    assert_eq!(pixel(&ppu, 0, 16), 0x33);
+    assert_eq!(pixel(&ppu, 1, 16), 0x0F);
+
+    // Fine X scroll moves the column left, and the left 8 pixels can be
+    // hidden
+    ppu.write_register(0x2005, 0x03, Some(&mut cartridge));
+    ppu.write_register(0x2005, 0x00, Some(&mut cartridge));
+    ppu.write_register(0x2001, 0x08, Some(&mut cartridge));
+    run_cartridge_to(&mut ppu, &mut cartridge, 261, 0);
+    run_cartridge_to(&mut ppu, &mut cartridge, 241, 0);
+
+    assert_eq!(pixel(&ppu, 7, 16), 0x0F);
+    assert_eq!(pixel(&ppu, 8, 16), 0x11);
+    assert_eq!(pixel(&ppu, 252, 0), 0x11);
+    assert_eq!(pixel(&ppu, 253, 0), 0x22);
+}
+
+#[test]
+fn test_scroll_split() {
+    let (mut ppu, mut cartridge) = background();
+
+    ppu.write_register(0x2001, 0x00, Some(&mut cartridge));
+    write_vram(&mut ppu, &mut cartridge, 0x2400, &[3]);
+    reset_scroll(&mut ppu, &mut cartridge);
+    ppu.write_register(0x2001, 0x0A, Some(&mut cartridge));
+    run_cartridge_to(&mut ppu, &mut cartridge, 261, 0);
+
+    // Like a status bar: the top of the screen stays put, then the game
+    // scrolls to the right nametable partway down. The new scroll is
+    // picked up at the end of the line.
+    run_cartridge_to(&mut ppu, &mut cartridge, 32, 200);
+    ppu.write_register(0x2000, 0x01, Some(&mut cartridge));
+    ppu.write_register(0x2005, 0x08, Some(&mut cartridge));
+    run_cartridge_to(&mut ppu, &mut cartridge, 241, 0);
+
+    assert_eq!(pixel(&ppu, 255, 32), 0x11);
+    assert_eq!(pixel(&ppu, 0, 33), 0x22);
+    assert_eq!(pixel(&ppu, 247, 33), 0x22);
+    assert_eq!(pixel(&ppu, 248, 33), 0x11);
+
+    // A $2006 write mid-frame sets the vertical scroll as well, after a
+    // $2002 read resets the toggle the single $2005 write left set
+    run_cartridge_to(&mut ppu, &mut cartridge, 100, 260);
+    ppu.read_register(0x2002, Some(&mut cartridge));
+    ppu.write_register(0x2006, 0x04, Some(&mut cartridge));
+    ppu.write_register(0x2006, 0x00, Some(&mut cartridge));
+    run_cartridge_to(&mut ppu, &mut cartridge, 241, 0);
+
+    assert_eq!(pixel(&ppu, 255, 100), 0x11);
+    assert_eq!(pixel(&ppu, 0, 101), 0x33);
+    assert_eq!(pixel(&ppu, 1, 101), 0x0F);
+    assert_eq!(pixel(&ppu, 255, 101), 0x22);
+    assert_eq!(pixel(&ppu, 0, 108), 0x33);
+    assert_eq!(pixel(&ppu, 0, 109), 0x22);
+}
+
+#[test]
+fn test_odd_frames() {
+    let (mut ppu, mut cartridge) = background();
+    let mut frame_dots = || {
+        let frame = ppu.frame();
+        let mut dots = 0;
+
+        while ppu.frame() == frame {
+            ppu.clock(Some(&mut cartridge));
+            dots += 1;
+        }
+
+        dots
+    };
+
+    frame_dots();
+    let frames = [frame_dots(), frame_dots()];
+    assert!(frames == [89342, 89341] || frames == [89341, 89342]);
+}
+
+#[test]
+fn test_regions() {
+    let frame_dots = |region: Region| {
+        let (mut ppu, mut cartridge) = background();
+        ppu.set_region(region);
+        run_cartridge_to(&mut ppu, &mut cartridge, 0, 0);
+
+        let mut vblank = None;
+        let mut dots = 0;
+
+        for _ in 0..2 {
+            let frame = ppu.frame();
+            dots = 0;
+
+            while ppu.frame() == frame {
+                ppu.clock(Some(&mut cartridge));
+                dots += 1;
+
+                if vblank.is_none() && ppu.status.contains(PpuStatus::VBLANK) {
+                    vblank = Some(ppu.scanline());
+                }
+            }
+        }
+
+        (dots, vblank.unwrap())
+    };
+
+    // 312 lines, with no dot skipped on odd frames
+    assert_eq!(frame_dots(Region::Pal), (106392, 241));
+    assert_eq!(frame_dots(Region::Dendy), (106392, 291));
+
+    let mut ppu = Ppu::new();
+    ppu.set_region(Region::Multiple);
+    assert_eq!(ppu.region(), Region::Ntsc);
+    assert_eq!(ppu.scanlines_per_frame(), SCANLINES_PER_FRAME);
+}
+
+#[test]
+fn test_pal_emphasis() {
+    // Red emphasis is bit 6 of $2001 on PAL, green on NTSC
+    let emphasis = |region: Region| {
+        let (mut ppu, mut cartridge) = background();
+        ppu.set_region(region);
+        ppu.write_register(0x2001, 0x2A, Some(&mut cartridge));
+        run_cartridge_to(&mut ppu, &mut cartridge, 10, 0);
+        pixel(&ppu, 10, 5) & !0x3F
+    };
+
+    assert_eq!(emphasis(Region::Ntsc), crate::video::EMPHASIS_RED);
+    assert_eq!(emphasis(Region::Pal), crate::video::EMPHASIS_GREEN);
+    assert_eq!(emphasis(Region::Dendy), crate::video::EMPHASIS_GREEN);
+}
+
+#[test]
+fn test_cpu_regions() {
+    let dots_per_cycles = |cpu: &mut CPU, cycles: usize| {
+        let position = |ppu: &Ppu| {
+            ppu.frame() * ppu.scanlines_per_frame() as u64 * DOTS_PER_SCANLINE as u64
+                + ppu.scanline() as u64 * DOTS_PER_SCANLINE as u64
+                + ppu.dot() as u64
+        };
+        let start = position(cpu.ppu());
+
+        for _ in 0..cycles {
+            cpu.idle();
+        }
+
+        position(cpu.ppu()) - start
+    };
+
+    // 3.2 dots a cycle on PAL, 3 elsewhere
+    let mut cpu = CPU::new();
+    cpu.set_region(Region::Pal);
+    assert_eq!(dots_per_cycles(&mut cpu, 100), 320);
+    cpu.set_region(Region::Dendy);
+    assert_eq!(dots_per_cycles(&mut cpu, 100), 300);
+
+    // Inserting a cartridge takes its region
+    let mut rom = vec![
+        b'N', b'E', b'S', 0x1A, 1, 0, 0x01, 0x08, 0, 0, 0, 0, 0x03, 0, 0, 0,
+    ];
+    rom.extend(vec![0; 0x4000]);
+    cpu.insert_cartridge(Cartridge::load(&rom).unwrap());
+    assert_eq!(cpu.region(), Region::Dendy);
+    assert_eq!(cpu.ppu().region(), Region::Dendy);
+
+    cpu.insert_cartridge(cartridge());
+    assert_eq!(cpu.region(), Region::Ntsc);
+}
+
+// The background with sprite palettes and a transparent row 10, lines
+// 80-87, and `sprites` at the start of OAM
+fn sprites(sprites: &[u8]) -> (Ppu, Cartridge) {
+    let (mut ppu, mut cartridge) = background();
+
+    ppu.write_register(0x2001, 0x00, Some(&mut cartridge));
+    write_vram(&mut ppu, &mut cartridge, 0x2140, &[0; 32]);
+    write_vram(
+        &mut ppu,
+        &mut cartridge,
+        0x3F10,
+        &[0x0F, 0x15, 0x25, 0x35, 0x0F, 0x16, 0x26, 0x36],
+    );
+    reset_scroll(&mut ppu, &mut cartridge);
+    ppu.write_register(0x2001, 0x1E, Some(&mut cartridge));
+
+    ppu.oam = [0xFF; 256];
+    ppu.oam[..sprites.len()].copy_from_slice(sprites);
+
+    run_cartridge_to(&mut ppu, &mut cartridge, 261, 0);
+    run_cartridge_to(&mut ppu, &mut cartridge, 241, 0);
+
+    (ppu, cartridge)
+}
+
+#[test]
+fn test_sprites() {
+    let (ppu, _) = sprites(&[
+        79, 2, 0x00, 16, // Solid colour 2
+        79, 1, 0x21, 20, // Behind sprite 0, and the background
+        99, 1, 0x20, 40, // Behind the background
+        99, 1, 0x00, 48, // In front of it
+        119, 3, 0x40, 64, // Flipped, so the column is on the right
+    ]);
+
+    // Drawn a line below their Y
+    assert_eq!(pixel(&ppu, 16, 79), 0x11);
+    assert_eq!(pixel(&ppu, 15, 80), 0x0F);
+    assert_eq!(pixel(&ppu, 16, 80), 0x25);
+    assert_eq!(pixel(&ppu, 23, 87), 0x25);
+    assert_eq!(pixel(&ppu, 16, 88), 0x11);
+
+    // Lower sprites win where they overlap, even behind the background
+    assert_eq!(pixel(&ppu, 20, 80), 0x25);
+    assert_eq!(pixel(&ppu, 24, 80), 0x16);
+
+    assert_eq!(pixel(&ppu, 40, 100), 0x11);
+    assert_eq!(pixel(&ppu, 48, 100), 0x15);
+
+    assert_eq!(pixel(&ppu, 64, 120), 0x11);
+    assert_eq!(pixel(&ppu, 71, 120), 0x35);
+
+    assert!(!ppu.status.contains(PpuStatus::SPRITE_ZERO_HIT));
+}
+
+#[test]
+fn test_tall_sprites() {
+    let (mut ppu, mut cartridge) = background();
+    ppu.write_register(0x2001, 0x00, Some(&mut cartridge));
+
+    // Odd tiles take the pair from $1000
+    let mut patterns = vec![0x00; 8];
+    patterns.extend([0xFF; 8]);
+    patterns.extend([0x80; 16]);
+    write_vram(&mut ppu, &mut cartridge, 0x1020, &patterns);
+
+    write_vram(&mut ppu, &mut cartridge, 0x2140, &[0; 32]);
+    write_vram(&mut ppu, &mut cartridge, 0x3F10, &[0x0F, 0x15, 0x25, 0x35]);
+    reset_scroll(&mut ppu, &mut cartridge);
+    ppu.write_register(0x2000, 0x20, Some(&mut cartridge));
+    ppu.write_register(0x2001, 0x1E, Some(&mut cartridge));
+
+    ppu.oam = [0xFF; 256];
+    ppu.oam[..8].copy_from_slice(&[79, 0x03, 0x00, 16, 79, 0x03, 0x80, 32]);
+    run_cartridge_to(&mut ppu, &mut cartridge, 261, 0);
+    run_cartridge_to(&mut ppu, &mut cartridge, 241, 0);
+
+    assert_eq!(pixel(&ppu, 17, 80), 0x25);
+    assert_eq!(pixel(&ppu, 17, 87), 0x25);
+    assert_eq!(pixel(&ppu, 16, 88), 0x35);
+    assert_eq!(pixel(&ppu, 17, 95), 0x11);
+    assert_eq!(pixel(&ppu, 16, 96), 0x11);
+
+    // Flipping vertically swaps the tiles too
+    assert_eq!(pixel(&ppu, 32, 80), 0x35);
+    assert_eq!(pixel(&ppu, 33, 80), 0x0F);
+    assert_eq!(pixel(&ppu, 33, 88), 0x25);
+}
+
+#[test]
+fn test_sprite_zero_hit() {
+    let hit = |sprite: [u8; 4], mask: u8| {
+        let (mut ppu, mut cartridge) = sprites(&sprite);
+        ppu.write_register(0x2001, mask, Some(&mut cartridge));
+        run_cartridge_to(&mut ppu, &mut cartridge, 261, 0);
+        run_cartridge_to(&mut ppu, &mut cartridge, 241, 0);
+        ppu.status.contains(PpuStatus::SPRITE_ZERO_HIT)
+    };
+
+    assert!(hit([29, 1, 0x00, 100], 0x1E));
+    assert!(hit([29, 1, 0x20, 100], 0x1E));
+
+    // Nothing at the last column, over a transparent background, or where
+    // either layer is hidden on the left
+    assert!(!hit([29, 1, 0x00, 255], 0x1E));
+    assert!(!hit([79, 1, 0x00, 100], 0x1E));
+    assert!(hit([29, 1, 0x00, 0], 0x1E));
+    assert!(!hit([29, 1, 0x00, 0], 0x1C));
+    assert!(!hit([29, 1, 0x00, 0], 0x1A));
+    assert!(!hit([29, 1, 0x00, 100], 0x10));
+
+    // It's set at the pixel, and cleared on the pre-render line
+    let (mut ppu, mut cartridge) = sprites(&[29, 1, 0x00, 100]);
+    run_cartridge_to(&mut ppu, &mut cartridge, 30, 101);
+    assert_eq!(ppu.read_register(0x2002, Some(&mut cartridge)) & 0x40, 0x00);
+    run_cartridge_to(&mut ppu, &mut cartridge, 30, 102);
+    assert_eq!(ppu.read_register(0x2002, Some(&mut cartridge)) & 0x40, 0x40);
+    run_cartridge_to(&mut ppu, &mut cartridge, 261, 2);
+    assert_eq!(ppu.read_register(0x2002, Some(&mut cartridge)) & 0x40, 0x00);
+}
+
+#[test]
+fn test_sprite_overflow() {
+    let overflow = |oam: &[u8]| {
+        let (ppu, _) = sprites(oam);
+        ppu.status.contains(PpuStatus::SPRITE_OVERFLOW)
+    };
+
+    let mut oam = [[49, 1, 0x00, 0]; 8].concat();
+    assert!(!overflow(&oam));
+
+    // Only 8 are drawn
+    oam.extend([49, 1, 0x00, 200]);
+    assert!(overflow(&oam));
+    let (ppu, _) = sprites(&oam);
+    assert_eq!(pixel(&ppu, 200, 50), 0x11);
+
+    // After 8, the wrong byte of each entry is taken for Y: a ninth sprite
+    // can be missed, and a tile number taken for one
+    oam.truncate(32);
+    oam.extend([0xF0; 4]);
+    oam.extend([49, 0xF0, 0xF0, 0xF0]);
+    assert!(!overflow(&oam));
+
+    oam.truncate(32);
+    oam.extend([0xF0; 4]);
+    oam.extend([0xF0, 49, 0xF0, 0xF0]);
+    assert!(overflow(&oam));
+}
+
+#[test]
+fn test_debug_viewers() {
+    let (mut ppu, mut cartridge) = background();
+    ppu.write_register(0x2001, 0x00, Some(&mut cartridge));
+    write_vram(&mut ppu, &mut cartridge, 0x3F14, &[0x0F, 0x16, 0x26, 0x36]);
+    reset_scroll(&mut ppu, &mut cartridge);
+
+    let palette = Palette::default();
+    let rgba = |output: &[u8], width: usize, x: usize, y: usize| {
+        let offset = (y * width + x) * 4;
+        output[offset..offset + 4].to_vec()
+    };
+    let opaque = |color: u16| {
+        let [r, g, b] = palette.color(color);
+        vec![r, g, b, 0xFF]
+    };
+
+    // Tile 1 on the left, tile 2 on the right and, mirrored, below them,
+    // with the screen outlined at the top left
+    let output = debug::nametables(&ppu, Some(&mut cartridge), &palette);
+    let width = debug::NAMETABLES_WIDTH;
+    assert_eq!(output.len(), width * debug::NAMETABLES_HEIGHT * 4);
+    assert_eq!(rgba(&output, width, 10, 10), opaque(0x11));
+    assert_eq!(rgba(&output, width, 266, 10), opaque(0x22));
+    assert_eq!(rgba(&output, width, 10, 250), opaque(0x11));
+    assert_eq!(rgba(&output, width, 0, 0), vec![255, 0, 255, 255]);
+    assert_eq!(rgba(&output, width, 255, 100), vec![255, 0, 255, 255]);
+
+    // 8 pixels into the right nametable, the outline wraps round to the
+    // left one
+    ppu.write_register(0x2000, 0x01, Some(&mut cartridge));
+    ppu.write_register(0x2005, 0x08, Some(&mut cartridge));
+    ppu.write_register(0x2005, 0x00, Some(&mut cartridge));
+    let output = debug::nametables(&ppu, Some(&mut cartridge), &palette);
+    assert_eq!(rgba(&output, width, 0, 100), opaque(0x11));
+    assert_eq!(rgba(&output, width, 264, 100), vec![255, 0, 255, 255]);
+    assert_eq!(rgba(&output, width, 7, 100), vec![255, 0, 255, 255]);
+    assert_eq!(rgba(&output, width, 3, 0), vec![255, 0, 255, 255]);
+
+    // Tile 3 is only its left column, in palette 1
+    let output = debug::pattern_table(&ppu, Some(&mut cartridge), 0, 1, &palette);
+    let size = debug::PATTERN_TABLE_SIZE;
+    assert_eq!(output.len(), size * size * 4);
+    assert_eq!(rgba(&output, size, 8, 0), opaque(0x14));
+    assert_eq!(rgba(&output, size, 24, 7), opaque(0x34));
+    assert_eq!(rgba(&output, size, 25, 7), opaque(0x0F));
+
+    // Sprite 1 is tile 3 flipped, in sprite palette 1
+    ppu.oam = [0xFF; 256];
+    ppu.oam[4..8].copy_from_slice(&[0x20, 0x03, 0x61, 0x40]);
+    let entries = debug::oam_entries(&ppu);
+    assert_eq!(entries.len(), 64);
+    assert_eq!(
+        entries[1],
+        debug::OamEntry {
+            x: 0x40,
+            y: 0x20,
+            tile: 0x03,
+            palette: 1,
+            behind_background: true,
+            flip_horizontal: true,
+            flip_vertical: false,
+        }
+    );
+
+    let output = debug::oam_sheet(&ppu, Some(&mut cartridge), &palette);
+    let width = debug::OAM_SHEET_WIDTH;
+    assert_eq!(output.len(), width * debug::OAM_SHEET_HEIGHT * 4);
+    assert_eq!(rgba(&output, width, 15, 3), opaque(0x36));
+    assert_eq!(rgba(&output, width, 8, 3), vec![0; 4]);
+    assert_eq!(rgba(&output, width, 15, 8), vec![0; 4]);
+
+    let output = debug::palette_ram(&ppu, &palette);
+    let width = debug::PALETTE_WIDTH;
+    assert_eq!(output.len(), width * debug::PALETTE_HEIGHT * 4);
+    assert_eq!(rgba(&output, width, 20, 5), opaque(0x11));
+    assert_eq!(rgba(&output, width, 5 * 16 + 3, 16 + 3), opaque(0x16));
+    assert_eq!(rgba(&output, width, 3, 16 + 3), opaque(0x0F));
+}
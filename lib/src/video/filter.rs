@@ -0,0 +1,168 @@
+// A software NTSC filter. Each line of pixels is turned into the composite
+// signal the PPU would put out, 8 samples a pixel, and decoded again the
+// way a TV would. Decoding can't separate brightness from colour cleanly,
+// so colours fringe at edges, patterns blend and the picture crawls as the
+// subcarrier's phase moves from line to line and frame to frame.
+
+use super::ntsc::{self, NtscSettings};
+use super::{Palette, Rgb, PALETTE_SIZE};
+use crate::ppu::{HEIGHT, WIDTH};
+
+// The PPU's master clock runs at 6 times the subcarrier, with 8 ticks a
+// pixel. Samples here are at twice the master clock, 12 a colour cycle.
+const SAMPLES_PER_PIXEL: usize = 8;
+const PHASES: usize = 12;
+const LINE_SAMPLES: usize = WIDTH * SAMPLES_PER_PIXEL;
+
+// Each output pixel is decoded from 4 samples on, two per PPU pixel
+const SAMPLES_PER_OUTPUT: usize = 4;
+pub const OUTPUT_WIDTH: usize = LINE_SAMPLES / SAMPLES_PER_OUTPUT;
+pub const OUTPUT_HEIGHT: usize = HEIGHT;
+
+// A line takes 341 pixels of 8 samples, which moves the phase on by 4
+const LINE_PHASE_STEP: usize = 341 * SAMPLES_PER_PIXEL % PHASES;
+
+// A frame of 262 lines moves it on by 4 more. With rendering on, odd frames
+// skip a dot, which makes that 8 and brings the next frame back to the
+// start, so the phase only alternates between two frames.
+const FRAME_PHASE_STEP: usize = 262 * 341 * SAMPLES_PER_PIXEL % PHASES;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterPreset {
+    // Brightness and colour share one signal, and bleed into each other
+    Composite,
+    // Brightness has its own wire, so only colour is blurred
+    SVideo,
+    // No signal at all: the palette, stretched to the same size
+    Rgb,
+}
+
+impl FilterPreset {
+    // How many samples luma and chroma are averaged over. A full colour
+    // cycle removes the subcarrier from luma; less leaves some of it in.
+    fn widths(self) -> (usize, usize) {
+        match self {
+            FilterPreset::Composite => (6, 24),
+            FilterPreset::SVideo => (PHASES, 24),
+            FilterPreset::Rgb => (1, 1),
+        }
+    }
+}
+
+pub struct NtscFilter {
+    preset: FilterPreset,
+    settings: NtscSettings,
+    // The signal of every pixel value at every phase
+    signals: Box<[[f32; PHASES]; PALETTE_SIZE]>,
+    carrier: [(f32, f32); PHASES],
+    palette: Palette,
+    line: Vec<f32>,
+}
+
+impl NtscFilter {
+    pub fn new(preset: FilterPreset, settings: &NtscSettings) -> Self {
+        let mut signals = Box::new([[0.0; PHASES]; PALETTE_SIZE]);
+
+        for (pixel, phases) in signals.iter_mut().enumerate() {
+            for (phase, signal) in phases.iter_mut().enumerate() {
+                *signal = ntsc::signal(pixel as u16, phase, settings);
+            }
+        }
+
+        let mut carrier = [(0.0, 0.0); PHASES];
+
+        for (phase, value) in carrier.iter_mut().enumerate() {
+            *value = ntsc::carrier(phase, settings);
+        }
+
+        NtscFilter {
+            preset,
+            settings: *settings,
+            signals,
+            carrier,
+            palette: Palette::ntsc(settings),
+            line: vec![0.0; LINE_SAMPLES],
+        }
+    }
+
+    pub fn preset(&self) -> FilterPreset {
+        self.preset
+    }
+
+    // Filters a frame of WIDTH * HEIGHT pixels into OUTPUT_WIDTH *
+    // OUTPUT_HEIGHT pixels of RGBA. `frame` is the PPU's frame count, which
+    // sets where the subcarrier starts.
+    pub fn filter(&mut self, pixels: &[u16], frame: u64, output: &mut [u8]) {
+        let rows = pixels
+            .chunks_exact(WIDTH)
+            .zip(output.chunks_exact_mut(OUTPUT_WIDTH * 4));
+
+        for (y, (row, output)) in rows.enumerate() {
+            if self.preset == FilterPreset::Rgb {
+                for (&pixel, rgba) in row.iter().zip(output.chunks_exact_mut(8)) {
+                    let [r, g, b] = self.palette.color(pixel);
+                    rgba.copy_from_slice(&[r, g, b, 0xFF, r, g, b, 0xFF]);
+                }
+
+                continue;
+            }
+
+            // The phase repeats every 3 lines and every 2 frames
+            let phase = (frame as usize % 2 * FRAME_PHASE_STEP + y * LINE_PHASE_STEP) % PHASES;
+            self.modulate(row, phase);
+
+            for (x, rgba) in output.chunks_exact_mut(4).enumerate() {
+                let [r, g, b] = self.demodulate(x * SAMPLES_PER_OUTPUT, phase);
+                rgba.copy_from_slice(&[r, g, b, 0xFF]);
+            }
+        }
+    }
+
+    pub fn filter_to_vec(&mut self, pixels: &[u16], frame: u64) -> Vec<u8> {
+        let mut output = vec![0; OUTPUT_WIDTH * OUTPUT_HEIGHT * 4];
+        self.filter(pixels, frame, &mut output);
+        output
+    }
+
+    fn modulate(&mut self, row: &[u16], phase: usize) {
+        for (x, &pixel) in row.iter().enumerate() {
+            let signals = &self.signals[pixel as usize % PALETTE_SIZE];
+
+            for sample in 0..SAMPLES_PER_PIXEL {
+                let position = x * SAMPLES_PER_PIXEL + sample;
+                self.line[position] = signals[(phase + position) % PHASES];
+            }
+        }
+    }
+
+    // Luma and chroma low-passed with box filters centred on the output
+    // pixel, with the chroma shifted down to baseband first
+    fn demodulate(&self, start: usize, phase: usize) -> Rgb {
+        let (luma_width, chroma_width) = self.preset.widths();
+        let center = start + SAMPLES_PER_OUTPUT / 2;
+
+        let sample = |offset: isize| {
+            let position = (center as isize + offset).clamp(0, LINE_SAMPLES as isize - 1);
+            (position as usize, self.line[position as usize])
+        };
+
+        let half = luma_width as isize / 2;
+        let y = (-half..luma_width as isize - half)
+            .map(|offset| sample(offset).1)
+            .sum::<f32>()
+            / luma_width as f32;
+
+        let half = chroma_width as isize / 2;
+        let (mut i, mut q) = (0.0, 0.0);
+
+        for offset in -half..chroma_width as isize - half {
+            let (position, value) = sample(offset);
+            let (cos, sin) = self.carrier[(phase + position) % PHASES];
+            i += value * cos;
+            q += value * sin;
+        }
+
+        let chroma_width = chroma_width as f32;
+        ntsc::yiq_to_rgb(y, i / chroma_width, q / chroma_width, &self.settings)
+    }
+}
@@ -0,0 +1,262 @@
+// Turning the PPU's pixels into colours, and the basic places to send
+// frames to
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::VideoInterface;
+
+pub mod filter;
+pub mod ntsc;
+pub mod png;
+
+pub use ntsc::NtscSettings;
+
+// Where the emphasis bits sit in a pixel, above the 6-bit palette index
+pub const EMPHASIS_SHIFT: u16 = 6;
+pub const EMPHASIS_RED: u16 = 1 << EMPHASIS_SHIFT;
+pub const EMPHASIS_GREEN: u16 = 1 << (EMPHASIS_SHIFT + 1);
+pub const EMPHASIS_BLUE: u16 = 1 << (EMPHASIS_SHIFT + 2);
+
+// Every palette index with every combination of emphasis bits
+pub const PALETTE_SIZE: usize = 512;
+
+// How much emphasising a colour dims the other two
+const EMPHASIS_ATTENUATION: f32 = 0.816;
+
+pub type Rgb = [u8; 3];
+
+#[derive(Debug)]
+pub enum PaletteError {
+    // .pal files hold 64 or 512 colours of 3 bytes each
+    InvalidSize(usize),
+    Io(io::Error),
+}
+
+impl fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PaletteError::InvalidSize(size) => write!(
+                f,
+                "palette files must be 192 or 1536 bytes, not {} bytes",
+                size
+            ),
+            PaletteError::Io(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for PaletteError {}
+
+// A 2C02's colours as most emulators show them
+const DEFAULT_COLORS: [Rgb; 64] = [
+    [84, 84, 84],
+    [0, 30, 116],
+    [8, 16, 144],
+    [48, 0, 136],
+    [68, 0, 100],
+    [92, 0, 48],
+    [84, 4, 0],
+    [60, 24, 0],
+    [32, 42, 0],
+    [8, 58, 0],
+    [0, 64, 0],
+    [0, 60, 0],
+    [0, 50, 60],
+    [0, 0, 0],
+    [0, 0, 0],
+    [0, 0, 0],
+    [152, 150, 152],
+    [8, 76, 196],
+    [48, 50, 236],
+    [92, 30, 228],
+    [136, 20, 176],
+    [160, 20, 100],
+    [152, 34, 32],
+    [120, 60, 0],
+    [84, 90, 0],
+    [40, 114, 0],
+    [8, 124, 0],
+    [0, 118, 40],
+    [0, 102, 120],
+    [0, 0, 0],
+    [0, 0, 0],
+    [0, 0, 0],
+    [236, 238, 236],
+    [76, 154, 236],
+    [120, 124, 236],
+    [176, 98, 236],
+    [228, 84, 236],
+    [236, 88, 180],
+    [236, 106, 100],
+    [212, 136, 32],
+    [160, 170, 0],
+    [116, 196, 0],
+    [76, 208, 32],
+    [56, 204, 108],
+    [56, 180, 204],
+    [60, 60, 60],
+    [0, 0, 0],
+    [0, 0, 0],
+    [236, 238, 236],
+    [168, 204, 236],
+    [188, 188, 236],
+    [212, 178, 236],
+    [236, 174, 236],
+    [236, 174, 212],
+    [236, 180, 176],
+    [228, 196, 144],
+    [204, 210, 120],
+    [180, 222, 120],
+    [168, 226, 144],
+    [152, 226, 180],
+    [160, 214, 228],
+    [160, 162, 160],
+    [0, 0, 0],
+    [0, 0, 0],
+];
+
+// The colour of every pixel value, emphasis included
+#[derive(Clone)]
+pub struct Palette {
+    colors: Box<[Rgb; PALETTE_SIZE]>,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::new(&DEFAULT_COLORS)
+    }
+}
+
+impl Palette {
+    // 64 colours, with emphasis approximated by dimming the colours that
+    // aren't emphasised
+    pub fn new(colors: &[Rgb; 64]) -> Self {
+        let mut full = Box::new([[0; 3]; PALETTE_SIZE]);
+
+        for (pixel, color) in full.iter_mut().enumerate() {
+            let emphasis = pixel >> EMPHASIS_SHIFT;
+            *color = colors[pixel & 0x3F];
+
+            for (channel, value) in color.iter_mut().enumerate() {
+                // Each emphasis bit dims the channels other than its own
+                let dimmed = (0..3)
+                    .filter(|&bit| bit != channel && emphasis & (1 << bit) != 0)
+                    .count();
+
+                for _ in 0..dimmed {
+                    *value = (*value as f32 * EMPHASIS_ATTENUATION).round() as u8;
+                }
+            }
+        }
+
+        Palette { colors: full }
+    }
+
+    // A colour for each of the 512 pixel values
+    pub fn with_emphasis(colors: &[Rgb; PALETTE_SIZE]) -> Self {
+        Palette {
+            colors: Box::new(*colors),
+        }
+    }
+
+    // Decoded from the composite signal, with a TV's picture settings
+    pub fn ntsc(settings: &NtscSettings) -> Self {
+        Palette {
+            colors: ntsc::decode_all(settings),
+        }
+    }
+
+    // The contents of a .pal file: RGB triples for the 64 colours, or for
+    // all 512 with the emphasis combinations in order after them
+    pub fn parse(data: &[u8]) -> Result<Self, PaletteError> {
+        let mut colors = [[0; 3]; PALETTE_SIZE];
+        let count = data.len() / 3;
+
+        if data.len() != 64 * 3 && data.len() != PALETTE_SIZE * 3 {
+            return Err(PaletteError::InvalidSize(data.len()));
+        }
+
+        for (color, rgb) in colors.iter_mut().zip(data.chunks_exact(3)) {
+            color.copy_from_slice(rgb);
+        }
+
+        if count == 64 {
+            let mut base = [[0; 3]; 64];
+            base.copy_from_slice(&colors[..64]);
+            Ok(Self::new(&base))
+        } else {
+            Ok(Self::with_emphasis(&colors))
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, PaletteError> {
+        Self::parse(&fs::read(path).map_err(PaletteError::Io)?)
+    }
+
+    pub fn color(&self, pixel: u16) -> Rgb {
+        self.colors[pixel as usize % PALETTE_SIZE]
+    }
+
+    pub fn colors(&self) -> &[Rgb; PALETTE_SIZE] {
+        &self.colors
+    }
+}
+
+// Writes 4 bytes of RGBA per pixel into `output`, which has to be big
+// enough for them
+pub fn write_rgba(pixels: &[u16], palette: &Palette, output: &mut [u8]) {
+    for (&pixel, rgba) in pixels.iter().zip(output.chunks_exact_mut(4)) {
+        let [r, g, b] = palette.color(pixel);
+        rgba.copy_from_slice(&[r, g, b, 0xFF]);
+    }
+}
+
+pub fn to_rgba(pixels: &[u16], palette: &Palette) -> Vec<u8> {
+    let mut output = vec![0; pixels.len() * 4];
+    write_rgba(pixels, palette, &mut output);
+    output
+}
+
+// Throws frames away, for running without a screen
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NullVideo;
+
+impl VideoInterface for NullVideo {
+    fn frame(&mut self, _pixels: &[u16]) {}
+}
+
+// Keeps every frame it's given, for tests to look at
+#[derive(Debug, Default, Clone)]
+pub struct CaptureVideo {
+    frames: Vec<Vec<u16>>,
+}
+
+impl CaptureVideo {
+    pub fn new() -> Self {
+        CaptureVideo { frames: Vec::new() }
+    }
+
+    pub fn frames(&self) -> &[Vec<u16>] {
+        &self.frames
+    }
+
+    pub fn last_frame(&self) -> Option<&[u16]> {
+        self.frames.last().map(|frame| frame.as_slice())
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+}
+
+impl VideoInterface for CaptureVideo {
+    fn frame(&mut self, pixels: &[u16]) {
+        self.frames.push(pixels.to_vec());
+    }
+}
+
+#[cfg(test)]
+mod tests;
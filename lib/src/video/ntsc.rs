@@ -0,0 +1,135 @@
+// Colours worked out from the composite signal the PPU puts out, as a TV
+// decodes it. Each pixel is a square wave between two voltages whose phase
+// gives the hue, sampled at 12 points a colour cycle and demodulated into
+// YIQ.
+
+use std::f32::consts::PI;
+
+use super::{Rgb, EMPHASIS_SHIFT, PALETTE_SIZE};
+
+// Voltages relative to sync, for the low and high half of the wave at each
+// of the 4 levels
+const SIGNAL_LOW: [f32; 4] = [0.350, 0.518, 0.962, 1.550];
+const SIGNAL_HIGH: [f32; 4] = [1.094, 1.506, 1.962, 1.962];
+const BLACK: f32 = 0.518;
+const WHITE: f32 = 1.962;
+// How much an emphasis bit cuts the signal during its third of the cycle
+const ATTENUATION: f32 = 0.746;
+
+// The gamma the decode comes out at, which `gamma` is corrected from
+const SOURCE_GAMMA: f32 = 2.2;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NtscSettings {
+    // In twelfths of a colour cycle, 30 degrees each
+    pub hue: f32,
+    pub saturation: f32,
+    pub contrast: f32,
+    pub brightness: f32,
+    pub gamma: f32,
+}
+
+impl Default for NtscSettings {
+    fn default() -> Self {
+        NtscSettings {
+            hue: 0.0,
+            saturation: 1.0,
+            contrast: 1.0,
+            brightness: 1.0,
+            gamma: 1.8,
+        }
+    }
+}
+
+// Whether the wave for `color` is high at `phase`. Colours 1-12 are 12
+// phases of the same wave.
+fn in_phase(color: usize, phase: usize) -> bool {
+    (color + phase + 8) % 12 < 6
+}
+
+// The signal for `pixel` at one of the 12 phases, scaled so black is 0
+// and white 1, then adjusted for contrast and brightness
+pub(crate) fn signal(pixel: u16, phase: usize, settings: &NtscSettings) -> f32 {
+    let color = pixel as usize & 0x0F;
+    let emphasis = pixel as usize >> EMPHASIS_SHIFT;
+
+    // $xE and $xF are black at any level
+    let level = if color < 0x0E {
+        (pixel as usize >> 4) & 0x03
+    } else {
+        1
+    };
+
+    // Colour 0 stays high, $D and up stay low, and the rest alternate
+    let mut signal = if (in_phase(color, phase) && color < 0x0D) || color == 0x00 {
+        SIGNAL_HIGH[level]
+    } else {
+        SIGNAL_LOW[level]
+    };
+
+    // Red, green and blue emphasis each cover the phases of colours $C, $4
+    // and $8
+    let attenuated = [12, 4, 8]
+        .iter()
+        .enumerate()
+        .any(|(bit, &phase_color)| emphasis & (1 << bit) != 0 && in_phase(phase_color, phase));
+
+    if attenuated {
+        signal *= ATTENUATION;
+    }
+
+    let value = (signal - BLACK) / (WHITE - BLACK);
+    ((value - 0.5) * settings.contrast + 0.5) * settings.brightness
+}
+
+// Where the subcarrier is at `phase`, for demodulating
+pub(crate) fn carrier(phase: usize, settings: &NtscSettings) -> (f32, f32) {
+    let angle = PI / 6.0 * (phase as f32 + settings.hue);
+    (angle.cos(), angle.sin())
+}
+
+// The FCC's YIQ to RGB matrix, with saturation and gamma
+pub(crate) fn yiq_to_rgb(y: f32, i: f32, q: f32, settings: &NtscSettings) -> Rgb {
+    let (i, q) = (i * settings.saturation, q * settings.saturation);
+
+    let channel = |value: f32| {
+        let corrected = if value <= 0.0 {
+            0.0
+        } else {
+            value.powf(SOURCE_GAMMA / settings.gamma)
+        };
+
+        (corrected * 255.95).clamp(0.0, 255.0) as u8
+    };
+
+    [
+        channel(y + 0.946882 * i + 0.623557 * q),
+        channel(y - 0.274788 * i - 0.635691 * q),
+        channel(y - 1.108545 * i + 1.709007 * q),
+    ]
+}
+
+// A whole colour cycle averaged, as a TV decodes a flat area
+pub fn decode(pixel: u16, settings: &NtscSettings) -> Rgb {
+    let (mut y, mut i, mut q) = (0.0, 0.0, 0.0);
+
+    for phase in 0..12 {
+        let value = signal(pixel, phase, settings) / 12.0;
+        let (cos, sin) = carrier(phase, settings);
+        y += value;
+        i += value * cos;
+        q += value * sin;
+    }
+
+    yiq_to_rgb(y, i, q, settings)
+}
+
+pub fn decode_all(settings: &NtscSettings) -> Box<[Rgb; PALETTE_SIZE]> {
+    let mut colors = Box::new([[0; 3]; PALETTE_SIZE]);
+
+    for (pixel, color) in colors.iter_mut().enumerate() {
+        *color = decode(pixel as u16, settings);
+    }
+
+    colors
+}
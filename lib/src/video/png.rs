@@ -0,0 +1,370 @@
+// PNG files of frames and debug views, written with the crate's own
+// deflate so screenshots don't need an image library. Reading is limited
+// to the 8-bit RGB and RGBA images this writes, for tests to compare
+// frames against.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use byteorder::{BigEndian, ByteOrder};
+
+use super::{write_rgba, Palette};
+use crate::cartridge::hash::crc32;
+use crate::ppu::{HEIGHT, WIDTH};
+use crate::zip;
+
+pub const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+const COLOR_TYPE_RGB: u8 = 2;
+const COLOR_TYPE_RGBA: u8 = 6;
+
+// Far beyond anything drawn here, so a corrupt header can't exhaust memory
+const MAX_PIXELS: usize = 0x100_0000;
+
+// tEXt keywords. Title is one of the standard ones.
+const ROM_NAME_KEYWORD: &str = "Title";
+const FRAME_KEYWORD: &str = "Frame";
+
+// zlib's header for deflate with a 32 KB window and no dictionary
+const ZLIB_HEADER: [u8; 2] = [0x78, 0x01];
+
+#[derive(Debug)]
+pub enum PngError {
+    InvalidImage,
+    // Anything but 8-bit RGB or RGBA without interlacing
+    UnsupportedFormat,
+    ChecksumMismatch,
+    Io(io::Error),
+}
+
+impl fmt::Display for PngError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PngError::InvalidImage => write!(f, "invalid PNG image"),
+            PngError::UnsupportedFormat => {
+                write!(f, "only 8-bit RGB and RGBA PNG images are supported")
+            }
+            PngError::ChecksumMismatch => write!(f, "PNG image fails its checksum"),
+            PngError::Io(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for PngError {}
+
+// Where a screenshot came from, stored as text chunks
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Metadata {
+    pub rom_name: Option<String>,
+    pub frame: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub rgba: Vec<u8>,
+    pub metadata: Metadata,
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+
+    // 5552 bytes is as many as can be summed before b could overflow
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+
+        a %= 65521;
+        b %= 65521;
+    }
+
+    b << 16 | a
+}
+
+fn write_chunk(output: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    let start = output.len() + 4;
+    output.extend((data.len() as u32).to_be_bytes());
+    output.extend_from_slice(kind);
+    output.extend_from_slice(data);
+    output.extend(crc32(&output[start..]).to_be_bytes());
+}
+
+// tEXt is Latin-1, so anything outside it becomes a question mark
+fn write_text(output: &mut Vec<u8>, keyword: &str, text: &str) {
+    let mut data = keyword.as_bytes().to_vec();
+    data.push(0);
+    data.extend(
+        text.chars()
+            .map(|c| if (c as u32) < 0x100 { c as u8 } else { b'?' }),
+    );
+    write_chunk(output, b"tEXt", &data);
+}
+
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let estimate = left as i16 + up as i16 - up_left as i16;
+    let distances = [left, up, up_left].map(|value| (estimate - value as i16).abs());
+
+    if distances[0] <= distances[1] && distances[0] <= distances[2] {
+        left
+    } else if distances[1] <= distances[2] {
+        up
+    } else {
+        up_left
+    }
+}
+
+// What filter `kind` predicts for byte `index` of a row, from the bytes
+// already known
+fn predict(kind: u8, row: &[u8], previous: &[u8], index: usize, bpp: usize) -> u8 {
+    let left = if index >= bpp { row[index - bpp] } else { 0 };
+    let up = previous[index];
+    let up_left = if index >= bpp {
+        previous[index - bpp]
+    } else {
+        0
+    };
+
+    match kind {
+        1 => left,
+        2 => up,
+        3 => ((left as u16 + up as u16) / 2) as u8,
+        4 => paeth(left, up, up_left),
+        _ => 0,
+    }
+}
+
+// Each row is filtered whichever way leaves the smallest differences, the
+// usual guess at what compresses best
+fn filter_rows(rgba: &[u8], width: usize) -> Vec<u8> {
+    let stride = width * 4;
+    let mut output = Vec::with_capacity(rgba.len() + rgba.len() / stride.max(1));
+    let mut previous = vec![0; stride];
+    let mut filtered = vec![0; stride];
+
+    for row in rgba.chunks_exact(stride) {
+        let mut best = (u64::MAX, 0, Vec::new());
+
+        for kind in 0..5 {
+            for (index, value) in filtered.iter_mut().enumerate() {
+                *value = row[index].wrapping_sub(predict(kind, row, &previous, index, 4));
+            }
+
+            let cost = filtered
+                .iter()
+                .map(|&value| (value as i8).unsigned_abs() as u64)
+                .sum();
+
+            if cost < best.0 {
+                best = (cost, kind, filtered.clone());
+            }
+        }
+
+        output.push(best.1);
+        output.extend(best.2);
+        previous.copy_from_slice(row);
+    }
+
+    output
+}
+
+// An RGBA image of `width` * `height` pixels. PNG has no empty images, and
+// `rgba` has to hold exactly that many.
+pub fn encode(
+    width: usize,
+    height: usize,
+    rgba: &[u8],
+    metadata: &Metadata,
+) -> Result<Vec<u8>, PngError> {
+    let size = width
+        .checked_mul(height)
+        .and_then(|pixels| pixels.checked_mul(4));
+
+    if width == 0 || height == 0 || size != Some(rgba.len()) {
+        return Err(PngError::InvalidImage);
+    }
+
+    let mut output = PNG_SIGNATURE.to_vec();
+
+    let mut header = [0; 13];
+    BigEndian::write_u32(&mut header[0..], width as u32);
+    BigEndian::write_u32(&mut header[4..], height as u32);
+    header[8] = 8;
+    header[9] = COLOR_TYPE_RGBA;
+    write_chunk(&mut output, b"IHDR", &header);
+
+    if let Some(name) = &metadata.rom_name {
+        write_text(&mut output, ROM_NAME_KEYWORD, name);
+    }
+
+    if let Some(frame) = metadata.frame {
+        write_text(&mut output, FRAME_KEYWORD, &frame.to_string());
+    }
+
+    let rows = filter_rows(rgba, width);
+    let mut data = ZLIB_HEADER.to_vec();
+    data.extend(zip::deflate(&rows));
+    data.extend(adler32(&rows).to_be_bytes());
+    write_chunk(&mut output, b"IDAT", &data);
+
+    write_chunk(&mut output, b"IEND", &[]);
+    Ok(output)
+}
+
+pub fn save<P: AsRef<Path>>(
+    path: P,
+    width: usize,
+    height: usize,
+    rgba: &[u8],
+    metadata: &Metadata,
+) -> Result<(), PngError> {
+    fs::write(path, encode(width, height, rgba, metadata)?).map_err(PngError::Io)
+}
+
+// A frame from the PPU, in `palette`'s colours
+pub fn screenshot(pixels: &[u16], palette: &Palette, metadata: &Metadata) -> Vec<u8> {
+    let mut rgba = vec![0; WIDTH * HEIGHT * 4];
+    write_rgba(pixels, palette, &mut rgba);
+    encode(WIDTH, HEIGHT, &rgba, metadata).expect("a frame is a valid image")
+}
+
+fn read_text(data: &[u8], metadata: &mut Metadata) {
+    if let Some(split) = data.iter().position(|&byte| byte == 0) {
+        let text: String = data[split + 1..].iter().map(|&byte| byte as char).collect();
+
+        match &data[..split] {
+            keyword if keyword == ROM_NAME_KEYWORD.as_bytes() => metadata.rom_name = Some(text),
+            keyword if keyword == FRAME_KEYWORD.as_bytes() => metadata.frame = text.parse().ok(),
+            _ => {}
+        }
+    }
+}
+
+fn unfilter_rows(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    bpp: usize,
+) -> Result<Vec<u8>, PngError> {
+    let stride = width * bpp;
+    let mut output = vec![0; stride * height];
+    let mut previous = vec![0; stride];
+
+    for (filtered, row) in data
+        .chunks_exact(stride + 1)
+        .zip(output.chunks_exact_mut(stride))
+    {
+        let kind = filtered[0];
+
+        if kind > 4 {
+            return Err(PngError::InvalidImage);
+        }
+
+        for index in 0..stride {
+            row[index] =
+                filtered[index + 1].wrapping_add(predict(kind, row, &previous, index, bpp));
+        }
+
+        previous.copy_from_slice(row);
+    }
+
+    Ok(output)
+}
+
+pub fn decode(data: &[u8]) -> Result<Image, PngError> {
+    if !data.starts_with(&PNG_SIGNATURE) {
+        return Err(PngError::InvalidImage);
+    }
+
+    let mut header = None;
+    let mut compressed = Vec::new();
+    let mut metadata = Metadata::default();
+    let mut offset = PNG_SIGNATURE.len();
+
+    loop {
+        let length =
+            BigEndian::read_u32(data.get(offset..offset + 4).ok_or(PngError::InvalidImage)?)
+                as usize;
+        let chunk = data
+            .get(offset + 4..offset + 8 + length)
+            .ok_or(PngError::InvalidImage)?;
+        let crc = data
+            .get(offset + 8 + length..offset + 12 + length)
+            .ok_or(PngError::InvalidImage)?;
+
+        if crc32(chunk) != BigEndian::read_u32(crc) {
+            return Err(PngError::ChecksumMismatch);
+        }
+
+        let (kind, body) = chunk.split_at(4);
+        offset += 12 + length;
+
+        match kind {
+            b"IHDR" if body.len() == 13 => header = Some(body),
+            b"IDAT" => compressed.extend_from_slice(body),
+            b"tEXt" => read_text(body, &mut metadata),
+            b"IEND" => break,
+            _ => {}
+        }
+    }
+
+    let header = header.ok_or(PngError::InvalidImage)?;
+    let width = BigEndian::read_u32(&header[0..]) as usize;
+    let height = BigEndian::read_u32(&header[4..]) as usize;
+
+    if width == 0 || height == 0 || width * height > MAX_PIXELS {
+        return Err(PngError::InvalidImage);
+    }
+
+    let bpp = match (header[8], header[9], &header[10..]) {
+        (8, COLOR_TYPE_RGB, [0, 0, 0]) => 3,
+        (8, COLOR_TYPE_RGBA, [0, 0, 0]) => 4,
+        _ => return Err(PngError::UnsupportedFormat),
+    };
+
+    // zlib: a deflate stream between a 2 byte header and an Adler-32
+    if compressed.len() < 6
+        || compressed[0] & 0x0F != 8
+        || compressed[1] & 0x20 != 0
+        || BigEndian::read_u16(&compressed) % 31 != 0
+    {
+        return Err(PngError::InvalidImage);
+    }
+
+    let size = (width * bpp + 1) * height;
+    let end = compressed.len() - 4;
+    let rows = zip::inflate(&compressed[2..end], size).map_err(|_| PngError::InvalidImage)?;
+
+    if rows.len() != size {
+        return Err(PngError::InvalidImage);
+    }
+
+    if adler32(&rows) != BigEndian::read_u32(&compressed[end..]) {
+        return Err(PngError::ChecksumMismatch);
+    }
+
+    let pixels = unfilter_rows(&rows, width, height, bpp)?;
+    let rgba = if bpp == 4 {
+        pixels
+    } else {
+        pixels
+            .chunks_exact(3)
+            .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 0xFF])
+            .collect()
+    };
+
+    Ok(Image {
+        width,
+        height,
+        rgba,
+        metadata,
+    })
+}
+
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Image, PngError> {
+    decode(&fs::read(path).map_err(PngError::Io)?)
+}
@@ -0,0 +1,297 @@
+use super::filter::{FilterPreset, NtscFilter};
+use super::png::{self, Metadata, PngError};
+use super::*;
+use crate::cpu::CPU;
+use crate::ppu::{HEIGHT, WIDTH};
+
+#[test]
+fn test_palette() {
+    let palette = Palette::default();
+    assert_eq!(palette.color(0x30), [236, 238, 236]);
+    assert_eq!(palette.color(0x0F), [0, 0, 0]);
+
+    // Emphasis dims the other channels, once for each bit doing so
+    assert_eq!(palette.color(0x30 | EMPHASIS_RED), [236, 194, 193]);
+    assert_eq!(
+        palette.color(0x30 | EMPHASIS_RED | EMPHASIS_GREEN),
+        [193, 194, 157]
+    );
+    assert_eq!(
+        palette.color(0x30 | EMPHASIS_RED | EMPHASIS_GREEN | EMPHASIS_BLUE),
+        [157, 158, 157]
+    );
+
+    let mut colors = [[0; 3]; PALETTE_SIZE];
+    colors[0x1FF] = [1, 2, 3];
+    assert_eq!(Palette::with_emphasis(&colors).color(0x1FF), [1, 2, 3]);
+}
+
+#[test]
+fn test_rgba() {
+    let palette = Palette::default();
+    let pixels = [0x30, 0x01 | EMPHASIS_BLUE];
+
+    assert_eq!(
+        to_rgba(&pixels, &palette),
+        [236, 238, 236, 255, 0, 24, 116, 255]
+    );
+}
+
+#[test]
+fn test_capture() {
+    let mut cpu = CPU::new();
+    cpu.load(vec![
+        0xA9, 0x3F, // LDA #$3F
+        0x8D, 0x06, 0x20, // STA $2006
+        0xA9, 0x00, // LDA #$00
+        0x8D, 0x06, 0x20, // STA $2006
+        0xA9, 0x21, // LDA #$21
+        0x8D, 0x07, 0x20, // STA $2007
+        0xA9, 0x28, // LDA #$28
+        0x8D, 0x01, 0x20, // STA $2001
+        0x4C, 0x14, 0x80, // JMP $8014
+    ]);
+    cpu.reset();
+
+    let mut video = CaptureVideo::new();
+    cpu.run_frame(&mut video);
+    cpu.run_frame(&mut video);
+    NullVideo.frame(&[]);
+
+    // The backdrop with red emphasis, everywhere
+    assert_eq!(video.frames().len(), 2);
+    let frame = video.last_frame().unwrap();
+    assert_eq!(frame.len(), WIDTH * HEIGHT);
+    assert!(frame.iter().all(|&pixel| pixel == 0x21 | EMPHASIS_RED));
+
+    video.clear();
+    assert!(video.last_frame().is_none());
+}
+
+#[test]
+fn test_ntsc_palette() {
+    let palette = Palette::ntsc(&NtscSettings::default());
+    assert_eq!(palette.color(0x0F), [0, 0, 0]);
+    assert_eq!(palette.color(0x0D), [0, 0, 0]);
+    assert_eq!(palette.color(0x00), [83, 83, 83]);
+    assert_eq!(palette.color(0x20), [255, 255, 255]);
+    assert_eq!(palette.color(0x16), [131, 46, 36]);
+
+    // Red emphasis darkens the other two the most
+    assert_eq!(palette.color(0x16 | EMPHASIS_RED), [127, 33, 23]);
+
+    let gray = Palette::ntsc(&NtscSettings {
+        saturation: 0.0,
+        ..NtscSettings::default()
+    });
+    assert_eq!(gray.color(0x16), [68, 68, 68]);
+
+    // A third of a turn takes red to blue
+    let rotated = Palette::ntsc(&NtscSettings {
+        hue: 4.0,
+        ..NtscSettings::default()
+    });
+    assert_eq!(rotated.color(0x16), [56, 55, 189]);
+
+    let brighter = Palette::ntsc(&NtscSettings {
+        brightness: 1.2,
+        contrast: 1.1,
+        gamma: 2.2,
+        ..NtscSettings::default()
+    });
+    assert!(brighter.color(0x00)[0] > 83);
+}
+
+#[test]
+fn test_pal_files() {
+    let colors: Vec<u8> = (0..64 * 3).map(|i| i as u8).collect();
+    let palette = Palette::parse(&colors).unwrap();
+    assert_eq!(palette.color(0x01), [3, 4, 5]);
+    assert_eq!(palette.color(0x01 | EMPHASIS_BLUE), [2, 3, 5]);
+
+    let colors: Vec<u8> = (0..PALETTE_SIZE * 3).map(|i| (i / 3) as u8).collect();
+    let palette = Palette::parse(&colors).unwrap();
+    assert_eq!(palette.color(0x141), [0x41, 0x41, 0x41]);
+
+    assert!(matches!(
+        Palette::parse(&[0; 193]),
+        Err(PaletteError::InvalidSize(193))
+    ));
+
+    let path = std::env::temp_dir().join(format!("nesemu-{}.pal", std::process::id()));
+    std::fs::write(&path, [0xFF; 64 * 3]).unwrap();
+    assert_eq!(Palette::load(&path).unwrap().color(0x3F), [0xFF; 3]);
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(matches!(Palette::load(&path), Err(PaletteError::Io(_))));
+}
+
+#[test]
+fn test_grayscale() {
+    // The backdrop as 0x16, then with grayscale on
+    let mut cpu = CPU::new();
+    cpu.load(vec![
+        0xA9, 0x3F, // LDA #$3F
+        0x8D, 0x06, 0x20, // STA $2006
+        0xA9, 0x00, // LDA #$00
+        0x8D, 0x06, 0x20, // STA $2006
+        0xA9, 0x16, // LDA #$16
+        0x8D, 0x07, 0x20, // STA $2007
+        0xA9, 0x09, // LDA #$09
+        0x8D, 0x01, 0x20, // STA $2001
+        0x4C, 0x14, 0x80, // JMP $8014
+    ]);
+    cpu.reset();
+
+    let mut video = CaptureVideo::new();
+    cpu.run_frame(&mut video);
+    cpu.run_frame(&mut video);
+
+    let frame = video.last_frame().unwrap();
+    assert!(frame.iter().all(|&pixel| pixel == 0x10));
+}
+
+#[test]
+fn test_ntsc_filter() {
+    let settings = NtscSettings::default();
+    let palette = Palette::ntsc(&settings);
+
+    // Red on the left half, white on the right
+    let mut pixels = vec![0x16; WIDTH * HEIGHT];
+    for row in pixels.chunks_exact_mut(WIDTH) {
+        row[WIDTH / 2..].fill(0x30);
+    }
+
+    let rgba = |output: &[u8], x: usize, y: usize| {
+        let offset = (y * filter::OUTPUT_WIDTH + x) * 4;
+        [output[offset], output[offset + 1], output[offset + 2]]
+    };
+    let close = |a: Rgb, b: Rgb| a.iter().zip(&b).all(|(&a, &b)| a.abs_diff(b) <= 2);
+
+    // RGB is just the palette, stretched
+    let mut rgb = NtscFilter::new(FilterPreset::Rgb, &settings);
+    let output = rgb.filter_to_vec(&pixels, 0);
+    assert_eq!(
+        output.len(),
+        filter::OUTPUT_WIDTH * filter::OUTPUT_HEIGHT * 4
+    );
+    assert_eq!(rgba(&output, 0, 0), palette.color(0x16));
+    assert_eq!(rgba(&output, 255, 0), palette.color(0x16));
+    assert_eq!(rgba(&output, 256, 0), palette.color(0x30));
+
+    // S-Video decodes flat areas to the palette, and they don't crawl
+    let mut svideo = NtscFilter::new(FilterPreset::SVideo, &settings);
+    let output = svideo.filter_to_vec(&pixels, 0);
+    assert!(close(rgba(&output, 100, 10), palette.color(0x16)));
+    assert!(close(rgba(&output, 400, 10), palette.color(0x30)));
+    assert_eq!(
+        rgba(&output, 100, 10),
+        rgba(&svideo.filter_to_vec(&pixels, 1), 100, 10)
+    );
+
+    // Composite leaves some of the subcarrier in, so flat colour crawls
+    // from line to line and frame to frame
+    let mut composite = NtscFilter::new(FilterPreset::Composite, &settings);
+    let first = composite.filter_to_vec(&pixels, 0);
+    let second = composite.filter_to_vec(&pixels, 1);
+    let differs =
+        |a: &[u8], b: &[u8], line: usize| (100..103).any(|x| rgba(a, x, 10) != rgba(b, x, line));
+    assert!(differs(&first, &second, 10));
+    assert!(differs(&first, &first, 11));
+
+    // The dot odd frames skip brings every other frame back to the same phase
+    assert_eq!(first, composite.filter_to_vec(&pixels, 2));
+    assert_eq!(second, composite.filter_to_vec(&pixels, 3));
+    assert!((100..103).any(|x| !close(rgba(&first, x, 10), palette.color(0x16))));
+
+    // Even with S-Video, red bleeds into the white next to it
+    let fringe = rgba(&output, 256, 10);
+    assert!(fringe[0] != fringe[2]);
+}
+
+#[test]
+fn test_png() {
+    let palette = Palette::default();
+    let mut pixels = vec![0x21; WIDTH * HEIGHT];
+    pixels[WIDTH * 100 + 50] = 0x16 | EMPHASIS_BLUE;
+
+    let metadata = Metadata {
+        rom_name: Some("Pac-Man (Japan) ★".to_string()),
+        frame: Some(1234),
+    };
+    let data = png::screenshot(&pixels, &palette, &metadata);
+    assert!(data.starts_with(&png::PNG_SIGNATURE));
+    assert!(data.len() < WIDTH * HEIGHT / 16);
+
+    // Text outside Latin-1 doesn't survive
+    let image = png::decode(&data).unwrap();
+    assert_eq!((image.width, image.height), (WIDTH, HEIGHT));
+    assert_eq!(image.rgba, to_rgba(&pixels, &palette));
+    assert_eq!(
+        image.metadata.rom_name.as_deref(),
+        Some("Pac-Man (Japan) ?")
+    );
+    assert_eq!(image.metadata.frame, Some(1234));
+
+    // Noise exercises every filter, and RGB images read as opaque RGBA
+    let rgba: Vec<u8> = (0..64 * 48 * 4u32)
+        .map(|i| (i * i % 251 + i / 256) as u8)
+        .collect();
+    let image = png::decode(&png::encode(64, 48, &rgba, &Metadata::default()).unwrap()).unwrap();
+    assert_eq!(image.rgba, rgba);
+    assert_eq!(image.metadata, Metadata::default());
+
+    // Empty images and pixels that don't match the size are refused
+    for (width, height, length) in [(0, 1, 0), (1, 0, 0), (0, 0, 0), (64, 48, 100)] {
+        assert!(matches!(
+            png::encode(width, height, &vec![0; length], &Metadata::default()),
+            Err(PngError::InvalidImage)
+        ));
+    }
+    assert!(matches!(
+        png::encode(2, 1, &rgba, &Metadata::default()),
+        Err(PngError::InvalidImage)
+    ));
+
+    // zlib's output for a single dark green pixel
+    let mut rgb = png::PNG_SIGNATURE.to_vec();
+    let chunk = |output: &mut Vec<u8>, kind: &[u8], body: &[u8]| {
+        let mut data = kind.to_vec();
+        data.extend_from_slice(body);
+        output.extend((body.len() as u32).to_be_bytes());
+        output.extend_from_slice(&data);
+        output.extend(crate::cartridge::hash::crc32(&data).to_be_bytes());
+    };
+    chunk(&mut rgb, b"IHDR", &[0, 0, 0, 1, 0, 0, 0, 1, 8, 2, 0, 0, 0]);
+    chunk(
+        &mut rgb,
+        b"IDAT",
+        &[
+            0x78, 0x9C, 0x63, 0x60, 0x10, 0x60, 0x00, 0x00, 0x00, 0x24, 0x00, 0x11,
+        ],
+    );
+    chunk(&mut rgb, b"IEND", &[]);
+    assert_eq!(png::decode(&rgb).unwrap().rgba, [0x00, 0x10, 0x00, 0xFF]);
+
+    // Damage is caught by the checksums
+    let mut corrupt = data.clone();
+    corrupt[40] ^= 0x01;
+    assert!(matches!(
+        png::decode(&corrupt),
+        Err(PngError::ChecksumMismatch)
+    ));
+    assert!(matches!(
+        png::decode(&data[..100]),
+        Err(PngError::InvalidImage)
+    ));
+    assert!(matches!(
+        png::decode(b"GIF89a"),
+        Err(PngError::InvalidImage)
+    ));
+
+    let path = std::env::temp_dir().join(format!("nesemu-{}.png", std::process::id()));
+    png::save(&path, WIDTH, HEIGHT, &to_rgba(&pixels, &palette), &metadata).unwrap();
+    assert_eq!(png::load(&path).unwrap().rgba, to_rgba(&pixels, &palette));
+    std::fs::remove_file(&path).unwrap();
+    assert!(matches!(png::load(&path), Err(PngError::Io(_))));
+}
@@ -0,0 +1,187 @@
+// Raw deflate streams, compressed with LZ77 matches and the fixed Huffman
+// codes. Not as small as zlib's output, but screenshots are mostly runs
+// and repeated tiles, which matches catch anyway.
+
+use super::inflate::{DISTANCE_BASES, DISTANCE_EXTRA_BITS, LENGTH_BASES, LENGTH_EXTRA_BITS};
+
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const WINDOW_SIZE: usize = 32768;
+
+const HASH_BITS: u32 = 15;
+// How many earlier positions with the same hash are tried for a match
+const MAX_CHAIN: usize = 64;
+
+const END_OF_BLOCK: u16 = 256;
+
+struct BitWriter {
+    data: Vec<u8>,
+    bits: u32,
+    count: u8,
+}
+
+impl BitWriter {
+    // Least significant bit first, as the reader takes them
+    fn bits(&mut self, value: u32, count: u8) {
+        self.bits |= value << self.count;
+        self.count += count;
+
+        while self.count >= 8 {
+            self.data.push(self.bits as u8);
+            self.bits >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    // Huffman codes go most significant bit first
+    fn code(&mut self, code: u32, length: u8) {
+        self.bits(code.reverse_bits() >> (32 - length), length);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.data.push(self.bits as u8);
+        }
+
+        self.data
+    }
+}
+
+// The fixed literal/length code for `symbol`, and its length
+fn literal_code(symbol: u16) -> (u32, u8) {
+    let symbol = symbol as u32;
+
+    match symbol {
+        0..=143 => (0x30 + symbol, 8),
+        144..=255 => (0x190 + symbol - 144, 9),
+        256..=279 => (symbol - 256, 7),
+        _ => (0xC0 + symbol - 280, 8),
+    }
+}
+
+// The last code whose base is at most `value`
+fn code_index(bases: &[u16], value: usize) -> usize {
+    bases
+        .iter()
+        .rposition(|&base| base as usize <= value)
+        .unwrap()
+}
+
+fn hash(data: &[u8]) -> usize {
+    let value = (data[0] as u32) << 16 | (data[1] as u32) << 8 | data[2] as u32;
+    (value.wrapping_mul(0x9E37_79B1) >> (32 - HASH_BITS)) as usize
+}
+
+// Earlier positions by the hash of the 3 bytes there, newest first
+struct Matcher {
+    head: Vec<usize>,
+    previous: Vec<usize>,
+}
+
+impl Matcher {
+    fn new() -> Self {
+        Matcher {
+            head: vec![usize::MAX; 1 << HASH_BITS],
+            previous: vec![usize::MAX; WINDOW_SIZE],
+        }
+    }
+
+    fn insert(&mut self, data: &[u8], position: usize) {
+        if position + MIN_MATCH <= data.len() {
+            let hash = hash(&data[position..]);
+            self.previous[position % WINDOW_SIZE] = self.head[hash];
+            self.head[hash] = position;
+        }
+    }
+
+    // The longest match for `position` among earlier positions with the
+    // same hash, as (length, distance)
+    fn longest_match(&self, data: &[u8], position: usize) -> (usize, usize) {
+        let mut best = (0, 0);
+
+        if position + MIN_MATCH > data.len() {
+            return best;
+        }
+
+        let mut candidate = self.head[hash(&data[position..])];
+        let limit = (data.len() - position).min(MAX_MATCH);
+
+        for _ in 0..MAX_CHAIN {
+            if candidate == usize::MAX || position - candidate > WINDOW_SIZE {
+                break;
+            }
+
+            let length = data[candidate..]
+                .iter()
+                .zip(&data[position..position + limit])
+                .take_while(|(a, b)| a == b)
+                .count();
+
+            if length > best.0 {
+                best = (length, position - candidate);
+
+                if length == limit {
+                    break;
+                }
+            }
+
+            candidate = self.previous[candidate % WINDOW_SIZE];
+        }
+
+        best
+    }
+}
+
+// Compresses `data` into a single fixed Huffman block
+pub fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter {
+        data: Vec::with_capacity(data.len() / 2),
+        bits: 0,
+        count: 0,
+    };
+
+    // A final block with the fixed codes
+    writer.bits(1, 1);
+    writer.bits(1, 2);
+
+    let mut matcher = Matcher::new();
+    let mut position = 0;
+
+    while position < data.len() {
+        let (length, distance) = matcher.longest_match(data, position);
+
+        if length < MIN_MATCH {
+            let (code, bits) = literal_code(data[position] as u16);
+            writer.code(code, bits);
+            matcher.insert(data, position);
+            position += 1;
+            continue;
+        }
+
+        let index = code_index(&LENGTH_BASES, length);
+        let (code, bits) = literal_code(257 + index as u16);
+        writer.code(code, bits);
+        writer.bits(
+            (length - LENGTH_BASES[index] as usize) as u32,
+            LENGTH_EXTRA_BITS[index],
+        );
+
+        // Distance codes are all 5 bits
+        let index = code_index(&DISTANCE_BASES, distance);
+        writer.code(index as u32, 5);
+        writer.bits(
+            (distance - DISTANCE_BASES[index] as usize) as u32,
+            DISTANCE_EXTRA_BITS[index],
+        );
+
+        for offset in position..position + length {
+            matcher.insert(data, offset);
+        }
+
+        position += length;
+    }
+
+    let (code, bits) = literal_code(END_OF_BLOCK);
+    writer.code(code, bits);
+    writer.finish()
+}
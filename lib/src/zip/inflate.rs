@@ -0,0 +1,328 @@
+// Raw deflate streams (RFC 1951), as zip stores them
+
+use super::ZipError;
+
+const MAX_BITS: usize = 15;
+
+// Base lengths and extra bits for length codes 257-285
+pub(super) const LENGTH_BASES: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+pub(super) const LENGTH_EXTRA_BITS: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+
+// Base distances and extra bits for distance codes 0-29
+pub(super) const DISTANCE_BASES: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+pub(super) const DISTANCE_EXTRA_BITS: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+// The order code length code lengths are sent in
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+    bits: u32,
+    count: u8,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        BitReader {
+            data,
+            offset: 0,
+            bits: 0,
+            count: 0,
+        }
+    }
+
+    // Deflate packs bits from the least significant end of each byte
+    fn bits(&mut self, count: u8) -> Result<u32, ZipError> {
+        while self.count < count {
+            let byte = *self.data.get(self.offset).ok_or(ZipError::InvalidDeflate)?;
+            self.bits |= (byte as u32) << self.count;
+            self.offset += 1;
+            self.count += 8;
+        }
+
+        let value = self.bits & ((1u32 << count) - 1);
+        self.bits >>= count;
+        self.count -= count;
+
+        Ok(value)
+    }
+
+    // Stored blocks start on a byte boundary
+    fn align(&mut self) {
+        self.bits = 0;
+        self.count = 0;
+    }
+
+    fn bytes(&mut self, length: usize) -> Result<&'a [u8], ZipError> {
+        let end = self.offset + length;
+        let bytes = self
+            .data
+            .get(self.offset..end)
+            .ok_or(ZipError::InvalidDeflate)?;
+        self.offset = end;
+
+        Ok(bytes)
+    }
+}
+
+struct Output {
+    data: Vec<u8>,
+    size: usize,
+}
+
+impl Output {
+    fn reserve(&self, length: usize) -> Result<(), ZipError> {
+        if self.data.len() + length > self.size {
+            return Err(ZipError::InvalidDeflate);
+        }
+
+        Ok(())
+    }
+}
+
+// A canonical Huffman code, as the number of codes of each length and the
+// symbols sorted by code
+struct Huffman {
+    counts: [u16; MAX_BITS + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Self, ZipError> {
+        let mut counts = [0u16; MAX_BITS + 1];
+
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+
+        counts[0] = 0;
+
+        // More codes of a length than there's room for can't be decoded.
+        // Fewer is allowed, as with a single distance code.
+        let mut left: i32 = 1;
+
+        for &count in &counts[1..] {
+            left = left * 2 - count as i32;
+
+            if left < 0 {
+                return Err(ZipError::InvalidDeflate);
+            }
+        }
+
+        let mut offsets = [0u16; MAX_BITS + 2];
+
+        for length in 1..=MAX_BITS {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+
+        let mut symbols = vec![0; offsets[MAX_BITS + 1] as usize];
+
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+
+        Ok(Huffman { counts, symbols })
+    }
+
+    // Codes are sent most significant bit first, so they're read a bit at a
+    // time, walking down the lengths
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, ZipError> {
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+
+        for &count in &self.counts[1..] {
+            code |= reader.bits(1)? as i32;
+            let count = count as i32;
+
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+
+        Err(ZipError::InvalidDeflate)
+    }
+}
+
+// Decompresses `data` to the `size` bytes the archive says it holds. Any
+// more is an error, so a corrupt stream can't grow without bound.
+pub fn inflate(data: &[u8], size: usize) -> Result<Vec<u8>, ZipError> {
+    let mut reader = BitReader::new(data);
+    let mut output = Output {
+        data: Vec::with_capacity(size),
+        size,
+    };
+
+    loop {
+        let last = reader.bits(1)? == 1;
+
+        match reader.bits(2)? {
+            0 => stored(&mut reader, &mut output)?,
+            1 => {
+                let (literals, distances) = fixed_codes()?;
+                codes(&mut reader, &mut output, &literals, &distances)?;
+            }
+            2 => {
+                let (literals, distances) = dynamic_codes(&mut reader)?;
+                codes(&mut reader, &mut output, &literals, &distances)?;
+            }
+            _ => return Err(ZipError::InvalidDeflate),
+        }
+
+        if last {
+            return Ok(output.data);
+        }
+    }
+}
+
+fn stored(reader: &mut BitReader, output: &mut Output) -> Result<(), ZipError> {
+    reader.align();
+    let header = reader.bytes(4)?;
+    let length = u16::from_le_bytes([header[0], header[1]]);
+    let complement = u16::from_le_bytes([header[2], header[3]]);
+
+    if length != !complement {
+        return Err(ZipError::InvalidDeflate);
+    }
+
+    output.reserve(length as usize)?;
+    output
+        .data
+        .extend_from_slice(reader.bytes(length as usize)?);
+
+    Ok(())
+}
+
+fn fixed_codes() -> Result<(Huffman, Huffman), ZipError> {
+    let mut lengths = [0u8; 288];
+    lengths[0..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..288].fill(8);
+
+    Ok((Huffman::new(&lengths)?, Huffman::new(&[5; 30])?))
+}
+
+fn dynamic_codes(reader: &mut BitReader) -> Result<(Huffman, Huffman), ZipError> {
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_length_count = reader.bits(4)? as usize + 4;
+
+    if literal_count > 286 || distance_count > 30 {
+        return Err(ZipError::InvalidDeflate);
+    }
+
+    let mut code_lengths = [0u8; 19];
+
+    for &index in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_lengths[index] = reader.bits(3)? as u8;
+    }
+
+    let code_lengths = Huffman::new(&code_lengths)?;
+
+    // Both codes' lengths come as one run-length encoded sequence
+    let mut lengths = vec![0u8; literal_count + distance_count];
+    let mut index = 0;
+
+    while index < lengths.len() {
+        let symbol = code_lengths.decode(reader)?;
+
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *index
+                    .checked_sub(1)
+                    .and_then(|previous| lengths.get(previous))
+                    .ok_or(ZipError::InvalidDeflate)?;
+                (previous, 3 + reader.bits(2)? as usize)
+            }
+            17 => (0, 3 + reader.bits(3)? as usize),
+            _ => (0, 11 + reader.bits(7)? as usize),
+        };
+
+        if index + repeat > lengths.len() {
+            return Err(ZipError::InvalidDeflate);
+        }
+
+        lengths[index..index + repeat].fill(value);
+        index += repeat;
+    }
+
+    // Without an end of block code the block could never end
+    if lengths[256] == 0 {
+        return Err(ZipError::InvalidDeflate);
+    }
+
+    Ok((
+        Huffman::new(&lengths[..literal_count])?,
+        Huffman::new(&lengths[literal_count..])?,
+    ))
+}
+
+fn codes(
+    reader: &mut BitReader,
+    output: &mut Output,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> Result<(), ZipError> {
+    loop {
+        let symbol = literals.decode(reader)? as usize;
+
+        match symbol {
+            0..=255 => {
+                output.reserve(1)?;
+                output.data.push(symbol as u8);
+            }
+            256 => return Ok(()),
+            257..=285 => {
+                let index = symbol - 257;
+                let length =
+                    LENGTH_BASES[index] as usize + reader.bits(LENGTH_EXTRA_BITS[index])? as usize;
+
+                let index = distances.decode(reader)? as usize;
+
+                if index >= DISTANCE_BASES.len() {
+                    return Err(ZipError::InvalidDeflate);
+                }
+
+                let distance = DISTANCE_BASES[index] as usize
+                    + reader.bits(DISTANCE_EXTRA_BITS[index])? as usize;
+
+                if distance > output.data.len() {
+                    return Err(ZipError::InvalidDeflate);
+                }
+
+                output.reserve(length)?;
+
+                // The match can overlap what it's copying, repeating it
+                let start = output.data.len() - distance;
+
+                for offset in 0..length {
+                    output.data.push(output.data[start + offset]);
+                }
+            }
+            _ => return Err(ZipError::InvalidDeflate),
+        }
+    }
+}
@@ -0,0 +1,239 @@
+// Zip archives, read far enough to get a ROM out of one. Only stored and
+// deflated entries are supported, which is everything zip tools write by
+// default.
+
+use std::fmt;
+use std::path::Path;
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use crate::cartridge::hash::crc32;
+
+mod deflate;
+mod inflate;
+
+// Also used for PNG screenshots
+pub(crate) use deflate::deflate;
+pub(crate) use inflate::inflate;
+
+pub const ZIP_MAGIC: [u8; 4] = [b'P', b'K', 0x03, 0x04];
+
+const END_OF_DIRECTORY_SIGNATURE: u32 = 0x0605_4B50;
+const END_OF_DIRECTORY_SIZE: usize = 22;
+const DIRECTORY_ENTRY_SIGNATURE: u32 = 0x0201_4B50;
+const DIRECTORY_ENTRY_SIZE: usize = 46;
+const LOCAL_HEADER_SIGNATURE: u32 = 0x0403_4B50;
+const LOCAL_HEADER_SIZE: usize = 30;
+
+// The end of directory record can be followed by a comment this long
+const MAX_COMMENT_SIZE: usize = 0xFFFF;
+
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATED: u16 = 8;
+const FLAG_ENCRYPTED: u16 = 1 << 0;
+
+// Far beyond any NES ROM, so a corrupt size can't exhaust memory
+const MAX_ENTRY_SIZE: usize = 0x400_0000;
+
+// Everything the loaders know how to open
+pub const ROM_EXTENSIONS: [&str; 7] = ["nes", "unf", "unif", "fds", "qd", "nsf", "nsfe"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ZipError {
+    InvalidArchive,
+    Encrypted,
+    UnsupportedCompression(u16),
+    InvalidDeflate,
+    ChecksumMismatch,
+    NoRom,
+    // The caller has to pick one of these
+    MultipleRoms(Vec<String>),
+    MissingEntry(String),
+}
+
+impl fmt::Display for ZipError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ZipError::InvalidArchive => write!(f, "invalid zip archive"),
+            ZipError::Encrypted => write!(f, "encrypted zip entries aren't supported"),
+            ZipError::UnsupportedCompression(method) => {
+                write!(f, "unsupported zip compression method: {}", method)
+            }
+            ZipError::InvalidDeflate => write!(f, "corrupt compressed data"),
+            ZipError::ChecksumMismatch => write!(f, "zip entry fails its checksum"),
+            ZipError::NoRom => write!(f, "no ROM in the zip archive"),
+            ZipError::MultipleRoms(names) => {
+                write!(f, "several ROMs in the zip archive: {}", names.join(", "))
+            }
+            ZipError::MissingEntry(name) => write!(f, "no {} in the zip archive", name),
+        }
+    }
+}
+
+impl std::error::Error for ZipError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub name: String,
+    pub size: usize,
+    method: u16,
+    flags: u16,
+    crc32: u32,
+    compressed_size: usize,
+    header_offset: usize,
+}
+
+impl Entry {
+    pub fn is_rom(&self) -> bool {
+        let extension = Path::new(&self.name)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase());
+
+        matches!(extension, Some(extension) if ROM_EXTENSIONS.contains(&extension.as_str()))
+    }
+}
+
+pub struct ZipArchive<'a> {
+    data: &'a [u8],
+    entries: Vec<Entry>,
+}
+
+impl<'a> ZipArchive<'a> {
+    // Reads the central directory at the end of the archive
+    pub fn parse(data: &'a [u8]) -> Result<Self, ZipError> {
+        let end = find_end_of_directory(data)?;
+        let count = LittleEndian::read_u16(&data[end + 10..]) as usize;
+        let mut offset = LittleEndian::read_u32(&data[end + 16..]) as usize;
+        let mut entries = Vec::with_capacity(count);
+
+        for _ in 0..count {
+            let header = data
+                .get(offset..offset + DIRECTORY_ENTRY_SIZE)
+                .ok_or(ZipError::InvalidArchive)?;
+
+            if LittleEndian::read_u32(header) != DIRECTORY_ENTRY_SIGNATURE {
+                return Err(ZipError::InvalidArchive);
+            }
+
+            let name_length = LittleEndian::read_u16(&header[28..]) as usize;
+            let extra_length = LittleEndian::read_u16(&header[30..]) as usize;
+            let comment_length = LittleEndian::read_u16(&header[32..]) as usize;
+
+            let name_start = offset + DIRECTORY_ENTRY_SIZE;
+            let name = data
+                .get(name_start..name_start + name_length)
+                .ok_or(ZipError::InvalidArchive)?;
+
+            entries.push(Entry {
+                name: String::from_utf8_lossy(name).into_owned(),
+                size: LittleEndian::read_u32(&header[24..]) as usize,
+                method: LittleEndian::read_u16(&header[10..]),
+                flags: LittleEndian::read_u16(&header[8..]),
+                crc32: LittleEndian::read_u32(&header[16..]),
+                compressed_size: LittleEndian::read_u32(&header[20..]) as usize,
+                header_offset: LittleEndian::read_u32(&header[42..]) as usize,
+            });
+
+            offset = name_start + name_length + extra_length + comment_length;
+        }
+
+        Ok(ZipArchive { data, entries })
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    // The entry called `name`, or the only ROM in the archive when no name
+    // is given
+    pub fn find_rom(&self, name: Option<&str>) -> Result<&Entry, ZipError> {
+        if let Some(name) = name {
+            return self
+                .entries
+                .iter()
+                .find(|entry| entry.name == name)
+                .ok_or_else(|| ZipError::MissingEntry(name.to_string()));
+        }
+
+        let roms: Vec<&Entry> = self.entries.iter().filter(|entry| entry.is_rom()).collect();
+
+        match roms.as_slice() {
+            [] => Err(ZipError::NoRom),
+            [rom] => Ok(rom),
+            _ => Err(ZipError::MultipleRoms(
+                roms.iter().map(|entry| entry.name.clone()).collect(),
+            )),
+        }
+    }
+
+    // Decompresses an entry and checks it against its CRC32
+    pub fn read(&self, entry: &Entry) -> Result<Vec<u8>, ZipError> {
+        if entry.flags & FLAG_ENCRYPTED != 0 {
+            return Err(ZipError::Encrypted);
+        }
+
+        if entry.size > MAX_ENTRY_SIZE {
+            return Err(ZipError::InvalidArchive);
+        }
+
+        // The local header repeats the name, with its own extra field
+        let offset = entry.header_offset;
+        let header = self
+            .data
+            .get(offset..offset + LOCAL_HEADER_SIZE)
+            .ok_or(ZipError::InvalidArchive)?;
+
+        if LittleEndian::read_u32(header) != LOCAL_HEADER_SIGNATURE {
+            return Err(ZipError::InvalidArchive);
+        }
+
+        let name_length = LittleEndian::read_u16(&header[26..]) as usize;
+        let extra_length = LittleEndian::read_u16(&header[28..]) as usize;
+        let start = offset + LOCAL_HEADER_SIZE + name_length + extra_length;
+        let compressed = self
+            .data
+            .get(start..start + entry.compressed_size)
+            .ok_or(ZipError::InvalidArchive)?;
+
+        let data = match entry.method {
+            METHOD_STORED => compressed.to_vec(),
+            METHOD_DEFLATED => inflate::inflate(compressed, entry.size)?,
+            method => return Err(ZipError::UnsupportedCompression(method)),
+        };
+
+        if data.len() != entry.size || crc32(&data) != entry.crc32 {
+            return Err(ZipError::ChecksumMismatch);
+        }
+
+        Ok(data)
+    }
+}
+
+// The end of directory record is the last thing in the archive, but for a
+// comment of unknown length
+fn find_end_of_directory(data: &[u8]) -> Result<usize, ZipError> {
+    if data.len() < END_OF_DIRECTORY_SIZE {
+        return Err(ZipError::InvalidArchive);
+    }
+
+    let last = data.len() - END_OF_DIRECTORY_SIZE;
+    let first = last.saturating_sub(MAX_COMMENT_SIZE);
+
+    (first..=last)
+        .rev()
+        .find(|&offset| LittleEndian::read_u32(&data[offset..]) == END_OF_DIRECTORY_SIGNATURE)
+        .ok_or(ZipError::InvalidArchive)
+}
+
+// Pulls the ROM called `name` out of a zip archive, or its only ROM.
+// Returns the entry's name along with its data.
+pub fn extract_rom(data: &[u8], name: Option<&str>) -> Result<(String, Vec<u8>), ZipError> {
+    let archive = ZipArchive::parse(data)?;
+    let entry = archive.find_rom(name)?;
+
+    Ok((entry.name.clone(), archive.read(entry)?))
+}
+
+#[cfg(test)]
+mod tests;
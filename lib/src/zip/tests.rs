@@ -0,0 +1,272 @@
+use super::*;
+use crate::cartridge::{Cartridge, CartridgeError, OpenOptions};
+
+// zlib's output for `sample()`, with its own Huffman codes
+const DYNAMIC_BLOCK: [u8; 158] = [
+    0xED, 0xCF, 0x4B, 0x6E, 0x05, 0x20, 0x08, 0x05, 0xD0, 0xB5, 0x82, 0xFC, 0x04, 0x41, 0xF6, 0x3F,
+    0x2A, 0xAF, 0xE9, 0xA0, 0xE9, 0x12, 0x1A, 0x47, 0xC6, 0x70, 0x2F, 0xE1, 0x00, 0xB2, 0x83, 0x33,
+    0xC2, 0xCF, 0x2B, 0xEA, 0x57, 0xAE, 0x9F, 0x38, 0x97, 0x7A, 0x13, 0xB0, 0xEC, 0xF2, 0x55, 0x7D,
+    0xDB, 0x92, 0x30, 0xBD, 0x2E, 0x9E, 0x05, 0xA7, 0xB2, 0x24, 0xB4, 0x3B, 0x01, 0xFD, 0x06, 0x91,
+    0x70, 0x94, 0x51, 0xCE, 0x9C, 0x14, 0x52, 0xC4, 0x34, 0xD9, 0x85, 0xA6, 0x1F, 0x6B, 0x7A, 0x48,
+    0xB6, 0x21, 0x94, 0x59, 0xE5, 0x50, 0xB9, 0xD9, 0xE4, 0x8E, 0x60, 0x37, 0xFE, 0xFE, 0x7F, 0xE6,
+    0x01, 0xDB, 0x3E, 0xF9, 0x5C, 0xC1, 0x30, 0x7B, 0x9D, 0x53, 0x4D, 0x24, 0x41, 0x27, 0x97, 0x49,
+    0x56, 0xC1, 0xB3, 0x37, 0xAE, 0x23, 0x64, 0xB7, 0x86, 0xCC, 0x29, 0x07, 0xD6, 0xC1, 0x3B, 0xF3,
+    0xE9, 0xD9, 0x9C, 0x5A, 0xCB, 0x6B, 0xCB, 0xF4, 0x77, 0xD3, 0x1D, 0xCA, 0x37, 0x49, 0xE5, 0x0F,
+    0x15, 0x1E, 0xFD, 0xD1, 0x1F, 0xFD, 0xD1, 0x1F, 0xFD, 0xD1, 0xFF, 0x0B, 0xFD, 0x0B,
+];
+
+// zlib's output for FIXED_TEXT, with the fixed Huffman codes
+const FIXED_BLOCK: [u8; 10] = [0xF3, 0x73, 0x0D, 0x96, 0x72, 0x74, 0x72, 0xC6, 0x8A, 0x00];
+const FIXED_TEXT: &[u8] = b"NES\x1aABCABCABCABCABCABCABCABC";
+
+fn sample() -> Vec<u8> {
+    (0..2000u32)
+        .map(|i| (i * i % 251 % 16) as u8 + b'a')
+        .collect()
+}
+
+// A single final stored block
+fn stored(data: &[u8]) -> Vec<u8> {
+    let length = data.len() as u16;
+    let mut block = vec![0x01];
+    block.extend(length.to_le_bytes());
+    block.extend((!length).to_le_bytes());
+    block.extend_from_slice(data);
+    block
+}
+
+struct ZipEntry<'a> {
+    name: &'a str,
+    method: u16,
+    flags: u16,
+    compressed: Vec<u8>,
+    data: &'a [u8],
+}
+
+fn stored_entry<'a>(name: &'a str, data: &'a [u8]) -> ZipEntry<'a> {
+    ZipEntry {
+        name,
+        method: METHOD_STORED,
+        flags: 0,
+        compressed: data.to_vec(),
+        data,
+    }
+}
+
+fn zip(entries: &[ZipEntry]) -> Vec<u8> {
+    let mut archive = Vec::new();
+    let mut directory = Vec::new();
+
+    for entry in entries {
+        let offset = archive.len() as u32;
+        let mut common = Vec::new();
+        common.extend(20u16.to_le_bytes());
+        common.extend(entry.flags.to_le_bytes());
+        common.extend(entry.method.to_le_bytes());
+        common.extend([0; 4]);
+        common.extend(crc32(entry.data).to_le_bytes());
+        common.extend((entry.compressed.len() as u32).to_le_bytes());
+        common.extend((entry.data.len() as u32).to_le_bytes());
+        common.extend((entry.name.len() as u16).to_le_bytes());
+        common.extend(0u16.to_le_bytes());
+
+        archive.extend(LOCAL_HEADER_SIGNATURE.to_le_bytes());
+        archive.extend(&common);
+        archive.extend(entry.name.as_bytes());
+        archive.extend(&entry.compressed);
+
+        directory.extend(DIRECTORY_ENTRY_SIGNATURE.to_le_bytes());
+        directory.extend(20u16.to_le_bytes());
+        directory.extend(&common);
+        // No comment, disk number or attributes
+        directory.extend([0; 10]);
+        directory.extend(offset.to_le_bytes());
+        directory.extend(entry.name.as_bytes());
+    }
+
+    let directory_offset = archive.len() as u32;
+    archive.extend(&directory);
+    archive.extend(END_OF_DIRECTORY_SIGNATURE.to_le_bytes());
+    archive.extend([0; 4]);
+    archive.extend((entries.len() as u16).to_le_bytes());
+    archive.extend((entries.len() as u16).to_le_bytes());
+    archive.extend((directory.len() as u32).to_le_bytes());
+    archive.extend(directory_offset.to_le_bytes());
+
+    // A comment, which hides the record from a fixed offset
+    archive.extend(7u16.to_le_bytes());
+    archive.extend(b"comment");
+    archive
+}
+
+#[test]
+fn test_inflate() {
+    assert_eq!(inflate::inflate(&stored(b"stored"), 6).unwrap(), b"stored");
+    assert_eq!(
+        inflate::inflate(&FIXED_BLOCK, FIXED_TEXT.len()).unwrap(),
+        FIXED_TEXT
+    );
+    assert_eq!(inflate::inflate(&DYNAMIC_BLOCK, 2000).unwrap(), sample());
+
+    // Streams that run out, use the reserved block type or grow past the
+    // size they should have
+    let errors = [
+        inflate::inflate(&DYNAMIC_BLOCK[..100], 2000),
+        inflate::inflate(&[0x07], 10),
+        inflate::inflate(&DYNAMIC_BLOCK, 1999),
+        inflate::inflate(&stored(b"stored")[..8], 6),
+    ];
+
+    for error in errors {
+        assert_eq!(error, Err(ZipError::InvalidDeflate));
+    }
+
+    // The length of a stored block is checked against its complement
+    let mut block = stored(b"stored");
+    block[3] ^= 0x01;
+    assert_eq!(inflate::inflate(&block, 6), Err(ZipError::InvalidDeflate));
+}
+
+#[test]
+fn test_deflate() {
+    let runs: Vec<u8> = (0..70000u32).map(|i| (i / 1000) as u8).collect();
+    let far: Vec<u8> = sample().into_iter().cycle().take(40000).collect();
+
+    for data in [&b""[..], b"a", b"ab", FIXED_TEXT, &sample(), &runs, &far] {
+        let compressed = deflate(data);
+        assert_eq!(inflate(&compressed, data.len()).unwrap(), data);
+    }
+
+    // Matches cover repeats, including the longest ones
+    assert_eq!(deflate(FIXED_TEXT), FIXED_BLOCK);
+    assert!(deflate(&runs).len() < 1000);
+}
+
+#[test]
+fn test_zip_archive() {
+    let sample = sample();
+    let data = zip(&[
+        stored_entry("readme.txt", b"hello"),
+        ZipEntry {
+            name: "Game (USA).NES",
+            method: METHOD_DEFLATED,
+            flags: 0,
+            compressed: DYNAMIC_BLOCK.to_vec(),
+            data: &sample,
+        },
+    ]);
+
+    let archive = ZipArchive::parse(&data).unwrap();
+    assert_eq!(archive.entries().len(), 2);
+    assert_eq!(archive.entries()[1].size, 2000);
+    assert!(!archive.entries()[0].is_rom());
+
+    let entry = archive.find_rom(None).unwrap();
+    assert_eq!(entry.name, "Game (USA).NES");
+    assert_eq!(archive.read(entry).unwrap(), sample);
+
+    let readme = archive.find_rom(Some("readme.txt")).unwrap();
+    assert_eq!(archive.read(readme).unwrap(), b"hello");
+    assert_eq!(
+        archive.find_rom(Some("other.nes")),
+        Err(ZipError::MissingEntry("other.nes".to_string()))
+    );
+
+    assert_eq!(
+        extract_rom(&data, None).unwrap(),
+        ("Game (USA).NES".to_string(), sample.clone())
+    );
+
+    assert!(matches!(
+        ZipArchive::parse(&data[..data.len() - 30]),
+        Err(ZipError::InvalidArchive)
+    ));
+}
+
+#[test]
+fn test_zip_errors() {
+    let several = zip(&[stored_entry("a.nes", b"a"), stored_entry("b.fds", b"b")]);
+    assert_eq!(
+        extract_rom(&several, None),
+        Err(ZipError::MultipleRoms(vec![
+            "a.nes".to_string(),
+            "b.fds".to_string()
+        ]))
+    );
+    assert_eq!(extract_rom(&several, Some("b.fds")).unwrap().1, b"b");
+
+    let none = zip(&[stored_entry("readme.txt", b"hello")]);
+    assert_eq!(extract_rom(&none, None), Err(ZipError::NoRom));
+
+    let mut corrupt = stored_entry("game.nes", b"game");
+    corrupt.compressed = b"gamf".to_vec();
+    assert_eq!(
+        extract_rom(&zip(&[corrupt]), None),
+        Err(ZipError::ChecksumMismatch)
+    );
+
+    let mut encrypted = stored_entry("game.nes", b"game");
+    encrypted.flags = FLAG_ENCRYPTED;
+    assert_eq!(
+        extract_rom(&zip(&[encrypted]), None),
+        Err(ZipError::Encrypted)
+    );
+
+    let mut bzip2 = stored_entry("game.nes", b"game");
+    bzip2.method = 12;
+    assert_eq!(
+        extract_rom(&zip(&[bzip2]), None),
+        Err(ZipError::UnsupportedCompression(12))
+    );
+}
+
+#[test]
+fn test_open_zipped() {
+    let dir = std::env::temp_dir().join(format!("nesemu-zip-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    // An NROM image with 0x42 at the start of PRG-ROM
+    let mut rom = vec![b'N', b'E', b'S', 0x1A, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    rom.extend(vec![0x42; 0x4000]);
+    rom.extend(vec![0; 0x2000]);
+
+    let zip_path = dir.join("game.zip");
+    std::fs::write(&zip_path, zip(&[stored_entry("game.nes", &rom)])).unwrap();
+
+    let mut cartridge = Cartridge::open(&zip_path).unwrap();
+    assert_eq!(cartridge.read_prg(0x8000), 0x42);
+
+    // Disks are told apart by the entry's name, and need the BIOS
+    let disk = vec![0; 65500];
+    let zip_path = dir.join("disks.zip");
+    std::fs::write(
+        &zip_path,
+        zip(&[
+            stored_entry("game.nes", &rom),
+            stored_entry("disk.fds", &disk),
+        ]),
+    )
+    .unwrap();
+
+    assert!(matches!(
+        Cartridge::open(&zip_path),
+        Err(CartridgeError::Zip(ZipError::MultipleRoms(_)))
+    ));
+
+    let mut options = OpenOptions {
+        patch: None,
+        auto_patch: false,
+        entry: Some("disk.fds".to_string()),
+        fds_bios: None,
+    };
+    assert!(matches!(
+        Cartridge::open_with(&zip_path, &options),
+        Err(CartridgeError::MissingBios)
+    ));
+
+    options.fds_bios = Some(vec![0; 0x2000]);
+    let mut cartridge = Cartridge::open_with(&zip_path, &options).unwrap();
+    assert_eq!(cartridge.disk_side_count(), 1);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}